@@ -0,0 +1,55 @@
+//! Talks to a server over a unix domain socket rather than TCP, for when both run on the same machine.
+//!
+//! Run with `cargo run -p comms --example unix_socket --features="client,server"`
+
+use comms::{
+    command::{self, UserCommand},
+    event::{self, Event},
+    transport,
+};
+use tokio::net::{UnixListener, UnixStream};
+use tokio_stream::StreamExt;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let path = std::env::temp_dir().join(format!("comms-example-{}.sock", std::process::id()));
+    let listener = UnixListener::bind(&path)?;
+
+    let result = tokio::try_join!(server(listener), client(&path));
+    let _ = std::fs::remove_file(&path);
+    result?;
+
+    Ok(())
+}
+
+/// Answers the pings of a single client until it disconnects
+async fn server(listener: UnixListener) -> anyhow::Result<()> {
+    let (stream, _) = listener.accept().await?;
+    // the same transport works for any stream, the framing is identical to TCP
+    let (mut command_stream, mut event_writer) = transport::server::split_stream(stream);
+
+    while let Some(command) = command_stream.next().await {
+        println!("SERVER: Command received: {:?}", command?);
+        event_writer
+            .write(&Event::Pong(event::PongReplyEvent))
+            .await?;
+    }
+
+    Ok(())
+}
+
+async fn client(path: &std::path::Path) -> anyhow::Result<()> {
+    let stream = UnixStream::connect(path).await?;
+    let (mut event_stream, mut command_writer) = transport::client::split_stream(stream);
+
+    command_writer
+        .write(&UserCommand::Ping(command::PingCommand))
+        .await?;
+
+    match event_stream.next().await {
+        Some(event) => println!("CLIENT: Event received: {:?}", event?),
+        None => anyhow::bail!("server closed the connection"),
+    }
+
+    Ok(())
+}
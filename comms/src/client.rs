@@ -0,0 +1,108 @@
+use anyhow::Context;
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio_stream::StreamExt;
+
+use crate::{
+    command::{self, UserCommand},
+    event::Event,
+    transport::{
+        client::{split_stream_with_codec, CommandWriter, EventStream},
+        codec::Codec,
+    },
+};
+
+/// [ChatClient] is a connection to the chat server, for building bots and clients
+/// without dealing with the [EventStream] and [CommandWriter] directly.
+pub struct ChatClient {
+    event_stream: EventStream,
+    command_writer: CommandWriter,
+}
+
+impl ChatClient {
+    /// Connects to the server with the default [Codec] and introduces the client to it.
+    ///
+    /// # Arguments
+    ///
+    /// - `addr` - Address of the server, e.g. `localhost:8080`
+    pub async fn connect(addr: impl ToSocketAddrs) -> anyhow::Result<Self> {
+        Self::connect_with_codec(addr, Codec::default()).await
+    }
+
+    /// Connects to the server and introduces the client to it.
+    ///
+    /// # Arguments
+    ///
+    /// - `addr` - Address of the server, e.g. `localhost:8080`
+    /// - `codec` - The [Codec] to read and write with, the server must use the same one
+    pub async fn connect_with_codec(
+        addr: impl ToSocketAddrs,
+        codec: Codec,
+    ) -> anyhow::Result<Self> {
+        let stream = TcpStream::connect(addr)
+            .await
+            .context("could not connect to the server")?;
+        let (event_stream, command_writer) = split_stream_with_codec(stream, codec);
+
+        let mut client = Self {
+            event_stream,
+            command_writer,
+        };
+        // the server can reject us if the protocols do not match
+        client
+            .send_command(&UserCommand::Hello(command::HelloCommand {
+                client_version: crate::PROTOCOL_VERSION,
+                sequenced: false,
+            }))
+            .await
+            .context("could not say hello")?;
+
+        Ok(client)
+    }
+
+    /// Joins the given room, the server replies with an [Event::UserJoinedRoom] with the room details
+    pub async fn join_room(&mut self, room: &str) -> anyhow::Result<()> {
+        self.send_command(&UserCommand::JoinRoom(command::JoinRoomCommand {
+            room: room.into(),
+        }))
+        .await
+    }
+
+    /// Sends a message to the given room, which the user should have joined
+    pub async fn send_message(&mut self, room: &str, content: &str) -> anyhow::Result<()> {
+        self.send_command(&UserCommand::SendMessage(command::SendMessageCommand {
+            room: room.into(),
+            content: content.into(),
+            client_msg_id: None,
+        }))
+        .await
+    }
+
+    /// Leaves the given room
+    pub async fn leave_room(&mut self, room: &str) -> anyhow::Result<()> {
+        self.send_command(&UserCommand::LeaveRoom(command::LeaveRoomCommand {
+            room: room.into(),
+        }))
+        .await
+    }
+
+    /// Sends any [UserCommand] to the server, for the commands without a dedicated method
+    ///
+    /// # Cancel Safety
+    ///
+    /// This method is not cancellation safe, see [CommandWriter::write].
+    pub async fn send_command(&mut self, command: &UserCommand) -> anyhow::Result<()> {
+        self.command_writer.write(command).await
+    }
+
+    /// Waits for the next [Event] from the server, None once the server has closed the connection
+    ///
+    /// The server closes the connection if its [Event::Ping]s are not answered with a [UserCommand::Pong] in time.
+    ///
+    /// # Cancel Safety
+    ///
+    /// This method is cancel-safe, meaning that it can be used in [tokio::select!]
+    /// without the risk of missing events.
+    pub async fn next_event(&mut self) -> Option<anyhow::Result<Event>> {
+        self.event_stream.next().await
+    }
+}
@@ -1,5 +1,30 @@
 use serde::{Deserialize, Serialize};
 
+use crate::event::PresenceStatus;
+
+/// User Command for introducing the client to the server, should be the first command sent,
+/// right after the [AuthenticateCommand] if the server requires authentication.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HelloCommand {
+    // The protocol version the client speaks, see [crate::PROTOCOL_VERSION].
+    #[serde(rename = "v")]
+    pub client_version: u32,
+    // Asks the server to stamp the events it sends afterwards with their [crate::event::SequencedEvent::seq].
+    // Only for the clients reading [crate::event::SequencedEvent]s, the plain events without fields can not be read with it.
+    #[serde(rename = "sq", default, skip_serializing_if = "std::ops::Not::not")]
+    pub sequenced: bool,
+}
+
+/// User Command for proving that the user is allowed to connect, should be sent before any other command
+/// if the server requires authentication. The server replies with a [crate::event::Event::LoginSuccessful],
+/// or an [crate::event::ErrorCode::Unauthorized] error before closing the connection.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuthenticateCommand {
+    // One of the tokens the server is configured with.
+    #[serde(rename = "t")]
+    pub token: String,
+}
+
 /// User Command for joining a room.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct JoinRoomCommand {
@@ -25,8 +50,120 @@ pub struct SendMessageCommand {
     // The content of the message.
     #[serde(rename = "c")]
     pub content: String,
+    // An id picked by the client, echoed back in a message ack once the message is sent.
+    #[serde(rename = "cid", default, skip_serializing_if = "Option::is_none")]
+    pub client_msg_id: Option<String>,
+}
+
+/// User Command for deleting a message the user has previously sent.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeleteMessageCommand {
+    // The room the message was sent to.
+    #[serde(rename = "r")]
+    pub room: String,
+    // The id of the message to delete.
+    #[serde(rename = "id")]
+    pub message_id: String,
+}
+
+/// User Command for replacing the random user id of the session with a fresh one, e.g. when it collides with another user's.
+/// The server replies with a [crate::event::Event::Reidentified], and rejoins the rooms with the new id.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReidentifyCommand;
+
+/// User Command for loading the messages of a room older than the ones the client has, a page at a time.
+/// The server replies with a [crate::event::Event::HistoryPage].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FetchHistoryCommand {
+    // The room to load the messages of, which the user should have joined.
+    #[serde(rename = "r")]
+    pub room: String,
+    // The messages sent before this one are loaded, or the most recent ones if not set.
+    #[serde(rename = "b", default, skip_serializing_if = "Option::is_none")]
+    pub before_message_id: Option<String>,
+    // How many messages to load at most, the server may load fewer.
+    #[serde(rename = "l")]
+    pub limit: usize,
+}
+
+/// User Command for picking a display name for the session.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SetUsernameCommand {
+    // The display name to use.
+    #[serde(rename = "n")]
+    pub name: String,
+}
+
+/// User Command for changing the presence status of the user in all of their rooms.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SetPresenceCommand {
+    // The new presence status.
+    #[serde(rename = "s")]
+    pub status: PresenceStatus,
+}
+
+/// User Command for kicking a user out of a room, only allowed for the moderators of the room.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KickUserCommand {
+    // The room to kick the user from.
+    #[serde(rename = "r")]
+    pub room: String,
+    // The id of the user to kick.
+    #[serde(rename = "u")]
+    pub user_id: String,
+}
+
+/// User Command for setting the minimum interval between the messages of each user in a room,
+/// only allowed for the moderators of the room. The server broadcasts a [crate::event::Event::SlowModeChanged] to the room.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SetSlowModeCommand {
+    // The room to set the slow mode of.
+    #[serde(rename = "r")]
+    pub room: String,
+    // The minimum number of seconds between the messages of a user, 0 turns the slow mode off.
+    #[serde(rename = "s")]
+    pub seconds: u64,
 }
 
+/// User Command for creating a new room on the server.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CreateRoomCommand {
+    // The name of the room to create, must be unique.
+    #[serde(rename = "n")]
+    pub name: String,
+    // The description of the room.
+    #[serde(rename = "d")]
+    pub description: String,
+}
+
+/// User Command for listing the rooms available on the server.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ListRoomsCommand;
+
+/// User Command for listing the users currently in a room, without joining it.
+/// The server replies with a [crate::event::Event::RoomUsers].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ListRoomUsersCommand {
+    // The room to list the users of.
+    #[serde(rename = "r")]
+    pub room: String,
+}
+
+/// User Command for listing the rooms the user is currently in.
+/// The server replies with a [crate::event::Event::JoinedRooms].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ListJoinedRoomsCommand;
+
+/// User Command for checking whether the connection to the server is alive.
+/// The server replies with a [crate::event::Event::Pong] immediately.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PingCommand;
+
+/// User Command for answering a [crate::event::Event::Ping] of the server.
+/// The server closes the connection if a ping is not answered in time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PongCommand;
+
 /// User Command for quitting the whole chat session.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct QuitCommand;
@@ -36,9 +173,24 @@ pub struct QuitCommand;
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "_ct", rename_all = "snake_case")]
 pub enum UserCommand {
+    Hello(HelloCommand),
+    Authenticate(AuthenticateCommand),
     JoinRoom(JoinRoomCommand),
     LeaveRoom(LeaveRoomCommand),
     SendMessage(SendMessageCommand),
+    DeleteMessage(DeleteMessageCommand),
+    FetchHistory(FetchHistoryCommand),
+    SetUsername(SetUsernameCommand),
+    Reidentify(ReidentifyCommand),
+    SetPresence(SetPresenceCommand),
+    ListRooms(ListRoomsCommand),
+    ListRoomUsers(ListRoomUsersCommand),
+    ListJoinedRooms(ListJoinedRoomsCommand),
+    CreateRoom(CreateRoomCommand),
+    KickUser(KickUserCommand),
+    SetSlowMode(SetSlowModeCommand),
+    Ping(PingCommand),
+    Pong(PongCommand),
     Quit(QuitCommand),
 }
 
@@ -54,6 +206,32 @@ mod tests {
         assert_eq!(deserialized, *command);
     }
 
+    #[test]
+    fn test_hello_command() {
+        let command = UserCommand::Hello(HelloCommand {
+            client_version: 1,
+            sequenced: false,
+        });
+
+        assert_command_serialization(&command, r#"{"_ct":"hello","v":1}"#);
+
+        let command = UserCommand::Hello(HelloCommand {
+            client_version: 1,
+            sequenced: true,
+        });
+
+        assert_command_serialization(&command, r#"{"_ct":"hello","v":1,"sq":true}"#);
+    }
+
+    #[test]
+    fn test_authenticate_command() {
+        let command = UserCommand::Authenticate(AuthenticateCommand {
+            token: "secret".to_string(),
+        });
+
+        assert_command_serialization(&command, r#"{"_ct":"authenticate","t":"secret"}"#);
+    }
+
     #[test]
     fn test_join_command() {
         let command = UserCommand::JoinRoom(JoinRoomCommand {
@@ -77,9 +255,166 @@ mod tests {
         let command = UserCommand::SendMessage(SendMessageCommand {
             room: "test".to_string(),
             content: "test".to_string(),
+            client_msg_id: None,
         });
 
         assert_command_serialization(&command, r#"{"_ct":"send_message","r":"test","c":"test"}"#);
+
+        let command = UserCommand::SendMessage(SendMessageCommand {
+            room: "test".to_string(),
+            content: "test".to_string(),
+            client_msg_id: Some("1".to_string()),
+        });
+
+        assert_command_serialization(
+            &command,
+            r#"{"_ct":"send_message","r":"test","c":"test","cid":"1"}"#,
+        );
+
+        // the line breaks are escaped, so a multiline message is still a single line of json
+        let command = UserCommand::SendMessage(SendMessageCommand {
+            room: "test".to_string(),
+            content: "first\nsecond".to_string(),
+            client_msg_id: None,
+        });
+
+        assert_command_serialization(
+            &command,
+            r#"{"_ct":"send_message","r":"test","c":"first\nsecond"}"#,
+        );
+    }
+
+    #[test]
+    fn test_delete_message_command() {
+        let command = UserCommand::DeleteMessage(DeleteMessageCommand {
+            room: "test".to_string(),
+            message_id: "message-id-1".to_string(),
+        });
+
+        assert_command_serialization(
+            &command,
+            r#"{"_ct":"delete_message","r":"test","id":"message-id-1"}"#,
+        );
+    }
+
+    #[test]
+    fn test_set_username_command() {
+        let command = UserCommand::SetUsername(SetUsernameCommand {
+            name: "alice".to_string(),
+        });
+
+        assert_command_serialization(&command, r#"{"_ct":"set_username","n":"alice"}"#);
+    }
+
+    #[test]
+    fn test_reidentify_command() {
+        let command = UserCommand::Reidentify(ReidentifyCommand);
+
+        assert_command_serialization(&command, r#"{"_ct":"reidentify"}"#);
+    }
+
+    #[test]
+    fn test_set_presence_command() {
+        let command = UserCommand::SetPresence(SetPresenceCommand {
+            status: PresenceStatus::Away,
+        });
+
+        assert_command_serialization(&command, r#"{"_ct":"set_presence","s":"away"}"#);
+    }
+
+    #[test]
+    fn test_create_room_command() {
+        let command = UserCommand::CreateRoom(CreateRoomCommand {
+            name: "room-1".to_string(),
+            description: "some description".to_string(),
+        });
+
+        assert_command_serialization(
+            &command,
+            r#"{"_ct":"create_room","n":"room-1","d":"some description"}"#,
+        );
+    }
+
+    #[test]
+    fn test_kick_user_command() {
+        let command = UserCommand::KickUser(KickUserCommand {
+            room: "room-1".to_string(),
+            user_id: "user-id-1".to_string(),
+        });
+
+        assert_command_serialization(
+            &command,
+            r#"{"_ct":"kick_user","r":"room-1","u":"user-id-1"}"#,
+        );
+    }
+
+    #[test]
+    fn test_fetch_history_command() {
+        let command = UserCommand::FetchHistory(FetchHistoryCommand {
+            room: "room-1".to_string(),
+            before_message_id: Some("message-id-1".to_string()),
+            limit: 50,
+        });
+
+        assert_command_serialization(
+            &command,
+            r#"{"_ct":"fetch_history","r":"room-1","b":"message-id-1","l":50}"#,
+        );
+
+        let command = UserCommand::FetchHistory(FetchHistoryCommand {
+            room: "room-1".to_string(),
+            before_message_id: None,
+            limit: 50,
+        });
+
+        assert_command_serialization(&command, r#"{"_ct":"fetch_history","r":"room-1","l":50}"#);
+    }
+
+    #[test]
+    fn test_set_slow_mode_command() {
+        let command = UserCommand::SetSlowMode(SetSlowModeCommand {
+            room: "room-1".to_string(),
+            seconds: 30,
+        });
+
+        assert_command_serialization(&command, r#"{"_ct":"set_slow_mode","r":"room-1","s":30}"#);
+    }
+
+    #[test]
+    fn test_list_rooms_command() {
+        let command = UserCommand::ListRooms(ListRoomsCommand);
+
+        assert_command_serialization(&command, r#"{"_ct":"list_rooms"}"#);
+    }
+
+    #[test]
+    fn test_list_room_users_command() {
+        let command = UserCommand::ListRoomUsers(ListRoomUsersCommand {
+            room: "room-1".to_string(),
+        });
+
+        assert_command_serialization(&command, r#"{"_ct":"list_room_users","r":"room-1"}"#);
+    }
+
+    #[test]
+    fn test_list_joined_rooms_command() {
+        let command = UserCommand::ListJoinedRooms(ListJoinedRoomsCommand);
+
+        assert_command_serialization(&command, r#"{"_ct":"list_joined_rooms"}"#);
+    }
+
+    #[test]
+    fn test_ping_command() {
+        let command = UserCommand::Ping(PingCommand);
+
+        assert_command_serialization(&command, r#"{"_ct":"ping"}"#);
+    }
+
+    #[test]
+    fn test_pong_command() {
+        let command = UserCommand::Pong(PongCommand);
+
+        assert_command_serialization(&command, r#"{"_ct":"pong"}"#);
     }
 
     #[test]
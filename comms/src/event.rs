@@ -1,3 +1,5 @@
+use std::{collections::HashMap, fmt};
+
 use serde::{Deserialize, Serialize};
 
 /// The detail of a given room
@@ -9,6 +11,10 @@ pub struct RoomDetail {
     /// The description of the room
     #[serde(rename = "d")]
     pub description: String,
+    /// How many unique users are in the room
+    /// Servers predating the participant counts do not send it, which is read as `0`
+    #[serde(rename = "pc", default)]
+    pub participant_count: usize,
 }
 
 /// A user has successfully logged in
@@ -23,6 +29,18 @@ pub struct LoginSuccessfulReplyEvent {
     /// The list of rooms the user can participate, unique and ordered
     #[serde(rename = "rs")]
     pub rooms: Vec<RoomDetail>,
+    /// The protocol version the server speaks, see [crate::PROTOCOL_VERSION]
+    /// Servers predating the versioning do not send it, which is read as version `0`
+    #[serde(rename = "v", default)]
+    pub server_version: u32,
+}
+
+/// A reply to the user with the rooms currently available on the server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomListReplyEvent {
+    /// The list of rooms the user can participate, unique and ordered
+    #[serde(rename = "rs")]
+    pub rooms: Vec<RoomDetail>,
 }
 
 /// Users new room participation status
@@ -33,18 +51,86 @@ pub enum RoomParticipationStatus {
     Left,
 }
 
+/// Why a user has left a room
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DepartureReason {
+    /// The user has left the room on purpose
+    Left,
+    /// The user has quit the chat
+    Quit,
+    /// The connection of the user was lost or closed by the server
+    Disconnected,
+    /// A moderator has kicked the user out of the room
+    Kicked,
+    /// The user has picked a new id, and rejoins the room with it
+    Reidentified,
+}
+
+/// Whether a user is actively using the client or not
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PresenceStatus {
+    Active,
+    Away,
+}
+
 /// A user has joined or left a room
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct RoomParticipationBroacastEvent {
+pub struct RoomParticipationBroadcastEvent {
     /// The slug of the room the user has joined or left
     #[serde(rename = "r")]
     pub room: String,
     /// The id of the user that has joined or left
     #[serde(rename = "u")]
     pub user_id: String,
+    /// The display name of the user that has joined or left
+    #[serde(rename = "n", default)]
+    pub display_name: String,
     /// The new status of the user in the room
     #[serde(rename = "s")]
     pub status: RoomParticipationStatus,
+    /// Why the user has left the room, not set for joins nor by the servers predating it
+    #[serde(rename = "dr", default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<DepartureReason>,
+}
+
+/// The misspelled name of [RoomParticipationBroadcastEvent], kept so the existing code keeps compiling
+#[deprecated(note = "renamed to RoomParticipationBroadcastEvent")]
+pub type RoomParticipationBroacastEvent = RoomParticipationBroadcastEvent;
+
+/// The users currently in a room, broadcasted to the room whenever a user joins or leaves it
+/// so the participants can correct their user list if they have missed a [RoomParticipationBroadcastEvent]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomUsersSnapshotBroadcastEvent {
+    /// The slug of the room
+    #[serde(rename = "r")]
+    pub room: String,
+    /// The users currently in the room, unique
+    #[serde(rename = "us")]
+    pub users: Vec<String>,
+}
+
+/// A reply to the user with the users currently in a room, which the user does not have to be in
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomUsersReplyEvent {
+    /// The slug of the room
+    #[serde(rename = "r")]
+    pub room: String,
+    /// The users currently in the room, unique
+    #[serde(rename = "us")]
+    pub users: Vec<String>,
+    /// The display names of the users currently in the room, keyed by user id
+    #[serde(rename = "ns", default)]
+    pub display_names: HashMap<String, String>,
+}
+
+/// A reply to the user with the rooms they are currently in
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JoinedRoomsReplyEvent {
+    /// The slugs of the rooms, unique and ordered
+    #[serde(rename = "rs")]
+    pub rooms: Vec<String>,
 }
 
 /// A reply to the user when they have joined a room
@@ -56,6 +142,16 @@ pub struct UserJoinedRoomReplyEvent {
     /// The users currently in the room, unique and ordered
     #[serde(rename = "us")]
     pub users: Vec<String>,
+    /// The display names of the users currently in the room, keyed by user id
+    #[serde(rename = "ns", default)]
+    pub display_names: HashMap<String, String>,
+    /// When the users currently in the room have joined it, as unix timestamps in seconds keyed by user id
+    /// Servers predating the join times do not send them
+    #[serde(rename = "ja", default, skip_serializing_if = "HashMap::is_empty")]
+    pub joined_at: HashMap<String, u64>,
+    /// The most recent messages of the room, oldest first
+    #[serde(rename = "h", default)]
+    pub history: Vec<UserMessageBroadcastEvent>,
 }
 
 /// A user has sent a message to a room
@@ -64,23 +160,314 @@ pub struct UserMessageBroadcastEvent {
     /// The slug of the room the user has sent the message to
     #[serde(rename = "r")]
     pub room: String,
+    /// The id of the message, unique within the room
+    #[serde(rename = "id")]
+    pub message_id: String,
     /// The id of the user that has sent the message
     #[serde(rename = "u")]
     pub user_id: String,
+    /// The display name of the user that has sent the message
+    #[serde(rename = "n", default)]
+    pub display_name: String,
     /// The content of the message
     #[serde(rename = "c")]
     pub content: String,
 }
 
+/// The message sent by the user with a client message id has been sent to the room
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessageAckReplyEvent {
+    /// The slug of the room the message was sent to
+    #[serde(rename = "r")]
+    pub room: String,
+    /// The id the client has picked for the message
+    #[serde(rename = "cid")]
+    pub client_msg_id: String,
+    /// The id the server has assigned to the message
+    #[serde(rename = "id")]
+    pub message_id: String,
+}
+
+/// A reply to the user with a page of the messages of a room, sent before the requested message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryPageReplyEvent {
+    /// The slug of the room
+    #[serde(rename = "r")]
+    pub room: String,
+    /// The message the page precedes, as it was requested, the page has the most recent messages if not set
+    #[serde(rename = "b", default, skip_serializing_if = "Option::is_none")]
+    pub before_message_id: Option<String>,
+    /// The messages of the page, oldest first
+    #[serde(rename = "ms")]
+    pub messages: Vec<UserMessageBroadcastEvent>,
+    /// Whether there are older messages than the ones in the page
+    #[serde(rename = "hm")]
+    pub has_more: bool,
+}
+
+/// A user has deleted one of their messages in a room
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessageDeletedBroadcastEvent {
+    /// The slug of the room the message was deleted from
+    #[serde(rename = "r")]
+    pub room: String,
+    /// The id of the deleted message
+    #[serde(rename = "id")]
+    pub message_id: String,
+}
+
+/// A user has changed their display name
+/// Sent as a reply to the user, and broadcasted to all the rooms the user has joined
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DisplayNameChangedBroadcastEvent {
+    /// The id of the user that has changed their display name
+    #[serde(rename = "u")]
+    pub user_id: String,
+    /// The new display name of the user
+    #[serde(rename = "n")]
+    pub display_name: String,
+}
+
+/// A reply to the user when the server has given them a new user id
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReidentifiedReplyEvent {
+    /// The id the user had until now
+    #[serde(rename = "pu")]
+    pub previous_user_id: String,
+    /// The new id of the user
+    #[serde(rename = "u")]
+    pub user_id: String,
+}
+
+/// A user has been kicked out of a room by a moderator
+/// Broadcasted to the room, but only delivered to the sessions of the kicked user
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserKickedBroadcastEvent {
+    /// The slug of the room the user was kicked from
+    #[serde(rename = "r")]
+    pub room: String,
+    /// The id of the kicked user
+    #[serde(rename = "u")]
+    pub user_id: String,
+    /// The id of the moderator who kicked the user
+    #[serde(rename = "m")]
+    pub moderator_id: String,
+}
+
+/// A moderator has changed the slow mode of a room, broadcasted to the room
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SlowModeChangedBroadcastEvent {
+    /// The slug of the room
+    #[serde(rename = "r")]
+    pub room: String,
+    /// The id of the moderator who changed the slow mode
+    #[serde(rename = "m")]
+    pub moderator_id: String,
+    /// The minimum number of seconds between the messages of a user, 0 if the slow mode is off
+    #[serde(rename = "s")]
+    pub seconds: u64,
+}
+
+/// A user has changed their presence status
+/// Broadcasted to all the rooms the user has joined
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PresenceChangedBroadcastEvent {
+    /// The id of the user that has changed their presence
+    #[serde(rename = "u")]
+    pub user_id: String,
+    /// The new presence status of the user
+    #[serde(rename = "s")]
+    pub status: PresenceStatus,
+}
+
+/// The user could not keep up with the events of a room, and some of them were skipped
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessagesMissedReplyEvent {
+    /// The slug of the room the events were missed from
+    #[serde(rename = "r")]
+    pub room: String,
+    /// How many events were skipped
+    #[serde(rename = "c")]
+    pub count: u64,
+}
+
+/// The number of unique users in a room has changed, sent to every user so they can see how busy the rooms are
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomOccupancyChangedBroadcastEvent {
+    /// The slug of the room
+    #[serde(rename = "r")]
+    pub room: String,
+    /// How many unique users are in the room now
+    #[serde(rename = "c")]
+    pub count: usize,
+}
+
+/// A message from the operators of the server, e.g. the message of the day sent right after the login
+/// It is not tied to any room, so clients show it regardless of the rooms the user has joined
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SystemMessageBroadcastEvent {
+    /// The content of the message
+    #[serde(rename = "c")]
+    pub content: String,
+}
+
+/// The server checking whether the user is still reading, to be answered with a [crate::command::UserCommand::Pong]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PingRequestEvent;
+
+/// The server is shutting down and is about to close the connection
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServerShutdownBroadcastEvent {
+    /// Human readable reason of the shutdown
+    #[serde(rename = "r")]
+    pub reason: String,
+    /// How many seconds the client should wait before trying to reconnect
+    #[serde(rename = "ra")]
+    pub reconnect_after_secs: u64,
+}
+
+/// A reply to the user's ping, confirming that the connection is alive
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PongReplyEvent;
+
+/// The reason a user command has failed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The client speaks a protocol version the server does not support
+    IncompatibleVersion,
+    /// The server requires authentication and the user has not given a valid token, the connection is being closed
+    Unauthorized,
+    /// The command could not be read or parsed
+    InvalidCommand,
+    /// The room does not exist on the server
+    RoomNotFound,
+    /// A room with the same name already exists
+    RoomAlreadyExists,
+    /// The name of the room to create is not valid
+    InvalidRoomName,
+    /// The description of the room to create is not valid
+    InvalidRoomDescription,
+    /// The room has reached its maximum number of users
+    RoomFull,
+    /// The user has already joined the room
+    AlreadyJoined,
+    /// The user has joined as many rooms as a session is allowed to
+    TooManyRooms,
+    /// The user has not joined the room
+    NotInRoom,
+    /// The user is not a moderator of the room
+    NotModerator,
+    /// The targeted user is not a participant of the room
+    UserNotInRoom,
+    /// The message does not exist or was not sent by the user
+    MessageNotFound,
+    /// The message content exceeds the maximum allowed length
+    MessageTooLong,
+    /// The message content is empty or only whitespace
+    EmptyMessage,
+    /// The user is sending messages faster than allowed
+    RateLimited,
+    /// The room is in slow mode and the user has posted to it too recently
+    SlowMode,
+    /// The display name is empty or too long
+    InvalidDisplayName,
+    /// The display name is used by another connected user
+    DisplayNameTaken,
+    /// The session has not sent any commands for too long and is being closed
+    IdleTimeout,
+    /// The server has reached its maximum number of connections, the connection is being closed
+    ServerFull,
+    /// The user can not keep up with the events of the rooms, the connection is being closed
+    TooSlow,
+    /// The server has failed to process the command, e.g. it could not persist a message
+    Internal,
+}
+
+/// A reply to the user when a command they have sent could not be processed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorReplyEvent {
+    /// The reason of the failure
+    #[serde(rename = "c")]
+    pub code: ErrorCode,
+    /// Human readable description of the failure
+    #[serde(rename = "m")]
+    pub message: String,
+    /// How many seconds the user should wait before retrying, only set for the failures which pass in time
+    #[serde(rename = "ra", default, skip_serializing_if = "Option::is_none")]
+    pub retry_after_secs: Option<u64>,
+}
+
+impl ErrorReplyEvent {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        ErrorReplyEvent {
+            code,
+            message: message.into(),
+            retry_after_secs: None,
+        }
+    }
+
+    /// Lets the user know how many seconds to wait before retrying
+    pub fn with_retry_after_secs(mut self, retry_after_secs: u64) -> Self {
+        self.retry_after_secs = Some(retry_after_secs);
+
+        self
+    }
+}
+
+impl fmt::Display for ErrorReplyEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}: {}", self.code, self.message)
+    }
+}
+
+/// Allows the server to propagate [ErrorReplyEvent]s as errors, and reply them to the user
+impl std::error::Error for ErrorReplyEvent {}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "_et", rename_all = "snake_case")]
 /// Events that can be sent to the client
 /// Events maybe related to different users and rooms, the receipient is a single chat session
 pub enum Event {
     LoginSuccessful(LoginSuccessfulReplyEvent),
-    RoomParticipation(RoomParticipationBroacastEvent),
+    RoomList(RoomListReplyEvent),
+    RoomCreated(RoomDetail),
+    RoomOccupancyChanged(RoomOccupancyChangedBroadcastEvent),
+    RoomParticipation(RoomParticipationBroadcastEvent),
+    RoomUsersSnapshot(RoomUsersSnapshotBroadcastEvent),
+    RoomUsers(RoomUsersReplyEvent),
+    JoinedRooms(JoinedRoomsReplyEvent),
     UserJoinedRoom(UserJoinedRoomReplyEvent),
     UserMessage(UserMessageBroadcastEvent),
+    MessageAck(MessageAckReplyEvent),
+    MessageDeleted(MessageDeletedBroadcastEvent),
+    HistoryPage(HistoryPageReplyEvent),
+    DisplayNameChanged(DisplayNameChangedBroadcastEvent),
+    Reidentified(ReidentifiedReplyEvent),
+    PresenceChanged(PresenceChangedBroadcastEvent),
+    UserKicked(UserKickedBroadcastEvent),
+    SlowModeChanged(SlowModeChangedBroadcastEvent),
+    MessagesMissed(MessagesMissedReplyEvent),
+    SystemMessage(SystemMessageBroadcastEvent),
+    Pong(PongReplyEvent),
+    Ping(PingRequestEvent),
+    ServerShutdown(ServerShutdownBroadcastEvent),
+    Error(ErrorReplyEvent),
+}
+
+/// An [Event] stamped with the order the server has produced it in.
+///
+/// The events of a single room always arrive in the order they have happened in that room,
+/// but the events of different rooms, and the replies to the user, may be interleaved differently than
+/// they have happened. Clients which care about the order across rooms, e.g. log viewers,
+/// can detect the reordering and sort by [SequencedEvent::seq].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SequencedEvent {
+    #[serde(flatten)]
+    pub event: Event,
+    /// Server wide number which grows with every event the server produces, not sent by the servers predating it
+    #[serde(rename = "sq", default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
 }
 
 #[cfg(test)]
@@ -95,6 +482,28 @@ mod tests {
         assert_eq!(deserialized, *event);
     }
 
+    #[test]
+    fn test_sequenced_event() {
+        let event = SequencedEvent {
+            event: Event::Pong(PongReplyEvent),
+            seq: Some(42),
+        };
+
+        let serialized = serde_json::to_string(&event).unwrap();
+        assert_eq!(serialized, r#"{"_et":"pong","sq":42}"#);
+        let deserialized: SequencedEvent = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, event);
+
+        // clients which do not care about the order read the plain event
+        let deserialized: Event =
+            serde_json::from_str(r#"{"_et":"room_created","n":"rust","d":"Rust","pc":0,"sq":7}"#)
+                .unwrap();
+        assert!(matches!(deserialized, Event::RoomCreated(_)));
+        // servers predating the sequence numbers do not send them
+        let deserialized: SequencedEvent = serde_json::from_str(r#"{"_et":"pong"}"#).unwrap();
+        assert_eq!(deserialized.seq, None);
+    }
+
     #[test]
     fn test_login_successful_event() {
         let event = Event::LoginSuccessful(LoginSuccessfulReplyEvent {
@@ -103,41 +512,91 @@ mod tests {
             rooms: vec![RoomDetail {
                 name: "room-1".to_string(),
                 description: "some description".to_string(),
+                participant_count: 5,
+            }],
+            server_version: 1,
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"login_successful","s":"session-id-1","u":"user-id-1","rs":[{"n":"room-1","d":"some description","pc":5}],"v":1}"#,
+        );
+    }
+
+    #[test]
+    fn test_login_successful_event_without_server_version() {
+        let deserialized: Event = serde_json::from_str(
+            r#"{"_et":"login_successful","s":"session-id-1","u":"user-id-1","rs":[]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            deserialized,
+            Event::LoginSuccessful(LoginSuccessfulReplyEvent {
+                session_id: "session-id-1".to_string(),
+                user_id: "user-id-1".to_string(),
+                rooms: vec![],
+                server_version: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn test_room_list_event() {
+        let event = Event::RoomList(RoomListReplyEvent {
+            rooms: vec![RoomDetail {
+                name: "room-1".to_string(),
+                description: "some description".to_string(),
+                participant_count: 5,
             }],
         });
 
         assert_event_serialization(
             &event,
-            r#"{"_et":"login_successful","s":"session-id-1","u":"user-id-1","rs":[{"n":"room-1","d":"some description"}]}"#,
+            r#"{"_et":"room_list","rs":[{"n":"room-1","d":"some description","pc":5}]}"#,
         );
     }
 
     #[test]
     fn test_room_participation_join_event() {
-        let event = Event::RoomParticipation(RoomParticipationBroacastEvent {
+        let event = Event::RoomParticipation(RoomParticipationBroadcastEvent {
             room: "test".to_string(),
             user_id: "test".to_string(),
+            display_name: "alice".to_string(),
             status: RoomParticipationStatus::Joined,
+            reason: None,
         });
 
         assert_event_serialization(
             &event,
-            r#"{"_et":"room_participation","r":"test","u":"test","s":"joined"}"#,
+            r#"{"_et":"room_participation","r":"test","u":"test","n":"alice","s":"joined"}"#,
         );
     }
 
     #[test]
     fn test_room_participation_leave_event() {
-        let event = Event::RoomParticipation(RoomParticipationBroacastEvent {
+        let event = Event::RoomParticipation(RoomParticipationBroadcastEvent {
             room: "test".to_string(),
             user_id: "test".to_string(),
+            display_name: "alice".to_string(),
             status: RoomParticipationStatus::Left,
+            reason: Some(DepartureReason::Disconnected),
         });
 
         assert_event_serialization(
             &event,
-            r#"{"_et":"room_participation","r":"test","u":"test","s":"left"}"#,
+            r#"{"_et":"room_participation","r":"test","u":"test","n":"alice","s":"left","dr":"disconnected"}"#,
         );
+
+        // servers predating the departure reasons do not send them
+        let deserialized: Event = serde_json::from_str(
+            r#"{"_et":"room_participation","r":"test","u":"test","n":"alice","s":"left"}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            deserialized,
+            Event::RoomParticipation(RoomParticipationBroadcastEvent { reason: None, .. })
+        ));
     }
 
     #[test]
@@ -145,11 +604,37 @@ mod tests {
         let event = Event::UserJoinedRoom(UserJoinedRoomReplyEvent {
             room: "test".to_string(),
             users: vec!["test".to_string()],
+            display_names: HashMap::from([("test".to_string(), "alice".to_string())]),
+            joined_at: HashMap::from([("test".to_string(), 1_700_000_000)]),
+            history: vec![UserMessageBroadcastEvent {
+                room: "test".to_string(),
+                message_id: "message-id-1".to_string(),
+                user_id: "test".to_string(),
+                display_name: "alice".to_string(),
+                content: "hello".to_string(),
+            }],
         });
 
         assert_event_serialization(
             &event,
-            r#"{"_et":"user_joined_room","r":"test","us":["test"]}"#,
+            r#"{"_et":"user_joined_room","r":"test","us":["test"],"ns":{"test":"alice"},"ja":{"test":1700000000},"h":[{"r":"test","id":"message-id-1","u":"test","n":"alice","c":"hello"}]}"#,
+        );
+    }
+
+    #[test]
+    fn test_user_joined_room_event_without_display_names_join_times_and_history() {
+        let deserialized: Event =
+            serde_json::from_str(r#"{"_et":"user_joined_room","r":"test","us":["test"]}"#).unwrap();
+
+        assert_eq!(
+            deserialized,
+            Event::UserJoinedRoom(UserJoinedRoomReplyEvent {
+                room: "test".to_string(),
+                users: vec!["test".to_string()],
+                display_names: HashMap::new(),
+                joined_at: HashMap::new(),
+                history: Vec::new(),
+            })
         );
     }
 
@@ -157,13 +642,335 @@ mod tests {
     fn test_user_message_event() {
         let event = Event::UserMessage(UserMessageBroadcastEvent {
             room: "test".to_string(),
+            message_id: "message-id-1".to_string(),
             user_id: "test".to_string(),
+            display_name: "alice".to_string(),
             content: "test".to_string(),
         });
 
         assert_event_serialization(
             &event,
-            r#"{"_et":"user_message","r":"test","u":"test","c":"test"}"#,
+            r#"{"_et":"user_message","r":"test","id":"message-id-1","u":"test","n":"alice","c":"test"}"#,
+        );
+    }
+
+    #[test]
+    fn test_user_message_event_without_display_name() {
+        let deserialized: Event = serde_json::from_str(
+            r#"{"_et":"user_message","r":"test","id":"message-id-1","u":"test","c":"test"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            deserialized,
+            Event::UserMessage(UserMessageBroadcastEvent {
+                room: "test".to_string(),
+                message_id: "message-id-1".to_string(),
+                user_id: "test".to_string(),
+                display_name: String::new(),
+                content: "test".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_user_message_event_id_round_trip() {
+        let serialized = serde_json::to_value(Event::UserMessage(UserMessageBroadcastEvent {
+            room: "test".to_string(),
+            message_id: "V1StGXR8_Z5jdHi6B-myT".to_string(),
+            user_id: "test".to_string(),
+            display_name: "test".to_string(),
+            content: "test".to_string(),
+        }))
+        .unwrap();
+
+        assert_eq!(serialized["id"], "V1StGXR8_Z5jdHi6B-myT");
+
+        let deserialized: Event = serde_json::from_value(serialized).unwrap();
+        match deserialized {
+            Event::UserMessage(event) => assert_eq!(event.message_id, "V1StGXR8_Z5jdHi6B-myT"),
+            _ => panic!("expected a user message event"),
+        }
+    }
+
+    #[test]
+    fn test_message_deleted_event() {
+        let event = Event::MessageDeleted(MessageDeletedBroadcastEvent {
+            room: "test".to_string(),
+            message_id: "message-id-1".to_string(),
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"message_deleted","r":"test","id":"message-id-1"}"#,
+        );
+    }
+
+    #[test]
+    fn test_display_name_changed_event() {
+        let event = Event::DisplayNameChanged(DisplayNameChangedBroadcastEvent {
+            user_id: "test".to_string(),
+            display_name: "alice".to_string(),
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"display_name_changed","u":"test","n":"alice"}"#,
+        );
+    }
+
+    #[test]
+    fn test_reidentified_event() {
+        let event = Event::Reidentified(ReidentifiedReplyEvent {
+            previous_user_id: "abc12".to_string(),
+            user_id: "xyz34".to_string(),
+        });
+
+        assert_event_serialization(&event, r#"{"_et":"reidentified","pu":"abc12","u":"xyz34"}"#);
+    }
+
+    #[test]
+    fn test_presence_changed_event() {
+        let event = Event::PresenceChanged(PresenceChangedBroadcastEvent {
+            user_id: "user-id-1".to_string(),
+            status: PresenceStatus::Away,
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"presence_changed","u":"user-id-1","s":"away"}"#,
+        );
+    }
+
+    #[test]
+    fn test_user_kicked_event() {
+        let event = Event::UserKicked(UserKickedBroadcastEvent {
+            room: "room-1".to_string(),
+            user_id: "user-id-1".to_string(),
+            moderator_id: "user-id-2".to_string(),
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"user_kicked","r":"room-1","u":"user-id-1","m":"user-id-2"}"#,
+        );
+    }
+
+    #[test]
+    fn test_history_page_event() {
+        let event = Event::HistoryPage(HistoryPageReplyEvent {
+            room: "test".to_string(),
+            before_message_id: Some("message-id-2".to_string()),
+            messages: vec![UserMessageBroadcastEvent {
+                room: "test".to_string(),
+                message_id: "message-id-1".to_string(),
+                user_id: "test".to_string(),
+                display_name: "alice".to_string(),
+                content: "hello".to_string(),
+            }],
+            has_more: false,
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"history_page","r":"test","b":"message-id-2","ms":[{"r":"test","id":"message-id-1","u":"test","n":"alice","c":"hello"}],"hm":false}"#,
+        );
+    }
+
+    #[test]
+    fn test_system_message_event() {
+        let event = Event::SystemMessage(SystemMessageBroadcastEvent {
+            content: "welcome to the server".to_string(),
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"system_message","c":"welcome to the server"}"#,
+        );
+    }
+
+    #[test]
+    fn test_slow_mode_changed_event() {
+        let event = Event::SlowModeChanged(SlowModeChangedBroadcastEvent {
+            room: "room-1".to_string(),
+            moderator_id: "user-id-1".to_string(),
+            seconds: 30,
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"slow_mode_changed","r":"room-1","m":"user-id-1","s":30}"#,
+        );
+    }
+
+    #[test]
+    fn test_room_created_event() {
+        let event = Event::RoomCreated(RoomDetail {
+            name: "room-1".to_string(),
+            description: "some description".to_string(),
+            participant_count: 0,
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"room_created","n":"room-1","d":"some description","pc":0}"#,
+        );
+    }
+
+    #[test]
+    fn test_room_occupancy_changed_event() {
+        let event = Event::RoomOccupancyChanged(RoomOccupancyChangedBroadcastEvent {
+            room: "room-1".to_string(),
+            count: 5,
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"room_occupancy_changed","r":"room-1","c":5}"#,
+        );
+    }
+
+    #[test]
+    fn test_room_users_snapshot_event() {
+        let event = Event::RoomUsersSnapshot(RoomUsersSnapshotBroadcastEvent {
+            room: "room-1".to_string(),
+            users: vec!["user-id-1".to_string(), "user-id-2".to_string()],
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"room_users_snapshot","r":"room-1","us":["user-id-1","user-id-2"]}"#,
+        );
+    }
+
+    #[test]
+    fn test_room_users_event() {
+        let event = Event::RoomUsers(RoomUsersReplyEvent {
+            room: "room-1".to_string(),
+            users: vec!["user-id-1".to_string()],
+            display_names: HashMap::from([("user-id-1".to_string(), "alice".to_string())]),
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"room_users","r":"room-1","us":["user-id-1"],"ns":{"user-id-1":"alice"}}"#,
+        );
+    }
+
+    #[test]
+    fn test_joined_rooms_event() {
+        let event = Event::JoinedRooms(JoinedRoomsReplyEvent {
+            rooms: vec!["room-1".to_string(), "room-2".to_string()],
+        });
+
+        assert_event_serialization(&event, r#"{"_et":"joined_rooms","rs":["room-1","room-2"]}"#);
+    }
+
+    #[test]
+    fn test_room_detail_without_participant_count() {
+        let deserialized: RoomDetail =
+            serde_json::from_str(r#"{"n":"room-1","d":"some description"}"#).unwrap();
+
+        assert_eq!(deserialized.participant_count, 0);
+    }
+
+    #[test]
+    fn test_message_ack_event() {
+        let event = Event::MessageAck(MessageAckReplyEvent {
+            room: "room-1".to_string(),
+            client_msg_id: "1".to_string(),
+            message_id: "message-id-1".to_string(),
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"message_ack","r":"room-1","cid":"1","id":"message-id-1"}"#,
+        );
+    }
+
+    #[test]
+    fn test_messages_missed_event() {
+        let event = Event::MessagesMissed(MessagesMissedReplyEvent {
+            room: "room-1".to_string(),
+            count: 42,
+        });
+
+        assert_event_serialization(&event, r#"{"_et":"messages_missed","r":"room-1","c":42}"#);
+    }
+
+    #[test]
+    fn test_pong_event() {
+        let event = Event::Pong(PongReplyEvent);
+
+        assert_event_serialization(&event, r#"{"_et":"pong"}"#);
+    }
+
+    #[test]
+    fn test_ping_event() {
+        let event = Event::Ping(PingRequestEvent);
+
+        assert_event_serialization(&event, r#"{"_et":"ping"}"#);
+    }
+
+    #[test]
+    fn test_server_shutdown_event() {
+        let event = Event::ServerShutdown(ServerShutdownBroadcastEvent {
+            reason: "the server is restarting".to_string(),
+            reconnect_after_secs: 5,
+        });
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"server_shutdown","r":"the server is restarting","ra":5}"#,
+        );
+    }
+
+    #[test]
+    fn test_unauthorized_error_event() {
+        let event = Event::Error(ErrorReplyEvent::new(
+            ErrorCode::Unauthorized,
+            "invalid token",
+        ));
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"error","c":"unauthorized","m":"invalid token"}"#,
+        );
+    }
+
+    #[test]
+    fn test_room_not_found_error_event() {
+        let event = Event::Error(ErrorReplyEvent::new(
+            ErrorCode::RoomNotFound,
+            "room 'test' not found",
+        ));
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"error","c":"room_not_found","m":"room 'test' not found"}"#,
+        );
+    }
+
+    #[test]
+    fn test_error_event() {
+        let event = Event::Error(ErrorReplyEvent::new(
+            ErrorCode::DisplayNameTaken,
+            "display name is taken",
+        ));
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"error","c":"display_name_taken","m":"display name is taken"}"#,
+        );
+
+        let event = Event::Error(
+            ErrorReplyEvent::new(ErrorCode::SlowMode, "room is in slow mode")
+                .with_retry_after_secs(5),
+        );
+
+        assert_event_serialization(
+            &event,
+            r#"{"_et":"error","c":"slow_mode","m":"room is in slow mode","ra":5}"#,
         );
     }
 }
@@ -1,7 +1,14 @@
+/// Version of the wire protocol, bumped on every breaking change to the commands or events
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// High level client for talking to the server, built on top of [transport::client].
+/// Requires the 'client' feature to be enabled
+#[cfg(feature = "client")]
+pub mod client;
 /// Set of commands which the server can receive and process
 pub mod command;
 /// Set of events split into Broadcast and Reply events according to their source
 pub mod event;
-/// Implementation of event and command transportation over TCP Streams.
+/// Implementation of event and command transportation over TCP Streams, or any other byte stream.
 /// Requires 'server' or 'client' features to be enabled and will bring in tokio dependency alongside with other dependencies
 pub mod transport;
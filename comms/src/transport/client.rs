@@ -1,13 +1,13 @@
 use anyhow::Context;
-use tokio::{
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
-    net::{tcp::OwnedWriteHalf, TcpStream},
-};
-use tokio_stream::{wrappers::LinesStream, StreamExt};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio_stream::StreamExt;
 
 use crate::{command, event};
 
-use super::common::{BoxedStream, NEW_LINE};
+use super::{
+    codec::Codec,
+    common::{BoxedStream, BoxedWriter},
+};
 
 /// [EventStream] is a stream of [crate::event::Event]s sent by the server
 ///
@@ -17,17 +17,29 @@ use super::common::{BoxedStream, NEW_LINE};
 /// without the risk of missing events.
 pub type EventStream = BoxedStream<anyhow::Result<event::Event>>;
 
-/// [CommandWriter] is a wrapper around a [TcpStream] which writes [crate::command::UserCommand]s to the server
+/// [SequencedEventStream] is a stream of [crate::event::SequencedEvent]s sent by the server,
+/// for the clients which order the events of different rooms themselves
+///
+/// # Cancel Safety
+///
+/// This stream is cancel-safe, see [EventStream].
+pub type SequencedEventStream = BoxedStream<anyhow::Result<event::SequencedEvent>>;
+
+/// [CommandWriter] is a wrapper around the write half of a stream which writes [crate::command::UserCommand]s to the server
 pub struct CommandWriter {
-    writer: OwnedWriteHalf,
+    writer: BoxedWriter,
+    codec: Codec,
 }
 
 impl CommandWriter {
-    pub fn new(writer: OwnedWriteHalf) -> Self {
-        Self { writer }
+    pub fn new(writer: impl AsyncWrite + Send + 'static, codec: Codec) -> Self {
+        Self {
+            writer: Box::pin(writer),
+            codec,
+        }
     }
 
-    /// Send a [crate::command::UserCommand] to the backing [TcpStream]
+    /// Send a [crate::command::UserCommand] to the backing stream
     ///
     /// # Cancel Safety
     ///
@@ -37,8 +49,7 @@ impl CommandWriter {
     /// partially written, but future calls to `write` will start over
     /// from the beginning of the buffer. Causing undefined behaviour.
     pub async fn write(&mut self, command: &command::UserCommand) -> anyhow::Result<()> {
-        let mut serialized_bytes = serde_json::to_vec(command)?;
-        serialized_bytes.extend_from_slice(NEW_LINE);
+        let serialized_bytes = self.codec.encode(command)?;
 
         self.writer.write_all(serialized_bytes.as_slice()).await?;
 
@@ -46,24 +57,73 @@ impl CommandWriter {
     }
 }
 
-/// Splits a TCP stream into a stream of events and a command writer.
+/// Splits a stream into a stream of events and a command writer, using the default [Codec].
+///
+/// # Arguments
+///
+/// - `stream` - A stream to split, e.g. a [tokio::net::TcpStream], a TLS stream over it or an in-memory [tokio::io::duplex]
+pub fn split_stream<S>(stream: S) -> (EventStream, CommandWriter)
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    split_stream_with_codec(stream, Codec::default())
+}
+
+/// Splits a stream into a stream of events and a command writer.
+///
+/// # Arguments
+///
+/// - `stream` - A stream to split, e.g. a [tokio::net::TcpStream], a TLS stream over it or an in-memory [tokio::io::duplex]
+/// - `codec` - The [Codec] to read and write with, the server must use the same one
+pub fn split_stream_with_codec<S>(stream: S, codec: Codec) -> (EventStream, CommandWriter)
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (reader, writer) = tokio::io::split(stream);
+
+    (
+        Box::pin(
+            codec
+                .decode_stream::<event::Event>(reader)
+                .map(|event| event.context("failed to read event from the server")),
+        ),
+        CommandWriter::new(writer, codec),
+    )
+}
+
+/// Splits a stream into a stream of events with their sequence numbers and a command writer, using the default [Codec].
+///
+/// # Arguments
+///
+/// - `stream` - A stream to split, e.g. a [tokio::net::TcpStream], a TLS stream over it or an in-memory [tokio::io::duplex]
+pub fn split_sequenced_stream<S>(stream: S) -> (SequencedEventStream, CommandWriter)
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    split_sequenced_stream_with_codec(stream, Codec::default())
+}
+
+/// Splits a stream into a stream of events with their sequence numbers and a command writer.
 ///
 /// # Arguments
 ///
-/// - `stream` - A [TcpStream] to split
-pub fn split_tcp_stream(stream: TcpStream) -> (EventStream, CommandWriter) {
-    let (reader, writer) = stream.into_split();
+/// - `stream` - A stream to split, e.g. a [tokio::net::TcpStream], a TLS stream over it or an in-memory [tokio::io::duplex]
+/// - `codec` - The [Codec] to read and write with, the server must use the same one
+pub fn split_sequenced_stream_with_codec<S>(
+    stream: S,
+    codec: Codec,
+) -> (SequencedEventStream, CommandWriter)
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (reader, writer) = tokio::io::split(stream);
 
     (
         Box::pin(
-            LinesStream::new(BufReader::new(reader).lines()).map(|line| {
-                line.context("could not read line from the server")
-                    .and_then(|line| {
-                        serde_json::from_str::<event::Event>(&line)
-                            .context("failed to deserialize event from the server")
-                    })
-            }),
+            codec
+                .decode_stream::<event::SequencedEvent>(reader)
+                .map(|event| event.context("failed to read event from the server")),
         ),
-        CommandWriter::new(writer),
+        CommandWriter::new(writer, codec),
     )
 }
@@ -0,0 +1,76 @@
+use anyhow::Context;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio_stream::{wrappers::LinesStream, StreamExt};
+
+use super::common::{BoxedStream, NEW_LINE};
+
+/// [Codec] decides how the commands and events are serialized and framed over a TCP stream
+///
+/// Both ends of a connection must use the same [Codec].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Codec {
+    /// Newline delimited JSON, human readable and easy to debug
+    #[default]
+    Json,
+    /// MessagePack frames prefixed with their length as a big endian `u32`, compact for high throughput
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+}
+
+impl Codec {
+    /// Serializes the value into a single frame ready to be written to the stream
+    pub(super) fn encode<T: Serialize>(&self, value: &T) -> anyhow::Result<Vec<u8>> {
+        match self {
+            Codec::Json => {
+                let mut serialized_bytes = serde_json::to_vec(value)?;
+                serialized_bytes.extend_from_slice(NEW_LINE);
+
+                Ok(serialized_bytes)
+            }
+            #[cfg(feature = "msgpack")]
+            Codec::MessagePack => {
+                // named serialization keeps the struct fields as map keys, which the tagged enums rely on
+                let payload = rmp_serde::to_vec_named(value)?;
+                let length = u32::try_from(payload.len()).context("frame is too large")?;
+
+                let mut serialized_bytes = Vec::with_capacity(4 + payload.len());
+                serialized_bytes.extend_from_slice(&length.to_be_bytes());
+                serialized_bytes.extend_from_slice(&payload);
+
+                Ok(serialized_bytes)
+            }
+        }
+    }
+
+    /// Turns the read half of a stream into a cancel-safe stream of deserialized frames
+    pub(super) fn decode_stream<T>(
+        &self,
+        reader: impl AsyncRead + Send + 'static,
+    ) -> BoxedStream<anyhow::Result<T>>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        match self {
+            Codec::Json => Box::pin(
+                LinesStream::new(BufReader::new(reader).lines()).map(|line| {
+                    line.context("could not read line").and_then(|line| {
+                        serde_json::from_str::<T>(&line).context("failed to deserialize")
+                    })
+                }),
+            ),
+            #[cfg(feature = "msgpack")]
+            Codec::MessagePack => Box::pin(
+                tokio_util::codec::FramedRead::new(
+                    reader,
+                    tokio_util::codec::LengthDelimitedCodec::new(),
+                )
+                .map(|frame| {
+                    frame.context("could not read frame").and_then(|frame| {
+                        rmp_serde::from_slice::<T>(&frame).context("failed to deserialize")
+                    })
+                }),
+            ),
+        }
+    }
+}
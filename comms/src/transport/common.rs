@@ -1,7 +1,11 @@
 use std::pin::Pin;
 
+use tokio::io::AsyncWrite;
 use tokio_stream::Stream;
 
 pub const NEW_LINE: &[u8; 2] = b"\r\n";
 
 pub type BoxedStream<Item> = Pin<Box<dyn Stream<Item = Item> + Send>>;
+
+/// The write half of a stream, boxed so plain and encrypted streams can be used interchangeably
+pub type BoxedWriter = Pin<Box<dyn AsyncWrite + Send>>;
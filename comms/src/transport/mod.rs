@@ -1,8 +1,14 @@
 /// Transport over TCP implementation for a client to be able to interact with the server
 #[cfg(feature = "client")]
 pub mod client;
+/// Wire encodings which can be picked when splitting a TCP stream
+#[cfg(any(feature = "client", feature = "server"))]
+pub mod codec;
 #[cfg(any(feature = "client", feature = "server"))]
 mod common;
 /// Transport over TCP implementation for a server to interact with a single client TCP Stream
 #[cfg(feature = "server")]
 pub mod server;
+/// Encryption of the TCP streams before they are split, requires the 'tls' feature alongside 'server' or 'client'
+#[cfg(all(feature = "tls", any(feature = "client", feature = "server")))]
+pub mod tls;
@@ -1,13 +1,13 @@
 use anyhow::Context;
-use tokio::{
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
-    net::{tcp::OwnedWriteHalf, TcpStream},
-};
-use tokio_stream::{wrappers::LinesStream, StreamExt};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio_stream::StreamExt;
 
 use crate::{command, event};
 
-use super::common::{BoxedStream, NEW_LINE};
+use super::{
+    codec::Codec,
+    common::{BoxedStream, BoxedWriter},
+};
 
 /// [CommandStream] is a stream of [crate::command::UserCommand]s sent by the client
 ///
@@ -17,17 +17,21 @@ use super::common::{BoxedStream, NEW_LINE};
 /// without the risk of missing commands.
 pub type CommandStream = BoxedStream<anyhow::Result<command::UserCommand>>;
 
-/// [EventWriter] is a wrapper around a [TcpStream] which writes [crate::event::Event]s to the client
+/// [EventWriter] is a wrapper around the write half of a stream which writes [crate::event::Event]s to the client
 pub struct EventWriter {
-    writer: OwnedWriteHalf,
+    writer: BoxedWriter,
+    codec: Codec,
 }
 
 impl EventWriter {
-    pub fn new(writer: OwnedWriteHalf) -> Self {
-        Self { writer }
+    pub fn new(writer: impl AsyncWrite + Send + 'static, codec: Codec) -> Self {
+        Self {
+            writer: Box::pin(writer),
+            codec,
+        }
     }
 
-    /// Send a [crate::event::Event] to the backing [TcpStream]
+    /// Send a [crate::event::Event] to the backing stream
     ///
     /// # Cancel Safety
     ///
@@ -37,33 +41,73 @@ impl EventWriter {
     /// partially written, but future calls to `write` will start over
     /// from the beginning of the buffer. Causing undefined behaviour.
     pub async fn write(&mut self, event: &event::Event) -> anyhow::Result<()> {
-        let mut serialized_bytes = serde_json::to_vec(event)?;
-        serialized_bytes.extend_from_slice(NEW_LINE);
+        let serialized_bytes = self.codec.encode(event)?;
 
         self.writer.write_all(serialized_bytes.as_slice()).await?;
 
         Ok(())
     }
+
+    /// Send a [crate::event::SequencedEvent] to the backing stream, with its sequence number
+    ///
+    /// # Cancel Safety
+    ///
+    /// This method is not cancellation safe, see [EventWriter::write].
+    pub async fn write_sequenced(&mut self, event: &event::SequencedEvent) -> anyhow::Result<()> {
+        let serialized_bytes = self.codec.encode(event)?;
+
+        self.writer.write_all(serialized_bytes.as_slice()).await?;
+
+        Ok(())
+    }
+
+    /// Send multiple [crate::event::SequencedEvent]s to the backing stream with a single write, in the given order
+    ///
+    /// # Cancel Safety
+    ///
+    /// This method is not cancellation safe, see [EventWriter::write].
+    pub async fn write_batch(&mut self, events: &[event::SequencedEvent]) -> anyhow::Result<()> {
+        let mut serialized_bytes = Vec::new();
+        for event in events {
+            serialized_bytes.extend(self.codec.encode(event)?);
+        }
+
+        self.writer.write_all(serialized_bytes.as_slice()).await?;
+
+        Ok(())
+    }
+}
+
+/// Splits a stream into a stream of commands and an event writer, using the default [Codec].
+///
+/// # Arguments
+///
+/// - `stream` - A stream to split, e.g. a [tokio::net::TcpStream], a TLS stream over it or an in-memory [tokio::io::duplex]
+pub fn split_stream<S>(stream: S) -> (CommandStream, EventWriter)
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    split_stream_with_codec(stream, Codec::default())
 }
 
-/// Splits a TCP stream into a stream of commands and an event writer.
+/// Splits a stream into a stream of commands and an event writer.
 ///
 /// # Arguments
 ///
-/// - `stream` - A [TcpStream] to split
-pub fn split_tcp_stream(stream: TcpStream) -> (CommandStream, EventWriter) {
-    let (reader, writer) = stream.into_split();
+/// - `stream` - A stream to split, e.g. a [tokio::net::TcpStream], a TLS stream over it or an in-memory [tokio::io::duplex]
+/// - `codec` - The [Codec] to read and write with, the client must use the same one
+pub fn split_stream_with_codec<S>(stream: S, codec: Codec) -> (CommandStream, EventWriter)
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (reader, writer) = tokio::io::split(stream);
 
     (
         Box::pin(
-            LinesStream::new(BufReader::new(reader).lines()).map(|line| {
-                line.context("could not read line from the client")
-                    .and_then(|line| {
-                        serde_json::from_str::<command::UserCommand>(&line)
-                            .context("failed to deserialize command from client")
-                    })
-            }),
+            codec
+                .decode_stream::<command::UserCommand>(reader)
+                .map(|command| command.context("failed to read command from the client")),
         ),
-        EventWriter::new(writer),
+        EventWriter::new(writer, codec),
     )
 }
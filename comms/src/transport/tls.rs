@@ -0,0 +1,104 @@
+use std::{path::Path, sync::Arc};
+
+use anyhow::Context;
+use tokio_rustls::rustls::{
+    self,
+    crypto::CryptoProvider,
+    pki_types::{self, pem::PemObject, CertificateDer},
+};
+
+pub use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+/// The crypto provider is picked explicitly, so the configs do not depend on the process wide default
+fn crypto_provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+fn load_certs(path: &Path) -> anyhow::Result<Vec<CertificateDer<'static>>> {
+    CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .with_context(|| format!("could not read the certificates in {}", path.display()))
+}
+
+/// Builds a [TlsAcceptor] for the server to encrypt the accepted TCP streams with
+///
+/// # Arguments
+///
+/// - `cert_path` - PEM file with the certificate chain of the server, leaf first
+/// - `key_path` - PEM file with the private key of the server certificate
+#[cfg(feature = "server")]
+pub fn acceptor(cert_path: &Path, key_path: &Path) -> anyhow::Result<TlsAcceptor> {
+    let certs = load_certs(cert_path)?;
+    let key = pki_types::PrivateKeyDer::from_pem_file(key_path)
+        .with_context(|| format!("could not read the private key in {}", key_path.display()))?;
+
+    let config = rustls::ServerConfig::builder_with_provider(crypto_provider())
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .context("invalid certificate or private key")?;
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Builds a [TlsConnector] for the client to encrypt the TCP stream to the server with
+///
+/// # Arguments
+///
+/// - `ca_path` - PEM file with the certificates to trust, e.g. for a self-signed server.
+///   The well known certificate authorities are trusted if not given
+#[cfg(feature = "client")]
+pub fn connector(ca_path: Option<&Path>) -> anyhow::Result<TlsConnector> {
+    let mut roots = rustls::RootCertStore::empty();
+    match ca_path {
+        Some(path) => {
+            for cert in load_certs(path)? {
+                roots
+                    .add(cert)
+                    .with_context(|| format!("invalid CA certificate in {}", path.display()))?;
+            }
+        }
+        None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+    }
+
+    let config = rustls::ClientConfig::builder_with_provider(crypto_provider())
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+/// The name to verify the certificate of the server against, the host of a `host:port` address
+#[cfg(feature = "client")]
+pub fn server_name(addr: &str) -> anyhow::Result<pki_types::ServerName<'static>> {
+    let host = addr.rsplit_once(':').map_or(addr, |(host, _port)| host);
+    // IPv6 addresses are written in brackets when followed by a port, e.g. [::1]:8080
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+
+    pki_types::ServerName::try_from(String::from(host))
+        .with_context(|| format!("'{}' is not a valid server name", host))
+}
+
+#[cfg(all(test, feature = "client"))]
+mod tests {
+    use super::*;
+    use pki_types::ServerName;
+
+    #[test]
+    fn test_server_name_is_the_host_of_the_address() {
+        assert_eq!(
+            server_name("chat.example.com:8080").unwrap(),
+            ServerName::try_from("chat.example.com").unwrap()
+        );
+        assert_eq!(
+            server_name("127.0.0.1:8080").unwrap(),
+            ServerName::try_from("127.0.0.1").unwrap()
+        );
+        assert_eq!(
+            server_name("[::1]:8080").unwrap(),
+            ServerName::try_from("::1").unwrap()
+        );
+        assert!(server_name("not a host:8080").is_err());
+    }
+}
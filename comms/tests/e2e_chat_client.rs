@@ -0,0 +1,83 @@
+use comms::{
+    client::ChatClient,
+    command::{self, UserCommand},
+    event::{self, Event},
+    transport,
+};
+use tokio::net::TcpListener;
+use tokio_stream::StreamExt;
+
+#[tokio::test]
+async fn assert_chat_client_commands_and_events() {
+    // bind to an ephemeral port to wait for client connection
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("could not bind to a port");
+    let addr = listener.local_addr().expect("could not get the local addr");
+
+    let (server_collected_commands, client_collected_events) =
+        tokio::join!(execute_server(listener), execute_client(addr));
+
+    assert_eq!(
+        server_collected_commands.unwrap(),
+        vec![
+            UserCommand::Hello(command::HelloCommand {
+                client_version: comms::PROTOCOL_VERSION,
+                sequenced: false,
+            }),
+            UserCommand::JoinRoom(command::JoinRoomCommand {
+                room: "room-1".into(),
+            }),
+            UserCommand::SendMessage(command::SendMessageCommand {
+                room: "room-1".into(),
+                content: "content-1".into(),
+                client_msg_id: None,
+            }),
+            UserCommand::LeaveRoom(command::LeaveRoomCommand {
+                room: "room-1".into(),
+            }),
+        ]
+    );
+
+    assert_eq!(client_collected_events.unwrap(), vec![login_successful()]);
+}
+
+fn login_successful() -> Event {
+    Event::LoginSuccessful(event::LoginSuccessfulReplyEvent {
+        user_id: "user-id-1".into(),
+        session_id: "session-id-1".into(),
+        rooms: Vec::default(),
+        server_version: comms::PROTOCOL_VERSION,
+    })
+}
+
+async fn execute_server(listener: TcpListener) -> anyhow::Result<Vec<command::UserCommand>> {
+    let (tcp_stream, _addr) = listener.accept().await?;
+    let (mut command_stream, mut event_writer) = transport::server::split_stream(tcp_stream);
+    let mut collected_commands = Vec::new();
+
+    event_writer.write(&login_successful()).await?;
+
+    // listen for commands from the client until the connection is closed
+    while let Some(command) = command_stream.next().await {
+        collected_commands.push(command?);
+    }
+
+    Ok(collected_commands)
+}
+
+async fn execute_client(addr: std::net::SocketAddr) -> anyhow::Result<Vec<event::Event>> {
+    let mut client = ChatClient::connect(addr).await?;
+
+    let welcome = client
+        .next_event()
+        .await
+        .ok_or_else(|| anyhow::anyhow!("server closed the connection"))??;
+
+    client.join_room("room-1").await?;
+    client.send_message("room-1", "content-1").await?;
+    client.leave_room("room-1").await?;
+
+    // dropping the client closes the connection, letting the server finish
+    Ok(vec![welcome])
+}
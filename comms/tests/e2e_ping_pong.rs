@@ -0,0 +1,67 @@
+use comms::{
+    command::{self, UserCommand},
+    event::{self, Event},
+    transport,
+};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_stream::StreamExt;
+
+const PING_COUNT: usize = 3;
+
+#[tokio::test]
+async fn assert_ping_pong_round_trips() {
+    // bind to an ephemeral port to avoid clashing with other tests
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("could not bind to a port");
+    let addr = listener.local_addr().unwrap();
+
+    let (server_result, client_result) =
+        tokio::join!(execute_server(listener), execute_client(addr.to_string()));
+
+    assert_eq!(server_result.unwrap(), PING_COUNT);
+    assert_eq!(client_result.unwrap(), PING_COUNT);
+}
+
+/// Replies every ping with a pong, returns the number of pings received
+async fn execute_server(listener: TcpListener) -> anyhow::Result<usize> {
+    let (tcp_stream, _) = listener.accept().await?;
+    let (mut command_stream, mut event_writer) = transport::server::split_stream(tcp_stream);
+    let mut ping_count = 0;
+
+    while let Some(result) = command_stream.next().await {
+        match result? {
+            UserCommand::Ping(_) => {
+                ping_count += 1;
+                event_writer
+                    .write(&Event::Pong(event::PongReplyEvent))
+                    .await?;
+            }
+            command => return Err(anyhow::anyhow!("unexpected command: {:?}", command)),
+        }
+    }
+
+    Ok(ping_count)
+}
+
+/// Sends pings one by one waiting for the pong in between, returns the number of pongs received
+async fn execute_client(addr: String) -> anyhow::Result<usize> {
+    let tcp_stream = TcpStream::connect(addr).await?;
+    let (mut event_stream, mut command_writer) = transport::client::split_stream(tcp_stream);
+    let mut pong_count = 0;
+
+    for _ in 0..PING_COUNT {
+        command_writer
+            .write(&UserCommand::Ping(command::PingCommand))
+            .await?;
+
+        match event_stream.next().await {
+            Some(Ok(Event::Pong(_))) => pong_count += 1,
+            Some(Ok(event)) => return Err(anyhow::anyhow!("unexpected event: {:?}", event)),
+            Some(Err(e)) => return Err(anyhow::anyhow!("could not parse event: {}", e)),
+            None => return Err(anyhow::anyhow!("server closed the connection")),
+        }
+    }
+
+    Ok(pong_count)
+}
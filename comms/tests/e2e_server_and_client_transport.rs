@@ -1,17 +1,30 @@
 use comms::{
     command::{self, UserCommand},
     event::{self, Event},
-    transport,
+    transport::{self, codec::Codec},
 };
-use tokio::net::{TcpListener, TcpStream};
+use tokio::io::{AsyncWriteExt, DuplexStream};
 use tokio_stream::StreamExt;
 
-const PORT: usize = 8081;
+#[tokio::test]
+async fn assert_server_client_transport_json() {
+    assert_server_client_transport(Codec::Json).await;
+}
 
+#[cfg(feature = "msgpack")]
 #[tokio::test]
-async fn assert_server_client_transport() {
-    let (server_collected_commands, client_collected_events) =
-        tokio::join!(execute_server(), execute_client());
+async fn assert_server_client_transport_msgpack() {
+    assert_server_client_transport(Codec::MessagePack).await;
+}
+
+async fn assert_server_client_transport(codec: Codec) {
+    // an in-memory pipe stands in for the tcp connection, so no ports are needed
+    let (server_stream, client_stream) = tokio::io::duplex(1024);
+
+    let (server_collected_commands, client_collected_events) = tokio::join!(
+        execute_server(server_stream, codec),
+        execute_client(client_stream, codec)
+    );
 
     assert!(server_collected_commands.is_ok());
     assert!(client_collected_events.is_ok());
@@ -25,6 +38,7 @@ async fn assert_server_client_transport() {
             UserCommand::SendMessage(command::SendMessageCommand {
                 room: "room-1".into(),
                 content: "content-1".into(),
+                client_msg_id: None,
             }),
         ]
     );
@@ -35,24 +49,99 @@ async fn assert_server_client_transport() {
             user_id: "user-id-1".into(),
             session_id: "session-id-1".into(),
             rooms: Vec::default(),
+            server_version: comms::PROTOCOL_VERSION,
         }),]
     );
 }
 
-async fn execute_server() -> anyhow::Result<Vec<command::UserCommand>> {
-    // bind to the example port to wait for client connection
-    let listener = TcpListener::bind(format!("0.0.0.0:{}", PORT))
+#[tokio::test]
+async fn assert_sequenced_events_json() {
+    assert_sequenced_events(Codec::Json).await;
+}
+
+#[cfg(feature = "msgpack")]
+#[tokio::test]
+async fn assert_sequenced_events_msgpack() {
+    assert_sequenced_events(Codec::MessagePack).await;
+}
+
+async fn assert_sequenced_events(codec: Codec) {
+    let events = [
+        event::SequencedEvent {
+            event: Event::Pong(event::PongReplyEvent),
+            seq: Some(2),
+        },
+        event::SequencedEvent {
+            event: Event::Pong(event::PongReplyEvent),
+            seq: Some(1),
+        },
+    ];
+    let (server_stream, client_stream) = tokio::io::duplex(1024);
+    let (_command_stream, mut event_writer) =
+        transport::server::split_stream_with_codec(server_stream, codec);
+    let (mut event_stream, _command_writer) =
+        transport::client::split_sequenced_stream_with_codec(client_stream, codec);
+
+    event_writer.write_batch(&events).await.unwrap();
+    event_writer
+        .write(&Event::Pong(event::PongReplyEvent))
+        .await
+        .unwrap();
+
+    // the order is left to the client, the events arrive as they were written
+    assert_eq!(event_stream.next().await.unwrap().unwrap(), events[0]);
+    assert_eq!(event_stream.next().await.unwrap().unwrap(), events[1]);
+    assert_eq!(event_stream.next().await.unwrap().unwrap().seq, None);
+
+    // an event without its sequence number is the same as the plain event on the wire
+    let (server_stream, client_stream) = tokio::io::duplex(1024);
+    let (_command_stream, mut event_writer) =
+        transport::server::split_stream_with_codec(server_stream, codec);
+    let (mut event_stream, _command_writer) =
+        transport::client::split_stream_with_codec(client_stream, codec);
+
+    event_writer
+        .write_sequenced(&event::SequencedEvent {
+            event: Event::Pong(event::PongReplyEvent),
+            seq: None,
+        })
         .await
-        .expect("could not bind to the port");
+        .unwrap();
+    assert_eq!(
+        event_stream.next().await.unwrap().unwrap(),
+        Event::Pong(event::PongReplyEvent)
+    );
+}
+
+#[tokio::test]
+async fn assert_malformed_events_are_yielded_as_errors() {
+    let (mut server_stream, client_stream) = tokio::io::duplex(1024);
+    let (mut event_stream, _command_writer) = transport::client::split_stream(client_stream);
 
-    // accept the only client connection we will have
-    let tcp_stream = match listener.accept().await {
-        Ok((tcp_stream, _addr)) => tcp_stream,
-        Err(e) => return Err(anyhow::anyhow!("failed to accept client: {}", e)),
-    };
+    // an event from a newer server and a corrupted line, followed by an event the client knows about
+    server_stream
+        .write_all(b"{\"_et\":\"from_the_future\"}\nnot even json\n{\"_et\":\"pong\"}\n")
+        .await
+        .unwrap();
+    drop(server_stream);
+
+    assert!(matches!(event_stream.next().await, Some(Err(_))));
+    assert!(matches!(event_stream.next().await, Some(Err(_))));
+    // the stream carries on after the bad lines
+    assert!(matches!(
+        event_stream.next().await,
+        Some(Ok(Event::Pong(_)))
+    ));
+    assert!(event_stream.next().await.is_none());
+}
 
+async fn execute_server(
+    stream: DuplexStream,
+    codec: Codec,
+) -> anyhow::Result<Vec<command::UserCommand>> {
     // break the client connection into higher level API for ease of use
-    let (mut command_stream, mut event_writer) = transport::server::split_tcp_stream(tcp_stream);
+    let (mut command_stream, mut event_writer) =
+        transport::server::split_stream_with_codec(stream, codec);
     // store commands received from the client
     let mut collected_commands = Vec::new();
 
@@ -62,6 +151,7 @@ async fn execute_server() -> anyhow::Result<Vec<command::UserCommand>> {
             user_id: "user-id-1".into(),
             session_id: "session-id-1".into(),
             rooms: Vec::default(),
+            server_version: comms::PROTOCOL_VERSION,
         }))
         .await?;
 
@@ -79,15 +169,10 @@ async fn execute_server() -> anyhow::Result<Vec<command::UserCommand>> {
     Ok(collected_commands)
 }
 
-async fn execute_client() -> anyhow::Result<Vec<event::Event>> {
-    // create a client connection to the server
-    let tcp_stream = match TcpStream::connect(format!("localhost:{}", PORT)).await {
-        Ok(tcp_stream) => tcp_stream,
-        Err(e) => return Err(anyhow::anyhow!("failed to connect to server: {}", e)),
-    };
-
+async fn execute_client(stream: DuplexStream, codec: Codec) -> anyhow::Result<Vec<event::Event>> {
     // break the server connection into higher level API for ease of use
-    let (mut event_stream, mut command_writer) = transport::client::split_tcp_stream(tcp_stream);
+    let (mut event_stream, mut command_writer) =
+        transport::client::split_stream_with_codec(stream, codec);
     // store events received from the server
     let mut collected_events = Vec::new();
 
@@ -113,6 +198,7 @@ async fn execute_client() -> anyhow::Result<Vec<event::Event>> {
         .write(&UserCommand::SendMessage(command::SendMessageCommand {
             room: "room-1".into(),
             content: "content-1".into(),
+            client_msg_id: None,
         }))
         .await?;
 
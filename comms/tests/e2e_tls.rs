@@ -0,0 +1,90 @@
+#![cfg(all(feature = "tls", feature = "client", feature = "server"))]
+
+use std::path::{Path, PathBuf};
+
+use comms::{
+    command::{self, UserCommand},
+    event::{self, Event},
+    transport::{self, tls},
+};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_stream::StreamExt;
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+#[tokio::test]
+async fn assert_server_client_transport_over_tls() {
+    let acceptor = tls::acceptor(&fixture("server.pem"), &fixture("server.key")).unwrap();
+    let connector = tls::connector(Some(&fixture("ca.pem"))).unwrap();
+
+    // bind to an ephemeral port to wait for client connection
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("could not bind to a port");
+    let addr = listener.local_addr().unwrap();
+
+    let server = async {
+        let (tcp_stream, _) = listener.accept().await?;
+        let tls_stream = acceptor.accept(tcp_stream).await?;
+        let (mut command_stream, mut event_writer) = transport::server::split_stream(tls_stream);
+
+        let command = command_stream.next().await.unwrap()?;
+        event_writer
+            .write(&Event::Pong(event::PongReplyEvent))
+            .await?;
+
+        anyhow::Ok(command)
+    };
+
+    let client = async {
+        let tcp_stream = TcpStream::connect(addr).await?;
+        let tls_stream = connector
+            .connect(tls::server_name("localhost:0")?, tcp_stream)
+            .await?;
+        let (mut event_stream, mut command_writer) = transport::client::split_stream(tls_stream);
+
+        command_writer
+            .write(&UserCommand::Ping(command::PingCommand))
+            .await?;
+
+        anyhow::Ok(event_stream.next().await.unwrap()?)
+    };
+
+    let (command, event) = tokio::join!(server, client);
+
+    assert_eq!(command.unwrap(), UserCommand::Ping(command::PingCommand));
+    assert_eq!(event.unwrap(), Event::Pong(event::PongReplyEvent));
+}
+
+#[tokio::test]
+async fn assert_untrusted_server_is_rejected() {
+    let acceptor = tls::acceptor(&fixture("server.pem"), &fixture("server.key")).unwrap();
+    // the test certificate is not signed by any of the well known authorities
+    let connector = tls::connector(None).unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("could not bind to a port");
+    let addr = listener.local_addr().unwrap();
+
+    let server = async {
+        let (tcp_stream, _) = listener.accept().await.unwrap();
+        acceptor.accept(tcp_stream).await
+    };
+
+    let client = async {
+        let tcp_stream = TcpStream::connect(addr).await.unwrap();
+        connector
+            .connect(tls::server_name("localhost:0").unwrap(), tcp_stream)
+            .await
+    };
+
+    let (server_result, client_result) = tokio::join!(server, client);
+
+    assert!(server_result.is_err());
+    assert!(client_result.is_err());
+}
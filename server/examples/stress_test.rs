@@ -1,7 +1,11 @@
-use std::time::Duration;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
 use comms::{
-    command::{JoinRoomCommand, UserCommand},
+    command::{JoinRoomCommand, PongCommand, UserCommand},
     event::Event,
     transport,
 };
@@ -11,12 +15,12 @@ use serde::{Deserialize, Serialize};
 use tokio::{net::TcpStream, task::JoinSet};
 use tokio_stream::StreamExt;
 
-/// Stres Test for the Chat Server
-///
-/// Generates synthetic load with users who joins and sends messages to random roms.
-/// The number of users, number of rooms joined per user and chattines of users can be configured.
-///
-/// !IMPORTANT! Be sure to check and configure your socket limits, before you run the tests
+// Stres Test for the Chat Server
+//
+// Generates synthetic load with users who joins and sends messages to random roms.
+// The number of users, number of rooms joined per user and chattines of users can be configured.
+//
+// !IMPORTANT! Be sure to check and configure your socket limits, before you run the tests
 
 const SERVER_ADDR: &str = "localhost:8080";
 const CHAT_ROOMS_METADATAS: &str = include_str!("../resources/chat_rooms_metadatas.json");
@@ -31,6 +35,12 @@ const LOAD_INCREMENTS: &str = r#"[
 const NUMBER_OF_ROOMS_TO_JOIN: usize = 5;
 // How many milliseconds to wait between each user message
 const USER_CHAT_DELAY_MILLIS: u64 = 10_000;
+// How many milliseconds to wait before the first reconnection attempt, doubled after each failed attempt
+const RECONNECT_BACKOFF_BASE_MILLIS: u64 = 500;
+// The most milliseconds to wait between two reconnection attempts
+const RECONNECT_BACKOFF_MAX_MILLIS: u64 = 30_000;
+// How often the message round-trip latencies are reported
+const LATENCY_REPORT_INTERVAL: Duration = Duration::from_secs(10);
 
 /// [RotatingIterator] is a simple iterator that rotates through a list of items
 /// and starts from the beginning when the end is reached.
@@ -68,25 +78,93 @@ struct LoadIncrements {
     steps: usize,
 }
 
-async fn spawn_single_user(rooms_to_join: Vec<String>) -> anyhow::Result<()> {
-    let result = spawn_single_user_raw(rooms_to_join).await;
+/// [LatencyRecorder] collects the round-trip latencies of the messages, from sending them until they are
+/// broadcasted back to their sender
+#[derive(Default)]
+struct LatencyRecorder {
+    samples: Mutex<Vec<Duration>>,
+    reconnects: Mutex<usize>,
+}
+
+impl LatencyRecorder {
+    fn record(&self, latency: Duration) {
+        self.samples.lock().unwrap().push(latency);
+    }
+
+    fn record_reconnect(&self) {
+        *self.reconnects.lock().unwrap() += 1;
+    }
+
+    /// Prints the percentiles of the latencies recorded since the last report
+    fn report(&self) {
+        let mut samples = std::mem::take(&mut *self.samples.lock().unwrap());
+        let reconnects = std::mem::take(&mut *self.reconnects.lock().unwrap());
+        if samples.is_empty() {
+            println!("latency: no messages received, reconnects: {}", reconnects);
+            return;
+        }
 
-    match result.as_ref() {
-        Ok(_) => println!("exited without problems"),
-        Err(err) => println!("some error occurred = {}", err.to_string()),
+        samples.sort_unstable();
+        println!(
+            "latency: messages = {}, p50 = {:?}, p90 = {:?}, p99 = {:?}, max = {:?}, reconnects: {}",
+            samples.len(),
+            percentile(&samples, 50),
+            percentile(&samples, 90),
+            percentile(&samples, 99),
+            samples[samples.len() - 1],
+            reconnects
+        );
     }
+}
+
+/// The value below which the given percent of the sorted, non-empty samples fall
+fn percentile(sorted_samples: &[Duration], percent: usize) -> Duration {
+    let index = (sorted_samples.len() * percent / 100).min(sorted_samples.len() - 1);
+
+    sorted_samples[index]
+}
+
+/// How long to wait before the given reconnection attempt, starting from 0. The wait is picked randomly
+/// up to the exponential backoff, so the users dropped together do not all come back at the same time
+fn reconnect_backoff(rng: &mut StdRng, attempt: u32) -> Duration {
+    let max_millis = RECONNECT_BACKOFF_BASE_MILLIS
+        .saturating_mul(2u64.saturating_pow(attempt))
+        .min(RECONNECT_BACKOFF_MAX_MILLIS);
+
+    Duration::from_millis(rng.gen_range(0..=max_millis))
+}
+
+/// Keeps the user connected, reconnecting with a backoff whenever the connection fails or is dropped
+async fn spawn_single_user(rooms_to_join: Vec<String>, latencies: Arc<LatencyRecorder>) {
+    let mut rng = StdRng::from_entropy();
+    let mut attempt = 0;
 
-    result
+    loop {
+        match spawn_single_user_raw(rooms_to_join.clone(), &latencies, &mut attempt).await {
+            Ok(_) => println!("connection closed by the server"),
+            Err(err) => println!("some error occurred = {}", err),
+        }
+
+        tokio::time::sleep(reconnect_backoff(&mut rng, attempt)).await;
+        attempt = attempt.saturating_add(1);
+        latencies.record_reconnect();
+    }
 }
 
-async fn spawn_single_user_raw(rooms_to_join: Vec<String>) -> anyhow::Result<()> {
+async fn spawn_single_user_raw(
+    rooms_to_join: Vec<String>,
+    latencies: &LatencyRecorder,
+    attempt: &mut u32,
+) -> anyhow::Result<()> {
     let tcp_stream = TcpStream::connect(SERVER_ADDR).await?;
-    let (mut event_stream, mut command_writer) = transport::client::split_tcp_stream(tcp_stream);
+    let (mut event_stream, mut command_writer) = transport::client::split_stream(tcp_stream);
 
-    let _login_event = match event_stream.next().await {
+    let login_event = match event_stream.next().await {
         Some(Ok(Event::LoginSuccessful(login_event))) => login_event,
         _ => return Err(anyhow::anyhow!("server did not send login successfull")),
     };
+    // the user is connected again, so the next drop starts the backoff over
+    *attempt = 0;
 
     for room_name in rooms_to_join.iter() {
         command_writer
@@ -96,7 +174,14 @@ async fn spawn_single_user_raw(rooms_to_join: Vec<String>) -> anyhow::Result<()>
             .await?;
     }
 
+    // the messages which are not broadcasted back yet, with the time they were sent
+    let sent_at = Arc::new(Mutex::new(HashMap::<String, Instant>::new()));
+    // shared with the chatting task, since the pings of the server are answered from here
+    let command_writer = Arc::new(tokio::sync::Mutex::new(command_writer));
+
     let join_handle = tokio::spawn({
+        let sent_at = sent_at.clone();
+        let command_writer = command_writer.clone();
         let mut rng = StdRng::from_entropy();
         let mut rooms_iterator = RotatingIterator::new(rooms_to_join);
         let to_sleep = Duration::from_millis(USER_CHAT_DELAY_MILLIS);
@@ -110,11 +195,19 @@ async fn spawn_single_user_raw(rooms_to_join: Vec<String>) -> anyhow::Result<()>
 
             loop {
                 let room_name = rooms_iterator.next().unwrap();
+                let content = nanoid!();
+                sent_at
+                    .lock()
+                    .unwrap()
+                    .insert(content.clone(), Instant::now());
                 let _ = command_writer
+                    .lock()
+                    .await
                     .write(&UserCommand::SendMessage(
                         comms::command::SendMessageCommand {
-                            room: String::from(room_name),
-                            content: nanoid!(),
+                            room: room_name,
+                            content,
+                            client_msg_id: None,
                         },
                     ))
                     .await;
@@ -124,7 +217,24 @@ async fn spawn_single_user_raw(rooms_to_join: Vec<String>) -> anyhow::Result<()>
         }
     });
 
-    while let Some(_) = event_stream.next().await {}
+    while let Some(event) = event_stream.next().await {
+        match event {
+            // the server drops the users who do not answer its pings
+            Ok(Event::Ping(_)) => {
+                command_writer
+                    .lock()
+                    .await
+                    .write(&UserCommand::Pong(PongCommand))
+                    .await?;
+            }
+            Ok(Event::UserMessage(event)) if event.user_id == login_event.user_id => {
+                if let Some(sent_at) = sent_at.lock().unwrap().remove(&event.content) {
+                    latencies.record(sent_at.elapsed());
+                }
+            }
+            _ => {}
+        }
+    }
 
     join_handle.abort();
     Ok(())
@@ -138,7 +248,23 @@ async fn main() {
         .expect("could not parse the chat rooms metadatas");
 
     let mut room_iterator = RotatingIterator::new(chat_room_metadatas);
-    let mut join_set: JoinSet<anyhow::Result<()>> = JoinSet::new();
+    let mut join_set: JoinSet<()> = JoinSet::new();
+    let latencies = Arc::new(LatencyRecorder::default());
+
+    tokio::spawn({
+        let latencies = latencies.clone();
+        let mut interval = tokio::time::interval(LATENCY_REPORT_INTERVAL);
+
+        async move {
+            // the first tick completes right away, when nothing is recorded yet
+            interval.tick().await;
+
+            loop {
+                interval.tick().await;
+                latencies.report();
+            }
+        }
+    });
 
     let mut current: usize = 0;
     for li in load_increments {
@@ -155,7 +281,7 @@ async fn main() {
                     .map(|metadata| metadata.name.clone())
                     .collect();
 
-                join_set.spawn(spawn_single_user(rooms_to_join));
+                join_set.spawn(spawn_single_user(rooms_to_join, latencies.clone()));
             }
 
             current += to_increment;
@@ -164,5 +290,5 @@ async fn main() {
         }
     }
 
-    while let Some(_) = join_set.join_next().await {}
+    while join_set.join_next().await.is_some() {}
 }
@@ -0,0 +1,80 @@
+use std::path::Path;
+
+use anyhow::Context;
+
+/// [AuthTokens] are the secrets which let the users in, when the server requires authentication.
+///
+/// The tokens are opaque strings handed out to the users out of band, e.g. generated with
+/// `openssl rand -hex 32`. They are read from a file with one token per line, where the blank lines
+/// and the lines starting with `#` are skipped. The users send one of them with
+/// [comms::command::AuthenticateCommand] as their first command.
+#[derive(Debug)]
+pub struct AuthTokens {
+    tokens: Vec<String>,
+}
+
+impl AuthTokens {
+    /// Parses the tokens, one per line, failing if there are none to accept
+    pub fn parse(raw: &str) -> anyhow::Result<Self> {
+        let tokens = raw
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(String::from)
+            .collect::<Vec<_>>();
+
+        if tokens.is_empty() {
+            anyhow::bail!("there are no tokens to accept");
+        }
+
+        Ok(AuthTokens { tokens })
+    }
+
+    /// Reads the tokens from the given file, see [AuthTokens::parse]
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let raw = std::fs::read_to_string(path)
+            .with_context(|| format!("could not read the tokens file {}", path.display()))?;
+
+        Self::parse(&raw).with_context(|| format!("invalid tokens file {}", path.display()))
+    }
+
+    /// Checks whether the given token is one of the accepted ones
+    pub fn is_valid(&self, token: &str) -> bool {
+        // every token is compared, so the time taken does not tell which one was close
+        self.tokens.iter().fold(false, |is_valid, accepted| {
+            constant_time_eq(accepted.as_bytes(), token.as_bytes()) | is_valid
+        })
+    }
+}
+
+/// Compares the bytes without stopping at the first difference, so the time taken does not leak the secret
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_skips_comments_and_blank_lines() {
+        let auth_tokens = AuthTokens::parse("# the bots\nbot-token\n\n  user-token  \n").unwrap();
+
+        assert!(auth_tokens.is_valid("bot-token"));
+        assert!(auth_tokens.is_valid("user-token"));
+        assert!(!auth_tokens.is_valid("# the bots"));
+        assert!(!auth_tokens.is_valid(""));
+        assert!(!auth_tokens.is_valid("bot-toke"));
+        assert!(!auth_tokens.is_valid("bot-tokens"));
+    }
+
+    #[test]
+    fn test_parse_requires_a_token() {
+        assert!(AuthTokens::parse("").is_err());
+        assert!(AuthTokens::parse("# nothing yet\n\n").is_err());
+    }
+}
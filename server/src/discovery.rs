@@ -0,0 +1,197 @@
+use std::{sync::Arc, time::Duration};
+
+use serde::Serialize;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tracing::{debug, warn};
+
+use crate::room_manager::{ChatRoomMetadata, RoomManager};
+
+/// How long a client has to send its request before the connection is closed
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// The requests are small, anything larger than this is not one of ours
+const MAX_REQUEST_LEN: usize = 8 * 1024;
+/// How long to wait before accepting again after a failure, which tends to repeat right away
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_millis(100);
+
+/// What is served about a room, leaving out the moderators
+#[derive(Debug, Serialize)]
+struct RoomListing {
+    name: String,
+    description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    capacity: Option<usize>,
+    participant_count: usize,
+}
+
+impl RoomListing {
+    fn new(metadata: ChatRoomMetadata, participant_count: usize) -> Self {
+        RoomListing {
+            name: metadata.name,
+            description: metadata.description,
+            capacity: metadata.capacity,
+            participant_count,
+        }
+    }
+}
+
+/// Serves the rooms of the server as JSON over HTTP, for the tools which do not speak the chat protocol.
+///
+/// `GET /rooms` answers with the names, descriptions, capacities and participant counts of the rooms,
+/// including the rooms created at runtime. The other paths are not found, and the other methods are not allowed.
+/// Each connection is answered once and closed.
+pub async fn serve(listener: TcpListener, room_manager: Arc<RoomManager>) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            // e.g. out of file descriptors, retrying right away would only spin
+            Err(err) => {
+                warn!(err = %err, "could not accept a discovery connection");
+                tokio::time::sleep(ACCEPT_ERROR_BACKOFF).await;
+                continue;
+            }
+        };
+        let room_manager = Arc::clone(&room_manager);
+
+        tokio::spawn(async move {
+            if let Err(err) = handle_request(stream, &room_manager).await {
+                debug!(err = %err, "could not answer the discovery request");
+            }
+        });
+    }
+}
+
+async fn handle_request(mut stream: TcpStream, room_manager: &RoomManager) -> anyhow::Result<()> {
+    let request = tokio::time::timeout(REQUEST_TIMEOUT, read_request_head(&mut stream)).await??;
+    let mut parts = request
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .split_whitespace();
+
+    let response = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/rooms")) => {
+            let rooms = room_manager
+                .chat_room_metadatas_with_participant_counts()
+                .await
+                .into_iter()
+                .map(|(metadata, participant_count)| RoomListing::new(metadata, participant_count))
+                .collect::<Vec<_>>();
+            let body = serde_json::to_string(&rooms)?;
+
+            response("200 OK", "application/json", &body)
+        }
+        (Some("GET"), _) => response("404 Not Found", "text/plain", "not found"),
+        _ => response(
+            "405 Method Not Allowed",
+            "text/plain",
+            "only GET is allowed",
+        ),
+    };
+
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+
+    Ok(())
+}
+
+/// Reads the request line and the headers, up to the blank line which ends them
+async fn read_request_head(stream: &mut TcpStream) -> anyhow::Result<String> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+
+    while !buf.windows(4).any(|window| window == b"\r\n\r\n") {
+        if buf.len() > MAX_REQUEST_LEN {
+            anyhow::bail!("the request is too large");
+        }
+
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            anyhow::bail!("the connection was closed before the request ended");
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+fn response(status: &str, content_type: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::room_manager::{RoomManagerBuilder, SessionAndUserId};
+
+    use super::*;
+
+    async fn request(addr: std::net::SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        response
+    }
+
+    #[tokio::test]
+    async fn test_rooms_are_served_as_json() {
+        let room_manager = Arc::new(
+            RoomManagerBuilder::new()
+                .create_room(ChatRoomMetadata {
+                    name: "rust".into(),
+                    description: "Rust".into(),
+                    capacity: Some(10),
+                    moderators: vec!["alice".into()],
+                })
+                .build()
+                .unwrap(),
+        );
+        room_manager
+            .create_room(ChatRoomMetadata {
+                name: "go".into(),
+                description: "Go".into(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let _joined = room_manager
+            .join_room(
+                "go",
+                &SessionAndUserId {
+                    session_id: "a".into(),
+                    user_id: "a".into(),
+                    display_name: "a".into(),
+                },
+            )
+            .await
+            .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, room_manager));
+
+        let response = request(addr, "GET /rooms HTTP/1.1\r\nHost: localhost\r\n\r\n").await;
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(head.contains("Content-Type: application/json"));
+        assert_eq!(
+            body,
+            r#"[{"name":"rust","description":"Rust","capacity":10,"participant_count":0},{"name":"go","description":"Go","participant_count":1}]"#
+        );
+
+        let response = request(addr, "GET /users HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+        let response = request(addr, "POST /rooms HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+    }
+}
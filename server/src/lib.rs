@@ -0,0 +1,9 @@
+//! A room based chat server, run by the `server` binary or embedded into other applications
+pub mod auth;
+pub mod discovery;
+pub mod message_store;
+pub mod room_event_observer;
+pub mod room_manager;
+mod sequencer;
+pub mod session;
+pub mod session_spawner;
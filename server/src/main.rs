@@ -1,50 +1,217 @@
-use std::sync::Arc;
+use std::{os::unix::fs::FileTypeExt, sync::Arc, time::Duration};
 
 use anyhow::Context;
-use room_manager::RoomManagerBuilder;
-use tokio::{net::TcpListener, signal::ctrl_c, sync::broadcast, task::JoinSet};
+use clap::Parser;
+use comms::{event::ServerShutdownBroadcastEvent, transport::tls};
+use tokio::{
+    net::{TcpListener, UnixListener},
+    signal::{
+        ctrl_c,
+        unix::{signal, SignalKind},
+    },
+    sync::broadcast,
+};
+use tracing::info;
+use tracing_subscriber::EnvFilter;
 
-use crate::room_manager::ChatRoomMetadata;
+use server::{
+    auth::AuthTokens,
+    discovery,
+    message_store::SqliteMessageStore,
+    room_event_observer::TracingRoomEventObserver,
+    room_manager::{ChatRoomMetadata, RoomManagerBuilder},
+    session_spawner::SessionSpawner,
+};
 
-mod room_manager;
-mod session;
-
-const PORT: u16 = 8080;
 const CHAT_ROOMS_METADATAS: &str = include_str!("../resources/chat_rooms_metadatas.json");
+/// How long the clients are asked to wait before reconnecting after a shutdown
+const SHUTDOWN_RECONNECT_AFTER_SECS: u64 = 5;
+
+/// Room based chat server
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Args {
+    /// The host to bind to
+    #[arg(long, default_value = "0.0.0.0")]
+    host: String,
+    /// The port to listen on
+    #[arg(long, default_value_t = 8080, value_parser = clap::value_parser!(u16).range(1..))]
+    port: u16,
+    /// JSON file with the metadatas of the rooms to create, defaults to the built-in rooms
+    #[arg(long)]
+    rooms_file: Option<std::path::PathBuf>,
+    /// SQLite database to persist the messages to, messages are only kept in memory if not set
+    #[arg(long)]
+    db: Option<std::path::PathBuf>,
+    /// Seconds a session can go without sending any commands besides the keepalives before it is closed
+    #[arg(long, default_value_t = 15 * 60, value_parser = clap::value_parser!(u64).range(1..))]
+    idle_timeout: u64,
+    /// Maximum number of connections to handle at once, the new ones are rejected beyond it
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    max_connections: Option<u64>,
+    /// Unix domain socket to also listen on, for the clients on the same machine
+    #[arg(long)]
+    socket: Option<std::path::PathBuf>,
+    /// PEM file with the TLS certificate chain, the connections are encrypted if given
+    #[arg(long, requires = "tls_key")]
+    tls_cert: Option<std::path::PathBuf>,
+    /// PEM file with the private key of the TLS certificate
+    #[arg(long, requires = "tls_cert")]
+    tls_key: Option<std::path::PathBuf>,
+    /// File with the tokens to accept, one per line, the users must authenticate with one of them to connect
+    #[arg(long, value_name = "TOKENS_FILE")]
+    require_auth: Option<std::path::PathBuf>,
+    /// Message of the day, sent to every user right after they log in
+    #[arg(long, conflicts_with = "motd_file")]
+    motd: Option<String>,
+    /// File with the message of the day, see --motd
+    #[arg(long)]
+    motd_file: Option<std::path::PathBuf>,
+    /// Port to serve the rooms as JSON over HTTP on, at `GET /rooms`, for the tools which do not speak the chat protocol
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    discovery_port: Option<u16>,
+}
+
+/// Reads the message of the day from the given file, without its trailing newline
+fn read_motd(path: &std::path::Path) -> anyhow::Result<String> {
+    let raw = std::fs::read_to_string(path)
+        .with_context(|| format!("could not read the motd file {}", path.display()))?;
+
+    Ok(String::from(raw.trim_end()))
+}
+
+/// Reads the metadatas of the rooms to create from the given file, or the built-in defaults
+fn read_chat_room_metadatas(
+    rooms_file: Option<&std::path::Path>,
+) -> anyhow::Result<Vec<ChatRoomMetadata>> {
+    let raw = match rooms_file {
+        Some(path) => std::fs::read_to_string(path)
+            .with_context(|| format!("could not read the rooms file {}", path.display()))?,
+        None => String::from(CHAT_ROOMS_METADATAS),
+    };
+
+    serde_json::from_str(&raw).context("could not parse the chat rooms metadatas")
+}
+
+/// Binds a unix domain socket, replacing the one left behind by a previous run
+fn bind_unix_socket(path: &std::path::Path) -> anyhow::Result<UnixListener> {
+    if std::fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        std::fs::remove_file(path)
+            .with_context(|| format!("could not remove the stale socket {}", path.display()))?;
+    }
+
+    UnixListener::bind(path).with_context(|| format!("could not bind to {}", path.display()))
+}
 
 #[tokio::main]
 async fn main() {
-    let chat_room_metadatas: Vec<ChatRoomMetadata> = serde_json::from_str(CHAT_ROOMS_METADATAS)
-        .expect("could not parse the chat rooms metadatas");
+    let args = Args::parse();
+
+    // the log level is picked with RUST_LOG, e.g. RUST_LOG=server=debug
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .init();
+
+    let chat_room_metadatas = read_chat_room_metadatas(args.rooms_file.as_deref())
+        .unwrap_or_else(|err| panic!("{:#}", err));
+    let mut room_manager_builder = chat_room_metadatas.into_iter().fold(
+        RoomManagerBuilder::new().observer(Arc::new(TracingRoomEventObserver)),
+        |builder, metadata| builder.create_room(metadata),
+    );
+    if let Some(path) = args.db.as_deref() {
+        let message_store =
+            SqliteMessageStore::open(path).unwrap_or_else(|err| panic!("{:#}", err));
+        room_manager_builder = room_manager_builder.message_store(Arc::new(message_store));
+    }
     let room_manager = Arc::new(
-        chat_room_metadatas
-            .into_iter()
-            .fold(RoomManagerBuilder::new(), |builder, metadata| {
-                builder.create_room(metadata)
-            })
-            .build(),
+        room_manager_builder
+            .build()
+            .unwrap_or_else(|err| panic!("{:#}", err)),
     );
 
-    let mut join_set: JoinSet<anyhow::Result<()>> = JoinSet::new();
-    let server = TcpListener::bind(format!("0.0.0.0:{}", PORT))
+    let server = TcpListener::bind((args.host.as_str(), args.port))
         .await
-        .expect("could not bind to the port");
-    let (quit_tx, quit_rx) = broadcast::channel::<()>(1);
+        .expect("could not bind to the address");
+    let unix_server = args
+        .socket
+        .as_deref()
+        .map(|path| bind_unix_socket(path).unwrap_or_else(|err| panic!("{:#}", err)));
+    let (quit_tx, quit_rx) = broadcast::channel::<ServerShutdownBroadcastEvent>(1);
+
+    let mut session_spawner = SessionSpawner::new(
+        Arc::clone(&room_manager),
+        quit_rx,
+        Duration::from_secs(args.idle_timeout),
+    );
+    if let Some(max_connections) = args.max_connections {
+        session_spawner = session_spawner.with_max_connections(max_connections as usize);
+    }
+    if let (Some(cert_path), Some(key_path)) = (args.tls_cert.as_deref(), args.tls_key.as_deref()) {
+        let tls_acceptor =
+            tls::acceptor(cert_path, key_path).unwrap_or_else(|err| panic!("{:#}", err));
+        session_spawner = session_spawner.with_tls(tls_acceptor);
+    }
+    if let Some(path) = args.require_auth.as_deref() {
+        let auth_tokens = AuthTokens::read(path).unwrap_or_else(|err| panic!("{:#}", err));
+        session_spawner = session_spawner.with_auth(auth_tokens);
+    }
+    let motd = match args.motd_file.as_deref() {
+        Some(path) => Some(read_motd(path).unwrap_or_else(|err| panic!("{:#}", err))),
+        None => args.motd,
+    };
+    if let Some(motd) = motd {
+        session_spawner = session_spawner.with_motd(motd);
+    }
+    // service managers such as systemd and docker stop the server with SIGTERM rather than SIGINT
+    let mut terminate =
+        signal(SignalKind::terminate()).expect("could not listen for the terminate signal");
 
-    println!("Listening on port {}", PORT);
-    loop {
+    info!(
+        addr = %server
+            .local_addr()
+            .expect("could not get the bound address"),
+        "listening"
+    );
+    if let Some(path) = args.socket.as_deref() {
+        info!(socket = %path.display(), "listening");
+    }
+    if let Some(port) = args.discovery_port {
+        let discovery_server = TcpListener::bind((args.host.as_str(), port))
+            .await
+            .expect("could not bind to the discovery address");
+        info!(port, "serving the rooms for discovery");
+        // answers the requests until the server exits, it has nothing to wind down
+        tokio::spawn(discovery::serve(
+            discovery_server,
+            Arc::clone(&room_manager),
+        ));
+    }
+    // accept users until the server is asked to stop, by either of the signals
+    let signal_name = loop {
         tokio::select! {
-            Ok(_) = ctrl_c() => {
-                println!("Server interrupted. Gracefully shutting down.");
-                quit_tx.send(()).context("failed to send quit signal").unwrap();
-                break;
-            }
-            Ok((socket, _)) = server.accept() => {
-                join_set.spawn(session::handle_user_session(Arc::clone(&room_manager), quit_rx.resubscribe(), socket));
+            Ok(_) = ctrl_c() => break "interrupted",
+            Some(_) = terminate.recv() => break "terminated",
+            Ok((socket, _)) = server.accept() => session_spawner.spawn_tcp(socket),
+            Ok((socket, _)) = async { unix_server.as_ref().unwrap().accept().await }, if unix_server.is_some() => {
+                session_spawner.spawn_unix(socket);
             }
         }
-    }
+    };
+
+    info!(signal = signal_name, "gracefully shutting down");
+    quit_tx
+        .send(ServerShutdownBroadcastEvent {
+            reason: String::from("the server is shutting down"),
+            reconnect_after_secs: SHUTDOWN_RECONNECT_AFTER_SECS,
+        })
+        .context("failed to send quit signal")
+        .unwrap();
 
-    while join_set.join_next().await.is_some() {}
-    println!("Server shut down");
+    session_spawner.wait().await;
+    if let Some(path) = args.socket.as_deref() {
+        let _ = std::fs::remove_file(path);
+    }
+    info!("server shut down");
 }
@@ -0,0 +1,62 @@
+use comms::event::UserMessageBroadcastEvent;
+
+pub use self::sqlite::SqliteMessageStore;
+
+mod sqlite;
+
+/// [MessageStore] persists the messages sent to the rooms, so the history of the rooms survives restarts
+pub trait MessageStore: std::fmt::Debug + Send + Sync {
+    /// Persist a message sent to a room
+    fn save(&self, message: &UserMessageBroadcastEvent) -> anyhow::Result<()>;
+
+    /// Remove a previously persisted message
+    fn delete(&self, room: &str, message_id: &str) -> anyhow::Result<()>;
+
+    /// Load the most recent messages of a room, oldest first
+    fn load_recent(
+        &self,
+        room: &str,
+        limit: usize,
+    ) -> anyhow::Result<Vec<UserMessageBroadcastEvent>>;
+
+    /// Load the most recent messages of a room sent before the given message, oldest first
+    /// Fails with a [comms::event::ErrorCode::MessageNotFound] if the given message is not in the store
+    fn load_before(
+        &self,
+        room: &str,
+        before_message_id: &str,
+        limit: usize,
+    ) -> anyhow::Result<Vec<UserMessageBroadcastEvent>>;
+}
+
+/// [NoopMessageStore] keeps nothing, used when persistence is not configured
+#[derive(Debug, Default)]
+pub struct NoopMessageStore;
+
+impl MessageStore for NoopMessageStore {
+    fn save(&self, _message: &UserMessageBroadcastEvent) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn delete(&self, _room: &str, _message_id: &str) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn load_recent(
+        &self,
+        _room: &str,
+        _limit: usize,
+    ) -> anyhow::Result<Vec<UserMessageBroadcastEvent>> {
+        Ok(Vec::new())
+    }
+
+    /// Nothing is kept, so there is nothing before any message
+    fn load_before(
+        &self,
+        _room: &str,
+        _before_message_id: &str,
+        _limit: usize,
+    ) -> anyhow::Result<Vec<UserMessageBroadcastEvent>> {
+        Ok(Vec::new())
+    }
+}
@@ -0,0 +1,356 @@
+use std::{
+    path::Path,
+    sync::mpsc,
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use comms::event::{self, UserMessageBroadcastEvent};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+
+use super::MessageStore;
+
+type LoadReply = mpsc::Sender<anyhow::Result<Vec<UserMessageBroadcastEvent>>>;
+
+/// Operations handed over to the thread owning the connection
+enum Operation {
+    Save(UserMessageBroadcastEvent),
+    Delete {
+        room: String,
+        message_id: String,
+    },
+    LoadRecent {
+        room: String,
+        limit: usize,
+        reply_tx: LoadReply,
+    },
+    LoadBefore {
+        room: String,
+        before_message_id: String,
+        limit: usize,
+        reply_tx: LoadReply,
+    },
+}
+
+/// [SqliteMessageStore] persists the messages to a SQLite database
+///
+/// The connection is owned by a dedicated thread, so the async tasks sending messages are never
+/// blocked by the disk. Operations are processed in the order they are received.
+#[derive(Debug)]
+pub struct SqliteMessageStore {
+    operation_tx: mpsc::Sender<Operation>,
+}
+
+impl SqliteMessageStore {
+    /// Opens or creates the database at the given path
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let connection = Connection::open(path)
+            .with_context(|| format!("could not open the database {}", path.display()))?;
+
+        connection
+            .execute_batch(
+                "CREATE TABLE IF NOT EXISTS messages (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    room TEXT NOT NULL,
+                    message_id TEXT NOT NULL UNIQUE,
+                    user_id TEXT NOT NULL,
+                    display_name TEXT NOT NULL,
+                    content TEXT NOT NULL,
+                    created_at INTEGER NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS messages_room_created_at ON messages (room, created_at);",
+            )
+            .context("could not create the messages table")?;
+
+        let (operation_tx, operation_rx) = mpsc::channel();
+        thread::Builder::new()
+            .name(String::from("sqlite-message-store"))
+            .spawn(move || run(connection, operation_rx))
+            .context("could not spawn the message store thread")?;
+
+        Ok(SqliteMessageStore { operation_tx })
+    }
+
+    fn send(&self, operation: Operation) -> anyhow::Result<()> {
+        self.operation_tx
+            .send(operation)
+            .map_err(|_| anyhow::anyhow!("the message store thread has stopped"))
+    }
+}
+
+impl MessageStore for SqliteMessageStore {
+    fn save(&self, message: &UserMessageBroadcastEvent) -> anyhow::Result<()> {
+        self.send(Operation::Save(message.clone()))
+    }
+
+    fn delete(&self, room: &str, message_id: &str) -> anyhow::Result<()> {
+        self.send(Operation::Delete {
+            room: String::from(room),
+            message_id: String::from(message_id),
+        })
+    }
+
+    fn load_recent(
+        &self,
+        room: &str,
+        limit: usize,
+    ) -> anyhow::Result<Vec<UserMessageBroadcastEvent>> {
+        let (reply_tx, reply_rx) = mpsc::channel();
+
+        self.send(Operation::LoadRecent {
+            room: String::from(room),
+            limit,
+            reply_tx,
+        })?;
+
+        reply_rx
+            .recv()
+            .context("the message store thread has stopped")?
+    }
+
+    fn load_before(
+        &self,
+        room: &str,
+        before_message_id: &str,
+        limit: usize,
+    ) -> anyhow::Result<Vec<UserMessageBroadcastEvent>> {
+        let (reply_tx, reply_rx) = mpsc::channel();
+
+        self.send(Operation::LoadBefore {
+            room: String::from(room),
+            before_message_id: String::from(before_message_id),
+            limit,
+            reply_tx,
+        })?;
+
+        reply_rx
+            .recv()
+            .context("the message store thread has stopped")?
+    }
+}
+
+/// Processes the operations until all the senders are dropped
+fn run(connection: Connection, operation_rx: mpsc::Receiver<Operation>) {
+    for operation in operation_rx {
+        let result = match operation {
+            Operation::Save(message) => save(&connection, &message),
+            Operation::Delete { room, message_id } => delete(&connection, &room, &message_id),
+            Operation::LoadRecent {
+                room,
+                limit,
+                reply_tx,
+            } => {
+                let _ = reply_tx.send(load_recent(&connection, &room, limit));
+                Ok(())
+            }
+            Operation::LoadBefore {
+                room,
+                before_message_id,
+                limit,
+                reply_tx,
+            } => {
+                let _ = reply_tx.send(load_before(&connection, &room, &before_message_id, limit));
+                Ok(())
+            }
+        };
+
+        // a failed write should not take down the server, the message is still delivered to the users
+        if let Err(err) = result {
+            tracing::error!("message store operation failed: {:#}", err);
+        }
+    }
+}
+
+fn save(connection: &Connection, message: &UserMessageBroadcastEvent) -> anyhow::Result<()> {
+    let created_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64;
+
+    connection
+        .execute(
+            "INSERT INTO messages (room, message_id, user_id, display_name, content, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                message.room,
+                message.message_id,
+                message.user_id,
+                message.display_name,
+                message.content,
+                created_at
+            ],
+        )
+        .context("could not save the message")?;
+
+    Ok(())
+}
+
+fn delete(connection: &Connection, room: &str, message_id: &str) -> anyhow::Result<()> {
+    connection
+        .execute(
+            "DELETE FROM messages WHERE room = ?1 AND message_id = ?2",
+            params![room, message_id],
+        )
+        .context("could not delete the message")?;
+
+    Ok(())
+}
+
+fn load_recent(
+    connection: &Connection,
+    room: &str,
+    limit: usize,
+) -> anyhow::Result<Vec<UserMessageBroadcastEvent>> {
+    let mut statement = connection.prepare(
+        "SELECT room, message_id, user_id, display_name, content FROM messages
+        WHERE room = ?1 ORDER BY created_at DESC, id DESC LIMIT ?2",
+    )?;
+
+    let mut messages = statement
+        .query_map(params![room, limit as i64], read_message)?
+        .collect::<Result<Vec<_>, _>>()
+        .context("could not load the messages")?;
+
+    // the most recent messages were queried first, but the history is ordered oldest first
+    messages.reverse();
+
+    Ok(messages)
+}
+
+fn load_before(
+    connection: &Connection,
+    room: &str,
+    before_message_id: &str,
+    limit: usize,
+) -> anyhow::Result<Vec<UserMessageBroadcastEvent>> {
+    // the messages are ordered the same way as the recent ones, the id breaks the ties of the same millisecond
+    let (created_at, id): (i64, i64) = connection
+        .query_row(
+            "SELECT created_at, id FROM messages WHERE room = ?1 AND message_id = ?2",
+            params![room, before_message_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+        .context("could not find the message to load the messages before")?
+        .ok_or_else(|| {
+            event::ErrorReplyEvent::new(
+                event::ErrorCode::MessageNotFound,
+                format!(
+                    "message '{}' is not in the history of room '{}'",
+                    before_message_id, room
+                ),
+            )
+        })?;
+
+    let mut statement = connection.prepare(
+        "SELECT room, message_id, user_id, display_name, content FROM messages
+        WHERE room = ?1 AND (created_at < ?2 OR (created_at = ?2 AND id < ?3))
+        ORDER BY created_at DESC, id DESC LIMIT ?4",
+    )?;
+
+    let mut messages = statement
+        .query_map(params![room, created_at, id, limit as i64], read_message)?
+        .collect::<Result<Vec<_>, _>>()
+        .context("could not load the messages")?;
+
+    messages.reverse();
+
+    Ok(messages)
+}
+
+fn read_message(row: &Row) -> rusqlite::Result<UserMessageBroadcastEvent> {
+    Ok(UserMessageBroadcastEvent {
+        room: row.get(0)?,
+        message_id: row.get(1)?,
+        user_id: row.get(2)?,
+        display_name: row.get(3)?,
+        content: row.get(4)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(room: &str, message_id: &str) -> UserMessageBroadcastEvent {
+        UserMessageBroadcastEvent {
+            room: room.into(),
+            message_id: message_id.into(),
+            user_id: "user-1".into(),
+            display_name: "alice".into(),
+            content: format!("content of {}", message_id),
+        }
+    }
+
+    #[test]
+    fn test_messages_survive_reopening_the_store() {
+        let path = std::env::temp_dir().join(format!("messages-{}.sqlite", nanoid::nanoid!()));
+
+        {
+            let store = SqliteMessageStore::open(&path).unwrap();
+            for message_id in ["1", "2", "3", "4"] {
+                store.save(&message("rust", message_id)).unwrap();
+            }
+            store.save(&message("go", "5")).unwrap();
+            store.delete("rust", "4").unwrap();
+
+            // operations are ordered, so the load sees all the writes above
+            assert_eq!(
+                store.load_recent("rust", 2).unwrap(),
+                vec![message("rust", "2"), message("rust", "3")]
+            );
+        }
+
+        let store = SqliteMessageStore::open(&path).unwrap();
+        assert_eq!(
+            store.load_recent("rust", 10).unwrap(),
+            vec![
+                message("rust", "1"),
+                message("rust", "2"),
+                message("rust", "3")
+            ]
+        );
+        assert_eq!(
+            store.load_recent("go", 10).unwrap(),
+            vec![message("go", "5")]
+        );
+
+        drop(store);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_messages_before_a_message_are_loaded_a_page_at_a_time() {
+        let path = std::env::temp_dir().join(format!("messages-{}.sqlite", nanoid::nanoid!()));
+        let store = SqliteMessageStore::open(&path).unwrap();
+        for message_id in ["1", "2", "3", "4", "5"] {
+            store.save(&message("rust", message_id)).unwrap();
+        }
+        store.save(&message("go", "6")).unwrap();
+
+        assert_eq!(
+            store.load_before("rust", "5", 2).unwrap(),
+            vec![message("rust", "3"), message("rust", "4")]
+        );
+        assert_eq!(
+            store.load_before("rust", "3", 10).unwrap(),
+            vec![message("rust", "1"), message("rust", "2")]
+        );
+        // there is nothing before the oldest message
+        assert!(store.load_before("rust", "1", 10).unwrap().is_empty());
+
+        // the message has to be in the history of the room
+        for (room, message_id) in [("rust", "unknown"), ("rust", "6")] {
+            let err = store
+                .load_before(room, message_id, 10)
+                .unwrap_err()
+                .downcast::<event::ErrorReplyEvent>()
+                .unwrap();
+            assert_eq!(err.code, event::ErrorCode::MessageNotFound);
+        }
+
+        drop(store);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,42 @@
+use comms::event::{RoomParticipationBroadcastEvent, UserMessageBroadcastEvent};
+
+/// [RoomEventObserver] is notified of what happens in the rooms, to hook logging, persistence or moderation
+/// into the server. The notifications are made while the room is locked, so they should return quickly.
+pub trait RoomEventObserver: std::fmt::Debug + Send + Sync {
+    /// A user joined or left a room, including being kicked out of it
+    fn on_participation(&self, _event: &RoomParticipationBroadcastEvent) {}
+
+    /// A user sent a message to a room
+    fn on_message(&self, _event: &UserMessageBroadcastEvent) {}
+}
+
+/// [NoopRoomEventObserver] ignores every event, used when no observer is configured
+#[derive(Debug, Default)]
+pub struct NoopRoomEventObserver;
+
+impl RoomEventObserver for NoopRoomEventObserver {}
+
+/// [TracingRoomEventObserver] logs the events of the rooms
+#[derive(Debug, Default)]
+pub struct TracingRoomEventObserver;
+
+impl RoomEventObserver for TracingRoomEventObserver {
+    fn on_participation(&self, event: &RoomParticipationBroadcastEvent) {
+        tracing::info!(
+            room = %event.room,
+            user_id = %event.user_id,
+            status = ?event.status,
+            reason = ?event.reason,
+            "room participation changed"
+        );
+    }
+
+    fn on_message(&self, event: &UserMessageBroadcastEvent) {
+        tracing::debug!(
+            room = %event.room,
+            user_id = %event.user_id,
+            message_id = %event.message_id,
+            "message sent"
+        );
+    }
+}
@@ -1,11 +1,17 @@
-use std::sync::Arc;
+use std::{collections::HashSet, sync::Arc};
 
-use tokio::sync::Mutex;
+use comms::event::{ErrorCode, ErrorReplyEvent};
 
-use self::room::ChatRoom;
-pub use self::room::{ChatRoomMetadata, SessionAndUserId, UserSessionHandle};
+use crate::{
+    message_store::{MessageStore, NoopMessageStore},
+    room_event_observer::{NoopRoomEventObserver, RoomEventObserver},
+};
 
-pub use self::room_manager::RoomManager;
+pub use self::room::{ChatRoomMetadata, RoomJoin, SessionAndUserId, UserSessionHandle};
+
+pub use self::room_manager::{room_not_found, RoomManager};
+
+use self::room_manager::validate_room_metadata;
 
 mod room;
 #[allow(clippy::module_inception)]
@@ -13,35 +19,64 @@ mod room_manager;
 
 #[derive(Debug)]
 pub struct RoomManagerBuilder {
-    chat_rooms: Vec<(ChatRoomMetadata, Arc<Mutex<room::ChatRoom>>)>,
+    chat_room_metadatas: Vec<ChatRoomMetadata>,
+    message_store: Arc<dyn MessageStore>,
+    observer: Arc<dyn RoomEventObserver>,
 }
 
 impl RoomManagerBuilder {
     pub fn new() -> Self {
         RoomManagerBuilder {
-            chat_rooms: Vec::new(),
+            chat_room_metadatas: Vec::new(),
+            message_store: Arc::new(NoopMessageStore),
+            observer: Arc::new(NoopRoomEventObserver),
         }
     }
 
     /// Add a room to the room manager
-    /// Will panic if a room with the same name already exists
+    /// The room is validated when the room manager is built
     pub fn create_room(mut self, metadata: ChatRoomMetadata) -> Self {
-        let chat_room = Arc::new(Mutex::new(ChatRoom::new(metadata.clone())));
-
-        if self
-            .chat_rooms
-            .iter()
-            .any(|(m, _)| m.name.eq(&metadata.name))
-        {
-            panic!("room with the same name already exists");
-        }
+        self.chat_room_metadatas.push(metadata);
+
+        self
+    }
+
+    /// Persist the messages of the rooms with the given store, nothing is persisted by default
+    pub fn message_store(mut self, message_store: Arc<dyn MessageStore>) -> Self {
+        self.message_store = message_store;
+
+        self
+    }
 
-        self.chat_rooms.push((metadata, chat_room));
+    /// Notify the given observer of the events of the rooms, nothing is notified by default
+    pub fn observer(mut self, observer: Arc<dyn RoomEventObserver>) -> Self {
+        self.observer = observer;
 
         self
     }
 
-    pub fn build(self) -> RoomManager {
-        RoomManager::new(self.chat_rooms)
+    /// Build the room manager, the history of the rooms is loaded from the message store
+    /// Fails if a room has an invalid name, or the same name as another room
+    pub fn build(self) -> anyhow::Result<RoomManager> {
+        let mut names = HashSet::new();
+        for metadata in &self.chat_room_metadatas {
+            validate_room_metadata(metadata)?;
+
+            if !names.insert(metadata.name.as_str()) {
+                return Err(ErrorReplyEvent::new(
+                    ErrorCode::RoomAlreadyExists,
+                    format!("room '{}' is configured more than once", metadata.name),
+                )
+                .into());
+            }
+        }
+
+        RoomManager::new(self.chat_room_metadatas, self.message_store, self.observer)
+    }
+}
+
+impl Default for RoomManagerBuilder {
+    fn default() -> Self {
+        Self::new()
     }
 }
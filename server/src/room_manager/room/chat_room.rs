@@ -1,88 +1,731 @@
-use comms::event::{self, Event};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use comms::event::{self, SequencedEvent};
 use serde::{Deserialize, Serialize};
 use tokio::sync::broadcast;
 
+use crate::{
+    message_store::MessageStore, room_event_observer::RoomEventObserver, sequencer::SequencedSender,
+};
+
 use super::{
-    user_registry::UserRegistry, user_session_handle::UserSessionHandle, SessionAndUserId,
+    history::History, slow_mode::SlowMode, user_registry::UserRegistry,
+    user_session_handle::UserSessionHandle, SessionAndUserId,
 };
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 /// [ChatRoomMetadata] holds the metadata that identifies a chat room
 pub struct ChatRoomMetadata {
     pub name: String,
     pub description: String,
+    /// The maximum number of unique users allowed in the room, unlimited if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capacity: Option<usize>,
+    /// Ids of the users who can moderate the room
+    /// If empty, the first user to join the room moderates it until they leave
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub moderators: Vec<String>,
 }
 
 const BROADCAST_CHANNEL_CAPACITY: usize = 100;
 
+/// What a user is handed out when they join a room
+#[derive(Debug)]
+pub struct RoomJoin {
+    /// Receives the events of the room which are not in the history
+    pub broadcast_rx: broadcast::Receiver<SequencedEvent>,
+    /// Lets the user interact with the room
+    pub handle: UserSessionHandle,
+    /// The users currently in the room, including the user who has joined
+    pub user_ids: Vec<String>,
+    /// The display names of the users currently in the room, keyed by user id
+    pub display_names: HashMap<String, String>,
+    /// The most recent messages of the room, oldest first
+    pub history: Vec<event::UserMessageBroadcastEvent>,
+    /// When the users have joined the room, as unix timestamps in seconds keyed by user id
+    pub joined_at: HashMap<String, u64>,
+}
+
 #[derive(Debug)]
 /// [ChatRoom] handles the participants of a chat room and the primary broadcast channel
 /// A [UserSessionHandle] is handed out to a user when they join the room
 pub struct ChatRoom {
     metadata: ChatRoomMetadata,
-    broadcast_tx: broadcast::Sender<event::Event>,
+    broadcast_tx: SequencedSender,
     user_registry: UserRegistry,
+    /// The most recent messages of the room, recorded by the handles as they send and delete messages
+    history: Arc<Mutex<History>>,
+    /// The first user to join the room, who moderates it when there are no configured moderators
+    first_joiner: Option<String>,
+    /// Where the messages of the room are persisted, handed out to the users with their handles
+    message_store: Arc<dyn MessageStore>,
+    /// Notified of the events of the room, handed out to the users with their handles
+    observer: Arc<dyn RoomEventObserver>,
+    /// Throttles the messages of each user when set by a moderator, handed out to the users with their handles
+    slow_mode: Arc<Mutex<SlowMode>>,
 }
 
 impl ChatRoom {
-    pub fn new(metadata: ChatRoomMetadata) -> Self {
-        let (broadcast_tx, _) = broadcast::channel(BROADCAST_CHANNEL_CAPACITY);
+    /// Create a room with the recent messages previously loaded from the given message store
+    pub fn new(
+        metadata: ChatRoomMetadata,
+        history: Vec<event::UserMessageBroadcastEvent>,
+        message_store: Arc<dyn MessageStore>,
+        observer: Arc<dyn RoomEventObserver>,
+    ) -> Self {
+        let (broadcast_tx, _) = SequencedSender::new(BROADCAST_CHANNEL_CAPACITY);
 
         ChatRoom {
             metadata,
             broadcast_tx,
             user_registry: UserRegistry::new(),
+            history: Arc::new(Mutex::new(History::new(history))),
+            first_joiner: None,
+            message_store,
+            observer,
+            slow_mode: Arc::new(Mutex::new(SlowMode::default())),
         }
     }
 
+    /// Whether the given user can moderate the room
+    pub fn is_moderator(&self, user_id: &str) -> bool {
+        self.metadata.moderators.iter().any(|id| id == user_id)
+            || self.first_joiner.as_deref() == Some(user_id)
+    }
+
+    /// The name of the room, as it was created
+    pub fn name(&self) -> &str {
+        &self.metadata.name
+    }
+
+    /// How many unique users are in the room, a user with multiple sessions is counted once
+    pub fn participant_count(&self) -> usize {
+        self.user_registry.len()
+    }
+
     pub fn get_unique_user_ids(&self) -> Vec<String> {
         self.user_registry.get_unique_user_ids()
     }
 
+    pub fn get_display_names(&self) -> HashMap<String, String> {
+        self.user_registry.get_display_names()
+    }
+
+    /// When the users have joined the room, as unix timestamps in seconds keyed by user id
+    pub fn get_joined_at(&self) -> HashMap<String, u64> {
+        self.user_registry.get_joined_at()
+    }
+
     /// Add a participant to the room and broadcast that they joined
+    /// Fails if the room is already at capacity
     ///
-    /// # Returns
-    ///
-    /// - A broadcast receiver for the user to receive messages from the room
-    /// - A [UserSessionHandle] for the user to be able to interact with the room
-    pub fn join(
-        &mut self,
-        session_and_user_id: &SessionAndUserId,
-    ) -> (broadcast::Receiver<Event>, UserSessionHandle) {
-        let broadcast_tx = self.broadcast_tx.clone();
-        let broadcast_rx = broadcast_tx.subscribe();
+    /// The user receives the recent messages of the room along with the broadcast receiver,
+    /// each message sent meanwhile is either in the history or in the receiver, never in both
+    pub fn join(&mut self, session_and_user_id: &SessionAndUserId) -> anyhow::Result<RoomJoin> {
+        // users who are already in the room with another session do not take up more space
+        if let Some(capacity) = self.metadata.capacity {
+            if self.user_registry.len() >= capacity
+                && !self.user_registry.contains(&session_and_user_id.user_id)
+            {
+                return Err(event::ErrorReplyEvent::new(
+                    event::ErrorCode::RoomFull,
+                    format!(
+                        "room '{}' is full, it can have at most {} users",
+                        self.metadata.name, capacity
+                    ),
+                )
+                .into());
+            }
+        }
+
+        // the messages are broadcasted while the history is locked, see [History]
+        let (broadcast_rx, history) = {
+            let history = self.history.lock().unwrap();
+            (self.broadcast_tx.subscribe(), history.messages())
+        };
         let user_session_handle = UserSessionHandle::new(
             self.metadata.name.clone(),
-            broadcast_tx,
+            self.broadcast_tx.clone(),
             session_and_user_id.clone(),
+            Arc::clone(&self.message_store),
+            Arc::clone(&self.observer),
+            Arc::clone(&self.slow_mode),
+            Arc::clone(&self.history),
         );
 
         // If the user is new e.g. they do not have another session with same user id,
         // broadcast that they joined to all users
         if self.user_registry.insert(&user_session_handle) {
-            let _ = self.broadcast_tx.send(event::Event::RoomParticipation(
-                event::RoomParticipationBroacastEvent {
-                    user_id: session_and_user_id.user_id.clone(),
-                    room: self.metadata.name.clone(),
-                    status: event::RoomParticipationStatus::Joined,
-                },
-            ));
+            if self.metadata.moderators.is_empty() && self.first_joiner.is_none() {
+                self.first_joiner = Some(session_and_user_id.user_id.clone());
+            }
+
+            self.broadcast_participation(event::RoomParticipationBroadcastEvent {
+                user_id: session_and_user_id.user_id.clone(),
+                display_name: session_and_user_id.display_name.clone(),
+                room: self.metadata.name.clone(),
+                status: event::RoomParticipationStatus::Joined,
+                reason: None,
+            });
+            self.broadcast_users_snapshot();
         }
 
-        (broadcast_rx, user_session_handle)
+        Ok(RoomJoin {
+            broadcast_rx,
+            handle: user_session_handle,
+            user_ids: self.get_unique_user_ids(),
+            display_names: self.get_display_names(),
+            history,
+            joined_at: self.get_joined_at(),
+        })
     }
 
-    /// Remove a participant from the room and broadcast that they left
+    /// Remove a participant from the room and broadcast that they left for the given reason
     /// Consume the [UserSessionHandle] to drop it
-    pub fn leave(&mut self, user_session_handle: UserSessionHandle) {
+    pub fn leave(
+        &mut self,
+        user_session_handle: UserSessionHandle,
+        reason: event::DepartureReason,
+    ) {
         if self.user_registry.remove(&user_session_handle) {
-            let _ = self.broadcast_tx.send(event::Event::RoomParticipation(
-                event::RoomParticipationBroacastEvent {
+            self.release_first_joiner(user_session_handle.user_id());
+
+            self.broadcast_participation(event::RoomParticipationBroadcastEvent {
+                user_id: String::from(user_session_handle.user_id()),
+                display_name: String::from(user_session_handle.display_name()),
+                room: self.metadata.name.clone(),
+                status: event::RoomParticipationStatus::Left,
+                reason: Some(reason),
+            });
+            self.broadcast_users_snapshot();
+        }
+    }
+
+    /// Forcefully remove all the sessions of a user from the room, on behalf of a moderator
+    /// Broadcasts that the user left, and lets the sessions of the user know that they were kicked
+    pub fn kick(&mut self, moderator_id: &str, user_id: &str) -> anyhow::Result<()> {
+        if !self.is_moderator(moderator_id) {
+            return Err(event::ErrorReplyEvent::new(
+                event::ErrorCode::NotModerator,
+                format!(
+                    "only moderators can kick users from room '{}'",
+                    self.metadata.name
+                ),
+            )
+            .into());
+        }
+
+        let display_name = self.user_registry.remove_user(user_id).ok_or_else(|| {
+            event::ErrorReplyEvent::new(
+                event::ErrorCode::UserNotInRoom,
+                format!("user '{}' is not in room '{}'", user_id, self.metadata.name),
+            )
+        })?;
+        self.release_first_joiner(user_id);
+
+        self.broadcast_participation(event::RoomParticipationBroadcastEvent {
+            user_id: String::from(user_id),
+            display_name,
+            room: self.metadata.name.clone(),
+            status: event::RoomParticipationStatus::Left,
+            reason: Some(event::DepartureReason::Kicked),
+        });
+        self.broadcast_users_snapshot();
+        // the kicked sessions still hold their revoked handles, they drop them once they receive this event
+        // or on their next command, if they have missed it
+        let _ = self
+            .broadcast_tx
+            .send(event::Event::UserKicked(event::UserKickedBroadcastEvent {
+                room: self.metadata.name.clone(),
+                user_id: String::from(user_id),
+                moderator_id: String::from(moderator_id),
+            }));
+
+        Ok(())
+    }
+
+    /// Set the minimum interval between the messages of each user, on behalf of a moderator
+    /// Zero seconds turns the slow mode off, the change is broadcasted to the room
+    pub fn set_slow_mode(&mut self, moderator_id: &str, seconds: u64) -> anyhow::Result<()> {
+        if !self.is_moderator(moderator_id) {
+            return Err(event::ErrorReplyEvent::new(
+                event::ErrorCode::NotModerator,
+                format!(
+                    "only moderators can set the slow mode of room '{}'",
+                    self.metadata.name
+                ),
+            )
+            .into());
+        }
+
+        self.slow_mode
+            .lock()
+            .unwrap()
+            .set_interval(Duration::from_secs(seconds));
+
+        let _ = self.broadcast_tx.send(event::Event::SlowModeChanged(
+            event::SlowModeChangedBroadcastEvent {
+                room: self.metadata.name.clone(),
+                moderator_id: String::from(moderator_id),
+                seconds,
+            },
+        ));
+
+        Ok(())
+    }
+
+    /// Let the observer and the participants know that a user joined or left the room
+    fn broadcast_participation(&self, event: event::RoomParticipationBroadcastEvent) {
+        self.observer.on_participation(&event);
+        let _ = self
+            .broadcast_tx
+            .send(event::Event::RoomParticipation(event));
+    }
+
+    /// Lets the participants reconcile their user list with the users actually in the room
+    fn broadcast_users_snapshot(&self) {
+        let _ = self.broadcast_tx.send(event::Event::RoomUsersSnapshot(
+            event::RoomUsersSnapshotBroadcastEvent {
+                room: self.metadata.name.clone(),
+                users: self.user_registry.get_unique_user_ids(),
+            },
+        ));
+    }
+
+    /// The first joiner no longer moderates the room once they are gone
+    fn release_first_joiner(&mut self, user_id: &str) {
+        if self.first_joiner.as_deref() == Some(user_id) {
+            self.first_joiner = None;
+        }
+    }
+
+    /// Update the display name of a participant and broadcast the change
+    pub fn change_display_name(&mut self, user_session_handle: &UserSessionHandle) {
+        if self.user_registry.set_display_name(user_session_handle) {
+            let _ = self.broadcast_tx.send(event::Event::DisplayNameChanged(
+                event::DisplayNameChangedBroadcastEvent {
                     user_id: String::from(user_session_handle.user_id()),
-                    room: self.metadata.name.clone(),
-                    status: event::RoomParticipationStatus::Left,
+                    display_name: String::from(user_session_handle.display_name()),
                 },
             ));
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use comms::event::Event;
+
+    use crate::{message_store::NoopMessageStore, room_event_observer::NoopRoomEventObserver};
+
+    use super::{super::history::HISTORY_CAPACITY, *};
+
+    fn session_and_user_id(id: &str) -> SessionAndUserId {
+        SessionAndUserId {
+            session_id: id.into(),
+            user_id: id.into(),
+            display_name: id.into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_history_keeps_the_most_recent_messages() {
+        let mut chat_room = ChatRoom::new(
+            ChatRoomMetadata {
+                name: "rust".into(),
+                description: "Rust".into(),
+                ..Default::default()
+            },
+            Vec::new(),
+            Arc::new(NoopMessageStore),
+            Arc::new(NoopRoomEventObserver),
+        );
+        let mut handle = chat_room.join(&session_and_user_id("a")).unwrap().handle;
+
+        for i in 0..BROADCAST_CHANNEL_CAPACITY * 2 {
+            handle.send_message(format!("message-{}", i)).await.unwrap();
+        }
+        let history = chat_room.join(&session_and_user_id("b")).unwrap().history;
+
+        assert_eq!(history.len(), HISTORY_CAPACITY);
+        assert_eq!(
+            history.first().unwrap().content,
+            format!(
+                "message-{}",
+                BROADCAST_CHANNEL_CAPACITY * 2 - HISTORY_CAPACITY
+            )
+        );
+        assert_eq!(
+            history.last().unwrap().content,
+            format!("message-{}", BROADCAST_CHANNEL_CAPACITY * 2 - 1)
+        );
+
+        // deleted messages are not replayed to the joining users
+        let deleted_id = history.last().unwrap().message_id.clone();
+        handle.delete_message(&deleted_id).await.unwrap();
+
+        assert!(chat_room
+            .join(&session_and_user_id("c"))
+            .unwrap()
+            .history
+            .iter()
+            .all(|message| message.message_id != deleted_id));
+    }
+
+    #[tokio::test]
+    async fn test_history_is_kept_when_the_room_is_busy() {
+        let mut chat_room = ChatRoom::new(
+            ChatRoomMetadata {
+                name: "rust".into(),
+                description: "Rust".into(),
+                ..Default::default()
+            },
+            Vec::new(),
+            Arc::new(NoopMessageStore),
+            Arc::new(NoopRoomEventObserver),
+        );
+        // a user who does not keep up with the room
+        let RoomJoin {
+            broadcast_rx: _lagging_rx,
+            mut handle,
+            ..
+        } = chat_room.join(&session_and_user_id("a")).unwrap();
+
+        // more events than the broadcast channel retains are sent after the message
+        handle.send_message("hello".into()).await.unwrap();
+        for _ in 0..BROADCAST_CHANNEL_CAPACITY * 2 {
+            handle.set_presence(event::PresenceStatus::Away).unwrap();
+        }
+
+        let history = chat_room
+            .join(&session_and_user_id("b"))
+            .unwrap()
+            .history
+            .into_iter()
+            .map(|message| message.content)
+            .collect::<Vec<_>>();
+        assert_eq!(history, vec![String::from("hello")]);
+    }
+
+    #[test]
+    fn test_join_is_rejected_when_room_is_full() {
+        let mut chat_room = ChatRoom::new(
+            ChatRoomMetadata {
+                name: "rust".into(),
+                description: "Rust".into(),
+                capacity: Some(2),
+                ..Default::default()
+            },
+            Vec::new(),
+            Arc::new(NoopMessageStore),
+            Arc::new(NoopRoomEventObserver),
+        );
+
+        assert!(chat_room.join(&session_and_user_id("a")).is_ok());
+        assert!(chat_room.join(&session_and_user_id("b")).is_ok());
+
+        let err = chat_room
+            .join(&session_and_user_id("c"))
+            .unwrap_err()
+            .downcast::<event::ErrorReplyEvent>()
+            .unwrap();
+        assert_eq!(err.code, event::ErrorCode::RoomFull);
+        assert_eq!(chat_room.get_unique_user_ids().len(), 2);
+
+        // another session of a user already in the room is still allowed
+        assert!(chat_room
+            .join(&SessionAndUserId {
+                session_id: "a-2".into(),
+                user_id: "a".into(),
+                display_name: "a".into(),
+            })
+            .is_ok());
+    }
+
+    #[test]
+    fn test_only_moderators_can_kick() {
+        let mut chat_room = ChatRoom::new(
+            ChatRoomMetadata {
+                name: "rust".into(),
+                description: "Rust".into(),
+                ..Default::default()
+            },
+            Vec::new(),
+            Arc::new(NoopMessageStore),
+            Arc::new(NoopRoomEventObserver),
+        );
+        let mut broadcast_rx = chat_room
+            .join(&session_and_user_id("a"))
+            .unwrap()
+            .broadcast_rx;
+        let _ = chat_room.join(&session_and_user_id("b")).unwrap();
+
+        // the first joiner is the moderator
+        assert!(chat_room.is_moderator("a"));
+        assert!(!chat_room.is_moderator("b"));
+
+        let err = chat_room
+            .kick("b", "a")
+            .unwrap_err()
+            .downcast::<event::ErrorReplyEvent>()
+            .unwrap();
+        assert_eq!(err.code, event::ErrorCode::NotModerator);
+
+        chat_room.kick("a", "b").unwrap();
+        assert_eq!(chat_room.get_unique_user_ids(), vec![String::from("a")]);
+
+        let events = std::iter::from_fn(|| {
+            broadcast_rx
+                .try_recv()
+                .ok()
+                .map(|sequenced| sequenced.event)
+        })
+        .collect::<Vec<_>>();
+        assert!(
+            events.contains(&Event::UserKicked(event::UserKickedBroadcastEvent {
+                room: "rust".into(),
+                user_id: "b".into(),
+                moderator_id: "a".into(),
+            }))
+        );
+
+        let err = chat_room
+            .kick("a", "b")
+            .unwrap_err()
+            .downcast::<event::ErrorReplyEvent>()
+            .unwrap();
+        assert_eq!(err.code, event::ErrorCode::UserNotInRoom);
+    }
+
+    #[tokio::test]
+    async fn test_kicked_user_can_not_use_their_handle_even_if_lagging() {
+        let mut chat_room = ChatRoom::new(
+            ChatRoomMetadata {
+                name: "rust".into(),
+                description: "Rust".into(),
+                ..Default::default()
+            },
+            Vec::new(),
+            Arc::new(NoopMessageStore),
+            Arc::new(NoopRoomEventObserver),
+        );
+        let moderator = chat_room.join(&session_and_user_id("a")).unwrap().handle;
+        let RoomJoin {
+            mut broadcast_rx,
+            mut handle,
+            ..
+        } = chat_room.join(&session_and_user_id("b")).unwrap();
+        let message_id = handle.send_message("hello".into()).await.unwrap();
+
+        // the kicked user falls behind the room before they see that they were kicked
+        chat_room.kick("a", "b").unwrap();
+        for _ in 0..BROADCAST_CHANNEL_CAPACITY * 2 {
+            moderator.set_presence(event::PresenceStatus::Away).unwrap();
+        }
+        assert!(matches!(
+            broadcast_rx.try_recv(),
+            Err(broadcast::error::TryRecvError::Lagged(_))
+        ));
+        assert!(std::iter::from_fn(|| broadcast_rx.try_recv().ok())
+            .all(|sequenced| !matches!(sequenced.event, Event::UserKicked(_))));
+
+        assert!(handle.is_revoked());
+        let err = handle
+            .send_message("still here?".into())
+            .await
+            .unwrap_err()
+            .downcast::<event::ErrorReplyEvent>()
+            .unwrap();
+        assert_eq!(err.code, event::ErrorCode::NotInRoom);
+        let err = handle
+            .delete_message(&message_id)
+            .await
+            .unwrap_err()
+            .downcast::<event::ErrorReplyEvent>()
+            .unwrap();
+        assert_eq!(err.code, event::ErrorCode::NotInRoom);
+
+        // the other users keep their handles
+        assert!(!moderator.is_revoked());
+    }
+
+    #[test]
+    fn test_join_times_are_kept_until_the_user_leaves() {
+        let mut chat_room = ChatRoom::new(
+            ChatRoomMetadata {
+                name: "rust".into(),
+                description: "Rust".into(),
+                ..Default::default()
+            },
+            Vec::new(),
+            Arc::new(NoopMessageStore),
+            Arc::new(NoopRoomEventObserver),
+        );
+        let first_handle = chat_room.join(&session_and_user_id("a")).unwrap().handle;
+        let joined_at = chat_room.get_joined_at()["a"];
+        assert!(joined_at > 0);
+
+        // another session of the user does not reset their join time
+        let second_handle = chat_room
+            .join(&SessionAndUserId {
+                session_id: "a-2".into(),
+                user_id: "a".into(),
+                display_name: "a".into(),
+            })
+            .unwrap()
+            .handle;
+        chat_room.leave(first_handle, event::DepartureReason::Left);
+        assert_eq!(chat_room.get_joined_at()["a"], joined_at);
+
+        chat_room.leave(second_handle, event::DepartureReason::Left);
+        assert!(chat_room.get_joined_at().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_only_moderators_can_set_the_slow_mode() {
+        let mut chat_room = ChatRoom::new(
+            ChatRoomMetadata {
+                name: "rust".into(),
+                description: "Rust".into(),
+                ..Default::default()
+            },
+            Vec::new(),
+            Arc::new(NoopMessageStore),
+            Arc::new(NoopRoomEventObserver),
+        );
+        let mut broadcast_rx = chat_room
+            .join(&session_and_user_id("a"))
+            .unwrap()
+            .broadcast_rx;
+        let mut handle = chat_room.join(&session_and_user_id("b")).unwrap().handle;
+
+        let err = chat_room
+            .set_slow_mode("b", 30)
+            .unwrap_err()
+            .downcast::<event::ErrorReplyEvent>()
+            .unwrap();
+        assert_eq!(err.code, event::ErrorCode::NotModerator);
+
+        chat_room.set_slow_mode("a", 30).unwrap();
+        handle.send_message("first".into()).await.unwrap();
+        let err = handle
+            .send_message("second".into())
+            .await
+            .unwrap_err()
+            .downcast::<event::ErrorReplyEvent>()
+            .unwrap();
+        assert_eq!(err.code, event::ErrorCode::SlowMode);
+
+        let events = std::iter::from_fn(|| {
+            broadcast_rx
+                .try_recv()
+                .ok()
+                .map(|sequenced| sequenced.event)
+        })
+        .collect::<Vec<_>>();
+        assert!(events.contains(&Event::SlowModeChanged(
+            event::SlowModeChangedBroadcastEvent {
+                room: "rust".into(),
+                moderator_id: "a".into(),
+                seconds: 30,
+            }
+        )));
+
+        // turning it off lets the user post again
+        chat_room.set_slow_mode("a", 0).unwrap();
+        assert!(handle.send_message("second".into()).await.is_ok());
+    }
+
+    #[test]
+    fn test_users_snapshot_is_broadcasted_on_participation_changes() {
+        let mut chat_room = ChatRoom::new(
+            ChatRoomMetadata {
+                name: "rust".into(),
+                description: "Rust".into(),
+                ..Default::default()
+            },
+            Vec::new(),
+            Arc::new(NoopMessageStore),
+            Arc::new(NoopRoomEventObserver),
+        );
+        let mut broadcast_rx = chat_room
+            .join(&session_and_user_id("a"))
+            .unwrap()
+            .broadcast_rx;
+        let handle = chat_room.join(&session_and_user_id("b")).unwrap().handle;
+        chat_room.leave(handle, event::DepartureReason::Left);
+
+        let snapshots = std::iter::from_fn(|| {
+            broadcast_rx
+                .try_recv()
+                .ok()
+                .map(|sequenced| sequenced.event)
+        })
+        .filter_map(|event| match event {
+            Event::RoomUsersSnapshot(snapshot) => {
+                let mut users = snapshot.users;
+                users.sort();
+                Some(users)
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+        assert_eq!(
+            snapshots,
+            vec![
+                vec![String::from("a")],
+                vec![String::from("a"), String::from("b")],
+                vec![String::from("a")],
+            ]
+        );
+    }
+
+    #[derive(Debug, Default)]
+    struct RecordingObserver(std::sync::Mutex<Vec<String>>);
+
+    impl RoomEventObserver for RecordingObserver {
+        fn on_participation(&self, event: &event::RoomParticipationBroadcastEvent) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("{:?} {}", event.status, event.user_id));
+        }
+
+        fn on_message(&self, event: &event::UserMessageBroadcastEvent) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("{}: {}", event.user_id, event.content));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_observer_is_notified_of_participation_and_messages() {
+        let observer = Arc::new(RecordingObserver::default());
+        let mut chat_room = ChatRoom::new(
+            ChatRoomMetadata {
+                name: "rust".into(),
+                description: "Rust".into(),
+                ..Default::default()
+            },
+            Vec::new(),
+            Arc::new(NoopMessageStore),
+            Arc::clone(&observer) as Arc<dyn RoomEventObserver>,
+        );
+        let mut handle = chat_room.join(&session_and_user_id("a")).unwrap().handle;
+        let _ = chat_room.join(&session_and_user_id("b")).unwrap();
+        handle.send_message("hi".into()).await.unwrap();
+        chat_room.kick("a", "b").unwrap();
+        chat_room.leave(handle, event::DepartureReason::Left);
+
+        assert_eq!(
+            *observer.0.lock().unwrap(),
+            vec!["Joined a", "Joined b", "a: hi", "Left b", "Left a"]
+        );
+    }
+}
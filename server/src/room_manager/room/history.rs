@@ -0,0 +1,46 @@
+use std::collections::VecDeque;
+
+use comms::event::UserMessageBroadcastEvent;
+
+/// How many of the most recent messages are kept to be sent to the joining users
+pub const HISTORY_CAPACITY: usize = 50;
+
+/// [History] keeps the most recent messages of a room, oldest first
+///
+/// It is shared by the room and the handles of the room. The handles broadcast their messages
+/// while holding its lock, and the room subscribes the joining users while holding it,
+/// so a joining user receives each message either in the history or from the broadcast channel.
+#[derive(Debug, Default)]
+pub struct History {
+    messages: VecDeque<UserMessageBroadcastEvent>,
+}
+
+impl History {
+    /// Create a history with the given messages, only the most recent ones are kept
+    pub fn new(messages: Vec<UserMessageBroadcastEvent>) -> Self {
+        let mut history = History::default();
+        messages
+            .into_iter()
+            .for_each(|message| history.push(message));
+
+        history
+    }
+
+    /// Record a message, dropping the oldest one if the history is full
+    pub fn push(&mut self, message: UserMessageBroadcastEvent) {
+        if self.messages.len() == HISTORY_CAPACITY {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(message);
+    }
+
+    /// Forget a deleted message, so it is not replayed to the joining users
+    pub fn remove(&mut self, message_id: &str) {
+        self.messages
+            .retain(|message| message.message_id != message_id);
+    }
+
+    pub fn messages(&self) -> Vec<UserMessageBroadcastEvent> {
+        self.messages.iter().cloned().collect()
+    }
+}
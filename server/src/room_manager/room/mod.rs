@@ -1,6 +1,9 @@
 mod chat_room;
+mod history;
+mod slow_mode;
 mod user_registry;
 mod user_session_handle;
 
-pub use self::chat_room::{ChatRoom, ChatRoomMetadata};
+pub use self::chat_room::{ChatRoom, ChatRoomMetadata, RoomJoin};
+pub use self::history::HISTORY_CAPACITY;
 pub use self::user_session_handle::{SessionAndUserId, UserSessionHandle};
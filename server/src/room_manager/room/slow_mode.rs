@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+
+use tokio::time::{Duration, Instant};
+
+/// [SlowMode] enforces a minimum interval between the messages of the same user in a room
+///
+/// It is off until a moderator sets an interval, and is independent of the rate limit of the sessions.
+/// A user with multiple sessions shares the same interval across them.
+#[derive(Debug, Default)]
+pub struct SlowMode {
+    interval: Option<Duration>,
+    last_message_at: HashMap<String, Instant>,
+}
+
+impl SlowMode {
+    /// Sets the minimum interval between the messages of a user, a zero interval turns the slow mode off
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = Some(interval).filter(|interval| !interval.is_zero());
+        self.last_message_at.clear();
+    }
+
+    /// Records a message of the user if they are allowed to post now
+    /// Otherwise returns how long they should wait before posting again
+    pub fn try_post(&mut self, user_id: &str) -> Result<(), Duration> {
+        let Some(interval) = self.interval else {
+            return Ok(());
+        };
+
+        let now = Instant::now();
+        if let Some(last_message_at) = self.last_message_at.get(user_id) {
+            let elapsed = now.duration_since(*last_message_at);
+            if elapsed < interval {
+                return Err(interval - elapsed);
+            }
+        }
+
+        // the users who are outside of the window can post anyway, no need to remember them
+        self.last_message_at
+            .retain(|_, last_message_at| now.duration_since(*last_message_at) < interval);
+        self.last_message_at.insert(String::from(user_id), now);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_slow_mode_throttles_each_user_within_the_window() {
+        let mut slow_mode = SlowMode::default();
+
+        // off by default
+        assert!((0..5).all(|_| slow_mode.try_post("a").is_ok()));
+
+        slow_mode.set_interval(Duration::from_secs(10));
+        assert!(slow_mode.try_post("a").is_ok());
+        assert_eq!(slow_mode.try_post("a"), Err(Duration::from_secs(10)));
+        // the other users have their own window
+        assert!(slow_mode.try_post("b").is_ok());
+
+        tokio::time::advance(Duration::from_secs(4)).await;
+        assert_eq!(slow_mode.try_post("a"), Err(Duration::from_secs(6)));
+
+        tokio::time::advance(Duration::from_secs(6)).await;
+        assert!(slow_mode.try_post("a").is_ok());
+        assert!(slow_mode.try_post("b").is_ok());
+
+        slow_mode.set_interval(Duration::ZERO);
+        assert!((0..5).all(|_| slow_mode.try_post("a").is_ok()));
+    }
+}
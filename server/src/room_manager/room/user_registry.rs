@@ -1,11 +1,22 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use super::user_session_handle::UserSessionHandle;
 
 #[derive(Debug)]
 pub struct UserRegistry {
-    user_id_to_sessions: HashMap<String, HashSet<String>>,
+    /// The sessions of each user, with the flags that revoke their handles when the user is kicked
+    user_id_to_sessions: HashMap<String, HashMap<String, Arc<AtomicBool>>>,
     user_ids: HashSet<String>,
+    user_id_to_display_name: HashMap<String, String>,
+    /// When the users have joined the room, as unix timestamps in seconds
+    user_id_to_joined_at: HashMap<String, u64>,
 }
 
 /// [UserRegistry] is a smart container for keeping track of which unique list of users are in a room
@@ -16,24 +27,41 @@ impl UserRegistry {
         UserRegistry {
             user_id_to_sessions: HashMap::new(),
             user_ids: HashSet::new(),
+            user_id_to_display_name: HashMap::new(),
+            user_id_to_joined_at: HashMap::new(),
         }
     }
 
+    /// The number of unique users in the room
+    pub fn len(&self) -> usize {
+        self.user_ids.len()
+    }
+
+    pub fn contains(&self, user_id: &str) -> bool {
+        self.user_ids.contains(user_id)
+    }
+
     /// Add a user to the room, returns true if the user is a new user
     pub fn insert(&mut self, user_session_handle: &UserSessionHandle) -> bool {
         let user_id = String::from(user_session_handle.user_id());
         let session_id = String::from(user_session_handle.session_id());
 
-        let sessions = self
-            .user_id_to_sessions
-            .entry(user_id.clone())
-            .or_insert_with(HashSet::new);
+        let sessions = self.user_id_to_sessions.entry(user_id.clone()).or_default();
 
-        sessions.insert(session_id);
+        sessions.insert(session_id, user_session_handle.revoked_flag());
 
         let is_new_user = sessions.len() == 1;
 
         if is_new_user {
+            self.user_id_to_display_name.insert(
+                user_id.clone(),
+                String::from(user_session_handle.display_name()),
+            );
+            let joined_at = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            self.user_id_to_joined_at.insert(user_id.clone(), joined_at);
             self.user_ids.insert(user_id);
         }
 
@@ -54,6 +82,8 @@ impl UserRegistry {
             if sessions.is_empty() {
                 self.user_id_to_sessions.remove(&user_id);
                 self.user_ids.remove(&user_id);
+                self.user_id_to_display_name.remove(&user_id);
+                self.user_id_to_joined_at.remove(&user_id);
 
                 true
             } else {
@@ -64,7 +94,43 @@ impl UserRegistry {
         }
     }
 
+    /// Removes all the sessions of a user from the participant list and revokes their handles
+    /// Returns the display name of the user, or None if the user does not exist
+    pub fn remove_user(&mut self, user_id: &str) -> Option<String> {
+        self.user_id_to_sessions
+            .remove(user_id)?
+            .values()
+            .for_each(|revoked| revoked.store(true, Ordering::Release));
+        self.user_ids.remove(user_id);
+        self.user_id_to_joined_at.remove(user_id);
+
+        self.user_id_to_display_name.remove(user_id)
+    }
+
     pub fn get_unique_user_ids(&self) -> Vec<String> {
         self.user_ids.iter().cloned().collect()
     }
+
+    /// Updates the display name of the user, returns false if the user is not in the room
+    pub fn set_display_name(&mut self, user_session_handle: &UserSessionHandle) -> bool {
+        match self
+            .user_id_to_display_name
+            .get_mut(user_session_handle.user_id())
+        {
+            Some(display_name) => {
+                *display_name = String::from(user_session_handle.display_name());
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn get_display_names(&self) -> HashMap<String, String> {
+        self.user_id_to_display_name.clone()
+    }
+
+    /// When the users have joined the room, as unix timestamps in seconds keyed by user id
+    pub fn get_joined_at(&self) -> HashMap<String, u64> {
+        self.user_id_to_joined_at.clone()
+    }
 }
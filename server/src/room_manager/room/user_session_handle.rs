@@ -1,11 +1,31 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
 use anyhow::Context;
 use comms::event;
-use tokio::sync::broadcast;
+use nanoid::nanoid;
+
+use crate::{
+    message_store::MessageStore, room_event_observer::RoomEventObserver, sequencer::SequencedSender,
+};
+
+use super::{history::History, slow_mode::SlowMode};
+
+/// How many of the most recently sent message ids are remembered per handle,
+/// messages older than this can no longer be deleted by their author
+const MAX_TRACKED_MESSAGE_IDS: usize = 100;
 
 #[derive(Debug, Clone)]
 pub struct SessionAndUserId {
     pub session_id: String,
     pub user_id: String,
+    /// The name the user is displayed with, defaults to the user id
+    pub display_name: String,
 }
 
 #[derive(Debug)]
@@ -17,21 +37,44 @@ pub struct UserSessionHandle {
     /// The name of the room which is associated with this handle
     room: String,
     /// The channel to use for sending events to the all users of the room
-    broadcast_tx: broadcast::Sender<event::Event>,
+    broadcast_tx: SequencedSender,
     /// The session and user id associated with this handle
     session_and_user_id: SessionAndUserId,
+    /// Ids of the messages sent through this handle, used to validate authorship on deletion
+    sent_message_ids: VecDeque<String>,
+    /// Where the messages sent through this handle are persisted
+    message_store: Arc<dyn MessageStore>,
+    /// Notified of the messages sent through this handle
+    observer: Arc<dyn RoomEventObserver>,
+    /// The slow mode of the room, shared by the handles of the room
+    slow_mode: Arc<Mutex<SlowMode>>,
+    /// The recent messages of the room, shared by the handles of the room
+    history: Arc<Mutex<History>>,
+    /// Set by the room when the user is kicked, so the handle can no longer be used
+    /// even if the session has not yet seen that it was kicked
+    revoked: Arc<AtomicBool>,
 }
 
 impl UserSessionHandle {
     pub(super) fn new(
         room: String,
-        broadcast_tx: broadcast::Sender<event::Event>,
+        broadcast_tx: SequencedSender,
         session_and_user_id: SessionAndUserId,
+        message_store: Arc<dyn MessageStore>,
+        observer: Arc<dyn RoomEventObserver>,
+        slow_mode: Arc<Mutex<SlowMode>>,
+        history: Arc<Mutex<History>>,
     ) -> Self {
         UserSessionHandle {
             room,
             broadcast_tx,
             session_and_user_id,
+            sent_message_ids: VecDeque::with_capacity(MAX_TRACKED_MESSAGE_IDS),
+            message_store,
+            observer,
+            slow_mode,
+            history,
+            revoked: Arc::new(AtomicBool::new(false)),
         }
     }
 
@@ -47,14 +90,146 @@ impl UserSessionHandle {
         &self.session_and_user_id.user_id
     }
 
-    /// Send a message to the room
-    pub fn send_message(&self, content: String) -> anyhow::Result<()> {
-        self.broadcast_tx
-            .send(comms::event::Event::UserMessage(
-                event::UserMessageBroadcastEvent {
+    pub fn display_name(&self) -> &str {
+        &self.session_and_user_id.display_name
+    }
+
+    pub fn set_display_name(&mut self, display_name: &str) {
+        self.session_and_user_id.display_name = String::from(display_name);
+    }
+
+    /// Whether the user was kicked from the room, after which the handle can not be used
+    pub fn is_revoked(&self) -> bool {
+        self.revoked.load(Ordering::Acquire)
+    }
+
+    /// The flag the room sets to revoke the handle
+    pub(super) fn revoked_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.revoked)
+    }
+
+    /// Fails if the user was kicked from the room
+    fn ensure_not_revoked(&self) -> Result<(), event::ErrorReplyEvent> {
+        if self.is_revoked() {
+            return Err(event::ErrorReplyEvent::new(
+                event::ErrorCode::NotInRoom,
+                format!("kicked from room '{}'", self.room),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Send a message to the room, returns the id assigned to the message
+    /// Fails if the user was kicked, if the room is in slow mode and the user has posted to it too recently,
+    /// or if the message could not be persisted, in which case it is not broadcasted
+    pub async fn send_message(&mut self, content: String) -> anyhow::Result<String> {
+        self.ensure_not_revoked()?;
+
+        if let Err(retry_after) = self
+            .slow_mode
+            .lock()
+            .unwrap()
+            .try_post(&self.session_and_user_id.user_id)
+        {
+            // rounded up, so the user does not retry a moment too early
+            let retry_after_secs =
+                retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+
+            return Err(event::ErrorReplyEvent::new(
+                event::ErrorCode::SlowMode,
+                format!(
+                    "room '{}' is in slow mode, retry in {} seconds",
+                    self.room, retry_after_secs
+                ),
+            )
+            .with_retry_after_secs(retry_after_secs)
+            .into());
+        }
+
+        let message_id = nanoid!();
+        let message = event::UserMessageBroadcastEvent {
+            room: self.room.clone(),
+            message_id: message_id.clone(),
+            user_id: self.session_and_user_id.user_id.clone(),
+            display_name: self.session_and_user_id.display_name.clone(),
+            content,
+        };
+
+        // the store may hit the disk, which should not block the other sessions
+        let message_store = Arc::clone(&self.message_store);
+        let message = tokio::task::spawn_blocking(move || {
+            message_store.save(&message)?;
+
+            anyhow::Ok(message)
+        })
+        .await??;
+        self.observer.on_message(&message);
+        {
+            // broadcasted while the history is locked, see [History]
+            let mut history = self.history.lock().unwrap();
+            history.push(message.clone());
+            // the message is in the history even if no one is listening right now
+            let _ = self
+                .broadcast_tx
+                .send(comms::event::Event::UserMessage(message));
+        }
+
+        if self.sent_message_ids.len() == MAX_TRACKED_MESSAGE_IDS {
+            self.sent_message_ids.pop_front();
+        }
+        self.sent_message_ids.push_back(message_id.clone());
+
+        Ok(message_id)
+    }
+
+    /// Delete a message previously sent through this handle
+    /// Fails if the user was kicked, if the message was not authored by the user or is too old to be tracked,
+    /// or if the message could not be removed from the store, in which case the deletion is not broadcasted
+    pub async fn delete_message(&mut self, message_id: &str) -> anyhow::Result<()> {
+        self.ensure_not_revoked()?;
+
+        let idx = self
+            .sent_message_ids
+            .iter()
+            .position(|id| id == message_id)
+            .ok_or_else(|| {
+                event::ErrorReplyEvent::new(
+                    event::ErrorCode::MessageNotFound,
+                    format!(
+                        "message '{}' was not sent by the user in room '{}'",
+                        message_id, self.room
+                    ),
+                )
+            })?;
+
+        let message_store = Arc::clone(&self.message_store);
+        let (room, id) = (self.room.clone(), String::from(message_id));
+        tokio::task::spawn_blocking(move || message_store.delete(&room, &id)).await??;
+
+        {
+            let mut history = self.history.lock().unwrap();
+            history.remove(message_id);
+            let _ = self.broadcast_tx.send(comms::event::Event::MessageDeleted(
+                event::MessageDeletedBroadcastEvent {
                     room: self.room.clone(),
+                    message_id: String::from(message_id),
+                },
+            ));
+        }
+
+        self.sent_message_ids.remove(idx);
+
+        Ok(())
+    }
+
+    /// Broadcast the presence status of the user to the room
+    pub fn set_presence(&self, status: event::PresenceStatus) -> anyhow::Result<()> {
+        self.broadcast_tx
+            .send(comms::event::Event::PresenceChanged(
+                event::PresenceChangedBroadcastEvent {
                     user_id: self.session_and_user_id.user_id.clone(),
-                    content,
+                    status,
                 },
             ))
             .context("could not write to the broadcast channel")?;
@@ -62,3 +237,76 @@ impl UserSessionHandle {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::broadcast;
+
+    use crate::{message_store::NoopMessageStore, room_event_observer::NoopRoomEventObserver};
+
+    use super::*;
+
+    fn create_handle() -> (
+        UserSessionHandle,
+        broadcast::Receiver<event::SequencedEvent>,
+    ) {
+        let (broadcast_tx, broadcast_rx) = SequencedSender::new(10);
+        let handle = UserSessionHandle::new(
+            "room".into(),
+            broadcast_tx,
+            SessionAndUserId {
+                session_id: "session".into(),
+                user_id: "user".into(),
+                display_name: "user".into(),
+            },
+            Arc::new(NoopMessageStore),
+            Arc::new(NoopRoomEventObserver),
+            Arc::new(Mutex::new(SlowMode::default())),
+            Arc::new(Mutex::new(History::default())),
+        );
+
+        (handle, broadcast_rx)
+    }
+
+    fn recv_message_id(broadcast_rx: &mut broadcast::Receiver<event::SequencedEvent>) -> String {
+        match broadcast_rx.try_recv().unwrap().event {
+            event::Event::UserMessage(event) => event.message_id,
+            event => panic!("unexpected event: {:?}", event),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_message_assigns_unique_ids() {
+        let (mut handle, mut broadcast_rx) = create_handle();
+
+        handle.send_message("first".into()).await.unwrap();
+        handle.send_message("second".into()).await.unwrap();
+
+        let first_id = recv_message_id(&mut broadcast_rx);
+        let second_id = recv_message_id(&mut broadcast_rx);
+
+        assert!(!first_id.is_empty());
+        assert_ne!(first_id, second_id);
+    }
+
+    #[tokio::test]
+    async fn test_delete_message_validates_authorship() {
+        let (mut handle, mut broadcast_rx) = create_handle();
+
+        handle.send_message("hello".into()).await.unwrap();
+        let message_id = recv_message_id(&mut broadcast_rx);
+
+        assert!(handle
+            .delete_message("someone-elses-message")
+            .await
+            .is_err());
+        assert!(handle.delete_message(&message_id).await.is_ok());
+        assert!(matches!(
+            broadcast_rx.try_recv().unwrap().event,
+            event::Event::MessageDeleted(event) if event.message_id == message_id
+        ));
+
+        // a message can only be deleted once
+        assert!(handle.delete_message(&message_id).await.is_err());
+    }
+}
@@ -1,69 +1,836 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, RwLock},
+};
 
-use comms::event::Event;
+use comms::event::{
+    DepartureReason, ErrorCode, ErrorReplyEvent, Event, HistoryPageReplyEvent, RoomDetail,
+    RoomOccupancyChangedBroadcastEvent, RoomUsersReplyEvent, SequencedEvent,
+};
 use tokio::sync::{broadcast, Mutex};
 
-use super::room::{ChatRoom, ChatRoomMetadata, SessionAndUserId, UserSessionHandle};
+use crate::{
+    message_store::MessageStore, room_event_observer::RoomEventObserver, sequencer::SequencedSender,
+};
 
-pub type RoomJoinResult = (broadcast::Receiver<Event>, UserSessionHandle, Vec<String>);
+use super::room::{
+    ChatRoom, ChatRoomMetadata, RoomJoin, SessionAndUserId, UserSessionHandle, HISTORY_CAPACITY,
+};
 
-#[derive(Debug, Clone)]
+/// Capacity of the channel used for server wide events, such as room creation
+const SERVER_EVENTS_CHANNEL_CAPACITY: usize = 100;
+const MAX_ROOM_NAME_LEN: usize = 32;
+const MAX_ROOM_DESCRIPTION_LEN: usize = 256;
+/// How many messages a page of history can have at most, larger pages are cut down to it
+const MAX_HISTORY_PAGE_SIZE: usize = 100;
+
+pub fn room_not_found(room_name: &str) -> ErrorReplyEvent {
+    ErrorReplyEvent::new(
+        ErrorCode::RoomNotFound,
+        format!("room '{}' not found", room_name),
+    )
+}
+
+/// Validates that a room name is a slug, made of 1 to 32 lowercase alphanumerics and hyphens,
+/// since room names identify the rooms and are displayed as `#name`
+pub fn validate_room_name(name: &str) -> Result<(), ErrorReplyEvent> {
+    let is_slug = name
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+    if name.is_empty() || name.len() > MAX_ROOM_NAME_LEN || !is_slug {
+        return Err(ErrorReplyEvent::new(
+            ErrorCode::InvalidRoomName,
+            format!(
+                "room name '{}' must be between 1 and {} characters, made of lowercase letters, digits and hyphens",
+                name, MAX_ROOM_NAME_LEN
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Checks that the metadata of a room to be created is within the limits
+pub(super) fn validate_room_metadata(metadata: &ChatRoomMetadata) -> Result<(), ErrorReplyEvent> {
+    validate_room_name(&metadata.name)?;
+
+    if metadata.description.chars().count() > MAX_ROOM_DESCRIPTION_LEN {
+        return Err(ErrorReplyEvent::new(
+            ErrorCode::InvalidRoomDescription,
+            format!(
+                "room description can not be longer than {} characters",
+                MAX_ROOM_DESCRIPTION_LEN
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+struct ChatRooms {
+    /// Rooms keyed by their lowercased name, for case-insensitive lookups
+    by_key: HashMap<String, Arc<Mutex<ChatRoom>>>,
+    /// Metadatas of the rooms in the order they were created
+    metadatas: Vec<ChatRoomMetadata>,
+}
+
+/// The names the connected users are known by, which can not be picked as a display name by the others
+#[derive(Debug, Default)]
+struct ConnectedUsers {
+    /// Ids of the connected users, which are also their display names until they pick one
+    user_ids: HashSet<String>,
+    /// Display names picked by the connected users
+    display_names: HashSet<String>,
+}
+
+#[derive(Debug)]
 pub struct RoomManager {
-    chat_rooms: HashMap<String, Arc<Mutex<ChatRoom>>>,
-    chat_room_metadatas: Vec<ChatRoomMetadata>,
+    /// Rooms can be created at runtime, hence the lock
+    chat_rooms: RwLock<ChatRooms>,
+    /// Ids and display names of the currently connected users
+    connected_users: Arc<std::sync::Mutex<ConnectedUsers>>,
+    /// Channel for events which concern all the connected users, such as room creation
+    server_events_tx: SequencedSender,
+    /// Where the messages of the rooms are persisted
+    message_store: Arc<dyn MessageStore>,
+    /// Notified of the events of the rooms
+    observer: Arc<dyn RoomEventObserver>,
 }
 
 impl RoomManager {
-    pub(super) fn new(chat_rooms: Vec<(ChatRoomMetadata, Arc<Mutex<ChatRoom>>)>) -> RoomManager {
-        let chat_room_metadatas = chat_rooms
+    pub(super) fn new(
+        chat_room_metadatas: Vec<ChatRoomMetadata>,
+        message_store: Arc<dyn MessageStore>,
+        observer: Arc<dyn RoomEventObserver>,
+    ) -> anyhow::Result<RoomManager> {
+        let (server_events_tx, _) = SequencedSender::new(SERVER_EVENTS_CHANNEL_CAPACITY);
+
+        let mut chat_rooms = ChatRooms::default();
+        for metadata in chat_room_metadatas {
+            let history = message_store.load_recent(&metadata.name, HISTORY_CAPACITY)?;
+            let chat_room = ChatRoom::new(
+                metadata.clone(),
+                history,
+                Arc::clone(&message_store),
+                Arc::clone(&observer),
+            );
+
+            chat_rooms.by_key.insert(
+                metadata.name.to_lowercase(),
+                Arc::new(Mutex::new(chat_room)),
+            );
+            chat_rooms.metadatas.push(metadata);
+        }
+
+        Ok(RoomManager {
+            chat_rooms: RwLock::new(chat_rooms),
+            connected_users: Arc::new(std::sync::Mutex::new(ConnectedUsers::default())),
+            server_events_tx,
+            message_store,
+            observer,
+        })
+    }
+
+    /// Resolves the given room name case-insensitively to the canonical name of the room
+    pub fn resolve_room_name(&self, room_name: &str) -> Option<String> {
+        let key = room_name.to_lowercase();
+
+        self.chat_rooms
+            .read()
+            .unwrap()
+            .metadatas
             .iter()
-            .map(|(metadata, _)| metadata.clone())
-            .collect();
-
-        RoomManager {
-            chat_room_metadatas,
-            chat_rooms: chat_rooms
-                .into_iter()
-                .map(|(metadata, chat_room)| (metadata.name.clone(), chat_room))
-                .collect(),
+            .find(|metadata| metadata.name.to_lowercase() == key)
+            .map(|metadata| metadata.name.clone())
+    }
+
+    fn get_chat_room(&self, room_name: &str) -> anyhow::Result<Arc<Mutex<ChatRoom>>> {
+        self.chat_rooms
+            .read()
+            .unwrap()
+            .by_key
+            .get(&room_name.to_lowercase())
+            .cloned()
+            .ok_or_else(|| room_not_found(room_name).into())
+    }
+
+    /// Snapshot of the rooms with their participant counts, in the order they were created
+    pub async fn room_details(&self) -> Vec<RoomDetail> {
+        self.chat_room_metadatas_with_participant_counts()
+            .await
+            .into_iter()
+            .map(|(metadata, participant_count)| RoomDetail {
+                name: metadata.name,
+                description: metadata.description,
+                participant_count,
+            })
+            .collect()
+    }
+
+    /// The metadatas of the rooms with their participant counts, in the order they were created
+    pub async fn chat_room_metadatas_with_participant_counts(
+        &self,
+    ) -> Vec<(ChatRoomMetadata, usize)> {
+        // the rooms are collected first, so the lock is not held while waiting for the rooms
+        let rooms = {
+            let chat_rooms = self.chat_rooms.read().unwrap();
+
+            chat_rooms
+                .metadatas
+                .iter()
+                .filter_map(|metadata| {
+                    chat_rooms
+                        .by_key
+                        .get(&metadata.name.to_lowercase())
+                        .map(|room| (metadata.clone(), Arc::clone(room)))
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut metadatas = Vec::with_capacity(rooms.len());
+        for (metadata, room) in rooms {
+            let participant_count = room.lock().await.participant_count();
+            metadatas.push((metadata, participant_count));
+        }
+
+        metadatas
+    }
+
+    /// The users currently in a room, for peeking into the room without joining it
+    pub async fn room_users(&self, room_name: &str) -> anyhow::Result<RoomUsersReplyEvent> {
+        let room = self.get_chat_room(room_name)?;
+        let room = room.lock().await;
+
+        Ok(RoomUsersReplyEvent {
+            room: String::from(room.name()),
+            users: room.get_unique_user_ids(),
+            display_names: room.get_display_names(),
+        })
+    }
+
+    /// Loads a page of the persisted messages of a room, sent before the given message or the most recent ones
+    /// The page has at most `limit` messages, capped at [MAX_HISTORY_PAGE_SIZE]
+    pub async fn fetch_history(
+        &self,
+        room_name: &str,
+        before_message_id: Option<String>,
+        limit: usize,
+    ) -> anyhow::Result<HistoryPageReplyEvent> {
+        let room = String::from(self.get_chat_room(room_name)?.lock().await.name());
+        let limit = limit.clamp(1, MAX_HISTORY_PAGE_SIZE);
+        let message_store = Arc::clone(&self.message_store);
+
+        // the store may hit the disk, which should not block the other sessions
+        let (room, before_message_id, mut messages) = tokio::task::spawn_blocking(move || {
+            // one more message than asked for tells whether there are older ones
+            let messages = match before_message_id.as_deref() {
+                Some(before_message_id) => {
+                    message_store.load_before(&room, before_message_id, limit + 1)
+                }
+                None => message_store.load_recent(&room, limit + 1),
+            }?;
+
+            anyhow::Ok((room, before_message_id, messages))
+        })
+        .await??;
+
+        let has_more = messages.len() > limit;
+        if has_more {
+            messages.remove(0);
+        }
+
+        Ok(HistoryPageReplyEvent {
+            room,
+            before_message_id,
+            messages,
+            has_more,
+        })
+    }
+
+    /// Lets all the connected users know about the new participant count of the room, if it has changed
+    fn notify_occupancy_change(&self, room: &ChatRoom, previous_count: usize) {
+        let count = room.participant_count();
+
+        if count != previous_count {
+            // there may be no connected users to receive the event, which is fine
+            let _ = self.server_events_tx.send(Event::RoomOccupancyChanged(
+                RoomOccupancyChangedBroadcastEvent {
+                    room: String::from(room.name()),
+                    count,
+                },
+            ));
+        }
+    }
+
+    /// Creates a new room and lets all the connected users know about it
+    /// Fails if a room with the same name, compared case-insensitively, already exists
+    pub async fn create_room(&self, metadata: ChatRoomMetadata) -> anyhow::Result<()> {
+        validate_room_metadata(&metadata)?;
+
+        let key = metadata.name.to_lowercase();
+        let room_already_exists = || {
+            ErrorReplyEvent::new(
+                ErrorCode::RoomAlreadyExists,
+                format!("room '{}' already exists", metadata.name),
+            )
+        };
+
+        if self.chat_rooms.read().unwrap().by_key.contains_key(&key) {
+            return Err(room_already_exists().into());
+        }
+
+        // a room with the same name may have existed before a restart, so its history is loaded,
+        // without holding the lock as the store may hit the disk
+        let message_store = Arc::clone(&self.message_store);
+        let room = metadata.name.clone();
+        let history =
+            tokio::task::spawn_blocking(move || message_store.load_recent(&room, HISTORY_CAPACITY))
+                .await??;
+
+        {
+            let mut chat_rooms = self.chat_rooms.write().unwrap();
+
+            // another session may have created the room while its history was loading
+            if chat_rooms.by_key.contains_key(&key) {
+                return Err(room_already_exists().into());
+            }
+
+            let chat_room = ChatRoom::new(
+                metadata.clone(),
+                history,
+                Arc::clone(&self.message_store),
+                Arc::clone(&self.observer),
+            );
+            chat_rooms
+                .by_key
+                .insert(key, Arc::new(Mutex::new(chat_room)));
+            chat_rooms.metadatas.push(metadata.clone());
         }
+
+        // there may be no connected users to receive the event, which is fine
+        let _ = self.server_events_tx.send(Event::RoomCreated(RoomDetail {
+            name: metadata.name,
+            description: metadata.description,
+            participant_count: 0,
+        }));
+
+        Ok(())
     }
 
-    pub fn chat_room_metadatas(&self) -> &Vec<ChatRoomMetadata> {
-        &self.chat_room_metadatas
+    /// Subscribe to the events which concern all the connected users
+    pub fn subscribe_server_events(&self) -> broadcast::Receiver<SequencedEvent> {
+        self.server_events_tx.subscribe()
     }
 
-    /// Joins to a room given a user session
+    /// Joins to a room given a user session, the room name is resolved case-insensitively
     pub async fn join_room(
         &self,
         room_name: &str,
         session_and_user_id: &SessionAndUserId,
-    ) -> anyhow::Result<RoomJoinResult> {
-        let room = self
-            .chat_rooms
-            .get(room_name)
-            .ok_or_else(|| anyhow::anyhow!("room '{}' not found", room_name))?;
+    ) -> anyhow::Result<RoomJoin> {
+        let room = self.get_chat_room(room_name)?;
+
+        let mut room = room.lock().await;
+        let previous_count = room.participant_count();
+        let room_join = room.join(session_and_user_id)?;
+        self.notify_occupancy_change(&room, previous_count);
+
+        Ok(room_join)
+    }
+
+    pub async fn drop_user_session_handle(
+        &self,
+        handle: UserSessionHandle,
+        reason: DepartureReason,
+    ) -> anyhow::Result<()> {
+        let room = self.get_chat_room(handle.room())?;
+
+        let mut room = room.lock().await;
+
+        let previous_count = room.participant_count();
+        room.leave(handle, reason);
+        self.notify_occupancy_change(&room, previous_count);
+
+        Ok(())
+    }
+
+    /// Broadcasts the display name of the given handle to its room
+    pub async fn change_display_name(&self, handle: &UserSessionHandle) -> anyhow::Result<()> {
+        let room = self.get_chat_room(handle.room())?;
+
+        room.lock().await.change_display_name(handle);
+
+        Ok(())
+    }
+
+    /// Kicks a user out of a room on behalf of a moderator of the room
+    pub async fn kick_user(
+        &self,
+        room_name: &str,
+        moderator_id: &str,
+        user_id: &str,
+    ) -> anyhow::Result<()> {
+        let room = self.get_chat_room(room_name)?;
 
         let mut room = room.lock().await;
-        let (broadcast_rx, user_session_handle) = room.join(session_and_user_id);
 
-        Ok((
-            broadcast_rx,
-            user_session_handle,
-            room.get_unique_user_ids().clone(),
-        ))
+        let previous_count = room.participant_count();
+        room.kick(moderator_id, user_id)?;
+        self.notify_occupancy_change(&room, previous_count);
+
+        Ok(())
     }
 
-    pub async fn drop_user_session_handle(&self, handle: UserSessionHandle) -> anyhow::Result<()> {
-        let room = self
-            .chat_rooms
-            .get(handle.room())
-            .ok_or_else(|| anyhow::anyhow!("room '{}' not found", handle.room()))?;
+    /// Sets the slow mode of a room on behalf of a moderator of the room, 0 seconds turns it off
+    pub async fn set_slow_mode(
+        &self,
+        room_name: &str,
+        moderator_id: &str,
+        seconds: u64,
+    ) -> anyhow::Result<()> {
+        let room = self.get_chat_room(room_name)?;
 
         let mut room = room.lock().await;
 
-        room.leave(handle);
+        room.set_slow_mode(moderator_id, seconds)
+    }
+
+    /// Registers the id of a connected user, so the others can not impersonate them by picking it as a display name
+    pub fn register_user_id(&self, user_id: &str) {
+        self.connected_users
+            .lock()
+            .unwrap()
+            .user_ids
+            .insert(String::from(user_id));
+    }
+
+    /// Releases the id of a user who has disconnected or picked a new id
+    pub fn release_user_id(&self, user_id: &str) {
+        self.connected_users
+            .lock()
+            .unwrap()
+            .user_ids
+            .remove(user_id);
+    }
+
+    /// Claims a display name for a user, releasing their previous one
+    /// Fails if the display name is already claimed by another user, or is the id of another connected user
+    pub fn claim_display_name(
+        &self,
+        display_name: &str,
+        user_id: &str,
+        previous: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let mut connected_users = self.connected_users.lock().unwrap();
+        let is_claimed =
+            Some(display_name) != previous && connected_users.display_names.contains(display_name);
+        let is_user_id = display_name != user_id && connected_users.user_ids.contains(display_name);
+
+        if is_claimed || is_user_id {
+            return Err(ErrorReplyEvent::new(
+                ErrorCode::DisplayNameTaken,
+                format!("display name '{}' is already taken", display_name),
+            )
+            .into());
+        }
+
+        if let Some(previous) = previous {
+            connected_users.display_names.remove(previous);
+        }
+        connected_users
+            .display_names
+            .insert(String::from(display_name));
 
         Ok(())
     }
+
+    /// Releases a display name so it can be claimed by other users
+    pub fn release_display_name(&self, display_name: &str) {
+        self.connected_users
+            .lock()
+            .unwrap()
+            .display_names
+            .remove(display_name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use comms::event::UserMessageBroadcastEvent;
+
+    use crate::{message_store::SqliteMessageStore, room_manager::RoomManagerBuilder};
+
+    use super::*;
+
+    fn create_room_manager() -> RoomManager {
+        RoomManagerBuilder::new()
+            .create_room(ChatRoomMetadata {
+                name: "rust".into(),
+                description: "Rust".into(),
+                ..Default::default()
+            })
+            .build()
+            .unwrap()
+    }
+
+    fn session_and_user_id(id: &str) -> SessionAndUserId {
+        SessionAndUserId {
+            session_id: id.into(),
+            user_id: id.into(),
+            display_name: id.into(),
+        }
+    }
+
+    #[test]
+    fn test_resolve_room_name_is_case_insensitive() {
+        let room_manager = create_room_manager();
+
+        assert_eq!(
+            room_manager.resolve_room_name("rust").as_deref(),
+            Some("rust")
+        );
+        assert_eq!(
+            room_manager.resolve_room_name("Rust").as_deref(),
+            Some("rust")
+        );
+        assert_eq!(
+            room_manager.resolve_room_name("RUST").as_deref(),
+            Some("rust")
+        );
+        assert_eq!(room_manager.resolve_room_name("go"), None);
+    }
+
+    #[tokio::test]
+    async fn test_join_room_with_differently_cased_names_joins_canonical_room() {
+        let room_manager = create_room_manager();
+
+        let handle_a = room_manager
+            .join_room("Rust", &session_and_user_id("a"))
+            .await
+            .unwrap()
+            .handle;
+        let RoomJoin {
+            handle: handle_b,
+            user_ids,
+            ..
+        } = room_manager
+            .join_room("RUST", &session_and_user_id("b"))
+            .await
+            .unwrap();
+
+        assert_eq!(handle_a.room(), "rust");
+        assert_eq!(handle_b.room(), "rust");
+        assert_eq!(user_ids.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_occupancy_changes_are_broadcasted() {
+        let room_manager = create_room_manager();
+        let mut server_events_rx = room_manager.subscribe_server_events();
+        let occupancy_changed = |count| {
+            Event::RoomOccupancyChanged(RoomOccupancyChangedBroadcastEvent {
+                room: "rust".into(),
+                count,
+            })
+        };
+
+        let handle_a = room_manager
+            .join_room("rust", &session_and_user_id("a"))
+            .await
+            .unwrap()
+            .handle;
+        assert_eq!(
+            server_events_rx.try_recv().unwrap().event,
+            occupancy_changed(1)
+        );
+
+        // another session of the same user does not change the count
+        let handle_a_2 = room_manager
+            .join_room(
+                "rust",
+                &SessionAndUserId {
+                    session_id: "a-2".into(),
+                    user_id: "a".into(),
+                    display_name: "a".into(),
+                },
+            )
+            .await
+            .unwrap()
+            .handle;
+        assert!(server_events_rx.try_recv().is_err());
+        assert_eq!(room_manager.room_details().await[0].participant_count, 1);
+
+        room_manager
+            .drop_user_session_handle(handle_a, DepartureReason::Left)
+            .await
+            .unwrap();
+        assert!(server_events_rx.try_recv().is_err());
+        room_manager
+            .drop_user_session_handle(handle_a_2, DepartureReason::Left)
+            .await
+            .unwrap();
+        assert_eq!(
+            server_events_rx.try_recv().unwrap().event,
+            occupancy_changed(0)
+        );
+        assert_eq!(room_manager.room_details().await[0].participant_count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_room_users_lists_the_participants_without_joining() {
+        let room_manager = create_room_manager();
+
+        let _handle_a = room_manager
+            .join_room("rust", &session_and_user_id("a"))
+            .await
+            .unwrap()
+            .handle;
+        let handle_b = room_manager
+            .join_room("rust", &session_and_user_id("b"))
+            .await
+            .unwrap()
+            .handle;
+        room_manager
+            .drop_user_session_handle(handle_b, DepartureReason::Left)
+            .await
+            .unwrap();
+
+        let room_users = room_manager.room_users("Rust").await.unwrap();
+        assert_eq!(room_users.room, "rust");
+        assert_eq!(room_users.users, vec![String::from("a")]);
+        assert_eq!(room_users.display_names["a"], "a");
+        // peeking does not count as a participant
+        assert_eq!(room_manager.room_details().await[0].participant_count, 1);
+
+        let err = room_manager.room_users("go").await.unwrap_err();
+        assert_eq!(
+            err.downcast::<ErrorReplyEvent>().unwrap().code,
+            ErrorCode::RoomNotFound
+        );
+    }
+
+    #[test]
+    fn test_claim_display_name_rejects_duplicates() {
+        let room_manager = create_room_manager();
+
+        assert!(room_manager
+            .claim_display_name("alice", "user-1", None)
+            .is_ok());
+        // claiming the same name again by the owner is a no-op
+        assert!(room_manager
+            .claim_display_name("alice", "user-1", Some("alice"))
+            .is_ok());
+        assert!(room_manager
+            .claim_display_name("alice", "user-2", None)
+            .is_err());
+
+        // once released, the name can be claimed by someone else
+        room_manager.release_display_name("alice");
+        assert!(room_manager
+            .claim_display_name("alice", "user-2", None)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_claim_display_name_rejects_the_ids_of_connected_users() {
+        let room_manager = create_room_manager();
+        room_manager.register_user_id("abc12");
+
+        // the user id is the default display name of its user, so no one else can pick it
+        assert!(room_manager
+            .claim_display_name("abc12", "def34", None)
+            .is_err());
+        // but the user can pick their own id back
+        assert!(room_manager
+            .claim_display_name("abc12", "abc12", None)
+            .is_ok());
+        room_manager.release_display_name("abc12");
+
+        room_manager.release_user_id("abc12");
+        assert!(room_manager
+            .claim_display_name("abc12", "def34", None)
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_join_unknown_room_fails() {
+        let room_manager = create_room_manager();
+
+        let err = room_manager
+            .join_room("go", &session_and_user_id("a"))
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            err.downcast::<ErrorReplyEvent>().unwrap().code,
+            ErrorCode::RoomNotFound
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_history_pages_through_the_stored_messages() {
+        let path = std::env::temp_dir().join(format!("messages-{}.sqlite", nanoid::nanoid!()));
+        let message_store = Arc::new(SqliteMessageStore::open(&path).unwrap());
+        for message_id in ["1", "2", "3", "4", "5"] {
+            message_store
+                .save(&UserMessageBroadcastEvent {
+                    room: "rust".into(),
+                    message_id: message_id.into(),
+                    user_id: "a".into(),
+                    display_name: "a".into(),
+                    content: message_id.into(),
+                })
+                .unwrap();
+        }
+        let room_manager = RoomManagerBuilder::new()
+            .create_room(ChatRoomMetadata {
+                name: "rust".into(),
+                description: "Rust".into(),
+                ..Default::default()
+            })
+            .message_store(message_store)
+            .build()
+            .unwrap();
+        let message_ids = |page: &HistoryPageReplyEvent| {
+            page.messages
+                .iter()
+                .map(|message| message.message_id.clone())
+                .collect::<Vec<_>>()
+        };
+
+        let page = room_manager.fetch_history("Rust", None, 2).await.unwrap();
+        assert_eq!(page.room, "rust");
+        assert_eq!(message_ids(&page), vec!["4", "5"]);
+        assert!(page.has_more);
+
+        let page = room_manager
+            .fetch_history("rust", Some("4".into()), 2)
+            .await
+            .unwrap();
+        assert_eq!(message_ids(&page), vec!["2", "3"]);
+        assert!(page.has_more);
+
+        let page = room_manager
+            .fetch_history("rust", Some("2".into()), 2)
+            .await
+            .unwrap();
+        assert_eq!(message_ids(&page), vec!["1"]);
+        assert!(!page.has_more);
+
+        let err = room_manager
+            .fetch_history("rust", Some("unknown".into()), 2)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ErrorReplyEvent>().unwrap().code,
+            ErrorCode::MessageNotFound
+        );
+
+        drop(room_manager);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_create_room_notifies_and_rejects_duplicates() {
+        let room_manager = create_room_manager();
+        let mut server_events_rx = room_manager.subscribe_server_events();
+
+        room_manager
+            .create_room(ChatRoomMetadata {
+                name: "go".into(),
+                description: "Go".into(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(
+            server_events_rx.try_recv().unwrap().event,
+            Event::RoomCreated(RoomDetail {
+                name: "go".into(),
+                description: "Go".into(),
+                participant_count: 0,
+            })
+        );
+        assert!(room_manager
+            .join_room("go", &session_and_user_id("a"))
+            .await
+            .is_ok());
+
+        let err = room_manager
+            .create_room(ChatRoomMetadata {
+                name: "rust".into(),
+                description: "Another Rust".into(),
+                ..Default::default()
+            })
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            err.downcast::<ErrorReplyEvent>().unwrap().code,
+            ErrorCode::RoomAlreadyExists
+        );
+        assert_eq!(room_manager.room_details().await.len(), 2);
+    }
+
+    #[test]
+    fn test_validate_room_name() {
+        for name in [
+            "rust",
+            "web-dev",
+            "os2",
+            "a",
+            &"a".repeat(MAX_ROOM_NAME_LEN),
+        ] {
+            assert!(validate_room_name(name).is_ok(), "{} should be valid", name);
+        }
+
+        for name in [
+            "",
+            "Rust",
+            "web dev",
+            "web_dev",
+            " rust",
+            "#rust",
+            "café",
+            &"a".repeat(MAX_ROOM_NAME_LEN + 1),
+        ] {
+            assert_eq!(
+                validate_room_name(name).unwrap_err().code,
+                ErrorCode::InvalidRoomName,
+                "{} should be invalid",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_builder_rejects_invalid_and_duplicate_rooms() {
+        let room = |name: &str| ChatRoomMetadata {
+            name: name.into(),
+            description: name.into(),
+            ..Default::default()
+        };
+
+        let err = RoomManagerBuilder::new()
+            .create_room(room("Rust"))
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ErrorReplyEvent>().unwrap().code,
+            ErrorCode::InvalidRoomName
+        );
+
+        let err = RoomManagerBuilder::new()
+            .create_room(room("rust"))
+            .create_room(room("go"))
+            .create_room(room("rust"))
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ErrorReplyEvent>().unwrap().code,
+            ErrorCode::RoomAlreadyExists
+        );
+    }
 }
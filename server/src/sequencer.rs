@@ -0,0 +1,85 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex, PoisonError,
+};
+
+use comms::event::{Event, SequencedEvent};
+use tokio::sync::broadcast;
+
+/// The next sequence number, shared by every room and session so the events of different rooms can be ordered
+static NEXT_SEQ: AtomicU64 = AtomicU64::new(1);
+
+/// Stamps the event with the next server wide sequence number
+pub(crate) fn sequence(event: Event) -> SequencedEvent {
+    SequencedEvent {
+        event,
+        seq: Some(NEXT_SEQ.fetch_add(1, Ordering::Relaxed)),
+    }
+}
+
+/// [SequencedSender] broadcasts events stamped with the server wide sequence number.
+///
+/// The events are stamped and sent under a lock shared by the clones of the sender,
+/// so the receivers get them in the order of their sequence numbers.
+#[derive(Debug, Clone)]
+pub(crate) struct SequencedSender {
+    tx: broadcast::Sender<SequencedEvent>,
+    lock: Arc<Mutex<()>>,
+}
+
+impl SequencedSender {
+    pub fn new(capacity: usize) -> (Self, broadcast::Receiver<SequencedEvent>) {
+        let (tx, rx) = broadcast::channel(capacity);
+
+        (
+            SequencedSender {
+                tx,
+                lock: Arc::new(Mutex::new(())),
+            },
+            rx,
+        )
+    }
+
+    /// Broadcasts the event, fails if there are no receivers, in which case the event is dropped
+    pub fn send(&self, event: Event) -> Result<usize, broadcast::error::SendError<()>> {
+        // nothing can panic while the lock is held, so a poisoned lock is still consistent
+        let _guard = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+
+        self.tx
+            .send(sequence(event))
+            .map_err(|_| broadcast::error::SendError(()))
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<SequencedEvent> {
+        self.tx.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use comms::event::PongReplyEvent;
+
+    use super::*;
+
+    #[test]
+    fn test_concurrent_senders_broadcast_in_sequence_order() {
+        let (sender, mut rx) = SequencedSender::new(1000);
+
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                let sender = sender.clone();
+                scope.spawn(move || {
+                    for _ in 0..100 {
+                        sender.send(Event::Pong(PongReplyEvent)).unwrap();
+                    }
+                });
+            }
+        });
+
+        let seqs = std::iter::from_fn(|| rx.try_recv().ok())
+            .map(|event| event.seq.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(seqs.len(), 400);
+        assert!(seqs.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+}
@@ -3,105 +3,447 @@ use std::{collections::HashMap, sync::Arc};
 use anyhow::Context;
 use comms::{
     command::UserCommand,
-    event::{self, Event},
+    event::{self, Event, SequencedEvent},
 };
 use tokio::{
-    sync::mpsc,
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc::{self, error::SendTimeoutError},
+        Notify,
+    },
     task::{AbortHandle, JoinSet},
+    time::Duration,
 };
+use tracing::{debug, error, info};
 
-use crate::room_manager::{RoomManager, SessionAndUserId, UserSessionHandle};
+use crate::{
+    room_manager::{room_not_found, RoomJoin, RoomManager, SessionAndUserId, UserSessionHandle},
+    sequencer::sequence,
+};
+
+use super::rate_limiter::TokenBucket;
+
+const MAX_DISPLAY_NAME_LEN: usize = 32;
+/// Maximum size of a message content in bytes, larger messages are rejected and not broadcasted
+const MAX_MESSAGE_LEN: usize = 4096;
+/// How many messages a user can send per second on average, across all rooms
+const MESSAGE_RATE_PER_SEC: u32 = 5;
+/// How many messages a user can send at once before being rate limited
+const MESSAGE_BURST: u32 = 10;
+
+/// How many rooms a user can be in at once, each joined room takes a forwarding task and a subscription
+const MAX_ROOMS_PER_SESSION: usize = 20;
+
+/// How many events can wait to be written to the user, across all rooms
+const SESSION_CHANNEL_CAPACITY: usize = 100;
+/// How long an event waits for room in a full session channel, before the user is deemed too slow
+const SLOW_CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn not_in_room(room: &str) -> event::ErrorReplyEvent {
+    event::ErrorReplyEvent::new(
+        event::ErrorCode::NotInRoom,
+        format!("not in room '{}'", room),
+    )
+}
+
+/// Keeps the errors meant for the user, the rest are logged and replaced with an internal error,
+/// so a failure of the server is not mistaken for a failure of the session
+fn internal_error(err: anyhow::Error, message: &str) -> anyhow::Error {
+    if err.is::<event::ErrorReplyEvent>() {
+        return err;
+    }
+
+    error!("{}: {:#}", message, err);
+    event::ErrorReplyEvent::new(event::ErrorCode::Internal, message).into()
+}
+
+/// Trims the given display name and validates its length
+fn validate_display_name(name: &str) -> Result<String, event::ErrorReplyEvent> {
+    let name = name.trim();
+    let len = name.chars().count();
+
+    if len == 0 || len > MAX_DISPLAY_NAME_LEN {
+        return Err(event::ErrorReplyEvent::new(
+            event::ErrorCode::InvalidDisplayName,
+            format!(
+                "display name must be between 1 and {} characters",
+                MAX_DISPLAY_NAME_LEN
+            ),
+        ));
+    }
+
+    Ok(String::from(name))
+}
 
 pub(super) struct ChatSession {
     session_and_user_id: SessionAndUserId,
+    /// The display name claimed by the user, if they have picked one
+    claimed_display_name: Option<String>,
     room_manager: Arc<RoomManager>,
+    /// Limits the rate of messages sent by the user
+    message_rate_limiter: TokenBucket,
     joined_rooms: HashMap<String, (UserSessionHandle, AbortHandle)>,
     join_set: JoinSet<()>,
-    mpsc_tx: mpsc::Sender<Event>,
-    mpsc_rx: mpsc::Receiver<Event>,
+    mpsc_tx: mpsc::Sender<SequencedEvent>,
+    mpsc_rx: mpsc::Receiver<SequencedEvent>,
+    /// Notified when the user can not keep up with the events of the rooms
+    too_slow: Arc<Notify>,
 }
 
 impl ChatSession {
     pub fn new(session_id: &str, user_id: &str, room_manager: Arc<RoomManager>) -> Self {
-        let (mpsc_tx, mpsc_rx) = mpsc::channel(100);
+        let (mpsc_tx, mpsc_rx) = mpsc::channel(SESSION_CHANNEL_CAPACITY);
+        // the user id is the display name of the user until they pick one, so no one else can pick it
+        room_manager.register_user_id(user_id);
         let session_and_user_id = SessionAndUserId {
             session_id: String::from(session_id),
             user_id: String::from(user_id),
+            display_name: String::from(user_id),
         };
 
         ChatSession {
             session_and_user_id,
+            claimed_display_name: None,
             room_manager,
+            message_rate_limiter: TokenBucket::new(MESSAGE_RATE_PER_SEC, MESSAGE_BURST),
             joined_rooms: HashMap::new(),
             join_set: JoinSet::new(),
             mpsc_tx,
             mpsc_rx,
+            too_slow: Arc::new(Notify::new()),
         }
     }
 
-    /// Handle a user command related to room management such as; join, leave, send message
+    /// Handle a user command related to room management such as; join, leave, list joined rooms, kick, slow mode,
+    /// send or delete message, fetch history, set username or presence, or pick a new user id
     pub async fn handle_user_command(&mut self, cmd: UserCommand) -> anyhow::Result<()> {
+        self.drop_revoked_rooms().await?;
+
         match cmd {
             UserCommand::JoinRoom(cmd) => {
-                if self.joined_rooms.contains_key(&cmd.room) {
-                    return Err(anyhow::anyhow!("already joined room '{}'", &cmd.room));
+                // resolve the canonical room name, so replies and broadcasts are consistent
+                // no matter how the user has typed the room name
+                let room = self
+                    .room_manager
+                    .resolve_room_name(&cmd.room)
+                    .ok_or_else(|| room_not_found(&cmd.room))?;
+
+                if self.joined_rooms.contains_key(&room) {
+                    return Err(event::ErrorReplyEvent::new(
+                        event::ErrorCode::AlreadyJoined,
+                        format!("already joined room '{}'", &room),
+                    )
+                    .into());
+                }
+
+                if self.joined_rooms.len() >= MAX_ROOMS_PER_SESSION {
+                    return Err(event::ErrorReplyEvent::new(
+                        event::ErrorCode::TooManyRooms,
+                        format!(
+                            "can not be in more than {} rooms at once",
+                            MAX_ROOMS_PER_SESSION
+                        ),
+                    )
+                    .into());
+                }
+
+                self.join_room(room).await?;
+            }
+            UserCommand::SendMessage(mut cmd) => {
+                cmd.room = self.canonical_room_name(&cmd.room);
+                let (user_session_handle, _) = self
+                    .joined_rooms
+                    .get_mut(&cmd.room)
+                    .ok_or_else(|| not_in_room(&cmd.room))?;
+
+                if cmd.content.trim().is_empty() {
+                    return Err(event::ErrorReplyEvent::new(
+                        event::ErrorCode::EmptyMessage,
+                        "message can not be empty",
+                    )
+                    .into());
+                }
+
+                if cmd.content.len() > MAX_MESSAGE_LEN {
+                    return Err(event::ErrorReplyEvent::new(
+                        event::ErrorCode::MessageTooLong,
+                        format!("message can not be longer than {} bytes", MAX_MESSAGE_LEN),
+                    )
+                    .into());
+                }
+
+                if !self.message_rate_limiter.try_acquire() {
+                    return Err(event::ErrorReplyEvent::new(
+                        event::ErrorCode::RateLimited,
+                        format!(
+                            "sending messages too fast, retry in {} ms",
+                            self.message_rate_limiter.retry_after().as_millis()
+                        ),
+                    )
+                    .into());
+                }
+
+                debug!(room = %cmd.room, len = cmd.content.len(), "sending message");
+                let message_id = match user_session_handle.send_message(cmd.content).await {
+                    Ok(message_id) => message_id,
+                    Err(err) => {
+                        // a message which was not sent does not count towards the rate limit
+                        self.message_rate_limiter.refund();
+
+                        // the user is told when the room rejects the message, e.g. due to its slow mode
+                        return Err(internal_error(err, "could not send the message"));
+                    }
+                };
+
+                // let the user know which of their messages has been sent, if they have asked for it
+                if let Some(client_msg_id) = cmd.client_msg_id {
+                    self.mpsc_tx
+                        .send(sequence(Event::MessageAck(event::MessageAckReplyEvent {
+                            room: cmd.room,
+                            client_msg_id,
+                            message_id,
+                        })))
+                        .await?;
+                }
+            }
+            UserCommand::DeleteMessage(mut cmd) => {
+                cmd.room = self.canonical_room_name(&cmd.room);
+                let (user_session_handle, _) = self
+                    .joined_rooms
+                    .get_mut(&cmd.room)
+                    .ok_or_else(|| not_in_room(&cmd.room))?;
+
+                user_session_handle
+                    .delete_message(&cmd.message_id)
+                    .await
+                    .map_err(|err| internal_error(err, "could not delete the message"))?;
+            }
+            UserCommand::FetchHistory(mut cmd) => {
+                cmd.room = self.canonical_room_name(&cmd.room);
+                if !self.joined_rooms.contains_key(&cmd.room) {
+                    return Err(not_in_room(&cmd.room).into());
                 }
 
-                let (mut broadcast_rx, user_session_handle, user_ids) = self
+                let history_page = self
                     .room_manager
-                    .join_room(&cmd.room, &self.session_and_user_id)
+                    .fetch_history(&cmd.room, cmd.before_message_id, cmd.limit)
+                    .await?;
+                self.mpsc_tx
+                    .send(sequence(Event::HistoryPage(history_page)))
                     .await?;
+            }
+            UserCommand::SetUsername(cmd) => {
+                let display_name = validate_display_name(&cmd.name)?;
 
-                // spawn a task to forward broadcasted messages to the users' mpsc channel
-                // hence the user can receive messages from different rooms via single channel
-                let abort_handle = self.join_set.spawn({
-                    let mpsc_tx = self.mpsc_tx.clone();
-
-                    // start with sending the user joined room event as a reply to the user
-                    mpsc_tx
-                        .send(Event::UserJoinedRoom(event::UserJoinedRoomReplyEvent {
-                            room: cmd.room.clone(),
-                            users: user_ids,
-                        }))
-                        .await?;
+                self.room_manager.claim_display_name(
+                    &display_name,
+                    &self.session_and_user_id.user_id,
+                    self.claimed_display_name.as_deref(),
+                )?;
+                self.claimed_display_name = Some(display_name.clone());
+                self.session_and_user_id.display_name = display_name.clone();
 
-                    async move {
-                        while let Ok(event) = broadcast_rx.recv().await {
-                            let _ = mpsc_tx.send(event).await;
-                        }
-                    }
-                });
+                // reply to the user directly, since they may not have joined any rooms yet
+                self.mpsc_tx
+                    .send(sequence(Event::DisplayNameChanged(
+                        event::DisplayNameChangedBroadcastEvent {
+                            user_id: self.session_and_user_id.user_id.clone(),
+                            display_name: display_name.clone(),
+                        },
+                    )))
+                    .await?;
 
-                // store references to the user session handle and abort handle
-                // this is used to send messages to the room and to cancel the task when user leaves the room
-                self.joined_rooms
-                    .insert(cmd.room.clone(), (user_session_handle, abort_handle));
+                for (user_session_handle, _) in self.joined_rooms.values_mut() {
+                    user_session_handle.set_display_name(&display_name);
+                    self.room_manager
+                        .change_display_name(user_session_handle)
+                        .await?;
+                }
             }
-            UserCommand::SendMessage(cmd) => {
-                if let Some((user_session_handle, _)) = self.joined_rooms.get(&cmd.room) {
-                    let _ = user_session_handle.send_message(cmd.content);
+            UserCommand::SetPresence(cmd) => {
+                for (user_session_handle, _) in self.joined_rooms.values() {
+                    user_session_handle.set_presence(cmd.status)?;
                 }
             }
-            UserCommand::LeaveRoom(cmd) => {
+            UserCommand::KickUser(mut cmd) => {
+                cmd.room = self.canonical_room_name(&cmd.room);
+                if !self.joined_rooms.contains_key(&cmd.room) {
+                    return Err(not_in_room(&cmd.room).into());
+                }
+
+                self.room_manager
+                    .kick_user(&cmd.room, &self.session_and_user_id.user_id, &cmd.user_id)
+                    .await?;
+                info!(room = %cmd.room, kicked_user_id = %cmd.user_id, "kicked user");
+            }
+            UserCommand::SetSlowMode(mut cmd) => {
+                cmd.room = self.canonical_room_name(&cmd.room);
+                if !self.joined_rooms.contains_key(&cmd.room) {
+                    return Err(not_in_room(&cmd.room).into());
+                }
+
+                self.room_manager
+                    .set_slow_mode(&cmd.room, &self.session_and_user_id.user_id, cmd.seconds)
+                    .await?;
+                info!(room = %cmd.room, seconds = cmd.seconds, "set slow mode");
+            }
+            UserCommand::LeaveRoom(mut cmd) => {
+                cmd.room = self.canonical_room_name(&cmd.room);
                 // remove the room from joined rooms and drop user session handle for the room
-                if let Some(urp) = self.joined_rooms.remove(&cmd.room) {
-                    self.cleanup_room(urp).await?;
+                let urp = self
+                    .joined_rooms
+                    .remove(&cmd.room)
+                    .ok_or_else(|| not_in_room(&cmd.room))?;
+
+                info!(room = %cmd.room, "left room");
+                self.cleanup_room(urp, event::DepartureReason::Left).await?;
+            }
+            UserCommand::Reidentify(_) => {
+                let previous_user_id = std::mem::replace(
+                    &mut self.session_and_user_id.user_id,
+                    super::generate_user_id(),
+                );
+                let user_id = self.session_and_user_id.user_id.clone();
+                self.room_manager.release_user_id(&previous_user_id);
+                self.room_manager.register_user_id(&user_id);
+                // the display name defaults to the user id, until the user picks one
+                if self.claimed_display_name.is_none() {
+                    self.session_and_user_id.display_name = user_id.clone();
+                }
+                tracing::Span::current().record("user_id", &user_id);
+                info!(%previous_user_id, "reidentified");
+
+                // reply before the rooms are rejoined, so the user recognizes themselves in the events of the rooms
+                self.mpsc_tx
+                    .send(sequence(Event::Reidentified(
+                        event::ReidentifiedReplyEvent {
+                            previous_user_id,
+                            user_id,
+                        },
+                    )))
+                    .await?;
+
+                // the rooms know the users by their ids, so the user leaves every room with the old id first
+                let drained = self.joined_rooms.drain().collect::<Vec<_>>();
+                let mut rooms = Vec::with_capacity(drained.len());
+                let mut first_err = None;
+                for (room, urp) in drained {
+                    if let Err(err) = self
+                        .cleanup_room(urp, event::DepartureReason::Reidentified)
+                        .await
+                    {
+                        first_err.get_or_insert(err);
+                    }
+                    rooms.push(room);
+                }
+
+                // then joins them back with the new one, a room which can not be joined does not keep the user out of the rest
+                for room in rooms {
+                    let Err(err) = self.join_room(room.clone()).await else {
+                        continue;
+                    };
+
+                    match err.downcast::<event::ErrorReplyEvent>() {
+                        Ok(mut error_event) => {
+                            info!(%room, code = ?error_event.code, "could not rejoin room");
+                            error_event.message =
+                                format!("could not rejoin '{}': {}", room, error_event.message);
+                            if let Err(err) =
+                                self.mpsc_tx.send(sequence(Event::Error(error_event))).await
+                            {
+                                first_err.get_or_insert(err.into());
+                            }
+                        }
+                        Err(err) => {
+                            first_err.get_or_insert(err);
+                        }
+                    }
+                }
+
+                if let Some(err) = first_err {
+                    return Err(err);
                 }
             }
+            UserCommand::ListJoinedRooms(_) => {
+                let mut rooms = self.joined_rooms.keys().cloned().collect::<Vec<_>>();
+                rooms.sort();
+
+                self.mpsc_tx
+                    .send(sequence(Event::JoinedRooms(event::JoinedRoomsReplyEvent {
+                        rooms,
+                    })))
+                    .await?;
+            }
             _ => {}
         }
 
         Ok(())
     }
 
+    /// The canonical name of the given room, which keys the joined rooms
+    /// Rooms which do not exist are kept as they are, since they can not have been joined either
+    fn canonical_room_name(&self, room: &str) -> String {
+        self.room_manager
+            .resolve_room_name(room)
+            .unwrap_or_else(|| String::from(room))
+    }
+
+    /// Joins the given room, replying the room details to the user and forwarding the events of the room to them
+    async fn join_room(&mut self, room: String) -> anyhow::Result<()> {
+        let RoomJoin {
+            broadcast_rx,
+            handle: user_session_handle,
+            user_ids,
+            display_names,
+            history,
+            joined_at,
+        } = self
+            .room_manager
+            .join_room(&room, &self.session_and_user_id)
+            .await?;
+
+        // start with sending the user joined room event as a reply to the user
+        // it is queued before the forwarder is spawned, so it precedes every broadcast of the room
+        self.mpsc_tx
+            .send(sequence(Event::UserJoinedRoom(
+                event::UserJoinedRoomReplyEvent {
+                    room: room.clone(),
+                    users: user_ids,
+                    display_names,
+                    joined_at,
+                    history,
+                },
+            )))
+            .await?;
+
+        // spawn a task to forward broadcasted messages to the users' mpsc channel
+        // hence the user can receive messages from different rooms via single channel
+        let abort_handle = self.join_set.spawn(forward_room_events(
+            room.clone(),
+            broadcast_rx,
+            self.mpsc_tx.clone(),
+            Arc::clone(&self.too_slow),
+            Arc::clone(&self.room_manager),
+        ));
+
+        info!(%room, "joined room");
+
+        // store references to the user session handle and abort handle
+        // this is used to send messages to the room and to cancel the task when user leaves the room
+        self.joined_rooms
+            .insert(room, (user_session_handle, abort_handle));
+
+        Ok(())
+    }
+
     // TODO: optimize the performance of this function. leaving one by one may not be a good idea.
-    /// Leave all the rooms the user is currently participating in
-    pub async fn leave_all_rooms(&mut self) -> anyhow::Result<()> {
+    /// Leave all the rooms the user is currently participating in, for the given reason
+    pub async fn leave_all_rooms(&mut self, reason: event::DepartureReason) -> anyhow::Result<()> {
         // drain the joined rooms to a variable, necessary to avoid borrowing self
         let drained = self.joined_rooms.drain().collect::<Vec<_>>();
 
         for (_, urp) in drained {
-            self.cleanup_room(urp).await?;
+            self.cleanup_room(urp, reason).await?;
         }
 
         Ok(())
@@ -112,21 +454,887 @@ impl ChatSession {
     async fn cleanup_room(
         &mut self,
         (user_session_handle, abort_handle): (UserSessionHandle, AbortHandle),
+        reason: event::DepartureReason,
     ) -> anyhow::Result<()> {
+        // stop forwarding first, so the task does not outlive the room even if leaving fails
+        abort_handle.abort();
+
         self.room_manager
-            .drop_user_session_handle(user_session_handle)
-            .await?;
+            .drop_user_session_handle(user_session_handle, reason)
+            .await
+    }
 
-        abort_handle.abort();
+    /// Leave the rooms the user was kicked from without noticing, e.g. while lagging behind the room
+    async fn drop_revoked_rooms(&mut self) -> anyhow::Result<()> {
+        let revoked = self
+            .joined_rooms
+            .iter()
+            .filter(|(_, (user_session_handle, _))| user_session_handle.is_revoked())
+            .map(|(room, _)| room.clone())
+            .collect::<Vec<_>>();
+
+        for room in revoked {
+            info!(room = %room, "kicked from room");
+            if let Some(urp) = self.joined_rooms.remove(&room) {
+                self.cleanup_room(urp, event::DepartureReason::Kicked)
+                    .await?;
+            }
+        }
 
         Ok(())
     }
 
+    /// Handle an event received from the rooms before it is sent to the user
+    ///
+    /// # Returns
+    ///
+    /// - The event to send to the user, or None if the event is not meant for the user
+    pub async fn handle_event(
+        &mut self,
+        event: SequencedEvent,
+    ) -> anyhow::Result<Option<SequencedEvent>> {
+        match &event.event {
+            Event::UserKicked(kicked) if kicked.user_id != self.session_and_user_id.user_id => {
+                Ok(None)
+            }
+            // the user was kicked out of the room, drop our handle and stop forwarding the room's events
+            Event::UserKicked(kicked) => {
+                info!(room = %kicked.room, "kicked from room");
+                if let Some(urp) = self.joined_rooms.remove(&kicked.room) {
+                    self.cleanup_room(urp, event::DepartureReason::Kicked)
+                        .await?;
+                }
+
+                Ok(Some(event))
+            }
+            _ => Ok(Some(event)),
+        }
+    }
+
     /// Recieve an event that may have originated from any of the rooms the user is actively participating in
-    pub async fn recv(&mut self) -> anyhow::Result<Event> {
+    pub async fn recv(&mut self) -> anyhow::Result<SequencedEvent> {
         self.mpsc_rx
             .recv()
             .await
             .context("could not recv from the broadcast channel")
     }
+
+    /// Notified when the user can not keep up with the events of the rooms, and should be disconnected
+    pub fn too_slow(&self) -> Arc<Notify> {
+        Arc::clone(&self.too_slow)
+    }
+
+    /// Recieve the events that are ready, up to the given limit, in the order they have arrived
+    /// Waits until there is at least one event, so the returned events are never empty
+    pub async fn recv_many(&mut self, limit: usize) -> anyhow::Result<Vec<SequencedEvent>> {
+        let mut events = vec![self.recv().await?];
+        while events.len() < limit {
+            match self.mpsc_rx.try_recv() {
+                Ok(event) => events.push(event),
+                Err(_) => break,
+            }
+        }
+
+        Ok(events)
+    }
+}
+
+/// Events which are superseded by the later ones, hence can be dropped for a user who is falling behind
+fn is_ephemeral(event: &Event) -> bool {
+    matches!(
+        event,
+        Event::PresenceChanged(_) | Event::RoomUsersSnapshot(_)
+    )
+}
+
+/// The events which let a user who could not keep up with the room recover from the missed events
+///
+/// The missed events may have changed the participants of the room, so the user is sent the current ones to reconcile with.
+async fn lag_recovery_events(
+    room: &str,
+    count: u64,
+    room_manager: &RoomManager,
+) -> Vec<SequencedEvent> {
+    let mut events = vec![sequence(Event::MessagesMissed(
+        event::MessagesMissedReplyEvent {
+            room: String::from(room),
+            count,
+        },
+    ))];
+
+    match room_manager.room_users(room).await {
+        Ok(room_users) => events.push(sequence(Event::RoomUsersSnapshot(
+            event::RoomUsersSnapshotBroadcastEvent {
+                room: room_users.room,
+                users: room_users.users,
+            },
+        ))),
+        // the room is going away, the forwarder stops once its channel is closed
+        Err(err) => debug!(%room, "could not take a users snapshot after the lag: {:#}", err),
+    }
+
+    events
+}
+
+/// Forwards the events of a room to the session channel of the user, until the room is closed
+///
+/// The chat messages are prioritized over the ephemeral events for the users who are falling behind:
+/// - the ephemeral events, such as presence changes, are dropped once the channel is three quarters full
+/// - every other event waits for room in the channel, if it waits longer than [SLOW_CLIENT_TIMEOUT]
+///   the user is deemed too slow, `too_slow` is notified and forwarding stops
+///
+/// Users who have lagged behind the room are told how many events they have missed,
+/// followed by a snapshot of the current users of the room, which is never dropped.
+async fn forward_room_events(
+    room: String,
+    mut broadcast_rx: broadcast::Receiver<SequencedEvent>,
+    mpsc_tx: mpsc::Sender<SequencedEvent>,
+    too_slow: Arc<Notify>,
+    room_manager: Arc<RoomManager>,
+) {
+    loop {
+        let events = match broadcast_rx.recv().await {
+            Ok(event) => {
+                if is_ephemeral(&event.event) && mpsc_tx.capacity() < mpsc_tx.max_capacity() / 4 {
+                    debug!(%room, "session channel is almost full, dropping an ephemeral event");
+                    continue;
+                }
+
+                vec![event]
+            }
+            // the user could not keep up with the room, let them know and keep forwarding
+            Err(RecvError::Lagged(count)) => lag_recovery_events(&room, count, &room_manager).await,
+            Err(RecvError::Closed) => break,
+        };
+
+        for event in events {
+            match mpsc_tx.send_timeout(event, SLOW_CLIENT_TIMEOUT).await {
+                Ok(()) => {}
+                Err(SendTimeoutError::Timeout(_)) => {
+                    too_slow.notify_one();
+                    return;
+                }
+                Err(SendTimeoutError::Closed(_)) => return,
+            }
+        }
+    }
+}
+
+impl Drop for ChatSession {
+    /// Release the id and the display name of the user, so they can be picked by other users
+    fn drop(&mut self) {
+        self.room_manager
+            .release_user_id(&self.session_and_user_id.user_id);
+        if let Some(display_name) = self.claimed_display_name.as_ref() {
+            self.room_manager.release_display_name(display_name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use comms::command;
+
+    use crate::{
+        message_store::MessageStore,
+        room_manager::{ChatRoomMetadata, RoomManagerBuilder},
+        sequencer::SequencedSender,
+    };
+
+    use super::*;
+
+    fn create_chat_session() -> ChatSession {
+        let room_manager = RoomManagerBuilder::new()
+            .create_room(ChatRoomMetadata {
+                name: "rust".into(),
+                description: "Rust".into(),
+                ..Default::default()
+            })
+            .build()
+            .unwrap();
+
+        ChatSession::new("session-1", "user-1", Arc::new(room_manager))
+    }
+
+    fn send_message(content: String) -> UserCommand {
+        UserCommand::SendMessage(command::SendMessageCommand {
+            room: "rust".into(),
+            content,
+            client_msg_id: None,
+        })
+    }
+
+    /// Receive events until a user message arrives and return its content
+    async fn recv_message_content(chat_session: &mut ChatSession) -> String {
+        loop {
+            if let Event::UserMessage(event) = chat_session.recv().await.unwrap().event {
+                return event.content;
+            }
+        }
+    }
+
+    async fn join_room(chat_session: &mut ChatSession) {
+        chat_session
+            .handle_user_command(UserCommand::JoinRoom(command::JoinRoomCommand {
+                room: "rust".into(),
+            }))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_room_scoped_commands_resolve_the_room_name() {
+        let mut chat_session = create_chat_session();
+        chat_session
+            .handle_user_command(UserCommand::JoinRoom(command::JoinRoomCommand {
+                room: "Rust".into(),
+            }))
+            .await
+            .unwrap();
+
+        chat_session
+            .handle_user_command(UserCommand::SendMessage(command::SendMessageCommand {
+                room: "RUST".into(),
+                content: "hello".into(),
+                client_msg_id: Some("client-1".into()),
+            }))
+            .await
+            .unwrap();
+        let ack = loop {
+            if let Event::MessageAck(ack) = chat_session.recv().await.unwrap().event {
+                break ack;
+            }
+        };
+        assert_eq!(ack.room, "rust");
+
+        chat_session
+            .handle_user_command(UserCommand::DeleteMessage(command::DeleteMessageCommand {
+                room: "Rust".into(),
+                message_id: ack.message_id,
+            }))
+            .await
+            .unwrap();
+        chat_session
+            .handle_user_command(UserCommand::LeaveRoom(command::LeaveRoomCommand {
+                room: "RUST".into(),
+            }))
+            .await
+            .unwrap();
+        assert!(chat_session.joined_rooms.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_joining_more_rooms_than_allowed_is_rejected() {
+        let room_manager = (0..=MAX_ROOMS_PER_SESSION)
+            .fold(RoomManagerBuilder::new(), |builder, i| {
+                builder.create_room(ChatRoomMetadata {
+                    name: format!("room-{}", i),
+                    description: format!("Room {}", i),
+                    ..Default::default()
+                })
+            })
+            .build()
+            .unwrap();
+        let mut chat_session = ChatSession::new("session-1", "user-1", Arc::new(room_manager));
+        let join = |i: usize| {
+            UserCommand::JoinRoom(command::JoinRoomCommand {
+                room: format!("room-{}", i),
+            })
+        };
+
+        for i in 0..MAX_ROOMS_PER_SESSION {
+            chat_session.handle_user_command(join(i)).await.unwrap();
+        }
+
+        let err = chat_session
+            .handle_user_command(join(MAX_ROOMS_PER_SESSION))
+            .await
+            .unwrap_err()
+            .downcast::<event::ErrorReplyEvent>()
+            .unwrap();
+        assert_eq!(err.code, event::ErrorCode::TooManyRooms);
+        assert_eq!(chat_session.joined_rooms.len(), MAX_ROOMS_PER_SESSION);
+    }
+
+    #[tokio::test]
+    async fn test_joined_rooms_are_listed() {
+        let room_manager = ["rust", "go", "zig"]
+            .into_iter()
+            .fold(RoomManagerBuilder::new(), |builder, name| {
+                builder.create_room(ChatRoomMetadata {
+                    name: name.into(),
+                    description: name.into(),
+                    ..Default::default()
+                })
+            })
+            .build()
+            .unwrap();
+        let mut chat_session = ChatSession::new("session-1", "user-1", Arc::new(room_manager));
+
+        for room in ["rust", "go"] {
+            chat_session
+                .handle_user_command(UserCommand::JoinRoom(command::JoinRoomCommand {
+                    room: room.into(),
+                }))
+                .await
+                .unwrap();
+        }
+        chat_session
+            .handle_user_command(UserCommand::ListJoinedRooms(
+                command::ListJoinedRoomsCommand,
+            ))
+            .await
+            .unwrap();
+
+        loop {
+            if let Event::JoinedRooms(event) = chat_session.recv().await.unwrap().event {
+                assert_eq!(event.rooms, vec!["go", "rust"]);
+                break;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_oversized_message_is_rejected_and_not_broadcasted() {
+        let mut chat_session = create_chat_session();
+        join_room(&mut chat_session).await;
+
+        let err = chat_session
+            .handle_user_command(send_message("a".repeat(MAX_MESSAGE_LEN + 1)))
+            .await
+            .unwrap_err()
+            .downcast::<event::ErrorReplyEvent>()
+            .unwrap();
+        assert_eq!(err.code, event::ErrorCode::MessageTooLong);
+
+        // the broadcast channel is ordered, so the first message we see must be the one sent afterwards
+        chat_session
+            .handle_user_command(send_message("a".repeat(MAX_MESSAGE_LEN)))
+            .await
+            .unwrap();
+        assert_eq!(
+            recv_message_content(&mut chat_session).await,
+            "a".repeat(MAX_MESSAGE_LEN)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_whitespace_only_message_is_rejected_and_not_broadcasted() {
+        let mut chat_session = create_chat_session();
+        join_room(&mut chat_session).await;
+
+        let err = chat_session
+            .handle_user_command(send_message("   ".into()))
+            .await
+            .unwrap_err()
+            .downcast::<event::ErrorReplyEvent>()
+            .unwrap();
+        assert_eq!(err.code, event::ErrorCode::EmptyMessage);
+
+        chat_session
+            .handle_user_command(send_message("hello".into()))
+            .await
+            .unwrap();
+        assert_eq!(recv_message_content(&mut chat_session).await, "hello");
+    }
+
+    /// Fails to persist any message, as if the disk was full
+    #[derive(Debug)]
+    struct FailingMessageStore;
+
+    impl MessageStore for FailingMessageStore {
+        fn save(&self, _message: &event::UserMessageBroadcastEvent) -> anyhow::Result<()> {
+            anyhow::bail!("disk is full")
+        }
+
+        fn delete(&self, _room: &str, _message_id: &str) -> anyhow::Result<()> {
+            anyhow::bail!("disk is full")
+        }
+
+        fn load_recent(
+            &self,
+            _room: &str,
+            _limit: usize,
+        ) -> anyhow::Result<Vec<event::UserMessageBroadcastEvent>> {
+            Ok(Vec::new())
+        }
+
+        fn load_before(
+            &self,
+            _room: &str,
+            _before_message_id: &str,
+            _limit: usize,
+        ) -> anyhow::Result<Vec<event::UserMessageBroadcastEvent>> {
+            Ok(Vec::new())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_message_which_could_not_be_persisted_is_rejected_and_not_broadcasted() {
+        let room_manager = RoomManagerBuilder::new()
+            .create_room(ChatRoomMetadata {
+                name: "rust".into(),
+                description: "Rust".into(),
+                ..Default::default()
+            })
+            .message_store(Arc::new(FailingMessageStore))
+            .build()
+            .unwrap();
+        let mut chat_session = ChatSession::new("session-1", "user-1", Arc::new(room_manager));
+        join_room(&mut chat_session).await;
+
+        let err = chat_session
+            .handle_user_command(send_message("hello".into()))
+            .await
+            .unwrap_err()
+            .downcast::<event::ErrorReplyEvent>()
+            .unwrap();
+        assert_eq!(err.code, event::ErrorCode::Internal);
+
+        let room_join = chat_session
+            .room_manager
+            .join_room(
+                "rust",
+                &SessionAndUserId {
+                    session_id: "session-2".into(),
+                    user_id: "user-2".into(),
+                    display_name: "user-2".into(),
+                },
+            )
+            .await
+            .unwrap();
+        assert!(room_join.history.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_message_with_client_id_is_acknowledged() {
+        let mut chat_session = create_chat_session();
+        join_room(&mut chat_session).await;
+
+        chat_session
+            .handle_user_command(UserCommand::SendMessage(command::SendMessageCommand {
+                room: "rust".into(),
+                content: "hello".into(),
+                client_msg_id: Some("1".into()),
+            }))
+            .await
+            .unwrap();
+
+        // the ack and the echo of the message are forwarded independently, hence may arrive in any order
+        let (mut ack, mut echo) = (None, None);
+        while ack.is_none() || echo.is_none() {
+            match chat_session.recv().await.unwrap().event {
+                Event::MessageAck(event) => ack = Some(event),
+                Event::UserMessage(event) => echo = Some(event),
+                _ => {}
+            }
+        }
+        let (ack, echo) = (ack.unwrap(), echo.unwrap());
+
+        assert_eq!(ack.room, "rust");
+        assert_eq!(ack.client_msg_id, "1");
+        assert_eq!(ack.message_id, echo.message_id);
+    }
+
+    #[tokio::test]
+    async fn test_recv_many_drains_the_ready_events_in_order() {
+        let mut chat_session = create_chat_session();
+        join_room(&mut chat_session).await;
+
+        for i in 0..5 {
+            chat_session
+                .handle_user_command(send_message(format!("message-{}", i)))
+                .await
+                .unwrap();
+        }
+
+        let mut contents = Vec::new();
+        while contents.len() < 5 {
+            let events = chat_session.recv_many(3).await.unwrap();
+            assert!(!events.is_empty() && events.len() <= 3);
+
+            contents.extend(events.into_iter().filter_map(|event| match event.event {
+                Event::UserMessage(event) => Some(event.content),
+                _ => None,
+            }));
+        }
+
+        assert_eq!(
+            contents,
+            (0..5).map(|i| format!("message-{}", i)).collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_ephemeral_events_are_dropped_and_slow_users_are_detected() {
+        let (broadcast_tx, broadcast_rx) = SequencedSender::new(16);
+        let (mpsc_tx, mut mpsc_rx) = mpsc::channel(8);
+        let too_slow = Arc::new(Notify::new());
+
+        let message = |id: usize| {
+            Event::UserMessage(event::UserMessageBroadcastEvent {
+                room: "rust".into(),
+                message_id: id.to_string(),
+                user_id: "user-2".into(),
+                display_name: "user-2".into(),
+                content: id.to_string(),
+            })
+        };
+        // the presence change arrives when the channel is almost full, and the last message does not fit
+        for id in 0..7 {
+            broadcast_tx.send(message(id)).unwrap();
+        }
+        broadcast_tx
+            .send(Event::PresenceChanged(
+                event::PresenceChangedBroadcastEvent {
+                    user_id: "user-2".into(),
+                    status: event::PresenceStatus::Away,
+                },
+            ))
+            .unwrap();
+        broadcast_tx.send(message(7)).unwrap();
+        broadcast_tx.send(message(8)).unwrap();
+
+        tokio::spawn(forward_room_events(
+            "rust".into(),
+            broadcast_rx,
+            mpsc_tx,
+            Arc::clone(&too_slow),
+            Arc::new(RoomManagerBuilder::new().build().unwrap()),
+        ));
+        too_slow.notified().await;
+
+        let events = std::iter::from_fn(|| mpsc_rx.try_recv().ok())
+            .map(|sequenced| sequenced.event)
+            .collect::<Vec<_>>();
+        assert_eq!(events, (0..8).map(message).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_reidentified_user_rejoins_with_the_new_id() {
+        let mut chat_session = create_chat_session();
+        join_room(&mut chat_session).await;
+        assert!(matches!(
+            chat_session.recv().await.unwrap().event,
+            Event::UserJoinedRoom(_)
+        ));
+
+        chat_session
+            .handle_user_command(UserCommand::Reidentify(command::ReidentifyCommand))
+            .await
+            .unwrap();
+
+        let user_id = match chat_session.recv().await.unwrap().event {
+            Event::Reidentified(event) => {
+                assert_eq!(event.previous_user_id, "user-1");
+                assert_ne!(event.user_id, "user-1");
+                event.user_id
+            }
+            event => panic!("expected the new user id first, got {:?}", event),
+        };
+        loop {
+            if let Event::UserJoinedRoom(event) = chat_session.recv().await.unwrap().event {
+                assert_eq!(event.users, vec![user_id]);
+                break;
+            }
+        }
+        assert_eq!(
+            chat_session.joined_rooms.keys().collect::<Vec<_>>(),
+            vec!["rust"]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_join_reply_precedes_the_broadcasts_of_the_room() {
+        let mut chat_session = create_chat_session();
+        let mut other_handle = chat_session
+            .room_manager
+            .join_room(
+                "rust",
+                &SessionAndUserId {
+                    session_id: "session-2".into(),
+                    user_id: "user-2".into(),
+                    display_name: "user-2".into(),
+                },
+            )
+            .await
+            .unwrap()
+            .handle;
+        other_handle.send_message("before".into()).await.unwrap();
+
+        // the other user keeps sending messages while we join
+        let sender = tokio::spawn(async move {
+            for i in 0..20 {
+                other_handle
+                    .send_message(format!("during-{}", i))
+                    .await
+                    .unwrap();
+                tokio::task::yield_now().await;
+            }
+            other_handle
+        });
+        join_room(&mut chat_session).await;
+        let _other_handle = sender.await.unwrap();
+
+        let mut contents = match chat_session.recv().await.unwrap().event {
+            Event::UserJoinedRoom(event) => event
+                .history
+                .into_iter()
+                .map(|message| message.content)
+                .collect::<Vec<_>>(),
+            event => panic!("expected the join reply first, got {:?}", event),
+        };
+        // the messages are either in the history or forwarded, never both nor neither
+        while contents.last().map(String::as_str) != Some("during-19") {
+            if let Event::UserMessage(event) = chat_session.recv().await.unwrap().event {
+                contents.push(event.content);
+            }
+        }
+
+        assert_eq!(
+            contents,
+            std::iter::once(String::from("before"))
+                .chain((0..20).map(|i| format!("during-{}", i)))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lagging_user_is_notified_and_keeps_receiving() {
+        let mut chat_session = create_chat_session();
+        join_room(&mut chat_session).await;
+
+        // flood the room from another user, without letting the forwarder catch up
+        let mut other_handle = chat_session
+            .room_manager
+            .join_room(
+                "rust",
+                &SessionAndUserId {
+                    session_id: "session-2".into(),
+                    user_id: "user-2".into(),
+                    display_name: "user-2".into(),
+                },
+            )
+            .await
+            .unwrap()
+            .handle;
+        for i in 0..300 {
+            other_handle
+                .send_message(format!("message-{}", i))
+                .await
+                .unwrap();
+        }
+
+        let mut missed = 0;
+        let last_content = loop {
+            match chat_session.recv().await.unwrap().event {
+                Event::MessagesMissed(event) => {
+                    missed += event.count;
+
+                    // the user can reconcile the participants they may have missed
+                    match chat_session.recv().await.unwrap().event {
+                        Event::RoomUsersSnapshot(snapshot) => {
+                            let mut users = snapshot.users;
+                            users.sort();
+                            assert_eq!(users, vec!["user-1", "user-2"]);
+                        }
+                        event => panic!("expected a users snapshot, got {:?}", event),
+                    }
+                }
+                Event::UserMessage(event) if event.content == "message-299" => break event.content,
+                _ => {}
+            }
+        };
+
+        assert!(missed > 0);
+        assert_eq!(last_content, "message-299");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_message_burst_is_rate_limited() {
+        let mut chat_session = create_chat_session();
+        join_room(&mut chat_session).await;
+
+        let mut rate_limited = 0;
+        for i in 0..20 {
+            if let Err(err) = chat_session
+                .handle_user_command(send_message(format!("message-{}", i)))
+                .await
+            {
+                let err = err.downcast::<event::ErrorReplyEvent>().unwrap();
+                assert_eq!(err.code, event::ErrorCode::RateLimited);
+                rate_limited += 1;
+            }
+        }
+        assert_eq!(rate_limited, 20 - MESSAGE_BURST);
+
+        // only the messages within the burst are broadcasted
+        for i in 0..MESSAGE_BURST {
+            assert_eq!(
+                recv_message_content(&mut chat_session).await,
+                format!("message-{}", i)
+            );
+        }
+        let next_message = tokio::time::timeout(
+            Duration::from_secs(1),
+            recv_message_content(&mut chat_session),
+        )
+        .await;
+        assert!(next_message.is_err());
+    }
+
+    #[test]
+    fn test_validate_display_name() {
+        assert_eq!(validate_display_name("  alice ").unwrap(), "alice");
+        assert_eq!(
+            validate_display_name(&"é".repeat(MAX_DISPLAY_NAME_LEN)).unwrap(),
+            "é".repeat(MAX_DISPLAY_NAME_LEN)
+        );
+
+        assert!(validate_display_name("").is_err());
+        assert!(validate_display_name("   ").is_err());
+        assert!(validate_display_name(&"a".repeat(MAX_DISPLAY_NAME_LEN + 1)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_kicked_user_leaves_the_room() {
+        let mut moderator = create_chat_session();
+        join_room(&mut moderator).await;
+        let mut kicked =
+            ChatSession::new("session-2", "user-2", Arc::clone(&moderator.room_manager));
+        join_room(&mut kicked).await;
+
+        moderator
+            .handle_user_command(UserCommand::KickUser(command::KickUserCommand {
+                room: "rust".into(),
+                user_id: "user-2".into(),
+            }))
+            .await
+            .unwrap();
+
+        // the moderator does not receive the kick event, only the kicked user does
+        let kick_event = loop {
+            let event = kicked.recv().await.unwrap();
+            if let Some(SequencedEvent {
+                event: event @ Event::UserKicked(_),
+                ..
+            }) = kicked.handle_event(event).await.unwrap()
+            {
+                break event;
+            }
+        };
+        assert_eq!(
+            kick_event,
+            Event::UserKicked(event::UserKickedBroadcastEvent {
+                room: "rust".into(),
+                user_id: "user-2".into(),
+                moderator_id: "user-1".into(),
+            })
+        );
+        assert!(!kicked.joined_rooms.contains_key("rust"));
+
+        let err = kicked
+            .handle_user_command(send_message("still here?".into()))
+            .await
+            .unwrap_err()
+            .downcast::<event::ErrorReplyEvent>()
+            .unwrap();
+        assert_eq!(err.code, event::ErrorCode::NotInRoom);
+    }
+
+    #[tokio::test]
+    async fn test_user_who_missed_the_kick_leaves_the_room_on_their_next_command() {
+        let mut moderator = create_chat_session();
+        join_room(&mut moderator).await;
+        let mut kicked =
+            ChatSession::new("session-2", "user-2", Arc::clone(&moderator.room_manager));
+        join_room(&mut kicked).await;
+
+        // the kicked user never handles the kick event, as if it was dropped while they were lagging
+        moderator
+            .handle_user_command(UserCommand::KickUser(command::KickUserCommand {
+                room: "rust".into(),
+                user_id: "user-2".into(),
+            }))
+            .await
+            .unwrap();
+
+        let err = kicked
+            .handle_user_command(send_message("still here?".into()))
+            .await
+            .unwrap_err()
+            .downcast::<event::ErrorReplyEvent>()
+            .unwrap();
+        assert_eq!(err.code, event::ErrorCode::NotInRoom);
+        assert!(!kicked.joined_rooms.contains_key("rust"));
+
+        // the user can join the room again, as they are no longer in it
+        join_room(&mut kicked).await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_slow_mode_throttles_the_messages_of_each_user() {
+        let mut moderator = create_chat_session();
+        join_room(&mut moderator).await;
+        let mut user = ChatSession::new("session-2", "user-2", Arc::clone(&moderator.room_manager));
+        join_room(&mut user).await;
+        let set_slow_mode = |seconds| {
+            UserCommand::SetSlowMode(command::SetSlowModeCommand {
+                room: "rust".into(),
+                seconds,
+            })
+        };
+
+        // only the moderators can turn the slow mode on
+        let err = user
+            .handle_user_command(set_slow_mode(30))
+            .await
+            .unwrap_err()
+            .downcast::<event::ErrorReplyEvent>()
+            .unwrap();
+        assert_eq!(err.code, event::ErrorCode::NotModerator);
+        moderator
+            .handle_user_command(set_slow_mode(30))
+            .await
+            .unwrap();
+
+        user.handle_user_command(send_message("first".into()))
+            .await
+            .unwrap();
+        assert_eq!(recv_message_content(&mut user).await, "first");
+
+        // the rejected messages do not use up the rate limit of the user
+        for _ in 0..MESSAGE_BURST {
+            let err = user
+                .handle_user_command(send_message("too soon".into()))
+                .await
+                .unwrap_err()
+                .downcast::<event::ErrorReplyEvent>()
+                .unwrap();
+            assert_eq!(err.code, event::ErrorCode::SlowMode);
+        }
+
+        tokio::time::advance(Duration::from_millis(10_500)).await;
+        let err = user
+            .handle_user_command(send_message("too soon".into()))
+            .await
+            .unwrap_err()
+            .downcast::<event::ErrorReplyEvent>()
+            .unwrap();
+        assert_eq!(err.code, event::ErrorCode::SlowMode);
+        assert_eq!(err.retry_after_secs, Some(20));
+
+        // the window is per user
+        moderator
+            .handle_user_command(send_message("from the moderator".into()))
+            .await
+            .unwrap();
+
+        tokio::time::advance(Duration::from_secs(20)).await;
+        user.handle_user_command(send_message("second".into()))
+            .await
+            .unwrap();
+        assert_eq!(recv_message_content(&mut user).await, "from the moderator");
+        assert_eq!(recv_message_content(&mut user).await, "second");
+    }
 }
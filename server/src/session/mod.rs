@@ -1,32 +1,141 @@
 use std::sync::Arc;
 
-use comms::{
-    command::UserCommand,
-    event::{self, RoomDetail},
-    transport,
-};
+use comms::{command::UserCommand, event, transport};
 use nanoid::nanoid;
-use tokio::{net::TcpStream, sync::broadcast};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::broadcast,
+    time::{Duration, Instant},
+};
 use tokio_stream::StreamExt;
+use tracing::{debug, info};
 
-use crate::room_manager::RoomManager;
+use crate::{
+    auth::AuthTokens,
+    room_manager::{ChatRoomMetadata, RoomManager},
+    sequencer::sequence,
+};
 
 use self::chat_session::ChatSession;
 
 mod chat_session;
+mod rate_limiter;
+
+/// How many of the ready events are written to the user at once
+const MAX_EVENT_BATCH_SIZE: usize = 32;
+/// How often the user is pinged, to find out whether they are still reading
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+/// How long the user has to answer a ping, before the connection is deemed dead
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long the user has to authenticate after connecting, when the server requires it
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Generates a random id for a user, since we don't have a login system
+fn generate_user_id() -> String {
+    String::from(&nanoid!()[0..5])
+}
+
+/// Writes the events to the user, with their sequence numbers if the user has asked for them
+struct SessionEventWriter {
+    event_writer: transport::server::EventWriter,
+    is_sequenced: bool,
+}
+
+impl SessionEventWriter {
+    /// Writes an event produced by the session itself, stamping it if the user has asked for the sequence numbers
+    async fn write(&mut self, event: &event::Event) -> anyhow::Result<()> {
+        if self.is_sequenced {
+            self.event_writer
+                .write_sequenced(&sequence(event.clone()))
+                .await
+        } else {
+            self.event_writer.write(event).await
+        }
+    }
+
+    /// Writes an event broadcasted to the user, which is stamped when it is broadcasted
+    async fn write_sequenced(&mut self, mut event: event::SequencedEvent) -> anyhow::Result<()> {
+        if !self.is_sequenced {
+            event.seq = None;
+        }
+
+        self.event_writer.write_sequenced(&event).await
+    }
+
+    async fn write_batch(&mut self, mut events: Vec<event::SequencedEvent>) -> anyhow::Result<()> {
+        if !self.is_sequenced {
+            events.iter_mut().for_each(|event| event.seq = None);
+        }
+
+        self.event_writer.write_batch(&events).await
+    }
+}
 
-/// Given a tcp stream and a room manager, handles the user session
-/// until the user quits the session, or the tcp stream is closed for some reason, or the server shuts down
-pub async fn handle_user_session(
+/// Waits for the user to authenticate with one of the accepted tokens as their first command,
+/// the reason is returned if they do not
+async fn authenticate(
+    commands: &mut transport::server::CommandStream,
+    auth_tokens: &AuthTokens,
+) -> Result<(), String> {
+    match tokio::time::timeout(AUTH_TIMEOUT, commands.next()).await {
+        Ok(Some(Ok(UserCommand::Authenticate(cmd)))) if auth_tokens.is_valid(&cmd.token) => Ok(()),
+        Ok(Some(Ok(UserCommand::Authenticate(_)))) => Err(String::from("invalid token")),
+        Ok(Some(_)) => Err(String::from("the server requires authentication")),
+        Ok(None) => Err(String::from(
+            "the connection was closed before authenticating",
+        )),
+        Err(_) => Err(format!(
+            "did not authenticate in {} secs",
+            AUTH_TIMEOUT.as_secs()
+        )),
+    }
+}
+
+/// Given a stream, plain or encrypted, and a room manager, handles the user session
+/// until the user quits the session, or the tcp stream is closed for some reason, or the server shuts down,
+/// or the user does not send any commands besides the keepalives within the idle timeout, or does not answer the pings of the server.
+/// If `auth_tokens` are given, the user has to authenticate with one of them before anything else.
+/// If a `motd` is given, the user is greeted with it as a system message right after they log in.
+#[tracing::instrument(name = "session", skip_all, fields(session_id, user_id))]
+pub async fn handle_user_session<S>(
     room_manager: Arc<RoomManager>,
-    mut quit_rx: broadcast::Receiver<()>,
-    stream: TcpStream,
-) -> anyhow::Result<()> {
+    mut quit_rx: broadcast::Receiver<event::ServerShutdownBroadcastEvent>,
+    stream: S,
+    idle_timeout: Duration,
+    auth_tokens: Option<Arc<AuthTokens>>,
+    motd: Option<Arc<str>>,
+) -> anyhow::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
     let session_id = nanoid!();
-    // Generate a random id for the user, since we don't have a login system
-    let user_id = String::from(&nanoid!()[0..5]);
+    let user_id = generate_user_id();
+    tracing::Span::current()
+        .record("session_id", &session_id)
+        .record("user_id", &user_id);
+    info!("session started");
     // Split the tcp stream into a command stream and an event writer with better ergonomics
-    let (mut commands, mut event_writer) = transport::server::split_tcp_stream(stream);
+    let (mut commands, event_writer) = transport::server::split_stream(stream);
+    // the events are stamped with their sequence numbers once the user asks for them in their hello
+    let mut event_writer = SessionEventWriter {
+        event_writer,
+        is_sequenced: false,
+    };
+
+    // Only the users with a valid token are let in, if the server requires authentication
+    if let Some(auth_tokens) = auth_tokens {
+        if let Err(reason) = authenticate(&mut commands, &auth_tokens).await {
+            info!(reason, "unauthorized, disconnecting");
+            // the user may have already gone away, which is fine since we are closing anyway
+            let _ = event_writer
+                .write(&event::Event::Error(event::ErrorReplyEvent::new(
+                    event::ErrorCode::Unauthorized,
+                    reason,
+                )))
+                .await;
+            return Ok(());
+        }
+    }
 
     // Welcoming the user with a login successful event and necessary information about the server
     event_writer
@@ -34,55 +143,641 @@ pub async fn handle_user_session(
             event::LoginSuccessfulReplyEvent {
                 session_id: session_id.clone(),
                 user_id: user_id.clone(),
-                rooms: room_manager
-                    .chat_room_metadatas()
-                    .iter()
-                    .map(|metadata| RoomDetail {
-                        name: metadata.name.clone(),
-                        description: metadata.description.clone(),
-                    })
-                    .collect(),
+                rooms: room_manager.room_details().await,
+                server_version: comms::PROTOCOL_VERSION,
             },
         ))
         .await?;
 
+    if let Some(motd) = motd {
+        event_writer
+            .write(&event::Event::SystemMessage(
+                event::SystemMessageBroadcastEvent {
+                    content: String::from(&*motd),
+                },
+            ))
+            .await?;
+    }
+
+    // Subscribe before answering any commands, so the user does not miss rooms created in the meantime
+    let mut server_events_rx = room_manager.subscribe_server_events();
+
     // Create a chat session with the given room manager
     // Chat Session will abstract the user session handling logic for multiple rooms
-    let mut chat_session = ChatSession::new(&session_id, &user_id, room_manager);
+    let mut chat_session = ChatSession::new(&session_id, &user_id, Arc::clone(&room_manager));
+    let too_slow = chat_session.too_slow();
+
+    // Closes the session if it elapses, pushed back every time the user sends a command
+    let idle = tokio::time::sleep(idle_timeout);
+    tokio::pin!(idle);
+    // Pings the user periodically, the half-open connections are not noticed otherwise until a write fails
+    let mut heartbeat =
+        tokio::time::interval_at(Instant::now() + HEARTBEAT_INTERVAL, HEARTBEAT_INTERVAL);
+    // Closes the session if it elapses while a ping is waiting for its pong
+    let pong_deadline = tokio::time::sleep(HEARTBEAT_TIMEOUT);
+    tokio::pin!(pong_deadline);
+    let mut is_awaiting_pong = false;
 
     loop {
         tokio::select! {
-            cmd = commands.next() => match cmd {
-                // If the user closes the tcp stream, or sends a quit cmd
-                // We need to cleanup resources in a way that the other users are notified about the user's departure
-                None | Some(Ok(UserCommand::Quit(_))) => {
-                    chat_session.leave_all_rooms().await?;
-                    break;
+            cmd = commands.next() => {
+                // the pings and pongs are sent by the client on its own, so they do not tell that the user is active
+                if !matches!(cmd, Some(Ok(UserCommand::Ping(_) | UserCommand::Pong(_)))) {
+                    idle.as_mut().reset(Instant::now() + idle_timeout);
                 }
-                // Handle a valid user command
-                Some(Ok(cmd)) => match cmd {
-                    // For user session related commands, we need to handle them in the chat session
-                    UserCommand::JoinRoom(_) | UserCommand::SendMessage(_) | UserCommand::LeaveRoom(_) => {
-                        chat_session.handle_user_command(cmd).await?;
+
+                match cmd {
+                    // If the user closes the tcp stream, or sends a quit cmd
+                    // We need to cleanup resources in a way that the other users are notified about the user's departure
+                    None => {
+                        info!("connection closed");
+                        chat_session.leave_all_rooms(event::DepartureReason::Disconnected).await?;
+                        break;
+                    }
+                    Some(Ok(UserCommand::Quit(_))) => {
+                        info!("user has quit");
+                        chat_session.leave_all_rooms(event::DepartureReason::Quit).await?;
+                        break;
+                    }
+                    // The client speaks a protocol version we can not understand, let them know and close the session
+                    // Clients which do not introduce themselves are assumed to be compatible
+                    Some(Ok(UserCommand::Hello(cmd))) if cmd.client_version != comms::PROTOCOL_VERSION => {
+                        info!(client_version = cmd.client_version, "incompatible client version");
+                        event_writer
+                            .write(&event::Event::Error(event::ErrorReplyEvent::new(
+                                event::ErrorCode::IncompatibleVersion,
+                                format!(
+                                    "client version {} is not supported, server version is {}",
+                                    cmd.client_version,
+                                    comms::PROTOCOL_VERSION
+                                ),
+                            )))
+                            .await?;
+                        chat_session.leave_all_rooms(event::DepartureReason::Disconnected).await?;
+                        break;
+                    }
+                    // Handle a valid user command
+                    Some(Ok(cmd)) => match cmd {
+                        // The user speaks our protocol, and may ask for the sequence numbers of the events
+                        UserCommand::Hello(cmd) => {
+                            event_writer.is_sequenced = cmd.sequenced;
+                        }
+                        // For user session related commands, we need to handle them in the chat session
+                        UserCommand::JoinRoom(_)
+                        | UserCommand::SendMessage(_)
+                        | UserCommand::DeleteMessage(_)
+                        | UserCommand::FetchHistory(_)
+                        | UserCommand::SetUsername(_)
+                        | UserCommand::Reidentify(_)
+                        | UserCommand::SetPresence(_)
+                        | UserCommand::KickUser(_)
+                        | UserCommand::SetSlowMode(_)
+                        | UserCommand::LeaveRoom(_)
+                        | UserCommand::ListJoinedRooms(_) => {
+                            if let Err(err) = chat_session.handle_user_command(cmd).await {
+                                // errors meant for the user are replied back, the rest are fatal for the session
+                                let error_event = err.downcast::<event::ErrorReplyEvent>()?;
+                                debug!(code = ?error_event.code, "command failed");
+                                event_writer.write(&event::Event::Error(error_event)).await?;
+                            }
+                        }
+                        // Keepalive pings are answered immediately
+                        UserCommand::Ping(_) => {
+                            event_writer.write(&event::Event::Pong(event::PongReplyEvent)).await?;
+                        }
+                        // The user is still reading, the connection is alive
+                        UserCommand::Pong(_) => {
+                            is_awaiting_pong = false;
+                        }
+                        // Server wide queries are answered directly with a fresh snapshot
+                        UserCommand::ListRooms(_) => {
+                            event_writer
+                                .write(&event::Event::RoomList(event::RoomListReplyEvent {
+                                    rooms: room_manager.room_details().await,
+                                }))
+                                .await?;
+                        }
+                        UserCommand::ListRoomUsers(cmd) => {
+                            let event = match room_manager.room_users(&cmd.room).await {
+                                Ok(room_users) => event::Event::RoomUsers(room_users),
+                                Err(err) => event::Event::Error(err.downcast::<event::ErrorReplyEvent>()?),
+                            };
+                            event_writer.write(&event).await?;
+                        }
+                        UserCommand::CreateRoom(cmd) => {
+                            if let Err(err) = room_manager.create_room(ChatRoomMetadata {
+                                name: cmd.name,
+                                description: cmd.description,
+                                ..Default::default()
+                            }).await {
+                                let error_event = err.downcast::<event::ErrorReplyEvent>()?;
+                                event_writer.write(&event::Event::Error(error_event)).await?;
+                            }
+                        }
+                        _ => {}
+                    }
+                    // The user has sent a command which could not be read or parsed, let them know
+                    Some(Err(err)) => {
+                        debug!("invalid command: {:#}", err);
+                        event_writer
+                            .write(&event::Event::Error(event::ErrorReplyEvent::new(
+                                event::ErrorCode::InvalidCommand,
+                                format!("{:#}", err),
+                            )))
+                            .await?;
                     }
-                    _ => {}
                 }
-                _ => {}
-            },
+            }
+            // The user has not sent anything for a while, let them know and close the session
+            _ = &mut idle => {
+                info!("session is idle, disconnecting");
+                event_writer
+                    .write(&event::Event::Error(event::ErrorReplyEvent::new(
+                        event::ErrorCode::IdleTimeout,
+                        format!("no commands received in {} secs", idle_timeout.as_secs()),
+                    )))
+                    .await?;
+                chat_session.leave_all_rooms(event::DepartureReason::Disconnected).await?;
+                break;
+            }
+            // Ping the user, unless the previous ping is still waiting for its pong
+            _ = heartbeat.tick(), if !is_awaiting_pong => {
+                // a user who has stopped reading fills up the socket, which would block the write indefinitely
+                let ping = event_writer.write(&event::Event::Ping(event::PingRequestEvent));
+                match tokio::time::timeout(HEARTBEAT_TIMEOUT, ping).await {
+                    Ok(result) => result?,
+                    Err(_) => {
+                        info!("could not ping the user in time, disconnecting");
+                        chat_session.leave_all_rooms(event::DepartureReason::Disconnected).await?;
+                        break;
+                    }
+                }
+
+                is_awaiting_pong = true;
+                pong_deadline.as_mut().reset(Instant::now() + HEARTBEAT_TIMEOUT);
+            }
+            // The user has not answered the ping in time, the connection is dead
+            _ = &mut pong_deadline, if is_awaiting_pong => {
+                info!("ping was not answered in time, disconnecting");
+                chat_session.leave_all_rooms(event::DepartureReason::Disconnected).await?;
+                break;
+            }
+            // The user can not keep up with the events of the rooms, let them know and close the session
+            _ = too_slow.notified() => {
+                info!("user is too slow to keep up, disconnecting");
+                // the user may not be reading anymore, which is fine since we are closing anyway
+                let _ = event_writer
+                    .write(&event::Event::Error(event::ErrorReplyEvent::new(
+                        event::ErrorCode::TooSlow,
+                        "could not keep up with the events of the rooms",
+                    )))
+                    .await;
+                chat_session.leave_all_rooms(event::DepartureReason::Disconnected).await?;
+                break;
+            }
+            // Server wide events, such as room creation, are sent to every user
+            Ok(event) = server_events_rx.recv() => {
+                event_writer.write_sequenced(event).await?;
+            }
             // Aggregated events from the chat session are sent to the user
-            Ok(event) = chat_session.recv() => {
-                event_writer.write(&event).await?;
+            // The events that are ready are drained and written together, to avoid a write per event under load
+            Ok(events) = chat_session.recv_many(MAX_EVENT_BATCH_SIZE) => {
+                let mut batch = Vec::with_capacity(events.len());
+                for event in events {
+                    if let Some(event) = chat_session.handle_event(event).await? {
+                        batch.push(event);
+                    }
+                }
+
+                if !batch.is_empty() {
+                    event_writer.write_batch(batch).await?;
+                }
             }
-            // If the server is shutting down, we can just close the tcp streams
+            // If the server is shutting down, we let the user know why and close the tcp streams
             // and exit the session handler. Since the server is shutting down,
             // we don't need to notify other users about the user's departure or cleanup resources
-            Ok(_) = quit_rx.recv() => {
+            Ok(shutdown) = quit_rx.recv() => {
+                // the user may have already gone away, which is fine since we are closing anyway
+                let _ = event_writer.write(&event::Event::ServerShutdown(shutdown)).await;
                 drop(event_writer);
-                println!("Gracefully shutting down user tcp stream.");
+                debug!("closing the session for the shutdown");
                 break;
             }
         }
     }
 
+    info!("session ended");
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use comms::{command, transport::client};
+    use tokio::net::{TcpListener, TcpStream};
+
+    use crate::room_manager::{RoomManagerBuilder, SessionAndUserId};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_idle_session_is_disconnected() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let room_manager = Arc::new(RoomManagerBuilder::new().build().unwrap());
+        let (_quit_tx, quit_rx) = broadcast::channel(1);
+
+        let session = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            handle_user_session(
+                room_manager,
+                quit_rx,
+                stream,
+                Duration::from_millis(200),
+                None,
+                None,
+            )
+            .await
+        });
+
+        let (mut events, mut command_writer) =
+            client::split_stream(TcpStream::connect(addr).await.unwrap());
+        assert!(matches!(
+            events.next().await.unwrap().unwrap(),
+            event::Event::LoginSuccessful(_)
+        ));
+
+        // a command in time pushes the timeout back
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        command_writer
+            .write(&UserCommand::ListRooms(command::ListRoomsCommand))
+            .await
+            .unwrap();
+        assert!(matches!(
+            events.next().await.unwrap().unwrap(),
+            event::Event::RoomList(_)
+        ));
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert!(!session.is_finished());
+
+        match events.next().await.unwrap().unwrap() {
+            event::Event::Error(err) => assert_eq!(err.code, event::ErrorCode::IdleTimeout),
+            event => panic!("expected an idle timeout error, got {:?}", event),
+        }
+        assert!(events.next().await.is_none());
+        session.await.unwrap().unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_session_which_only_pings_is_disconnected() {
+        let room_manager = Arc::new(RoomManagerBuilder::new().build().unwrap());
+        let (_quit_tx, quit_rx) = broadcast::channel(1);
+        let (server_stream, client_stream) = tokio::io::duplex(1024);
+
+        let session = tokio::spawn(handle_user_session(
+            room_manager,
+            quit_rx,
+            server_stream,
+            Duration::from_secs(1),
+            None,
+            None,
+        ));
+
+        let (mut events, mut command_writer) = client::split_stream(client_stream);
+        assert!(matches!(
+            events.next().await.unwrap().unwrap(),
+            event::Event::LoginSuccessful(_)
+        ));
+
+        // the keepalive pings of the client do not keep an idle session going
+        for _ in 0..3 {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            command_writer
+                .write(&UserCommand::Ping(command::PingCommand))
+                .await
+                .unwrap();
+            assert_eq!(
+                events.next().await.unwrap().unwrap(),
+                event::Event::Pong(event::PongReplyEvent)
+            );
+        }
+
+        let pinged_at = Instant::now();
+        match events.next().await.unwrap().unwrap() {
+            event::Event::Error(err) => assert_eq!(err.code, event::ErrorCode::IdleTimeout),
+            event => panic!("expected an idle timeout error, got {:?}", event),
+        }
+        assert!(pinged_at.elapsed() < Duration::from_millis(300));
+        assert!(events.next().await.is_none());
+        session.await.unwrap().unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_unanswered_ping_closes_the_session() {
+        let room_manager = Arc::new(RoomManagerBuilder::new().build().unwrap());
+        let (_quit_tx, quit_rx) = broadcast::channel(1);
+        let (server_stream, client_stream) = tokio::io::duplex(1024);
+
+        let session = tokio::spawn(handle_user_session(
+            room_manager,
+            quit_rx,
+            server_stream,
+            Duration::from_secs(60 * 60),
+            None,
+            None,
+        ));
+
+        let (mut events, mut command_writer) = client::split_stream(client_stream);
+        assert!(matches!(
+            events.next().await.unwrap().unwrap(),
+            event::Event::LoginSuccessful(_)
+        ));
+
+        // an answered ping keeps the session going
+        assert_eq!(
+            events.next().await.unwrap().unwrap(),
+            event::Event::Ping(event::PingRequestEvent)
+        );
+        command_writer
+            .write(&UserCommand::Pong(command::PongCommand))
+            .await
+            .unwrap();
+
+        let pinged_at = Instant::now();
+        assert_eq!(
+            events.next().await.unwrap().unwrap(),
+            event::Event::Ping(event::PingRequestEvent)
+        );
+        assert!(events.next().await.is_none());
+        assert!(pinged_at.elapsed() >= HEARTBEAT_INTERVAL + HEARTBEAT_TIMEOUT);
+        session.await.unwrap().unwrap();
+    }
+
+    /// Runs a session which requires one of the given tokens, and returns the events replied to the first command
+    async fn authenticate_with(tokens: &str, first_command: UserCommand) -> Vec<event::Event> {
+        let room_manager = Arc::new(RoomManagerBuilder::new().build().unwrap());
+        let (_quit_tx, quit_rx) = broadcast::channel(1);
+        let (server_stream, client_stream) = tokio::io::duplex(1024);
+
+        let session = tokio::spawn(handle_user_session(
+            room_manager,
+            quit_rx,
+            server_stream,
+            Duration::from_secs(60),
+            Some(Arc::new(AuthTokens::parse(tokens).unwrap())),
+            None,
+        ));
+
+        let (mut events, mut command_writer) = client::split_stream(client_stream);
+        command_writer.write(&first_command).await.unwrap();
+        let first_event = events.next().await.unwrap().unwrap();
+        if let event::Event::LoginSuccessful(_) = first_event {
+            command_writer
+                .write(&UserCommand::Quit(command::QuitCommand))
+                .await
+                .unwrap();
+        }
+
+        let mut replied = vec![first_event];
+        while let Some(event) = events.next().await {
+            replied.push(event.unwrap());
+        }
+        session.await.unwrap().unwrap();
+
+        replied
+    }
+
+    fn authenticate_command(token: &str) -> UserCommand {
+        UserCommand::Authenticate(command::AuthenticateCommand {
+            token: token.into(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_valid_token_is_let_in() {
+        let events = authenticate_with("secret", authenticate_command("secret")).await;
+
+        assert!(matches!(
+            events.as_slice(),
+            [event::Event::LoginSuccessful(_)]
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_or_missing_token_is_rejected() {
+        for first_command in [
+            authenticate_command("guess"),
+            UserCommand::Hello(command::HelloCommand {
+                client_version: comms::PROTOCOL_VERSION,
+                sequenced: false,
+            }),
+        ] {
+            match authenticate_with("secret", first_command).await.as_slice() {
+                [event::Event::Error(err)] => {
+                    assert_eq!(err.code, event::ErrorCode::Unauthorized)
+                }
+                events => panic!("expected an unauthorized error, got {:?}", events),
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_silent_user_is_rejected_after_the_auth_timeout() {
+        let room_manager = Arc::new(RoomManagerBuilder::new().build().unwrap());
+        let (_quit_tx, quit_rx) = broadcast::channel(1);
+        let (server_stream, client_stream) = tokio::io::duplex(1024);
+
+        let session = tokio::spawn(handle_user_session(
+            room_manager,
+            quit_rx,
+            server_stream,
+            Duration::from_secs(60 * 60),
+            Some(Arc::new(AuthTokens::parse("secret").unwrap())),
+            None,
+        ));
+
+        let (mut events, _command_writer) = client::split_stream(client_stream);
+        match events.next().await.unwrap().unwrap() {
+            event::Event::Error(err) => assert_eq!(err.code, event::ErrorCode::Unauthorized),
+            event => panic!("expected an unauthorized error, got {:?}", event),
+        }
+        assert!(events.next().await.is_none());
+        session.await.unwrap().unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_sequenced_events_can_be_sorted_across_rooms() {
+        let room_manager = Arc::new(
+            ["rust", "go"]
+                .into_iter()
+                .fold(RoomManagerBuilder::new(), |builder, name| {
+                    builder.create_room(ChatRoomMetadata {
+                        name: name.into(),
+                        description: name.into(),
+                        ..Default::default()
+                    })
+                })
+                .build()
+                .unwrap(),
+        );
+        let (_quit_tx, quit_rx) = broadcast::channel(1);
+        let (server_stream, client_stream) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(handle_user_session(
+            Arc::clone(&room_manager),
+            quit_rx,
+            server_stream,
+            Duration::from_secs(60),
+            None,
+            None,
+        ));
+
+        let (mut events, mut command_writer) = client::split_sequenced_stream(client_stream);
+        // the events before the hello are not stamped
+        let login = events.next().await.unwrap().unwrap();
+        assert!(matches!(login.event, event::Event::LoginSuccessful(_)));
+        assert_eq!(login.seq, None);
+        command_writer
+            .write(&UserCommand::Hello(command::HelloCommand {
+                client_version: comms::PROTOCOL_VERSION,
+                sequenced: true,
+            }))
+            .await
+            .unwrap();
+        for room in ["rust", "go"] {
+            command_writer
+                .write(&UserCommand::JoinRoom(command::JoinRoomCommand {
+                    room: room.into(),
+                }))
+                .await
+                .unwrap();
+            loop {
+                let joined = events.next().await.unwrap().unwrap();
+                assert!(joined.seq.is_some());
+                if let event::Event::UserJoinedRoom(_) = joined.event {
+                    break;
+                }
+            }
+        }
+
+        // another user talks in both rooms, in turns
+        let other = SessionAndUserId {
+            session_id: "session-2".into(),
+            user_id: "user-2".into(),
+            display_name: "user-2".into(),
+        };
+        let mut rust_handle = room_manager.join_room("rust", &other).await.unwrap().handle;
+        let mut go_handle = room_manager.join_room("go", &other).await.unwrap().handle;
+        let sent = (0..20)
+            .map(|i| format!("message-{}", i))
+            .collect::<Vec<_>>();
+        for (i, content) in sent.iter().enumerate() {
+            let handle = if i % 2 == 0 {
+                &mut rust_handle
+            } else {
+                &mut go_handle
+            };
+            handle.send_message(content.clone()).await.unwrap();
+        }
+
+        let mut messages = vec![];
+        while messages.len() < sent.len() {
+            let sequenced = events.next().await.unwrap().unwrap();
+            if let event::Event::UserMessage(message) = sequenced.event {
+                messages.push((sequenced.seq.unwrap(), message));
+            }
+        }
+
+        // the messages of a room arrive in order, the rooms may be interleaved differently
+        for room in ["rust", "go"] {
+            let seqs = messages
+                .iter()
+                .filter(|(_, message)| message.room == room)
+                .map(|(seq, _)| *seq)
+                .collect::<Vec<_>>();
+            assert!(seqs.windows(2).all(|pair| pair[0] < pair[1]));
+        }
+        messages.sort_by_key(|(seq, _)| *seq);
+        assert_eq!(
+            messages
+                .into_iter()
+                .map(|(_, message)| message.content)
+                .collect::<Vec<_>>(),
+            sent
+        );
+    }
+
+    #[tokio::test]
+    async fn test_motd_is_sent_right_after_the_login() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let room_manager = Arc::new(RoomManagerBuilder::new().build().unwrap());
+        let (_quit_tx, quit_rx) = broadcast::channel(1);
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            handle_user_session(
+                room_manager,
+                quit_rx,
+                stream,
+                Duration::from_secs(60),
+                None,
+                Some(Arc::from("welcome, be nice")),
+            )
+            .await
+        });
+
+        let (mut events, _command_writer) =
+            client::split_stream(TcpStream::connect(addr).await.unwrap());
+        assert!(matches!(
+            events.next().await.unwrap().unwrap(),
+            event::Event::LoginSuccessful(_)
+        ));
+        assert_eq!(
+            events.next().await.unwrap().unwrap(),
+            event::Event::SystemMessage(event::SystemMessageBroadcastEvent {
+                content: "welcome, be nice".into(),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_is_announced_to_the_session() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let room_manager = Arc::new(RoomManagerBuilder::new().build().unwrap());
+        let (quit_tx, quit_rx) = broadcast::channel(1);
+
+        let session = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            handle_user_session(
+                room_manager,
+                quit_rx,
+                stream,
+                Duration::from_secs(60),
+                None,
+                None,
+            )
+            .await
+        });
+
+        let (mut events, _command_writer) =
+            client::split_stream(TcpStream::connect(addr).await.unwrap());
+        assert!(matches!(
+            events.next().await.unwrap().unwrap(),
+            event::Event::LoginSuccessful(_)
+        ));
+
+        let shutdown = event::ServerShutdownBroadcastEvent {
+            reason: "restarting".into(),
+            reconnect_after_secs: 5,
+        };
+        quit_tx.send(shutdown.clone()).unwrap();
+
+        assert_eq!(
+            events.next().await.unwrap().unwrap(),
+            event::Event::ServerShutdown(shutdown)
+        );
+        assert!(events.next().await.is_none());
+        session.await.unwrap().unwrap();
+    }
+}
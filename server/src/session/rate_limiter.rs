@@ -0,0 +1,74 @@
+use tokio::time::{Duration, Instant};
+
+/// [TokenBucket] limits how often an action can be taken, while still allowing short bursts
+///
+/// The bucket starts full, every action takes a token and the tokens are refilled
+/// based on the wall-clock time elapsed since the last refill.
+#[derive(Debug)]
+pub(super) struct TokenBucket {
+    capacity: f64,
+    refill_per_sec: f64,
+    tokens: f64,
+    last_refill_at: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(refill_per_sec: u32, burst: u32) -> Self {
+        TokenBucket {
+            capacity: f64::from(burst),
+            refill_per_sec: f64::from(refill_per_sec),
+            tokens: f64::from(burst),
+            last_refill_at: Instant::now(),
+        }
+    }
+
+    /// Try to take a single token from the bucket, returns false if the bucket is empty
+    pub fn try_acquire(&mut self) -> bool {
+        self.refill();
+
+        if self.tokens < 1.0 {
+            return false;
+        }
+
+        self.tokens -= 1.0;
+        true
+    }
+
+    /// Give back a token taken for an action which did not happen after all
+    pub fn refund(&mut self) {
+        self.tokens = (self.tokens + 1.0).min(self.capacity);
+    }
+
+    /// How long to wait until the next token is available
+    pub fn retry_after(&self) -> Duration {
+        Duration::from_secs_f64(((1.0 - self.tokens) / self.refill_per_sec).max(0.0))
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill_at).as_secs_f64();
+
+        self.tokens = (self.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        self.last_refill_at = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_token_bucket_refills_over_time() {
+        let mut bucket = TokenBucket::new(5, 10);
+
+        assert_eq!((0..20).filter(|_| bucket.try_acquire()).count(), 10);
+        assert_eq!(bucket.retry_after(), Duration::from_millis(200));
+
+        tokio::time::advance(Duration::from_millis(400)).await;
+        assert_eq!((0..20).filter(|_| bucket.try_acquire()).count(), 2);
+
+        // the bucket never holds more than the burst size
+        tokio::time::advance(Duration::from_secs(60)).await;
+        assert_eq!((0..20).filter(|_| bucket.try_acquire()).count(), 10);
+    }
+}
@@ -0,0 +1,261 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::Context;
+use comms::{
+    event::{self, ServerShutdownBroadcastEvent},
+    transport::{self, tls::TlsAcceptor},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpStream, UnixStream},
+    sync::broadcast,
+    task::JoinSet,
+};
+use tracing::warn;
+
+use crate::{auth::AuthTokens, room_manager::RoomManager, session};
+
+/// How long a client has to complete the TLS handshake, so stalled handshakes do not hold up the shutdown
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Holds a place in the active connections until dropped
+struct ConnectionSlot(Arc<AtomicUsize>);
+
+impl ConnectionSlot {
+    /// Takes a place if there are less than `max_connections` active connections
+    fn acquire(active_connections: &Arc<AtomicUsize>, max_connections: usize) -> Option<Self> {
+        active_connections
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| {
+                (count < max_connections).then_some(count + 1)
+            })
+            .ok()
+            .map(|_| ConnectionSlot(Arc::clone(active_connections)))
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// An accepted connection, which runs a user session once its stream is ready
+struct PendingSession {
+    /// None if the server was full when the connection was accepted
+    slot: Option<ConnectionSlot>,
+    room_manager: Arc<RoomManager>,
+    quit_rx: broadcast::Receiver<ServerShutdownBroadcastEvent>,
+    idle_timeout: Duration,
+    auth_tokens: Option<Arc<AuthTokens>>,
+    motd: Option<Arc<str>>,
+    max_connections: usize,
+}
+
+impl PendingSession {
+    async fn run<S>(self, stream: S) -> anyhow::Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        // the place is held until the session is over
+        let Some(_slot) = self.slot else {
+            return reject_connection(stream, self.max_connections).await;
+        };
+
+        session::handle_user_session(
+            self.room_manager,
+            self.quit_rx,
+            stream,
+            self.idle_timeout,
+            self.auth_tokens,
+            self.motd,
+        )
+        .await
+    }
+}
+
+/// Lets the user know that the server is full, and closes the connection
+async fn reject_connection<S>(stream: S, max_connections: usize) -> anyhow::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    warn!(max_connections, "server is full, rejecting the connection");
+    let (_, mut event_writer) = transport::server::split_stream(stream);
+
+    event_writer
+        .write(&event::Event::Error(event::ErrorReplyEvent::new(
+            event::ErrorCode::ServerFull,
+            format!("server is full with {} connections", max_connections),
+        )))
+        .await
+}
+
+/// [SessionSpawner] runs a session in the background for each accepted connection,
+/// and keeps track of them to wait for them on shutdown
+pub struct SessionSpawner {
+    join_set: JoinSet<anyhow::Result<()>>,
+    room_manager: Arc<RoomManager>,
+    quit_rx: broadcast::Receiver<ServerShutdownBroadcastEvent>,
+    idle_timeout: Duration,
+    /// Encrypts the TCP connections if set
+    tls_acceptor: Option<TlsAcceptor>,
+    /// Only the users with one of these tokens are let in if set
+    auth_tokens: Option<Arc<AuthTokens>>,
+    /// Sent to every user right after they log in if set
+    motd: Option<Arc<str>>,
+    /// How many connections are being handled right now, rejected ones excluded
+    active_connections: Arc<AtomicUsize>,
+    /// New connections are rejected once there are this many active connections
+    max_connections: usize,
+}
+
+impl SessionSpawner {
+    pub fn new(
+        room_manager: Arc<RoomManager>,
+        quit_rx: broadcast::Receiver<ServerShutdownBroadcastEvent>,
+        idle_timeout: Duration,
+    ) -> Self {
+        SessionSpawner {
+            join_set: JoinSet::new(),
+            room_manager,
+            quit_rx,
+            idle_timeout,
+            tls_acceptor: None,
+            auth_tokens: None,
+            motd: None,
+            active_connections: Arc::new(AtomicUsize::new(0)),
+            max_connections: usize::MAX,
+        }
+    }
+
+    pub fn with_tls(self, tls_acceptor: TlsAcceptor) -> Self {
+        SessionSpawner {
+            tls_acceptor: Some(tls_acceptor),
+            ..self
+        }
+    }
+
+    /// Requires the users to authenticate with one of the given tokens before anything else
+    pub fn with_auth(self, auth_tokens: AuthTokens) -> Self {
+        SessionSpawner {
+            auth_tokens: Some(Arc::new(auth_tokens)),
+            ..self
+        }
+    }
+
+    /// Greets every user with the given message of the day, right after they log in
+    pub fn with_motd(self, motd: String) -> Self {
+        SessionSpawner {
+            motd: Some(Arc::from(motd)),
+            ..self
+        }
+    }
+
+    /// Rejects the new connections while there are `max_connections` active connections
+    pub fn with_max_connections(self, max_connections: usize) -> Self {
+        SessionSpawner {
+            max_connections,
+            ..self
+        }
+    }
+
+    /// Handles a connection over TCP, encrypted with TLS if enabled
+    pub fn spawn_tcp(&mut self, socket: TcpStream) {
+        match self.tls_acceptor.clone() {
+            Some(tls_acceptor) => {
+                let pending_session = self.accept();
+
+                self.join_set.spawn(async move {
+                    let stream =
+                        tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, tls_acceptor.accept(socket))
+                            .await
+                            .context("TLS handshake timed out")?
+                            .context("TLS handshake failed")?;
+
+                    pending_session.run(stream).await
+                });
+            }
+            None => {
+                let pending_session = self.accept();
+                self.join_set.spawn(pending_session.run(socket));
+            }
+        }
+    }
+
+    /// Handles a local connection over a unix domain socket, which is never encrypted
+    pub fn spawn_unix(&mut self, socket: UnixStream) {
+        let pending_session = self.accept();
+        self.join_set.spawn(pending_session.run(socket));
+    }
+
+    /// Takes a place for the accepted connection right away, before any handshakes
+    fn accept(&self) -> PendingSession {
+        PendingSession {
+            slot: ConnectionSlot::acquire(&self.active_connections, self.max_connections),
+            room_manager: Arc::clone(&self.room_manager),
+            quit_rx: self.quit_rx.resubscribe(),
+            idle_timeout: self.idle_timeout,
+            auth_tokens: self.auth_tokens.clone(),
+            motd: self.motd.clone(),
+            max_connections: self.max_connections,
+        }
+    }
+
+    /// Waits for all the sessions to finish, e.g. after the quit signal is sent
+    pub async fn wait(mut self) {
+        while self.join_set.join_next().await.is_some() {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use comms::transport::client;
+    use tokio_stream::StreamExt;
+
+    use crate::room_manager::RoomManagerBuilder;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_connections_beyond_the_limit_are_rejected() {
+        let room_manager = Arc::new(RoomManagerBuilder::new().build().unwrap());
+        let (_quit_tx, quit_rx) = broadcast::channel(1);
+        let mut spawner = SessionSpawner::new(room_manager, quit_rx, Duration::from_secs(60))
+            .with_max_connections(1);
+
+        let (client_stream, server_stream) = UnixStream::pair().unwrap();
+        spawner.spawn_unix(server_stream);
+        let (mut events, command_writer) = client::split_stream(client_stream);
+        assert!(matches!(
+            events.next().await.unwrap().unwrap(),
+            event::Event::LoginSuccessful(_)
+        ));
+
+        let (client_stream, server_stream) = UnixStream::pair().unwrap();
+        spawner.spawn_unix(server_stream);
+        let (mut rejected_events, _rejected_command_writer) = client::split_stream(client_stream);
+        match rejected_events.next().await.unwrap().unwrap() {
+            event::Event::Error(err) => assert_eq!(err.code, event::ErrorCode::ServerFull),
+            event => panic!("expected a server full error, got {:?}", event),
+        }
+        assert!(rejected_events.next().await.is_none());
+
+        // the place is freed once the first session is over
+        drop((events, command_writer));
+        while spawner.join_set.join_next().await.is_some() {}
+        assert_eq!(spawner.active_connections.load(Ordering::Acquire), 0);
+
+        let (client_stream, server_stream) = UnixStream::pair().unwrap();
+        spawner.spawn_unix(server_stream);
+        let (mut events, _command_writer) = client::split_stream(client_stream);
+        assert!(matches!(
+            events.next().await.unwrap().unwrap(),
+            event::Event::LoginSuccessful(_)
+        ));
+    }
+}
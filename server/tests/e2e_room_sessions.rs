@@ -0,0 +1,163 @@
+use std::{sync::Arc, time::Duration};
+
+use comms::{
+    command::{self, UserCommand},
+    event::{self, Event},
+    transport::{
+        self,
+        client::{CommandWriter, EventStream},
+    },
+};
+use server::{
+    room_manager::{ChatRoomMetadata, RoomManagerBuilder},
+    session_spawner::SessionSpawner,
+};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::broadcast,
+};
+use tokio_stream::StreamExt;
+
+/// How long a client waits for an event before the test is failed
+const EVENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Boots the server with the given rooms on an ephemeral port, returning the address it listens on
+async fn start_server(rooms: &[&str]) -> std::net::SocketAddr {
+    let room_manager = rooms
+        .iter()
+        .fold(RoomManagerBuilder::new(), |builder, name| {
+            builder.create_room(ChatRoomMetadata {
+                name: String::from(*name),
+                description: String::from(*name),
+                ..Default::default()
+            })
+        })
+        .build()
+        .expect("could not build the room manager");
+
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("could not bind to a port");
+    let addr = listener.local_addr().expect("could not get the local addr");
+
+    tokio::spawn(async move {
+        // the sender is kept alive, so the sessions are not told that the server is shutting down
+        let (_quit_tx, quit_rx) = broadcast::channel(1);
+        let mut session_spawner =
+            SessionSpawner::new(Arc::new(room_manager), quit_rx, Duration::from_secs(60));
+
+        while let Ok((socket, _)) = listener.accept().await {
+            session_spawner.spawn_tcp(socket);
+        }
+    });
+
+    addr
+}
+
+/// Connects a client to the server, returning its user id from the login reply
+async fn connect(addr: std::net::SocketAddr) -> (String, EventStream, CommandWriter) {
+    let stream = TcpStream::connect(addr)
+        .await
+        .expect("could not connect to the server");
+    let (mut events, commands) = transport::client::split_stream(stream);
+
+    match next_event(&mut events).await {
+        Event::LoginSuccessful(event) => (event.user_id, events, commands),
+        event => panic!("expected a login reply, got {:?}", event),
+    }
+}
+
+async fn next_event(events: &mut EventStream) -> Event {
+    tokio::time::timeout(EVENT_TIMEOUT, events.next())
+        .await
+        .expect("timed out waiting for an event")
+        .expect("the server closed the connection")
+        .expect("could not read the event")
+}
+
+/// Receive events until one matches the predicate, returning the matching event
+async fn wait_for_event(events: &mut EventStream, predicate: impl Fn(&Event) -> bool) -> Event {
+    loop {
+        let event = next_event(events).await;
+        if predicate(&event) {
+            return event;
+        }
+    }
+}
+
+async fn join_room(commands: &mut CommandWriter, events: &mut EventStream, room: &str) {
+    commands
+        .write(&UserCommand::JoinRoom(command::JoinRoomCommand {
+            room: room.into(),
+        }))
+        .await
+        .unwrap();
+    wait_for_event(
+        events,
+        |event| matches!(event, Event::UserJoinedRoom(event) if event.room == room),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn assert_room_events_are_broadcasted_between_sessions() {
+    let addr = start_server(&["rust", "go"]).await;
+
+    let (alice_id, mut alice_events, mut alice_commands) = connect(addr).await;
+    let (bob_id, mut bob_events, mut bob_commands) = connect(addr).await;
+
+    join_room(&mut alice_commands, &mut alice_events, "rust").await;
+    join_room(&mut bob_commands, &mut bob_events, "rust").await;
+    bob_commands
+        .write(&UserCommand::SendMessage(command::SendMessageCommand {
+            room: "rust".into(),
+            content: "hello alice".into(),
+            client_msg_id: None,
+        }))
+        .await
+        .unwrap();
+    bob_commands
+        .write(&UserCommand::LeaveRoom(command::LeaveRoomCommand {
+            room: "rust".into(),
+        }))
+        .await
+        .unwrap();
+
+    // alice sees her own join first, then everything bob does in the room, in order
+    let mut received = Vec::new();
+    while received.len() < 4 {
+        match next_event(&mut alice_events).await {
+            Event::RoomParticipation(event) => received.push(format!(
+                "{} {:?} {:?}",
+                event.user_id, event.status, event.reason
+            )),
+            Event::UserMessage(event) => {
+                received.push(format!("{}: {}", event.user_id, event.content))
+            }
+            _ => {}
+        }
+    }
+
+    assert_eq!(
+        received,
+        vec![
+            format!("{} Joined None", alice_id),
+            format!("{} Joined None", bob_id),
+            format!("{}: hello alice", bob_id),
+            format!("{} Left Some(Left)", bob_id),
+        ]
+    );
+
+    // bob is not in the room anymore, so alice is the only user listed
+    let snapshot = wait_for_event(&mut alice_events, |event| {
+        matches!(event, Event::RoomUsersSnapshot(_))
+    })
+    .await;
+    assert_eq!(
+        snapshot,
+        Event::RoomUsersSnapshot(event::RoomUsersSnapshotBroadcastEvent {
+            room: "rust".into(),
+            users: vec![alice_id],
+        })
+    );
+}
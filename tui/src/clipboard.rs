@@ -0,0 +1,27 @@
+use anyhow::Context;
+
+/// [Clipboard] lazily connects to the system clipboard and keeps the connection open,
+/// since on some platforms the copied content is only served while the connection is alive
+#[derive(Default)]
+pub struct Clipboard {
+    inner: Option<arboard::Clipboard>,
+}
+
+impl Clipboard {
+    /// Copies the given text to the system clipboard
+    /// Fails when there is no clipboard available e.g. a headless or ssh session
+    pub fn copy(&mut self, text: &str) -> anyhow::Result<()> {
+        let clipboard = match self.inner.as_mut() {
+            Some(clipboard) => clipboard,
+            None => self
+                .inner
+                .insert(arboard::Clipboard::new().context("clipboard is not available")?),
+        };
+
+        clipboard
+            .set_text(text)
+            .context("could not copy to the clipboard")?;
+
+        Ok(())
+    }
+}
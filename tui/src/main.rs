@@ -1,18 +1,94 @@
-use state_store::StateStore;
+use anyhow::Context;
+use clap::Parser;
+use comms::transport::tls;
+use state_store::{action::Action, StateStore, DEFAULT_MESSAGE_HISTORY_SIZE};
 use termination::create_termination;
-use ui_management::UiManager;
+use ui_management::{UiManager, DEFAULT_TICK_RATE};
 
+mod clipboard;
 mod state_store;
 mod termination;
 mod ui_management;
 
 use termination::{Interrupted, Terminator};
 
+/// Terminal client for the room based chat server
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Args {
+    /// The server to connect to right away as host:port, skipping the connect page. The port defaults to 8080
+    #[arg(long, env = "CHAT_SERVER")]
+    server: Option<String>,
+    /// The unix domain socket of a server on the same machine to connect to right away
+    #[arg(long, conflicts_with = "server")]
+    socket: Option<std::path::PathBuf>,
+    /// Token to authenticate with, for the servers which require one
+    #[arg(long, env = "CHAT_TOKEN")]
+    token: Option<String>,
+    /// Do not reconnect to the server automatically when the connection is lost
+    #[arg(long)]
+    no_reconnect: bool,
+    /// Encrypt the connection to the server with TLS
+    #[arg(long)]
+    tls: bool,
+    /// PEM file with the certificates to trust for TLS, e.g. for a self-signed server, instead of the well known ones
+    #[arg(long, requires = "tls")]
+    tls_ca: Option<std::path::PathBuf>,
+    /// How many of the most recent messages are kept per room
+    #[arg(
+        long,
+        env = "CHAT_HISTORY_SIZE",
+        default_value_t = DEFAULT_MESSAGE_HISTORY_SIZE as u64,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    history_size: u64,
+    /// How often in milliseconds the screen is checked for changing parts, e.g. the clocks, to redraw.
+    /// The screen is redrawn right away on the input and the new messages regardless
+    #[arg(
+        long,
+        env = "CHAT_TICK_RATE",
+        default_value_t = DEFAULT_TICK_RATE.as_millis() as u64,
+        value_parser = clap::value_parser!(u64).range(10..)
+    )]
+    tick_rate: u64,
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+
     let (terminator, mut interrupt_rx) = create_termination();
     let (state_store, state_rx) = StateStore::new();
+    let mut state_store = state_store
+        .with_auto_reconnect(!args.no_reconnect)
+        .with_message_history_size(args.history_size as usize);
+    if args.tls {
+        let tls_connector =
+            tls::connector(args.tls_ca.as_deref()).context("could not set up TLS")?;
+        state_store = state_store.with_tls(tls_connector);
+    }
     let (ui_manager, action_rx) = UiManager::new();
+    let ui_manager = ui_manager.with_tick_rate(std::time::Duration::from_millis(args.tick_rate));
+
+    // sockets are told apart from host:port addresses by their leading slash
+    let socket_addr = match args.socket.as_deref() {
+        Some(path) => Some(
+            std::path::absolute(path)
+                .context("could not resolve the socket path")?
+                .to_string_lossy()
+                .into_owned(),
+        ),
+        None => None,
+    };
+
+    // the connect page is still shown if the connection fails
+    if let Some(addr) = args.server.or(socket_addr) {
+        ui_manager.dispatch(Action::ConnectToServerRequest {
+            addr,
+            display_name: None,
+            token: args.token,
+        })?;
+    }
 
     tokio::try_join!(
         state_store.main_loop(terminator, action_rx, interrupt_rx.resubscribe()),
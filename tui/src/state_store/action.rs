@@ -1,7 +1,49 @@
 #[derive(Debug, Clone)]
 pub enum Action {
-    ConnectToServerRequest { addr: String },
-    SendMessage { content: String },
-    SelectRoom { room: String },
+    ConnectToServerRequest {
+        addr: String,
+        display_name: Option<String>,
+        /// Sent to authenticate before anything else, for the servers which require it
+        token: Option<String>,
+    },
+    SendMessage {
+        content: String,
+    },
+    DeleteLastMessage,
+    CopyConversation,
+    SelectRoom {
+        room: String,
+    },
+    /// Join a room without making it the active room, to follow its unread messages
+    JoinRoomInBackground {
+        room: String,
+    },
+    /// Leave the active room
+    LeaveRoom,
+    /// Ask the server for a new user id, e.g. when it collides with another user's
+    Reidentify,
+    RefreshRooms,
+    /// Load the messages of the room older than the loaded ones, e.g. once the user has scrolled past them
+    FetchHistory {
+        room: String,
+    },
+    /// Peek at the users of a room without joining it
+    ListRoomUsers {
+        room: String,
+    },
+    /// Dismiss the users of the room the user has peeked into
+    CloseRoomUsers,
+    /// Show an error to the user in the active room, e.g. for an invalid command
+    ShowError {
+        message: String,
+    },
+    /// Turn the terminal bell on the mentions on or off
+    ToggleMentionBell,
+    /// Mute the room if it is not muted, unmute it otherwise
+    ToggleMuteRoom {
+        room: String,
+    },
+    /// The user has interacted with the app, e.g. pressed a key
+    UserActivity,
     Exit,
 }
@@ -5,3 +5,4 @@ pub mod action;
 mod state;
 #[allow(clippy::module_inception)]
 mod state_store;
+pub mod transcript;
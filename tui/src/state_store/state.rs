@@ -1,15 +1,100 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
 use circular_queue::CircularQueue;
-use comms::event;
+use comms::{command, event};
 
 #[derive(Debug, Clone)]
 pub enum MessageBoxItem {
-    Message { user_id: String, content: String },
+    Message {
+        message_id: String,
+        user_id: String,
+        /// The display name of the user at the time the message was sent
+        display_name: String,
+        content: String,
+    },
+    /// A message the user has sent, which the server has not acknowledged yet
+    PendingMessage {
+        client_msg_id: String,
+        user_id: String,
+        display_name: String,
+        content: String,
+    },
     Notification(String),
+    /// A message from the operators of the server, e.g. the message of the day, shown in every room
+    System(String),
 }
 
-const MAX_MESSAGES_TO_STORE_PER_ROOM: usize = 100;
+/// Placeholder shown in place of a message that has been deleted by its author
+const DELETED_MESSAGE_PLACEHOLDER: &str = "[message deleted]";
+
+/// How many of the most recent messages are kept per room, unless configured otherwise
+pub const DEFAULT_MESSAGE_HISTORY_SIZE: usize = 100;
+/// How many older messages are asked for at once, when the user scrolls past the loaded ones
+const HISTORY_PAGE_SIZE: usize = 50;
+/// How long a toast is shown for
+const TOAST_DURATION: Duration = Duration::from_secs(5);
+/// How many toasts are shown at once, the oldest ones make room for the new ones
+const MAX_TOASTS: usize = 3;
+
+/// Whether the content mentions any of the given names as `@name`
+fn is_mentioned(content: &str, names: &[&str]) -> bool {
+    content
+        .split_whitespace()
+        .filter_map(|word| word.strip_prefix('@'))
+        // allow punctuation right after the mention, e.g. `@alice, hi!`
+        .map(|word| {
+            word.trim_end_matches(|c: char| c.is_ascii_punctuation() && c != '_' && c != '-')
+        })
+        .any(|word| names.contains(&word))
+}
+
+/// Describes how a user has joined or left a room, telling apart the users who have lost their connection
+fn participation_notification(event: &event::RoomParticipationBroadcastEvent) -> String {
+    let action = match (&event.status, event.reason) {
+        (event::RoomParticipationStatus::Joined, _) => "has joined the room",
+        (event::RoomParticipationStatus::Left, Some(event::DepartureReason::Quit)) => "has quit",
+        (event::RoomParticipationStatus::Left, Some(event::DepartureReason::Disconnected)) => {
+            "has lost connection"
+        }
+        (event::RoomParticipationStatus::Left, Some(event::DepartureReason::Kicked)) => {
+            "has been kicked from the room"
+        }
+        (event::RoomParticipationStatus::Left, Some(event::DepartureReason::Reidentified)) => {
+            "has changed their id"
+        }
+        (event::RoomParticipationStatus::Left, _) => "has left the room",
+    };
+
+    format!("{} {}", event.display_name, action)
+}
+
+/// The current time as a unix timestamp in seconds, the unit the server reports the join times in
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// The data of the given room, which is created if the room is not known yet,
+/// e.g. the server has sent an event for a room created after the room list was received
+fn get_or_insert_room<'a>(
+    room_data_map: &'a mut HashMap<String, Arc<RoomData>>,
+    room: &str,
+    history_size: usize,
+) -> &'a mut RoomData {
+    Arc::make_mut(room_data_map.entry(String::from(room)).or_insert_with(|| {
+        Arc::new(RoomData::new(
+            String::from(room),
+            String::new(),
+            history_size,
+        ))
+    }))
+}
 
 /// RoomData holds the data for a room
 #[derive(Debug, Clone)]
@@ -20,12 +105,27 @@ pub struct RoomData {
     pub description: String,
     /// List of users in the room
     pub users: HashSet<String>,
+    /// When the users have joined the room, as unix timestamps in seconds keyed by user id
+    /// Only known for the users the server has reported, or who have joined while we were in the room
+    pub joined_at: HashMap<String, u64>,
     /// History of recorded messages
     pub messages: CircularQueue<MessageBoxItem>,
+    /// How many messages have been recorded so far, keeps counting after the oldest messages are dropped
+    pub received_messages: usize,
     /// Has joined the room
     pub has_joined: bool,
-    /// Has unread messages
-    pub has_unread: bool,
+    /// Number of messages received while the room was not active
+    pub unread_count: usize,
+    /// Has unread messages which mention the user
+    pub has_mention: bool,
+    /// Whether the user has muted the room, its messages are still recorded but never counted as unread or mentions
+    pub muted: bool,
+    /// How many unique users are in the room, as announced by the server
+    pub participant_count: usize,
+    /// Whether the server may have older messages than the loaded ones, until it says otherwise
+    pub has_older_history: bool,
+    /// Whether older messages have been asked for, and have not arrived yet
+    pub is_fetching_history: bool,
 }
 
 impl Default for RoomData {
@@ -34,29 +134,133 @@ impl Default for RoomData {
             name: String::new(),
             description: String::new(),
             users: HashSet::new(),
-            messages: CircularQueue::with_capacity(MAX_MESSAGES_TO_STORE_PER_ROOM),
+            joined_at: HashMap::new(),
+            messages: CircularQueue::with_capacity(DEFAULT_MESSAGE_HISTORY_SIZE),
+            received_messages: 0,
             has_joined: false,
-            has_unread: false,
+            unread_count: 0,
+            has_mention: false,
+            muted: false,
+            participant_count: 0,
+            has_older_history: true,
+            is_fetching_history: false,
         }
     }
 }
 
 impl RoomData {
-    pub fn new(name: String, description: String) -> Self {
+    /// Creates the data of a room, which keeps the given number of the most recent messages
+    pub fn new(name: String, description: String, history_size: usize) -> Self {
         RoomData {
             name,
             description,
+            messages: CircularQueue::with_capacity(history_size),
             ..Default::default()
         }
     }
+
+    /// Records a message to the history of the room
+    pub fn push_message(&mut self, item: MessageBoxItem) {
+        self.messages.push(item);
+        self.received_messages += 1;
+    }
+
+    /// Records older messages before the history of the room, skipping the ones it already has
+    /// The received message count is left as is, since these are not new messages
+    pub fn prepend_messages(&mut self, items: impl IntoIterator<Item = MessageBoxItem>) {
+        // CircularQueue only takes the newer messages, so the history is rebuilt with the older messages first
+        let mut messages = CircularQueue::with_capacity(self.messages.capacity());
+        for item in items {
+            let is_known = matches!(&item, MessageBoxItem::Message { message_id, .. } if self.has_message(message_id));
+            if !is_known {
+                messages.push(item);
+            }
+        }
+        for item in self.messages.asc_iter() {
+            messages.push(item.clone());
+        }
+
+        self.messages = messages;
+    }
+
+    /// The id of the oldest message in the history of the room
+    pub fn oldest_message_id(&self) -> Option<&str> {
+        self.messages.asc_iter().find_map(|item| match item {
+            MessageBoxItem::Message { message_id, .. } => Some(message_id.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Whether the message with the given id is in the history of the room
+    pub fn has_message(&self, id: &str) -> bool {
+        self.messages.iter().any(
+            |item| matches!(item, MessageBoxItem::Message { message_id, .. } if message_id == id),
+        )
+    }
+
+    /// Marks a pending message as sent, with the id assigned to it by the server
+    /// The pending message is dropped if the message itself has already been received
+    pub fn confirm_pending_message(&mut self, client_msg_id: &str, message_id: &str) {
+        let is_pending = |item: &MessageBoxItem| matches!(item, MessageBoxItem::PendingMessage { client_msg_id: id, .. } if id == client_msg_id);
+
+        if self.has_message(message_id) {
+            // CircularQueue does not support removal, so the history is rebuilt without the pending message
+            let mut messages = CircularQueue::with_capacity(self.messages.capacity());
+            for item in self.messages.asc_iter().filter(|item| !is_pending(item)) {
+                messages.push(item.clone());
+            }
+            if messages.len() < self.messages.len() {
+                self.received_messages -= 1;
+            }
+            self.messages = messages;
+
+            return;
+        }
+
+        if let Some(item) = self.messages.iter_mut().find(|item| is_pending(item)) {
+            if let MessageBoxItem::PendingMessage {
+                user_id,
+                display_name,
+                content,
+                ..
+            } = item
+            {
+                *item = MessageBoxItem::Message {
+                    message_id: String::from(message_id),
+                    user_id: user_id.clone(),
+                    display_name: display_name.clone(),
+                    content: content.clone(),
+                };
+            }
+        }
+    }
+}
+
+/// The users of a room the user has asked to peek into, which they do not have to have joined
+#[derive(Debug, Clone)]
+pub struct RoomUsersPreview {
+    /// The name of the room
+    pub room: String,
+    /// Ids of the users in the room
+    pub users: Vec<String>,
 }
 
 #[derive(Debug, Clone)]
 pub enum ServerConnectionStatus {
     Uninitalized,
     Connecting,
-    Connected { addr: String },
-    Errored { err: String },
+    Connected {
+        addr: String,
+    },
+    Errored {
+        err: String,
+    },
+    /// The connection was lost, and is being retried in the background
+    Reconnecting {
+        addr: String,
+        attempt: u32,
+        err: String,
+    },
 }
 
 /// State holds the state of the application
@@ -67,10 +271,28 @@ pub struct State {
     pub active_room: Option<String>,
     /// The id of the user
     pub user_id: String,
-    /// Storage of room data
-    pub room_data_map: HashMap<String, RoomData>,
-    /// Timer since app was opened
-    pub timer: usize,
+    /// Display names of the known users, keyed by user id
+    pub display_names: HashMap<String, String>,
+    /// Ids of the users who are currently away
+    pub away_users: HashSet<String>,
+    /// Storage of room data. The rooms are shared with the clones of the state, which are sent to the UI,
+    /// and only copied once they are changed
+    pub room_data_map: HashMap<String, Arc<RoomData>>,
+    /// The users of the room the user is peeking into, shown until dismissed
+    pub room_users_preview: Option<RoomUsersPreview>,
+    /// How many times the user was mentioned outside the active room, to alert them of the new mentions
+    pub mention_count: usize,
+    /// Whether to ring the terminal bell on the new mentions
+    pub is_bell_enabled: bool,
+    /// When the app first connected to a server. Kept across the reconnects and the resets,
+    /// so the elapsed time covers the whole chat session rather than the latest connection
+    pub connected_at: Option<Instant>,
+    /// How many of the most recent messages are kept per room
+    pub message_history_size: usize,
+    /// How many messages the user has sent on this connection, used to pick the client ids of the messages
+    pub sent_message_count: usize,
+    /// Short lived feedback which does not belong to a room, e.g. the errors, oldest first along with when they were raised
+    pub toasts: VecDeque<(String, Instant)>,
 }
 
 impl Default for State {
@@ -79,8 +301,16 @@ impl Default for State {
             server_connection_status: ServerConnectionStatus::Uninitalized,
             active_room: None,
             user_id: String::new(),
+            display_names: HashMap::new(),
+            away_users: HashSet::new(),
             room_data_map: HashMap::new(),
-            timer: 0,
+            room_users_preview: None,
+            mention_count: 0,
+            is_bell_enabled: true,
+            connected_at: None,
+            message_history_size: DEFAULT_MESSAGE_HISTORY_SIZE,
+            sent_message_count: 0,
+            toasts: VecDeque::new(),
         }
     }
 }
@@ -90,63 +320,469 @@ impl State {
         match event {
             event::Event::LoginSuccessful(event) => {
                 self.user_id = event.user_id.clone();
+                self.display_names.clear();
+                let history_size = self.message_history_size;
                 self.room_data_map = event
                     .rooms
                     .clone()
                     .into_iter()
-                    .map(|r| (r.name.clone(), RoomData::new(r.name, r.description)))
+                    .map(|r| {
+                        let room_data = RoomData {
+                            participant_count: r.participant_count,
+                            ..RoomData::new(r.name.clone(), r.description, history_size)
+                        };
+
+                        (r.name, Arc::new(room_data))
+                    })
+                    .collect();
+
+                // the active room may be carried over from a previous connection
+                if let Some(active_room) = self.active_room.as_ref() {
+                    if !self.room_data_map.contains_key(active_room) {
+                        self.active_room = None;
+                    }
+                }
+            }
+            event::Event::RoomList(event) => {
+                // keep the data of the rooms which still exist, so message history is not lost
+                let mut room_data_map = std::mem::take(&mut self.room_data_map);
+                let history_size = self.message_history_size;
+
+                self.room_data_map = event
+                    .rooms
+                    .iter()
+                    .map(|r| {
+                        let room_data = match room_data_map.remove(&r.name) {
+                            Some(room_data) => RoomData {
+                                description: r.description.clone(),
+                                participant_count: r.participant_count,
+                                ..Arc::unwrap_or_clone(room_data)
+                            },
+                            None => RoomData {
+                                participant_count: r.participant_count,
+                                ..RoomData::new(r.name.clone(), r.description.clone(), history_size)
+                            },
+                        };
+
+                        (r.name.clone(), Arc::new(room_data))
+                    })
                     .collect();
+
+                if let Some(active_room) = self.active_room.as_ref() {
+                    if !self.room_data_map.contains_key(active_room) {
+                        self.active_room = None;
+                    }
+                }
+            }
+            event::Event::RoomCreated(event) => {
+                self.room_data_map
+                    .entry(event.name.clone())
+                    .or_insert_with(|| {
+                        Arc::new(RoomData {
+                            participant_count: event.participant_count,
+                            ..RoomData::new(
+                                event.name.clone(),
+                                event.description.clone(),
+                                self.message_history_size,
+                            )
+                        })
+                    });
+            }
+            event::Event::RoomOccupancyChanged(event) => {
+                if let Some(room_data) = self.room_data_mut(&event.room) {
+                    room_data.participant_count = event.count;
+                }
             }
             event::Event::RoomParticipation(event) => {
-                if let Some(room_data) = self.room_data_map.get_mut(&event.room) {
-                    match event.status {
-                        event::RoomParticipationStatus::Joined => {
-                            room_data.users.insert(event.user_id.clone());
-                            if event.user_id == self.user_id {
-                                room_data.has_joined = true;
-                            }
+                self.display_names
+                    .insert(event.user_id.clone(), event.display_name.clone());
+
+                let room_data = get_or_insert_room(
+                    &mut self.room_data_map,
+                    &event.room,
+                    self.message_history_size,
+                );
+
+                // the user list may already know about the change, e.g. from the reply to our join,
+                // so the notification is only shown if the participation has actually changed
+                let has_changed = match event.status {
+                    event::RoomParticipationStatus::Joined => {
+                        let is_new_user = room_data.users.insert(event.user_id.clone());
+                        room_data
+                            .joined_at
+                            .entry(event.user_id.clone())
+                            .or_insert_with(unix_now);
+                        if event.user_id == self.user_id {
+                            // we are listed in the reply to our own join, before this event arrives
+                            !std::mem::replace(&mut room_data.has_joined, true)
+                        } else {
+                            is_new_user
                         }
-                        event::RoomParticipationStatus::Left => {
-                            room_data.users.remove(&event.user_id);
-                            if event.user_id == self.user_id {
-                                room_data.has_joined = false;
+                    }
+                    event::RoomParticipationStatus::Left => {
+                        let was_present = room_data.users.remove(&event.user_id);
+                        room_data.joined_at.remove(&event.user_id);
+                        if event.user_id == self.user_id {
+                            if self.active_room.as_ref() == Some(&event.room) {
+                                self.active_room = None;
                             }
+                            std::mem::replace(&mut room_data.has_joined, false) || was_present
+                        } else {
+                            was_present
                         }
                     }
+                };
 
+                if has_changed {
+                    room_data.push_message(MessageBoxItem::Notification(
+                        participation_notification(event),
+                    ));
+                }
+            }
+            event::Event::RoomUsersSnapshot(event) => {
+                // the server knows best, in case we have missed some participation events
+                if let Some(room_data) = self.room_data_mut(&event.room) {
+                    room_data.users = event.users.iter().cloned().collect();
                     room_data
-                        .messages
-                        .push(MessageBoxItem::Notification(format!(
-                            "{} has {} the room",
-                            event.user_id,
-                            match event.status {
-                                event::RoomParticipationStatus::Joined => "joined",
-                                event::RoomParticipationStatus::Left => "left",
-                            }
-                        )));
+                        .joined_at
+                        .retain(|user_id, _| room_data.users.contains(user_id));
                 }
             }
+            event::Event::RoomUsers(event) => {
+                self.display_names.extend(event.display_names.clone());
+
+                let mut users = event.users.clone();
+                users.sort();
+                self.room_users_preview = Some(RoomUsersPreview {
+                    room: event.room.clone(),
+                    users,
+                });
+            }
             event::Event::UserJoinedRoom(event) => {
-                self.room_data_map.get_mut(&event.room).unwrap().users =
-                    event.users.clone().into_iter().collect();
+                self.display_names.extend(event.display_names.clone());
+
+                let room_data = get_or_insert_room(
+                    &mut self.room_data_map,
+                    &event.room,
+                    self.message_history_size,
+                );
+                room_data.users = event.users.clone().into_iter().collect();
+                room_data.joined_at = event.joined_at.clone();
+
+                // seed the room with its recent messages, skipping the ones we already have from an earlier join
+                for message in event.history.iter() {
+                    if !room_data.has_message(&message.message_id) {
+                        room_data.push_message(MessageBoxItem::Message {
+                            message_id: message.message_id.clone(),
+                            user_id: message.user_id.clone(),
+                            display_name: message.display_name.clone(),
+                            content: message.content.clone(),
+                        });
+                    }
+                }
             }
             event::Event::UserMessage(event) => {
-                let room_data = self.room_data_map.get_mut(&event.room).unwrap();
+                self.display_names
+                    .insert(event.user_id.clone(), event.display_name.clone());
 
-                room_data.messages.push(MessageBoxItem::Message {
+                let room_data = get_or_insert_room(
+                    &mut self.room_data_map,
+                    &event.room,
+                    self.message_history_size,
+                );
+                // our own message may have been recorded already, when the server acknowledged it
+                if room_data.has_message(&event.message_id) {
+                    return;
+                }
+
+                room_data.push_message(MessageBoxItem::Message {
+                    message_id: event.message_id.clone(),
                     user_id: event.user_id.clone(),
+                    display_name: event.display_name.clone(),
                     content: event.content.clone(),
                 });
 
-                if let Some(active_room) = self.active_room.as_ref() {
-                    if !active_room.eq(&event.room) {
-                        room_data.has_unread = true;
+                // rooms joined in the background count their messages even if no room is active
+                if self.active_room.as_ref() != Some(&event.room) && !room_data.muted {
+                    room_data.unread_count += 1;
+
+                    let own_display_name = self
+                        .display_names
+                        .get(&self.user_id)
+                        .map(String::as_str)
+                        .unwrap_or(&self.user_id);
+                    if event.user_id != self.user_id
+                        && is_mentioned(&event.content, &[&self.user_id, own_display_name])
+                    {
+                        room_data.has_mention = true;
+                        self.mention_count += 1;
+                    }
+                }
+            }
+            event::Event::MessageAck(event) => {
+                if let Some(room_data) = self.room_data_mut(&event.room) {
+                    room_data.confirm_pending_message(&event.client_msg_id, &event.message_id);
+                }
+            }
+            event::Event::MessageDeleted(event) => {
+                if let Some(room_data) = self.room_data_mut(&event.room) {
+                    // CircularQueue does not support removal, so the deleted message is replaced in place
+                    // with a placeholder. This keeps the message count, hence the scroll position, stable.
+                    if let Some(item) = room_data.messages.iter_mut().find(|item| {
+                        matches!(item, MessageBoxItem::Message { message_id, .. } if message_id.eq(&event.message_id))
+                    }) {
+                        *item = MessageBoxItem::Notification(String::from(DELETED_MESSAGE_PLACEHOLDER));
+                    }
+                }
+            }
+            event::Event::Reidentified(event) => {
+                // we keep the display name we have picked, if any
+                if let Some(display_name) = self.display_names.get(&event.previous_user_id).cloned()
+                {
+                    self.display_names
+                        .insert(event.user_id.clone(), display_name);
+                }
+                self.user_id = event.user_id.clone();
+
+                if let Some(active_room) = self.active_room.clone() {
+                    self.push_notification(
+                        &active_room,
+                        format!("your user id is now {}", event.user_id),
+                    );
+                }
+            }
+            event::Event::DisplayNameChanged(event) => {
+                self.display_names
+                    .insert(event.user_id.clone(), event.display_name.clone());
+            }
+            // keepalive is handled by the state store
+            event::Event::UserKicked(event) => {
+                let moderator = self
+                    .display_names
+                    .get(&event.moderator_id)
+                    .cloned()
+                    .unwrap_or_else(|| event.moderator_id.clone());
+
+                if let Some(room_data) = self.room_data_mut(&event.room) {
+                    room_data.has_joined = false;
+                    room_data.users.clear();
+                }
+                self.push_notification(
+                    &event.room,
+                    format!("you have been kicked from the room by {}", moderator),
+                );
+            }
+            event::Event::SlowModeChanged(event) => {
+                let moderator = self
+                    .display_names
+                    .get(&event.moderator_id)
+                    .cloned()
+                    .unwrap_or_else(|| event.moderator_id.clone());
+
+                let content = match event.seconds {
+                    0 => format!("{} has turned the slow mode off", moderator),
+                    seconds => format!(
+                        "{} has turned the slow mode on, one message every {} seconds",
+                        moderator, seconds
+                    ),
+                };
+                self.push_notification(&event.room, content);
+            }
+            event::Event::PresenceChanged(event) => match event.status {
+                event::PresenceStatus::Active => {
+                    self.away_users.remove(&event.user_id);
+                }
+                event::PresenceStatus::Away => {
+                    self.away_users.insert(event.user_id.clone());
+                }
+            },
+            event::Event::MessagesMissed(event) => {
+                self.push_notification(
+                    &event.room,
+                    format!(
+                        "missed {} messages, could not keep up with the room",
+                        event.count
+                    ),
+                );
+            }
+            event::Event::HistoryPage(event) => {
+                if let Some(room_data) = self.room_data_mut(&event.room) {
+                    room_data.is_fetching_history = false;
+                    room_data.has_older_history = event.has_more;
+                    room_data.prepend_messages(event.messages.iter().map(|message| {
+                        MessageBoxItem::Message {
+                            message_id: message.message_id.clone(),
+                            user_id: message.user_id.clone(),
+                            display_name: message.display_name.clone(),
+                            content: message.content.clone(),
+                        }
+                    }));
+                }
+            }
+            event::Event::SystemMessage(event) => {
+                for room_data in self.room_data_map.values_mut().map(Arc::make_mut) {
+                    room_data.push_message(MessageBoxItem::System(event.content.clone()));
+                }
+                self.push_toast(event.content.clone());
+            }
+            // the joined rooms are already tracked by the room data
+            event::Event::JoinedRooms(_) => {}
+            event::Event::Pong(_) | event::Event::Ping(_) => {}
+            // the connection is about to close, which the state store handles
+            event::Event::ServerShutdown(_) => {}
+            event::Event::Error(event) => {
+                // the oldest message may have been deleted from the server, its older messages can not be paged to
+                if event.code == event::ErrorCode::MessageNotFound {
+                    for room_data in self.room_data_map.values_mut() {
+                        if room_data.is_fetching_history {
+                            let room_data = Arc::make_mut(room_data);
+                            room_data.is_fetching_history = false;
+                            room_data.has_older_history = false;
+                        }
                     }
                 }
+
+                self.push_toast(format!("Error: {}", event.message));
             }
         }
     }
 
+    /// The data of the given room to change, copied first if a clone of the state still shares it
+    fn room_data_mut(&mut self, room: &str) -> Option<&mut RoomData> {
+        self.room_data_map.get_mut(room).map(Arc::make_mut)
+    }
+
+    /// Pushes a local notification to the given room, visible only to this client
+    pub fn push_notification(&mut self, room: &str, content: String) {
+        if let Some(room_data) = self.room_data_mut(room) {
+            room_data.push_message(MessageBoxItem::Notification(content));
+        }
+    }
+
+    /// Shows a toast for a while, dropping the oldest toast if there are too many
+    pub fn push_toast(&mut self, content: String) {
+        self.toasts.push_back((content, Instant::now()));
+        while self.toasts.len() > MAX_TOASTS {
+            self.toasts.pop_front();
+        }
+    }
+
+    /// Drops the toasts which have been shown for long enough, returns whether there were any
+    pub fn expire_toasts(&mut self) -> bool {
+        let count = self.toasts.len();
+        self.toasts
+            .retain(|(_, raised_at)| raised_at.elapsed() < TOAST_DURATION);
+
+        self.toasts.len() != count
+    }
+
+    /// Marks the given room as left, without waiting for the server to confirm it
+    pub fn leave_room(&mut self, room: &str) {
+        if let Some(room_data) = self.room_data_mut(room) {
+            room_data.has_joined = false;
+            room_data.users.clear();
+        }
+        if self.active_room.as_deref() == Some(room) {
+            self.active_room = None;
+        }
+    }
+
+    /// The name the user is displayed with, defaults to the user id
+    fn own_display_name(&self) -> &str {
+        self.display_names
+            .get(&self.user_id)
+            .map(String::as_str)
+            .unwrap_or(&self.user_id)
+    }
+
+    /// Records a message the user is sending to the given room, shown as pending until the server acknowledges it
+    /// Returns the client id of the message, which the server echoes back with the acknowledgement
+    pub fn push_pending_message(&mut self, room: &str, content: &str) -> Option<String> {
+        let client_msg_id = self.sent_message_count.to_string();
+        let item = MessageBoxItem::PendingMessage {
+            client_msg_id: client_msg_id.clone(),
+            user_id: self.user_id.clone(),
+            display_name: String::from(self.own_display_name()),
+            content: String::from(content),
+        };
+
+        self.room_data_mut(room)?.push_message(item);
+        self.sent_message_count += 1;
+
+        Some(client_msg_id)
+    }
+
+    /// The command to load the messages of the given room older than the loaded ones, if there may be any
+    /// Only a single page is asked for at a time, and only as many messages as the history has room for
+    pub fn history_request(&mut self, room: &str) -> Option<command::FetchHistoryCommand> {
+        let room_data = self.room_data_mut(room)?;
+        let free_space = room_data.messages.capacity() - room_data.messages.len();
+
+        if !room_data.has_joined
+            || !room_data.has_older_history
+            || room_data.is_fetching_history
+            || free_space == 0
+        {
+            return None;
+        }
+
+        // the history we were given on join is empty, so there is nothing older
+        let before_message_id = String::from(room_data.oldest_message_id()?);
+        room_data.is_fetching_history = true;
+
+        Some(command::FetchHistoryCommand {
+            room: String::from(room),
+            before_message_id: Some(before_message_id),
+            limit: free_space.min(HISTORY_PAGE_SIZE),
+        })
+    }
+
+    /// Finds the id of the most recent message the user has sent to the given room
+    pub fn find_last_own_message_id(&self, room: &str) -> Option<String> {
+        self.room_data_map
+            .get(room)?
+            .messages
+            .iter()
+            .find_map(|item| match item {
+                MessageBoxItem::Message {
+                    message_id,
+                    user_id,
+                    ..
+                } if user_id.eq(&self.user_id) => Some(message_id.clone()),
+                _ => None,
+            })
+    }
+
+    /// The names of the rooms the user has joined
+    pub fn joined_rooms(&self) -> Vec<String> {
+        self.room_data_map
+            .values()
+            .filter(|room_data| room_data.has_joined)
+            .map(|room_data| room_data.name.clone())
+            .collect()
+    }
+
+    /// Forgets everything about the connection, keeping the preferences of the user
+    /// and the time of the first connection
+    pub fn reset(&mut self) {
+        *self = State {
+            is_bell_enabled: self.is_bell_enabled,
+            connected_at: self.connected_at,
+            message_history_size: self.message_history_size,
+            // the toasts may be telling why the connection was lost
+            toasts: std::mem::take(&mut self.toasts),
+            ..State::default()
+        };
+    }
+
+    pub fn mark_reconnecting(&mut self, addr: &str, attempt: u32, err: anyhow::Error) {
+        self.server_connection_status = ServerConnectionStatus::Reconnecting {
+            addr: String::from(addr),
+            attempt,
+            err: err.to_string(),
+        };
+    }
+
     pub fn mark_connection_request_start(&mut self) {
         self.server_connection_status = ServerConnectionStatus::Connecting;
     }
@@ -154,24 +790,1082 @@ impl State {
     /// Processes the result of a connection request to change the state of the application
     pub fn process_connection_request_result(&mut self, result: anyhow::Result<String>) {
         self.server_connection_status = match result {
-            Ok(addr) => ServerConnectionStatus::Connected { addr: addr.clone() },
+            Ok(addr) => {
+                self.connected_at.get_or_insert_with(Instant::now);
+
+                ServerConnectionStatus::Connected { addr }
+            }
             Err(err) => ServerConnectionStatus::Errored {
                 err: err.to_string(),
             },
         }
     }
 
+    /// Mutes or unmutes the given room, muting clears its unread messages and mentions
+    pub fn set_muted(&mut self, room: &str, muted: bool) {
+        if let Some(room_data) = self.room_data_mut(room) {
+            room_data.muted = muted;
+            if muted {
+                room_data.unread_count = 0;
+                room_data.has_mention = false;
+            }
+        }
+    }
+
+    /// The names of the rooms the user has muted
+    pub fn muted_rooms(&self) -> Vec<String> {
+        self.room_data_map
+            .values()
+            .filter(|room_data| room_data.muted)
+            .map(|room_data| room_data.name.clone())
+            .collect()
+    }
+
     /// Tries to set the active room as the given room. Returns the [RoomData] associated to the room.
     pub fn try_set_active_room(&mut self, room: &str) -> Option<&RoomData> {
-        let room_data = self.room_data_map.get_mut(room)?;
-        room_data.has_unread = false;
+        let room_data = Arc::make_mut(self.room_data_map.get_mut(room)?);
+        room_data.unread_count = 0;
+        room_data.has_mention = false;
 
         self.active_room = Some(String::from(room));
 
         Some(room_data)
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_mentioned() {
+        assert!(is_mentioned("@alice hi", &["alice"]));
+        assert!(is_mentioned("hi @alice, how are you?", &["abc12", "alice"]));
+        assert!(is_mentioned("ping @abc12!", &["abc12", "alice"]));
+
+        assert!(!is_mentioned("alice hi", &["alice"]));
+        assert!(!is_mentioned("@alice_b hi", &["alice"]));
+        assert!(!is_mentioned("mail@alice", &["alice"]));
+    }
+
+    #[test]
+    fn test_unread_messages_are_counted_until_the_room_is_active() {
+        let mut state = State {
+            user_id: "alice".into(),
+            active_room: Some("go".into()),
+            ..Default::default()
+        };
+        for name in ["go", "rust"] {
+            state.room_data_map.insert(
+                name.into(),
+                Arc::new(RoomData::new(
+                    name.into(),
+                    name.into(),
+                    DEFAULT_MESSAGE_HISTORY_SIZE,
+                )),
+            );
+        }
+
+        for i in 0..3 {
+            state.handle_server_event(&event::Event::UserMessage(
+                event::UserMessageBroadcastEvent {
+                    room: "rust".into(),
+                    message_id: format!("message-{}", i),
+                    user_id: "bob".into(),
+                    display_name: "bob".into(),
+                    content: "hello".into(),
+                },
+            ));
+        }
+        assert_eq!(state.room_data_map["rust"].unread_count, 3);
+        assert_eq!(state.room_data_map["go"].unread_count, 0);
+
+        state.try_set_active_room("rust");
+        assert_eq!(state.room_data_map["rust"].unread_count, 0);
+    }
+
+    #[test]
+    fn test_muted_rooms_record_the_messages_without_the_indicators() {
+        let mut state = State {
+            user_id: "alice".into(),
+            ..Default::default()
+        };
+        state.room_data_map.insert(
+            "rust".into(),
+            Arc::new(RoomData::new(
+                "rust".into(),
+                "Rust".into(),
+                DEFAULT_MESSAGE_HISTORY_SIZE,
+            )),
+        );
+        let mention = |message_id: &str| {
+            event::Event::UserMessage(event::UserMessageBroadcastEvent {
+                room: "rust".into(),
+                message_id: message_id.into(),
+                user_id: "bob".into(),
+                display_name: "bob".into(),
+                content: "hi @alice".into(),
+            })
+        };
+
+        state.handle_server_event(&mention("1"));
+        assert_eq!(state.room_data_map["rust"].unread_count, 1);
+
+        // muting clears the indicators, and keeps them off
+        state.set_muted("rust", true);
+        assert!(!state.room_data_map["rust"].has_mention);
+        state.handle_server_event(&mention("2"));
+        let rust = &state.room_data_map["rust"];
+        assert_eq!(rust.messages.len(), 2);
+        assert_eq!(rust.unread_count, 0);
+        assert!(!rust.has_mention);
+        assert_eq!(state.mention_count, 1);
+        assert_eq!(state.muted_rooms(), vec!["rust"]);
+
+        state.set_muted("rust", false);
+        state.handle_server_event(&mention("3"));
+        assert_eq!(state.room_data_map["rust"].unread_count, 1);
+        assert!(state.room_data_map["rust"].has_mention);
+    }
+
+    #[test]
+    fn test_clones_share_the_rooms_until_they_change() {
+        let mut state = State::default();
+        for name in ["go", "rust"] {
+            state.room_data_map.insert(
+                name.into(),
+                Arc::new(RoomData::new(
+                    name.into(),
+                    name.into(),
+                    DEFAULT_MESSAGE_HISTORY_SIZE,
+                )),
+            );
+        }
 
-    pub fn tick_timer(&mut self) {
-        self.timer += 1;
+        let rendered = state.clone();
+        state.push_notification("rust", "hello".into());
+
+        assert!(Arc::ptr_eq(
+            &state.room_data_map["go"],
+            &rendered.room_data_map["go"]
+        ));
+        assert!(!Arc::ptr_eq(
+            &state.room_data_map["rust"],
+            &rendered.room_data_map["rust"]
+        ));
+        assert_eq!(state.room_data_map["rust"].messages.len(), 1);
+        assert!(rendered.room_data_map["rust"].messages.is_empty());
+    }
+
+    #[test]
+    fn test_mentions_are_counted_and_the_bell_preference_survives_a_reset() {
+        let mut state = State {
+            user_id: "abc12".into(),
+            active_room: Some("go".into()),
+            is_bell_enabled: false,
+            ..Default::default()
+        };
+        for name in ["go", "rust"] {
+            state.room_data_map.insert(
+                name.into(),
+                Arc::new(RoomData::new(
+                    name.into(),
+                    name.into(),
+                    DEFAULT_MESSAGE_HISTORY_SIZE,
+                )),
+            );
+        }
+
+        for (room, content) in [("rust", "hi @abc12"), ("rust", "hi all"), ("go", "@abc12")] {
+            state.handle_server_event(&event::Event::UserMessage(
+                event::UserMessageBroadcastEvent {
+                    room: room.into(),
+                    message_id: content.into(),
+                    user_id: "bob".into(),
+                    display_name: "bob".into(),
+                    content: content.into(),
+                },
+            ));
+        }
+        // only the mentions outside the active room count
+        assert_eq!(state.mention_count, 1);
+
+        state.reset();
+        assert_eq!(state.mention_count, 0);
+        assert!(state.room_data_map.is_empty());
+        assert!(!state.is_bell_enabled);
+    }
+
+    #[test]
+    fn test_leaving_the_active_room() {
+        let mut state = State {
+            user_id: "alice".into(),
+            active_room: Some("rust".into()),
+            ..Default::default()
+        };
+        state.room_data_map.insert(
+            "rust".into(),
+            Arc::new(RoomData {
+                has_joined: true,
+                ..RoomData::new("rust".into(), "Rust".into(), DEFAULT_MESSAGE_HISTORY_SIZE)
+            }),
+        );
+
+        state.handle_server_event(&event::Event::RoomParticipation(
+            event::RoomParticipationBroadcastEvent {
+                room: "rust".into(),
+                user_id: "alice".into(),
+                display_name: "alice".into(),
+                status: event::RoomParticipationStatus::Left,
+                reason: None,
+            },
+        ));
+
+        assert!(!state.room_data_map["rust"].has_joined);
+        assert_eq!(state.active_room, None);
+    }
+
+    fn participation(user_id: &str, status: event::RoomParticipationStatus) -> event::Event {
+        event::Event::RoomParticipation(event::RoomParticipationBroadcastEvent {
+            room: "rust".into(),
+            user_id: user_id.into(),
+            display_name: user_id.into(),
+            status,
+            reason: None,
+        })
+    }
+
+    fn notifications(state: &State) -> Vec<String> {
+        state.room_data_map["rust"]
+            .messages
+            .asc_iter()
+            .filter_map(|item| match item {
+                MessageBoxItem::Notification(content) => Some(content.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_system_message_is_shown_in_every_room() {
+        let mut state = State::default();
+        state.handle_server_event(&event::Event::LoginSuccessful(
+            event::LoginSuccessfulReplyEvent {
+                session_id: "session".into(),
+                user_id: "alice".into(),
+                rooms: ["rust", "go"]
+                    .into_iter()
+                    .map(|name| event::RoomDetail {
+                        name: name.into(),
+                        description: name.into(),
+                        participant_count: 0,
+                    })
+                    .collect(),
+                server_version: 2,
+            },
+        ));
+
+        state.handle_server_event(&event::Event::SystemMessage(
+            event::SystemMessageBroadcastEvent {
+                content: "welcome".into(),
+            },
+        ));
+
+        for room_data in state.room_data_map.values() {
+            assert!(matches!(
+                room_data.messages.asc_iter().collect::<Vec<_>>()[..],
+                [MessageBoxItem::System(ref content)] if content == "welcome"
+            ));
+        }
+    }
+
+    #[test]
+    fn test_join_times_follow_the_participants() {
+        let mut state = State {
+            user_id: "alice".into(),
+            ..Default::default()
+        };
+
+        state.handle_server_event(&event::Event::UserJoinedRoom(
+            event::UserJoinedRoomReplyEvent {
+                room: "rust".into(),
+                users: vec!["alice".into(), "bob".into()],
+                display_names: HashMap::new(),
+                joined_at: HashMap::from([("alice".into(), 100), ("bob".into(), 50)]),
+                history: vec![],
+            },
+        ));
+        // the join time reported by the server is kept, the users joining afterwards are timed locally
+        state.handle_server_event(&participation(
+            "alice",
+            event::RoomParticipationStatus::Joined,
+        ));
+        state.handle_server_event(&participation(
+            "carol",
+            event::RoomParticipationStatus::Joined,
+        ));
+        state.handle_server_event(&participation("bob", event::RoomParticipationStatus::Left));
+
+        let joined_at = &state.room_data_map["rust"].joined_at;
+        assert_eq!(joined_at["alice"], 100);
+        assert!(joined_at["carol"] >= unix_now() - 1);
+        assert!(!joined_at.contains_key("bob"));
+    }
+
+    fn user_message(message_id: &str) -> event::UserMessageBroadcastEvent {
+        event::UserMessageBroadcastEvent {
+            room: "rust".into(),
+            message_id: message_id.into(),
+            user_id: "bob".into(),
+            display_name: "bob".into(),
+            content: message_id.into(),
+        }
+    }
+
+    fn message_ids(state: &State) -> Vec<String> {
+        state.room_data_map["rust"]
+            .messages
+            .asc_iter()
+            .filter_map(|item| match item {
+                MessageBoxItem::Message { message_id, .. } => Some(message_id.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_older_history_is_fetched_a_page_at_a_time() {
+        let mut state = State {
+            user_id: "alice".into(),
+            ..Default::default()
+        };
+        state.handle_server_event(&event::Event::UserJoinedRoom(
+            event::UserJoinedRoomReplyEvent {
+                room: "rust".into(),
+                users: vec!["alice".into()],
+                display_names: HashMap::new(),
+                joined_at: HashMap::new(),
+                history: vec![user_message("3"), user_message("4")],
+            },
+        ));
+        state.handle_server_event(&participation(
+            "alice",
+            event::RoomParticipationStatus::Joined,
+        ));
+
+        let received_messages = state.room_data_map["rust"].received_messages;
+        let request = state.history_request("rust").unwrap();
+        assert_eq!(request.before_message_id.as_deref(), Some("3"));
+        assert_eq!(request.limit, HISTORY_PAGE_SIZE);
+        // a single page is fetched at a time
+        assert!(state.history_request("rust").is_none());
+
+        state.handle_server_event(&event::Event::HistoryPage(event::HistoryPageReplyEvent {
+            room: "rust".into(),
+            before_message_id: Some("3".into()),
+            messages: vec![user_message("1"), user_message("2"), user_message("3")],
+            has_more: false,
+        }));
+
+        assert_eq!(message_ids(&state), vec!["1", "2", "3", "4"]);
+        // the older messages are not new to the user
+        assert_eq!(
+            state.room_data_map["rust"].received_messages,
+            received_messages
+        );
+        // the oldest message of the room has been reached
+        assert!(state.history_request("rust").is_none());
+    }
+
+    #[test]
+    fn test_participation_is_only_notified_when_it_changes() {
+        let mut state = State {
+            user_id: "alice".into(),
+            ..Default::default()
+        };
+        state.room_data_map.insert(
+            "rust".into(),
+            Arc::new(RoomData::new(
+                "rust".into(),
+                "Rust".into(),
+                DEFAULT_MESSAGE_HISTORY_SIZE,
+            )),
+        );
+
+        // the reply to our join already lists us, and bob who joins right after
+        state.handle_server_event(&event::Event::UserJoinedRoom(
+            event::UserJoinedRoomReplyEvent {
+                room: "rust".into(),
+                users: vec!["alice".into(), "bob".into()],
+                display_names: HashMap::new(),
+                joined_at: HashMap::new(),
+                history: vec![],
+            },
+        ));
+        state.handle_server_event(&participation(
+            "alice",
+            event::RoomParticipationStatus::Joined,
+        ));
+        state.handle_server_event(&participation(
+            "bob",
+            event::RoomParticipationStatus::Joined,
+        ));
+        state.handle_server_event(&participation(
+            "carol",
+            event::RoomParticipationStatus::Joined,
+        ));
+        state.handle_server_event(&participation(
+            "carol",
+            event::RoomParticipationStatus::Joined,
+        ));
+        state.handle_server_event(&participation("bob", event::RoomParticipationStatus::Left));
+        state.handle_server_event(&participation("bob", event::RoomParticipationStatus::Left));
+
+        assert_eq!(
+            notifications(&state),
+            vec![
+                "alice has joined the room",
+                "carol has joined the room",
+                "bob has left the room",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_departures_are_told_apart_by_their_reason() {
+        let mut state = State {
+            user_id: "alice".into(),
+            ..Default::default()
+        };
+        state.room_data_map.insert(
+            "rust".into(),
+            Arc::new(RoomData {
+                users: ["bob", "carol", "dave", "erin"]
+                    .map(String::from)
+                    .into_iter()
+                    .collect(),
+                ..RoomData::new("rust".into(), "Rust".into(), DEFAULT_MESSAGE_HISTORY_SIZE)
+            }),
+        );
+
+        for (user_id, reason) in [
+            ("bob", event::DepartureReason::Left),
+            ("carol", event::DepartureReason::Quit),
+            ("dave", event::DepartureReason::Disconnected),
+            ("erin", event::DepartureReason::Kicked),
+        ] {
+            state.handle_server_event(&event::Event::RoomParticipation(
+                event::RoomParticipationBroadcastEvent {
+                    room: "rust".into(),
+                    user_id: user_id.into(),
+                    display_name: user_id.into(),
+                    status: event::RoomParticipationStatus::Left,
+                    reason: Some(reason),
+                },
+            ));
+        }
+
+        assert_eq!(
+            notifications(&state),
+            vec![
+                "bob has left the room",
+                "carol has quit",
+                "dave has lost connection",
+                "erin has been kicked from the room",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_connected_at_is_set_once_and_survives_a_reset() {
+        let mut state = State::default();
+        state.process_connection_request_result(Err(anyhow::anyhow!("refused")));
+        assert!(state.connected_at.is_none());
+
+        state.process_connection_request_result(Ok("localhost:8080".into()));
+        let connected_at = state.connected_at.expect("set on the first connect");
+
+        state.reset();
+        state.process_connection_request_result(Ok("localhost:8080".into()));
+        assert_eq!(state.connected_at, Some(connected_at));
+    }
+
+    #[test]
+    fn test_pending_messages_are_confirmed_in_either_order() {
+        let mut state = State {
+            user_id: "abc12".into(),
+            ..Default::default()
+        };
+        state.room_data_map.insert(
+            "rust".into(),
+            Arc::new(RoomData::new(
+                "rust".into(),
+                "rust".into(),
+                DEFAULT_MESSAGE_HISTORY_SIZE,
+            )),
+        );
+        let echo = |message_id: &str, content: &str| {
+            event::Event::UserMessage(event::UserMessageBroadcastEvent {
+                room: "rust".into(),
+                message_id: message_id.into(),
+                user_id: "abc12".into(),
+                display_name: "abc12".into(),
+                content: content.into(),
+            })
+        };
+        let ack = |client_msg_id: &str, message_id: &str| {
+            event::Event::MessageAck(event::MessageAckReplyEvent {
+                room: "rust".into(),
+                client_msg_id: client_msg_id.into(),
+                message_id: message_id.into(),
+            })
+        };
+
+        let first = state.push_pending_message("rust", "first").unwrap();
+        let second = state.push_pending_message("rust", "second").unwrap();
+        assert_ne!(first, second);
+
+        // the ack arrives before the echo of the message
+        state.handle_server_event(&ack(&first, "m1"));
+        state.handle_server_event(&echo("m1", "first"));
+        // the echo arrives before the ack of the message
+        state.handle_server_event(&echo("m2", "second"));
+        state.handle_server_event(&ack(&second, "m2"));
+
+        let room_data = &state.room_data_map["rust"];
+        let message_ids = room_data
+            .messages
+            .asc_iter()
+            .map(|item| match item {
+                MessageBoxItem::Message { message_id, .. } => message_id.as_str(),
+                item => panic!("unexpected item: {:?}", item),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(message_ids, vec!["m1", "m2"]);
+        assert_eq!(room_data.received_messages, 2);
+    }
+
+    #[test]
+    fn test_rooms_keep_the_configured_number_of_messages() {
+        let mut state = State {
+            message_history_size: 2,
+            ..Default::default()
+        };
+        state.reset();
+        state.handle_server_event(&event::Event::LoginSuccessful(
+            event::LoginSuccessfulReplyEvent {
+                session_id: "session".into(),
+                user_id: "abc12".into(),
+                rooms: vec![event::RoomDetail {
+                    name: "rust".into(),
+                    description: "Rust".into(),
+                    participant_count: 0,
+                }],
+                server_version: comms::PROTOCOL_VERSION,
+            },
+        ));
+        Arc::make_mut(state.room_data_map.get_mut("rust").unwrap()).has_joined = true;
+
+        for i in 0..3 {
+            state.handle_server_event(&event::Event::UserMessage(
+                event::UserMessageBroadcastEvent {
+                    room: "rust".into(),
+                    message_id: format!("message-{}", i),
+                    user_id: "bob".into(),
+                    display_name: "bob".into(),
+                    content: format!("message-{}", i),
+                },
+            ));
+        }
+
+        let room_data = &state.room_data_map["rust"];
+        let contents = room_data
+            .messages
+            .asc_iter()
+            .map(|item| match item {
+                MessageBoxItem::Message { content, .. } => content.as_str(),
+                item => panic!("unexpected item: {:?}", item),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(contents, vec!["message-1", "message-2"]);
+        assert_eq!(room_data.received_messages, 3);
+    }
+
+    #[test]
+    fn test_events_for_unknown_rooms_create_the_rooms() {
+        let mut state = State::default();
+
+        state.handle_server_event(&event::Event::UserMessage(
+            event::UserMessageBroadcastEvent {
+                room: "new-room".into(),
+                message_id: "message-1".into(),
+                user_id: "bob".into(),
+                display_name: "bob".into(),
+                content: "hello".into(),
+            },
+        ));
+        state.handle_server_event(&event::Event::UserJoinedRoom(
+            event::UserJoinedRoomReplyEvent {
+                room: "other-room".into(),
+                users: vec!["bob".into()],
+                display_names: HashMap::new(),
+                joined_at: HashMap::new(),
+                history: Vec::new(),
+            },
+        ));
+
+        assert!(state.room_data_map["new-room"].has_message("message-1"));
+        assert!(state.room_data_map["other-room"].users.contains("bob"));
+    }
+
+    #[test]
+    fn test_participation_in_an_unknown_room_creates_the_room() {
+        let mut state = State {
+            user_id: "abc12".into(),
+            ..Default::default()
+        };
+
+        state.handle_server_event(&event::Event::RoomParticipation(
+            event::RoomParticipationBroadcastEvent {
+                user_id: "bob".into(),
+                display_name: "bob".into(),
+                room: "new-room".into(),
+                status: event::RoomParticipationStatus::Joined,
+                reason: None,
+            },
+        ));
+
+        let room_data = &state.room_data_map["new-room"];
+        assert_eq!(room_data.users, HashSet::from([String::from("bob")]));
+        assert!(!room_data.has_joined);
+    }
+
+    #[test]
+    fn test_reidentified_keeps_the_display_name() {
+        let mut state = State {
+            user_id: "abc12".into(),
+            active_room: Some("rust".into()),
+            ..Default::default()
+        };
+        state.room_data_map.insert(
+            "rust".into(),
+            Arc::new(RoomData::new(
+                "rust".into(),
+                "rust".into(),
+                DEFAULT_MESSAGE_HISTORY_SIZE,
+            )),
+        );
+        Arc::make_mut(state.room_data_map.get_mut("rust").unwrap())
+            .users
+            .insert("bob".into());
+        state.display_names.insert("abc12".into(), "ferris".into());
+
+        state.handle_server_event(&event::Event::Reidentified(event::ReidentifiedReplyEvent {
+            previous_user_id: "abc12".into(),
+            user_id: "xyz34".into(),
+        }));
+        state.handle_server_event(&event::Event::RoomParticipation(
+            event::RoomParticipationBroadcastEvent {
+                user_id: "bob".into(),
+                display_name: "bob".into(),
+                room: "rust".into(),
+                status: event::RoomParticipationStatus::Left,
+                reason: Some(event::DepartureReason::Reidentified),
+            },
+        ));
+
+        assert_eq!(state.user_id, "xyz34");
+        assert_eq!(state.display_names["xyz34"], "ferris");
+        let notifications = state.room_data_map["rust"]
+            .messages
+            .asc_iter()
+            .map(|item| match item {
+                MessageBoxItem::Notification(content) => content.as_str(),
+                item => panic!("unexpected item: {:?}", item),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            notifications,
+            vec!["your user id is now xyz34", "bob has changed their id"]
+        );
+    }
+
+    fn room_detail(name: &str, participant_count: usize) -> event::RoomDetail {
+        event::RoomDetail {
+            name: name.into(),
+            description: format!("{} room", name),
+            participant_count,
+        }
+    }
+
+    /// A state logged in as alice, who has joined the rust room and is chatting in it
+    fn joined_state() -> State {
+        let mut state = State::default();
+        state.handle_server_event(&event::Event::LoginSuccessful(
+            event::LoginSuccessfulReplyEvent {
+                session_id: "session".into(),
+                user_id: "alice".into(),
+                rooms: vec![room_detail("rust", 0), room_detail("go", 0)],
+                server_version: 2,
+            },
+        ));
+        state.handle_server_event(&participation(
+            "alice",
+            event::RoomParticipationStatus::Joined,
+        ));
+        state.try_set_active_room("rust");
+
+        state
+    }
+
+    #[test]
+    fn test_login_successful_populates_the_rooms() {
+        let mut state = State {
+            active_room: Some("python".into()),
+            display_names: HashMap::from([("bob".into(), "bobby".into())]),
+            ..Default::default()
+        };
+        state.room_data_map.insert(
+            "python".into(),
+            Arc::new(RoomData::new(
+                "python".into(),
+                "Python".into(),
+                DEFAULT_MESSAGE_HISTORY_SIZE,
+            )),
+        );
+
+        state.handle_server_event(&event::Event::LoginSuccessful(
+            event::LoginSuccessfulReplyEvent {
+                session_id: "session".into(),
+                user_id: "alice".into(),
+                rooms: vec![room_detail("rust", 3), room_detail("go", 0)],
+                server_version: 2,
+            },
+        ));
+
+        assert_eq!(state.user_id, "alice");
+        assert!(state.display_names.is_empty());
+        let mut rooms = state.room_data_map.keys().cloned().collect::<Vec<_>>();
+        rooms.sort();
+        assert_eq!(rooms, vec!["go", "rust"]);
+        let rust = &state.room_data_map["rust"];
+        assert_eq!(rust.description, "rust room");
+        assert_eq!(rust.participant_count, 3);
+        assert!(!rust.has_joined);
+        assert!(rust.messages.is_empty());
+        // the active room of the previous connection is gone
+        assert_eq!(state.active_room, None);
+    }
+
+    #[test]
+    fn test_participation_of_the_user_and_the_others() {
+        let mut state = State {
+            user_id: "alice".into(),
+            ..Default::default()
+        };
+
+        state.handle_server_event(&participation(
+            "bob",
+            event::RoomParticipationStatus::Joined,
+        ));
+        assert!(state.room_data_map["rust"].users.contains("bob"));
+        assert!(!state.room_data_map["rust"].has_joined);
+
+        state.handle_server_event(&participation(
+            "alice",
+            event::RoomParticipationStatus::Joined,
+        ));
+        assert!(state.room_data_map["rust"].users.contains("alice"));
+        assert!(state.room_data_map["rust"].has_joined);
+
+        state.handle_server_event(&participation("bob", event::RoomParticipationStatus::Left));
+        assert!(!state.room_data_map["rust"].users.contains("bob"));
+        assert!(state.room_data_map["rust"].has_joined);
+
+        state.handle_server_event(&participation(
+            "alice",
+            event::RoomParticipationStatus::Left,
+        ));
+        assert!(state.room_data_map["rust"].users.is_empty());
+        assert!(!state.room_data_map["rust"].has_joined);
+
+        assert_eq!(
+            notifications(&state),
+            vec![
+                "bob has joined the room",
+                "alice has joined the room",
+                "bob has left the room",
+                "alice has left the room",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_user_joined_room_replaces_the_users() {
+        let mut state = joined_state();
+        state.handle_server_event(&participation(
+            "carol",
+            event::RoomParticipationStatus::Joined,
+        ));
+
+        state.handle_server_event(&event::Event::UserJoinedRoom(
+            event::UserJoinedRoomReplyEvent {
+                room: "rust".into(),
+                users: vec!["alice".into(), "bob".into()],
+                display_names: HashMap::from([("bob".into(), "bobby".into())]),
+                joined_at: HashMap::new(),
+                history: vec![],
+            },
+        ));
+
+        assert_eq!(
+            state.room_data_map["rust"].users,
+            HashSet::from(["alice".into(), "bob".into()])
+        );
+        assert_eq!(state.display_names["bob"], "bobby");
+    }
+
+    #[test]
+    fn test_user_messages_are_appended_and_unread_outside_the_active_room() {
+        let mut state = joined_state();
+        let notified = state.room_data_map["rust"].messages.len();
+
+        for message_id in ["1", "2"] {
+            state.handle_server_event(&event::Event::UserMessage(user_message(message_id)));
+        }
+        // the same message may arrive twice, e.g. after a reconnect
+        state.handle_server_event(&event::Event::UserMessage(user_message("2")));
+        state.handle_server_event(&event::Event::UserMessage(
+            event::UserMessageBroadcastEvent {
+                room: "go".into(),
+                ..user_message("3")
+            },
+        ));
+
+        let rust = &state.room_data_map["rust"];
+        assert_eq!(message_ids(&state), vec!["1", "2"]);
+        assert_eq!(rust.messages.len(), notified + 2);
+        assert_eq!(rust.unread_count, 0);
+        assert_eq!(state.room_data_map["go"].unread_count, 1);
+        assert_eq!(state.display_names["bob"], "bob");
+    }
+
+    #[test]
+    fn test_room_list_keeps_the_history_of_the_remaining_rooms() {
+        let mut state = joined_state();
+        state.handle_server_event(&event::Event::UserMessage(user_message("1")));
+
+        state.handle_server_event(&event::Event::RoomList(event::RoomListReplyEvent {
+            rooms: vec![room_detail("rust", 2), room_detail("python", 1)],
+        }));
+        state.handle_server_event(&event::Event::RoomCreated(room_detail("zig", 0)));
+        state.handle_server_event(&event::Event::RoomOccupancyChanged(
+            event::RoomOccupancyChangedBroadcastEvent {
+                room: "zig".into(),
+                count: 4,
+            },
+        ));
+
+        let mut rooms = state.room_data_map.keys().cloned().collect::<Vec<_>>();
+        rooms.sort();
+        assert_eq!(rooms, vec!["python", "rust", "zig"]);
+        assert_eq!(message_ids(&state), vec!["1"]);
+        assert!(state.room_data_map["rust"].has_joined);
+        assert_eq!(state.room_data_map["rust"].participant_count, 2);
+        assert_eq!(state.room_data_map["zig"].participant_count, 4);
+        assert_eq!(state.active_room.as_deref(), Some("rust"));
+    }
+
+    #[test]
+    fn test_deleted_messages_are_replaced_in_place() {
+        let mut state = joined_state();
+        for message_id in ["1", "2", "3"] {
+            state.handle_server_event(&event::Event::UserMessage(user_message(message_id)));
+        }
+        let len = state.room_data_map["rust"].messages.len();
+
+        state.handle_server_event(&event::Event::MessageDeleted(
+            event::MessageDeletedBroadcastEvent {
+                room: "rust".into(),
+                message_id: "2".into(),
+            },
+        ));
+
+        assert_eq!(message_ids(&state), vec!["1", "3"]);
+        assert_eq!(state.room_data_map["rust"].messages.len(), len);
+        assert!(notifications(&state).contains(&String::from(DELETED_MESSAGE_PLACEHOLDER)));
+    }
+
+    #[test]
+    fn test_moderation_is_notified() {
+        let mut state = joined_state();
+        state.handle_server_event(&event::Event::DisplayNameChanged(
+            event::DisplayNameChangedBroadcastEvent {
+                user_id: "mod".into(),
+                display_name: "ferris".into(),
+            },
+        ));
+
+        for seconds in [10, 0] {
+            state.handle_server_event(&event::Event::SlowModeChanged(
+                event::SlowModeChangedBroadcastEvent {
+                    room: "rust".into(),
+                    moderator_id: "mod".into(),
+                    seconds,
+                },
+            ));
+        }
+        state.handle_server_event(&event::Event::UserKicked(event::UserKickedBroadcastEvent {
+            room: "rust".into(),
+            user_id: "alice".into(),
+            moderator_id: "mod".into(),
+        }));
+
+        assert!(!state.room_data_map["rust"].has_joined);
+        assert!(state.room_data_map["rust"].users.is_empty());
+        assert_eq!(
+            notifications(&state)[1..],
+            [
+                "ferris has turned the slow mode on, one message every 10 seconds",
+                "ferris has turned the slow mode off",
+                "you have been kicked from the room by ferris",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_presence_and_the_users_of_the_rooms_are_tracked() {
+        let mut state = joined_state();
+        state.handle_server_event(&participation(
+            "bob",
+            event::RoomParticipationStatus::Joined,
+        ));
+
+        for status in [event::PresenceStatus::Away, event::PresenceStatus::Active] {
+            state.handle_server_event(&event::Event::PresenceChanged(
+                event::PresenceChangedBroadcastEvent {
+                    user_id: "bob".into(),
+                    status,
+                },
+            ));
+            assert_eq!(
+                state.away_users.contains("bob"),
+                status == event::PresenceStatus::Away
+            );
+        }
+
+        // the snapshot of the server wins over the participation events
+        state.handle_server_event(&event::Event::RoomUsersSnapshot(
+            event::RoomUsersSnapshotBroadcastEvent {
+                room: "rust".into(),
+                users: vec!["alice".into(), "carol".into()],
+            },
+        ));
+        assert_eq!(
+            state.room_data_map["rust"].users,
+            HashSet::from(["alice".into(), "carol".into()])
+        );
+        assert!(!state.room_data_map["rust"].joined_at.contains_key("bob"));
+
+        state.handle_server_event(&event::Event::RoomUsers(event::RoomUsersReplyEvent {
+            room: "go".into(),
+            users: vec!["dave".into(), "bob".into()],
+            display_names: HashMap::from([("dave".into(), "davey".into())]),
+        }));
+        let preview = state.room_users_preview.as_ref().unwrap();
+        assert_eq!(preview.room, "go");
+        assert_eq!(preview.users, vec!["bob", "dave"]);
+        assert_eq!(state.display_names["dave"], "davey");
+    }
+
+    #[test]
+    fn test_errors_are_toasted_and_missed_messages_are_notified() {
+        let mut state = joined_state();
+        let request = state.history_request("rust");
+        assert!(request.is_none(), "there is no message to page back from");
+        Arc::make_mut(state.room_data_map.get_mut("rust").unwrap()).is_fetching_history = true;
+
+        state.handle_server_event(&event::Event::MessagesMissed(
+            event::MessagesMissedReplyEvent {
+                room: "rust".into(),
+                count: 5,
+            },
+        ));
+        state.handle_server_event(&event::Event::Error(event::ErrorReplyEvent::new(
+            event::ErrorCode::MessageNotFound,
+            "message not found",
+        )));
+
+        let rust = &state.room_data_map["rust"];
+        assert!(!rust.is_fetching_history);
+        assert!(!rust.has_older_history);
+        assert!(notifications(&state).contains(&String::from(
+            "missed 5 messages, could not keep up with the room"
+        )));
+        assert!(!rust
+            .messages
+            .asc_iter()
+            .any(|item| searchable(item).contains("message not found")));
+        assert_eq!(state.toasts[0].0, "Error: message not found");
+    }
+
+    fn searchable(item: &MessageBoxItem) -> &str {
+        match item {
+            MessageBoxItem::Message { content, .. }
+            | MessageBoxItem::PendingMessage { content, .. }
+            | MessageBoxItem::Notification(content)
+            | MessageBoxItem::System(content) => content,
+        }
+    }
+
+    #[test]
+    fn test_toasts_are_bounded_and_expire() {
+        let mut state = State::default();
+        for i in 0..5 {
+            state.push_toast(format!("toast {}", i));
+        }
+        let toasts = state
+            .toasts
+            .iter()
+            .map(|(content, _)| content.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(toasts, vec!["toast 2", "toast 3", "toast 4"]);
+        assert!(!state.expire_toasts());
+
+        // the toasts survive a reset, they may be telling why the connection was lost
+        state.reset();
+        assert_eq!(state.toasts.len(), 3);
+
+        let raised_at = Instant::now().checked_sub(TOAST_DURATION).unwrap();
+        state.toasts[0].1 = raised_at;
+        state.toasts[1].1 = raised_at;
+        assert!(state.expire_toasts());
+        assert_eq!(state.toasts.len(), 1);
+        assert_eq!(state.toasts[0].0, "toast 4");
+    }
+
+    #[test]
+    fn test_connection_events_leave_the_rooms_untouched() {
+        let mut state = joined_state();
+        let rendered = state.clone();
+
+        for event in [
+            event::Event::JoinedRooms(event::JoinedRoomsReplyEvent {
+                rooms: vec!["rust".into()],
+            }),
+            event::Event::Ping(event::PingRequestEvent),
+            event::Event::Pong(event::PongReplyEvent),
+            event::Event::ServerShutdown(event::ServerShutdownBroadcastEvent {
+                reason: "restarting".into(),
+                reconnect_after_secs: 5,
+            }),
+        ] {
+            state.handle_server_event(&event);
+        }
+
+        for (room, room_data) in &state.room_data_map {
+            assert!(Arc::ptr_eq(room_data, &rendered.room_data_map[room]));
+        }
+        assert_eq!(state.active_room, rendered.active_room);
     }
 }
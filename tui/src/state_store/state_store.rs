@@ -1,48 +1,328 @@
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::Context;
 use comms::{
-    command,
+    command, event,
     transport::{
         self,
         client::{CommandWriter, EventStream},
+        tls::{self, TlsConnector},
     },
 };
 use tokio::{
-    net::TcpStream,
-    sync::{
-        broadcast,
-        mpsc::{self, UnboundedReceiver, UnboundedSender},
-    },
+    net::{TcpStream, UnixStream},
+    sync::{broadcast, mpsc::UnboundedReceiver, watch},
 };
 use tokio_stream::StreamExt;
 
-use crate::{Interrupted, Terminator};
+use crate::{clipboard::Clipboard, Interrupted, Terminator};
 
-use super::{action::Action, State};
+use super::{action::Action, transcript::format_transcript, State, DEFAULT_MESSAGE_HISTORY_SIZE};
 
 pub struct StateStore {
-    state_tx: UnboundedSender<State>,
+    /// Only the latest state is kept for the UI, so the states it has not rendered yet are coalesced
+    state_tx: watch::Sender<State>,
+    /// Whether to reconnect to the server automatically when the connection is lost
+    auto_reconnect: bool,
+    /// Encrypts the connections to the server if set
+    tls_connector: Option<TlsConnector>,
+    /// How many of the most recent messages are kept per room
+    message_history_size: usize,
 }
 
 impl StateStore {
-    pub fn new() -> (Self, UnboundedReceiver<State>) {
-        let (state_tx, state_rx) = mpsc::unbounded_channel::<State>();
+    pub fn new() -> (Self, watch::Receiver<State>) {
+        // the UI waits for the main loop to send the initial state, so this one is never rendered
+        let (state_tx, state_rx) = watch::channel(State::default());
+
+        (
+            StateStore {
+                state_tx,
+                auto_reconnect: true,
+                tls_connector: None,
+                message_history_size: DEFAULT_MESSAGE_HISTORY_SIZE,
+            },
+            state_rx,
+        )
+    }
 
-        (StateStore { state_tx }, state_rx)
+    pub fn with_auto_reconnect(self, auto_reconnect: bool) -> Self {
+        StateStore {
+            auto_reconnect,
+            ..self
+        }
+    }
+
+    pub fn with_tls(self, tls_connector: TlsConnector) -> Self {
+        StateStore {
+            tls_connector: Some(tls_connector),
+            ..self
+        }
+    }
+
+    pub fn with_message_history_size(self, message_history_size: usize) -> Self {
+        StateStore {
+            message_history_size,
+            ..self
+        }
     }
 }
 
+/// How often a ping is sent to the server to check the connection
+const PING_INTERVAL: Duration = Duration::from_secs(5);
+/// How long to wait for the connection to the server to be established, including the TLS handshake
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long to wait for a pong before the connection is considered dead
+const PONG_TIMEOUT: Duration = Duration::from_secs(15);
+/// How long the user needs to be idle before they are marked as away
+const AWAY_AFTER: Duration = Duration::from_secs(5 * 60);
+/// How long to wait before the first reconnection attempt, doubled after each failed attempt
+const RECONNECT_BACKOFF_BASE: Duration = Duration::from_secs(1);
+/// The longest to wait between two reconnection attempts
+const RECONNECT_BACKOFF_MAX: Duration = Duration::from_secs(30);
+
+/// The port the server listens on by default, used when the address does not have one
+const DEFAULT_PORT: u16 = 8080;
+
 type ServerHandle = (EventStream, CommandWriter);
 
-async fn create_server_handle(addr: &str) -> anyhow::Result<ServerHandle> {
+/// Trims and validates the address the user has given, adding the default port if it does not have one.
+/// Absolute paths are unix domain sockets, and are kept as they are
+fn parse_server_addr(addr: &str) -> anyhow::Result<String> {
+    let addr = addr.trim();
+    if addr.starts_with('/') {
+        return Ok(String::from(addr));
+    }
+
+    let invalid = |reason: &str| anyhow::anyhow!("invalid address '{}': {}", addr, reason);
+
+    // IPv6 addresses are written in brackets when followed by a port, e.g. [::1]:8080
+    let (host, port) = if let Some(rest) = addr.strip_prefix('[') {
+        let (host, port) = rest
+            .split_once(']')
+            .ok_or_else(|| invalid("missing the closing bracket"))?;
+        host.parse::<std::net::Ipv6Addr>()
+            .map_err(|_| invalid("not an IPv6 address"))?;
+        let port = match port {
+            "" => None,
+            port => Some(
+                port.strip_prefix(':')
+                    .ok_or_else(|| invalid("expected a port after the brackets"))?,
+            ),
+        };
+
+        (format!("[{}]", host), port)
+    } else if addr.parse::<std::net::Ipv6Addr>().is_ok() {
+        (format!("[{}]", addr), None)
+    } else {
+        let (host, port) = match addr.split_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (addr, None),
+        };
+        let is_valid_host = !host.is_empty()
+            && host
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_'));
+        if !is_valid_host {
+            return Err(invalid("expected a host name or an IP address"));
+        }
+
+        (String::from(host), port)
+    };
+
+    let port = match port {
+        Some(port) => port
+            .parse::<u16>()
+            .ok()
+            .filter(|port| *port != 0)
+            .ok_or_else(|| invalid("the port must be a number between 1 and 65535"))?,
+        None => DEFAULT_PORT,
+    };
+
+    Ok(format!("{}:{}", host, port))
+}
+
+/// Connects to the server at the given `host:port`, or the unix domain socket at the given absolute path
+async fn create_server_handle(
+    addr: &str,
+    tls_connector: Option<&TlsConnector>,
+) -> anyhow::Result<ServerHandle> {
+    // local connections over a unix domain socket do not need to be encrypted
+    if addr.starts_with('/') {
+        let stream = UnixStream::connect(addr).await?;
+
+        return Ok(transport::client::split_stream(stream));
+    }
+
     let stream = TcpStream::connect(addr).await?;
-    let (event_stream, command_writer) = transport::client::split_tcp_stream(stream);
+
+    match tls_connector {
+        Some(tls_connector) => {
+            let stream = tls_connector
+                .connect(tls::server_name(addr)?, stream)
+                .await
+                .context("TLS handshake failed")?;
+
+            Ok(transport::client::split_stream(stream))
+        }
+        None => Ok(transport::client::split_stream(stream)),
+    }
+}
+
+/// Connects to the server and introduces the user, authenticating and picking the display name if given
+async fn connect(
+    addr: &str,
+    display_name: Option<String>,
+    token: Option<String>,
+    tls_connector: Option<&TlsConnector>,
+) -> anyhow::Result<ServerHandle> {
+    // an unreachable host may not refuse the connection, which would otherwise leave us waiting for minutes
+    let (event_stream, mut command_writer) =
+        tokio::time::timeout(CONNECT_TIMEOUT, create_server_handle(addr, tls_connector))
+            .await
+            .map_err(|_| {
+                anyhow::anyhow!(
+                    "could not connect to {} in {} secs",
+                    addr,
+                    CONNECT_TIMEOUT.as_secs()
+                )
+            })??;
+
+    // servers which require authentication expect the token before anything else
+    if let Some(token) = token {
+        command_writer
+            .write(&command::UserCommand::Authenticate(
+                command::AuthenticateCommand { token },
+            ))
+            .await
+            .context("could not authenticate")?;
+    }
+    // introduce ourselves, so the server can reject us if the protocols do not match
+    command_writer
+        .write(&command::UserCommand::Hello(command::HelloCommand {
+            client_version: comms::PROTOCOL_VERSION,
+            sequenced: false,
+        }))
+        .await
+        .context("could not say hello")?;
+    // pick the display name right after connecting, if the user has given one
+    if let Some(name) = display_name {
+        command_writer
+            .write(&command::UserCommand::SetUsername(
+                command::SetUsernameCommand { name },
+            ))
+            .await
+            .context("could not set the username")?;
+    }
 
     Ok((event_stream, command_writer))
 }
 
+/// Joins the given rooms again after a reconnection, skipping the ones the server does not have anymore
+async fn rejoin_rooms(
+    command_writer: &mut CommandWriter,
+    state: &State,
+    rooms: Vec<String>,
+) -> anyhow::Result<()> {
+    for room in rooms {
+        if !state.room_data_map.contains_key(&room) {
+            continue;
+        }
+
+        command_writer
+            .write(&command::UserCommand::JoinRoom(command::JoinRoomCommand {
+                room,
+            }))
+            .await
+            .context("could not join room")?;
+    }
+
+    Ok(())
+}
+
+/// How long to wait before the given reconnection attempt, starting from 0
+fn reconnect_backoff(attempt: u32) -> Duration {
+    RECONNECT_BACKOFF_BASE
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(RECONNECT_BACKOFF_MAX)
+}
+
+/// The server the user has connected to, to reconnect to it when the connection is lost
+#[derive(Debug, Clone)]
+struct Connection {
+    addr: String,
+    display_name: Option<String>,
+    token: Option<String>,
+}
+
+/// A scheduled attempt to reconnect to the server, with what the user was doing before the connection was lost
+struct PendingReconnect {
+    connection: Connection,
+    /// The rooms to join again once reconnected
+    rooms: Vec<String>,
+    /// The rooms to mute again once reconnected
+    muted_rooms: Vec<String>,
+    active_room: Option<String>,
+    attempt: u32,
+    at: tokio::time::Instant,
+}
+/// Checks whether the event tells that the server will not talk with us, e.g. the protocols do not match
+/// or the token is not accepted, so reconnecting would not help
+fn rejection_error(event: &event::Event) -> Option<anyhow::Error> {
+    match event {
+        event::Event::LoginSuccessful(login) if login.server_version != comms::PROTOCOL_VERSION => {
+            Some(anyhow::anyhow!(
+                "server version {} is not supported, client version is {}",
+                login.server_version,
+                comms::PROTOCOL_VERSION
+            ))
+        }
+        event::Event::Error(err)
+            if matches!(
+                err.code,
+                event::ErrorCode::IncompatibleVersion | event::ErrorCode::Unauthorized
+            ) =>
+        {
+            Some(anyhow::anyhow!("{}", err))
+        }
+        _ => None,
+    }
+}
+
 impl StateStore {
+    /// Resets the state after the connection to the server is lost, and schedules a reconnection if enabled
+    fn handle_connection_lost(
+        &self,
+        state: &mut State,
+        last_connection: Option<&Connection>,
+        err: anyhow::Error,
+        reconnect_after: Duration,
+    ) -> Option<PendingReconnect> {
+        let rooms = state.joined_rooms();
+        let muted_rooms = state.muted_rooms();
+        let active_room = state.active_room.clone();
+        state.reset();
+
+        match last_connection {
+            Some(connection) if self.auto_reconnect => {
+                state.mark_reconnecting(&connection.addr, 0, err);
+
+                Some(PendingReconnect {
+                    connection: connection.clone(),
+                    rooms,
+                    muted_rooms,
+                    active_room,
+                    attempt: 0,
+                    at: tokio::time::Instant::now() + reconnect_after,
+                })
+            }
+            _ => {
+                state.process_connection_request_result(Err(err));
+                None
+            }
+        }
+    }
+
     pub async fn main_loop(
         self,
         mut terminator: Terminator,
@@ -50,55 +330,256 @@ impl StateStore {
         mut interrupt_rx: broadcast::Receiver<Interrupted>,
     ) -> anyhow::Result<Interrupted> {
         let mut opt_server_handle: Option<ServerHandle> = None;
-        let mut state = State::default();
+        // the last server the user has connected to, and the next attempt to reconnect to it
+        let mut last_connection: Option<Connection> = None;
+        let mut pending_reconnect: Option<PendingReconnect> = None;
+        // rooms to join once the server has told us which rooms it has, after a reconnection
+        let mut rooms_to_rejoin: Vec<String> = vec![];
+        // rooms to mute again once the server has told us which rooms it has, after a reconnection
+        let mut rooms_to_mute: Vec<String> = vec![];
+        let mut state = State {
+            message_history_size: self.message_history_size,
+            ..State::default()
+        };
+        let mut clipboard = Clipboard::default();
 
         // the initial state once
         self.state_tx.send(state.clone())?;
 
         let mut ticker = tokio::time::interval(Duration::from_secs(1));
+        // keepalive bookkeeping for the current connection
+        let mut last_ping_at = Instant::now();
+        let mut last_pong_at = Instant::now();
+        // presence bookkeeping for the current connection
+        let mut last_activity_at = Instant::now();
+        let mut is_away = false;
 
         let result = loop {
+            // cleared by the branches that leave the state as it is, so the state is not sent for nothing
+            let mut is_state_changed = true;
+
             if let Some((event_stream, command_writer)) = opt_server_handle.as_mut() {
+                // a failed write means the connection is gone, the same as the event stream ending
+                let mut write_result = Ok(());
+
                 tokio::select! {
                     // Handle the server events as they come in
                     maybe_event = event_stream.next() => match maybe_event {
-                        Some(Ok(event)) => {
-                            state.handle_server_event(&event);
+                        // the server is going away, wait as long as it asks before reconnecting
+                        Some(Ok(event::Event::ServerShutdown(shutdown))) => {
+                            let reconnect_after = Duration::from_secs(shutdown.reconnect_after_secs);
+                            let err = if self.auto_reconnect {
+                                anyhow::anyhow!("{}, reconnecting in {}s", shutdown.reason, reconnect_after.as_secs())
+                            } else {
+                                anyhow::anyhow!("{}", shutdown.reason)
+                            };
+
+                            opt_server_handle = None;
+                            pending_reconnect = self.handle_connection_lost(
+                                &mut state,
+                                last_connection.as_ref(),
+                                err,
+                                reconnect_after,
+                            );
+                        },
+                        Some(Ok(event)) => match rejection_error(&event) {
+                            // the server can not talk with us, go back to the connect page with the reason
+                            Some(err) => {
+                                opt_server_handle = None;
+                                state.reset();
+                                state.process_connection_request_result(Err(err));
+                            },
+                            None => {
+                                if let event::Event::Pong(_) = event {
+                                    last_pong_at = Instant::now();
+                                    is_state_changed = false;
+                                }
+                                // the server checks whether we are still reading, it drops the connection otherwise
+                                if let event::Event::Ping(_) = event {
+                                    write_result = command_writer
+                                        .write(&command::UserCommand::Pong(command::PongCommand))
+                                        .await
+                                        .context("could not answer the ping");
+                                    is_state_changed = false;
+                                }
+
+                                state.handle_server_event(&event);
+
+                                // pick up where the user has left off before the connection was lost
+                                if let event::Event::LoginSuccessful(_) = event {
+                                    for room in std::mem::take(&mut rooms_to_mute) {
+                                        state.set_muted(&room, true);
+                                    }
+                                    write_result = rejoin_rooms(command_writer, &state, std::mem::take(&mut rooms_to_rejoin)).await;
+                                }
+                            },
+                        },
+                        // the server has sent something we could not understand, e.g. from a newer version,
+                        // skip it rather than giving up on the connection
+                        Some(Err(err)) => {
+                            state.push_toast(format!("skipped an unreadable event: {:#}", err));
                         },
                         // server disconnected, we need to reset the state
                         None => {
                             opt_server_handle = None;
-                            state = State::default();
+                            pending_reconnect = self.handle_connection_lost(
+                                &mut state,
+                                last_connection.as_ref(),
+                                anyhow::anyhow!("the server has closed the connection"),
+                                reconnect_backoff(0),
+                            );
                         },
-                        _ => (),
                     },
                     // Handle the actions coming from the UI
                     // and process them to do async operations
                     Some(action) = action_rx.recv() => match action {
                         Action::SendMessage { content } => {
-                            if let Some(active_room) = state.active_room.as_ref() {
-                                command_writer
+                            if let Some(active_room) = state.active_room.clone() {
+                                let client_msg_id = state.push_pending_message(&active_room, &content);
+
+                                write_result = command_writer
                                     .write(&command::UserCommand::SendMessage(
                                         command::SendMessageCommand {
-                                            room: active_room.clone(),
+                                            room: active_room,
                                             content,
+                                            client_msg_id,
                                         },
                                     ))
                                     .await
-                                    .context("could not send message")?;
+                                    .context("could not send message");
+                            }
+                        },
+                        Action::DeleteLastMessage => {
+                            if let Some(active_room) = state.active_room.as_ref() {
+                                if let Some(message_id) = state.find_last_own_message_id(active_room) {
+                                    write_result = command_writer
+                                        .write(&command::UserCommand::DeleteMessage(
+                                            command::DeleteMessageCommand {
+                                                room: active_room.clone(),
+                                                message_id,
+                                            },
+                                        ))
+                                        .await
+                                        .context("could not delete message");
+                                }
                             }
                         },
+                        Action::CopyConversation => {
+                            if let Some(active_room) = state.active_room.clone() {
+                                let transcript = state
+                                    .room_data_map
+                                    .get(&active_room)
+                                    .map(|room_data| format_transcript(room_data.messages.asc_iter()))
+                                    .unwrap_or_default();
+
+                                // let the user know about the result, since there is no other feedback for a copy
+                                let notification = match clipboard.copy(&transcript) {
+                                    Ok(_) => String::from("conversation copied to the clipboard"),
+                                    Err(err) => format!("could not copy the conversation: {:#}", err),
+                                };
+                                state.push_notification(&active_room, notification);
+                            }
+                        },
+                        Action::RefreshRooms => {
+                            is_state_changed = false;
+                            write_result = command_writer
+                                .write(&command::UserCommand::ListRooms(command::ListRoomsCommand))
+                                .await
+                                .context("could not list rooms");
+                        },
+                        Action::FetchHistory { room } => match state.history_request(&room) {
+                            Some(cmd) => {
+                                write_result = command_writer
+                                    .write(&command::UserCommand::FetchHistory(cmd))
+                                    .await
+                                    .context("could not fetch the history");
+                            }
+                            None => is_state_changed = false,
+                        },
+                        Action::ListRoomUsers { room } => {
+                            is_state_changed = false;
+                            write_result = command_writer
+                                .write(&command::UserCommand::ListRoomUsers(command::ListRoomUsersCommand {
+                                    room,
+                                }))
+                                .await
+                                .context("could not list the room users");
+                        },
+                        Action::CloseRoomUsers => {
+                            state.room_users_preview = None;
+                        },
                         Action::SelectRoom { room } => {
                             if let Some(false) = state.try_set_active_room(room.as_str()).map(|room_data| room_data.has_joined) {
-                                command_writer
+                                write_result = command_writer
+                                    .write(&command::UserCommand::JoinRoom(command::JoinRoomCommand {
+                                        room,
+                                    }))
+                                    .await
+                                    .context("could not join room");
+                            }
+                        },
+                        Action::JoinRoomInBackground { room } => {
+                            if let Some(false) = state.room_data_map.get(&room).map(|room_data| room_data.has_joined) {
+                                write_result = command_writer
                                     .write(&command::UserCommand::JoinRoom(command::JoinRoomCommand {
                                         room,
                                     }))
                                     .await
-                                    .context("could not join room")?;
+                                    .context("could not join room");
+                            }
+                        },
+                        Action::LeaveRoom => {
+                            if let Some(active_room) = state.active_room.clone() {
+                                write_result = command_writer
+                                    .write(&command::UserCommand::LeaveRoom(command::LeaveRoomCommand {
+                                        room: active_room.clone(),
+                                    }))
+                                    .await
+                                    .context("could not leave room");
+                                // our own departure may not be forwarded to us, since we stop listening to the room
+                                state.leave_room(&active_room);
+                            }
+                        },
+                        Action::Reidentify => {
+                            is_state_changed = false;
+                            write_result = command_writer
+                                .write(&command::UserCommand::Reidentify(command::ReidentifyCommand))
+                                .await
+                                .context("could not ask for a new user id");
+                        },
+                        Action::ShowError { message } => {
+                            state.push_toast(message);
+                        },
+                        Action::ToggleMentionBell => {
+                            state.is_bell_enabled = !state.is_bell_enabled;
+                        },
+                        Action::ToggleMuteRoom { room } => match state.room_data_map.get(&room) {
+                            Some(room_data) => {
+                                let muted = !room_data.muted;
+                                state.set_muted(&room, muted);
+                            }
+                            None => is_state_changed = false,
+                        },
+                        Action::UserActivity => {
+                            last_activity_at = Instant::now();
+                            is_state_changed = false;
+
+                            if is_away {
+                                is_away = false;
+                                write_result = command_writer
+                                    .write(&command::UserCommand::SetPresence(command::SetPresenceCommand {
+                                        status: event::PresenceStatus::Active,
+                                    }))
+                                    .await
+                                    .context("could not set the presence");
                             }
                         },
                         Action::Exit => {
+                            // let the server know we are leaving on purpose, rather than losing the connection
+                            // the server may have already gone away, which is fine since we are exiting anyway
+                            let _ = command_writer
+                                .write(&command::UserCommand::Quit(command::QuitCommand))
+                                .await;
                             let _ = terminator.terminate(Interrupted::UserInt);
 
                             break Interrupted::UserInt;
@@ -107,28 +588,95 @@ impl StateStore {
                     },
                     // Tick to terminate the select every N milliseconds
                     _ = ticker.tick() => {
-                        state.tick_timer();
+                        // the server has not answered our pings for a while, the connection is dead
+                        if last_pong_at.elapsed() > PONG_TIMEOUT {
+                            opt_server_handle = None;
+                            pending_reconnect = self.handle_connection_lost(
+                                &mut state,
+                                last_connection.as_ref(),
+                                anyhow::anyhow!(
+                                    "connection lost, the server did not respond in {} secs",
+                                    PONG_TIMEOUT.as_secs()
+                                ),
+                                reconnect_backoff(0),
+                            );
+                        } else {
+                            // pings and presence updates are not reflected in the state, only the expired toasts are
+                            is_state_changed = state.expire_toasts();
+
+                            if last_ping_at.elapsed() >= PING_INTERVAL {
+                                last_ping_at = Instant::now();
+                                write_result = command_writer
+                                    .write(&command::UserCommand::Ping(command::PingCommand))
+                                    .await
+                                    .context("could not send ping");
+                            }
+
+                            // the user has not touched the app for a while, let the others know
+                            if write_result.is_ok() && !is_away && last_activity_at.elapsed() >= AWAY_AFTER {
+                                is_away = true;
+                                write_result = command_writer
+                                    .write(&command::UserCommand::SetPresence(command::SetPresenceCommand {
+                                        status: event::PresenceStatus::Away,
+                                    }))
+                                    .await
+                                    .context("could not set the presence");
+                            }
+                        }
                     },
                     // Catch and handle interrupt signal to gracefully shutdown
                     Ok(interrupted) = interrupt_rx.recv() => {
                         break interrupted;
                     }
                 }
+
+                if let Err(err) = write_result {
+                    opt_server_handle = None;
+                    is_state_changed = true;
+                    pending_reconnect = self.handle_connection_lost(
+                        &mut state,
+                        last_connection.as_ref(),
+                        err,
+                        reconnect_backoff(0),
+                    );
+                }
             } else {
+                // the time of the next reconnection attempt, only awaited if there is one
+                let reconnect_at = pending_reconnect
+                    .as_ref()
+                    .map(|reconnect| reconnect.at)
+                    .unwrap_or_else(tokio::time::Instant::now);
+
                 tokio::select! {
                     Some(action) = action_rx.recv() => match action {
-                        Action::ConnectToServerRequest { addr } => {
+                        Action::ConnectToServerRequest { addr, display_name, token } => {
+                            // the user has picked a server themselves, stop trying to reconnect to the old one
+                            pending_reconnect = None;
+                            rooms_to_rejoin.clear();
+                            rooms_to_mute.clear();
                             state.mark_connection_request_start();
                             // emit event to re-render any part depending on the connection status
                             self.state_tx.send(state.clone())?;
 
-                            match create_server_handle(&addr).await {
-                                Ok(server_handle) => {
+                            let result = match parse_server_addr(&addr) {
+                                Ok(addr) => connect(&addr, display_name.clone(), token.clone(), self.tls_connector.as_ref())
+                                    .await
+                                    .map(|server_handle| (addr, server_handle)),
+                                Err(err) => Err(err),
+                            };
+
+                            match result {
+                                Ok((addr, server_handle)) => {
                                     // set the server handle and change status for further processing
-                                    let _ = opt_server_handle.insert(server_handle);
+                                    opt_server_handle = Some(server_handle);
+                                    last_connection = Some(Connection { addr: addr.clone(), display_name, token });
                                     state.process_connection_request_result(Ok(addr));
                                     // ticker needs to be resetted to avoid showing time spent inputting and connecting to the server address
                                     ticker.reset();
+                                    last_ping_at = Instant::now();
+                                    last_pong_at = Instant::now();
+                                    last_activity_at = Instant::now();
+                                    is_away = false;
                                 },
                                 Err(err) => {
                                     state.process_connection_request_result(Err(err));
@@ -142,6 +690,32 @@ impl StateStore {
                         },
                         _ => (),
                     },
+                    // Try to reconnect to the server the connection was lost to
+                    _ = tokio::time::sleep_until(reconnect_at), if pending_reconnect.is_some() => {
+                        let mut reconnect = pending_reconnect.take().unwrap();
+                        let Connection { addr, display_name, token } = reconnect.connection.clone();
+
+                        match connect(&addr, display_name, token, self.tls_connector.as_ref()).await {
+                            Ok(server_handle) => {
+                                opt_server_handle = Some(server_handle);
+                                rooms_to_rejoin = reconnect.rooms;
+                                rooms_to_mute = reconnect.muted_rooms;
+                                state.process_connection_request_result(Ok(addr));
+                                state.active_room = reconnect.active_room;
+                                ticker.reset();
+                                last_ping_at = Instant::now();
+                                last_pong_at = Instant::now();
+                                last_activity_at = Instant::now();
+                                is_away = false;
+                            },
+                            Err(err) => {
+                                reconnect.attempt += 1;
+                                reconnect.at = tokio::time::Instant::now() + reconnect_backoff(reconnect.attempt);
+                                state.mark_reconnecting(&addr, reconnect.attempt, err);
+                                pending_reconnect = Some(reconnect);
+                            }
+                        }
+                    },
                     // Catch and handle interrupt signal to gracefully shutdown
                     Ok(interrupted) = interrupt_rx.recv() => {
                         break interrupted;
@@ -149,9 +723,362 @@ impl StateStore {
                 }
             }
 
-            self.state_tx.send(state.clone())?;
+            if is_state_changed {
+                self.state_tx.send(state.clone())?;
+            }
         };
 
         Ok(result)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use comms::transport::server::{self, CommandStream, EventWriter};
+    use tokio::{
+        net::{TcpListener, UnixListener},
+        sync::mpsc,
+    };
+
+    use super::*;
+    use crate::state_store::ServerConnectionStatus;
+
+    /// Accepts a connection as the server, and logs the user in with the given rooms
+    async fn accept(listener: &TcpListener, rooms: &[&str]) -> (CommandStream, EventWriter) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (mut commands, mut event_writer) = server::split_stream(stream);
+
+        assert!(matches!(
+            next_command(&mut commands).await,
+            command::UserCommand::Hello(_)
+        ));
+        event_writer
+            .write(&event::Event::LoginSuccessful(
+                event::LoginSuccessfulReplyEvent {
+                    session_id: "session".into(),
+                    user_id: "alice".into(),
+                    rooms: rooms
+                        .iter()
+                        .map(|name| event::RoomDetail {
+                            name: String::from(*name),
+                            description: String::from(*name),
+                            participant_count: 0,
+                        })
+                        .collect(),
+                    server_version: comms::PROTOCOL_VERSION,
+                },
+            ))
+            .await
+            .unwrap();
+
+        (commands, event_writer)
+    }
+
+    /// Receive the next command from the client, skipping the keepalive pings
+    async fn next_command(commands: &mut CommandStream) -> command::UserCommand {
+        loop {
+            match commands.next().await.unwrap().unwrap() {
+                command::UserCommand::Ping(_) => continue,
+                command => return command,
+            }
+        }
+    }
+
+    async fn wait_for_state(
+        state_rx: &mut watch::Receiver<State>,
+        predicate: impl Fn(&State) -> bool,
+    ) {
+        state_rx.wait_for(predicate).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_joined_rooms_are_rejoined_after_reconnecting() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        let (state_store, mut state_rx) = StateStore::new();
+        let (action_tx, action_rx) = mpsc::unbounded_channel();
+        let (interrupt_tx, interrupt_rx) = broadcast::channel(1);
+        let main_loop = tokio::spawn(state_store.main_loop(
+            Terminator::new(interrupt_tx.clone()),
+            action_rx,
+            interrupt_rx,
+        ));
+
+        action_tx
+            .send(Action::ConnectToServerRequest {
+                addr,
+                display_name: None,
+                token: None,
+            })
+            .unwrap();
+
+        // the user joins both rooms, then the connection is lost
+        let (mut commands, mut event_writer) = accept(&listener, &["go", "rust"]).await;
+        wait_for_state(&mut state_rx, |state| state.room_data_map.len() == 2).await;
+        for room in ["go", "rust"] {
+            action_tx
+                .send(Action::SelectRoom { room: room.into() })
+                .unwrap();
+            assert!(matches!(
+                next_command(&mut commands).await,
+                command::UserCommand::JoinRoom(cmd) if cmd.room == room
+            ));
+            event_writer
+                .write(&event::Event::RoomParticipation(
+                    event::RoomParticipationBroadcastEvent {
+                        room: room.into(),
+                        user_id: "alice".into(),
+                        display_name: "alice".into(),
+                        status: event::RoomParticipationStatus::Joined,
+                        reason: None,
+                    },
+                ))
+                .await
+                .unwrap();
+        }
+        wait_for_state(&mut state_rx, |state| state.joined_rooms().len() == 2).await;
+        action_tx
+            .send(Action::ToggleMuteRoom {
+                room: "rust".into(),
+            })
+            .unwrap();
+        wait_for_state(&mut state_rx, |state| state.muted_rooms() == ["rust"]).await;
+        drop((commands, event_writer));
+
+        // the server comes back without one of the rooms, only the remaining room is joined again
+        let (mut commands, _event_writer) = accept(&listener, &["rust"]).await;
+        assert!(matches!(
+            next_command(&mut commands).await,
+            command::UserCommand::JoinRoom(cmd) if cmd.room == "rust"
+        ));
+        let next =
+            tokio::time::timeout(Duration::from_millis(200), next_command(&mut commands)).await;
+        assert!(next.is_err());
+        // the room stays muted
+        assert_eq!(state_rx.borrow().muted_rooms(), ["rust"]);
+
+        interrupt_tx.send(Interrupted::UserInt).unwrap();
+        main_loop.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_quit_is_sent_to_the_server_on_exit() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        let (state_store, mut state_rx) = StateStore::new();
+        let (action_tx, action_rx) = mpsc::unbounded_channel();
+        let (interrupt_tx, interrupt_rx) = broadcast::channel(1);
+        let main_loop = tokio::spawn(state_store.main_loop(
+            Terminator::new(interrupt_tx),
+            action_rx,
+            interrupt_rx,
+        ));
+
+        action_tx
+            .send(Action::ConnectToServerRequest {
+                addr,
+                display_name: None,
+                token: None,
+            })
+            .unwrap();
+        let (mut commands, _event_writer) = accept(&listener, &["rust"]).await;
+        wait_for_state(&mut state_rx, |state| state.room_data_map.len() == 1).await;
+
+        action_tx.send(Action::Exit).unwrap();
+
+        assert!(matches!(
+            next_command(&mut commands).await,
+            command::UserCommand::Quit(_)
+        ));
+        assert!(matches!(
+            main_loop.await.unwrap().unwrap(),
+            Interrupted::UserInt
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_state_is_only_sent_when_changed() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        let (state_store, mut state_rx) = StateStore::new();
+        let (action_tx, action_rx) = mpsc::unbounded_channel();
+        let (interrupt_tx, interrupt_rx) = broadcast::channel(1);
+        let main_loop = tokio::spawn(state_store.main_loop(
+            Terminator::new(interrupt_tx.clone()),
+            action_rx,
+            interrupt_rx,
+        ));
+
+        action_tx
+            .send(Action::ConnectToServerRequest {
+                addr,
+                display_name: None,
+                token: None,
+            })
+            .unwrap();
+        let (_commands, _event_writer) = accept(&listener, &["rust"]).await;
+        wait_for_state(&mut state_rx, |state| state.room_data_map.len() == 1).await;
+
+        // the activity only updates the presence, so the next state is the one with the bell toggled
+        for _ in 0..3 {
+            action_tx.send(Action::UserActivity).unwrap();
+        }
+        action_tx.send(Action::ToggleMentionBell).unwrap();
+        state_rx.changed().await.unwrap();
+        assert!(!state_rx.borrow_and_update().is_bell_enabled);
+
+        // nothing happens for longer than a tick, so no state is sent
+        let next = tokio::time::timeout(Duration::from_millis(1500), state_rx.changed()).await;
+        assert!(next.is_err());
+
+        interrupt_tx.send(Interrupted::UserInt).unwrap();
+        main_loop.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rejected_token_is_not_retried() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        let (state_store, mut state_rx) = StateStore::new();
+        let (action_tx, action_rx) = mpsc::unbounded_channel();
+        let (interrupt_tx, interrupt_rx) = broadcast::channel(1);
+        let main_loop = tokio::spawn(state_store.main_loop(
+            Terminator::new(interrupt_tx.clone()),
+            action_rx,
+            interrupt_rx,
+        ));
+
+        action_tx
+            .send(Action::ConnectToServerRequest {
+                addr,
+                display_name: None,
+                token: Some("guess".into()),
+            })
+            .unwrap();
+
+        // the token is sent before anything else, and the server turns it down
+        let (stream, _) = listener.accept().await.unwrap();
+        let (mut commands, mut event_writer) = server::split_stream(stream);
+        assert!(matches!(
+            next_command(&mut commands).await,
+            command::UserCommand::Authenticate(command::AuthenticateCommand { token }) if token == "guess"
+        ));
+        event_writer
+            .write(&event::Event::Error(event::ErrorReplyEvent::new(
+                event::ErrorCode::Unauthorized,
+                "invalid token",
+            )))
+            .await
+            .unwrap();
+        drop((commands, event_writer));
+
+        wait_for_state(&mut state_rx, |state| {
+            matches!(
+                &state.server_connection_status,
+                ServerConnectionStatus::Errored { err } if err.contains("invalid token")
+            )
+        })
+        .await;
+        // the same token would be turned down again, so there is no reconnection
+        let reconnection = tokio::time::timeout(reconnect_backoff(0) * 2, listener.accept()).await;
+        assert!(reconnection.is_err());
+
+        interrupt_tx.send(Interrupted::UserInt).unwrap();
+        main_loop.await.unwrap().unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_connecting_times_out() {
+        // the server accepts the connection but never answers the TLS handshake
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let tls_connector = tls::connector(None).unwrap();
+
+        let (result, _accepted) = tokio::join!(
+            connect(&addr, None, None, Some(&tls_connector)),
+            listener.accept()
+        );
+
+        let err = result.err().expect("the connection should time out");
+        assert_eq!(
+            err.to_string(),
+            format!("could not connect to {} in 10 secs", addr)
+        );
+    }
+
+    #[test]
+    fn test_server_addr_defaults_the_port() {
+        assert_eq!(
+            parse_server_addr(" chat.example.com ").unwrap(),
+            "chat.example.com:8080"
+        );
+        assert_eq!(
+            parse_server_addr("chat.example.com:9000").unwrap(),
+            "chat.example.com:9000"
+        );
+        assert_eq!(parse_server_addr("127.0.0.1").unwrap(), "127.0.0.1:8080");
+        assert_eq!(parse_server_addr("::1").unwrap(), "[::1]:8080");
+        assert_eq!(parse_server_addr("[::1]:9000").unwrap(), "[::1]:9000");
+        assert_eq!(
+            parse_server_addr("/tmp/chat.sock").unwrap(),
+            "/tmp/chat.sock"
+        );
+    }
+
+    #[test]
+    fn test_malformed_server_addr_is_rejected() {
+        for addr in [
+            "",
+            "   ",
+            ":8080",
+            "chat.example.com:",
+            "chat.example.com:http",
+            "chat.example.com:0",
+            "chat.example.com:70000",
+            "chat example.com",
+            "http://chat.example.com",
+            "[::1",
+            "[::1]8080",
+            "[chat]:8080",
+        ] {
+            let err = parse_server_addr(addr).unwrap_err();
+            assert!(err.to_string().starts_with("invalid address"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_reconnect_backoff_doubles_up_to_the_max() {
+        assert_eq!(reconnect_backoff(0), Duration::from_secs(1));
+        assert_eq!(reconnect_backoff(1), Duration::from_secs(2));
+        assert_eq!(reconnect_backoff(2), Duration::from_secs(4));
+        assert_eq!(reconnect_backoff(5), RECONNECT_BACKOFF_MAX);
+        assert_eq!(reconnect_backoff(100), RECONNECT_BACKOFF_MAX);
+    }
+
+    #[tokio::test]
+    async fn test_socket_paths_are_connected_over_unix_domain_sockets() {
+        let path = std::env::temp_dir().join(format!("tui-test-{}.sock", std::process::id()));
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let (server_handle, accepted) = tokio::join!(
+            connect(path.to_str().unwrap(), Some("alice".into()), None, None),
+            listener.accept()
+        );
+        let _ = std::fs::remove_file(&path);
+
+        let _server_handle = server_handle.unwrap();
+        let (mut commands, _) = server::split_stream(accepted.unwrap().0);
+        assert!(matches!(
+            next_command(&mut commands).await,
+            command::UserCommand::Hello(_)
+        ));
+        assert!(matches!(
+            next_command(&mut commands).await,
+            command::UserCommand::SetUsername(command::SetUsernameCommand { name }) if name == "alice"
+        ));
+    }
+}
@@ -0,0 +1,58 @@
+use super::MessageBoxItem;
+
+/// Formats the given messages as a plain text transcript, one message per line
+/// in the same shape they are rendered in the messages pane
+pub fn format_transcript<'a>(items: impl Iterator<Item = &'a MessageBoxItem>) -> String {
+    items
+        .map(|item| match item {
+            MessageBoxItem::Message {
+                display_name,
+                content,
+                ..
+            }
+            | MessageBoxItem::PendingMessage {
+                display_name,
+                content,
+                ..
+            } => format!("@{}: {}", display_name, content),
+            MessageBoxItem::Notification(content) | MessageBoxItem::System(content) => {
+                content.clone()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_transcript() {
+        let items = [
+            MessageBoxItem::Notification("alice has joined the room".into()),
+            MessageBoxItem::Message {
+                message_id: "1".into(),
+                user_id: "a".into(),
+                display_name: "alice".into(),
+                content: "hello".into(),
+            },
+            MessageBoxItem::Message {
+                message_id: "2".into(),
+                user_id: "b".into(),
+                display_name: "bob".into(),
+                content: "hi there".into(),
+            },
+        ];
+
+        assert_eq!(
+            format_transcript(items.iter()),
+            "alice has joined the room\n@alice: hello\n@bob: hi there"
+        );
+    }
+
+    #[test]
+    fn test_format_empty_transcript() {
+        assert_eq!(format_transcript(std::iter::empty()), "");
+    }
+}
@@ -1,5 +1,8 @@
-use crossterm::event::KeyEvent;
-use ratatui::{prelude::Backend, Frame};
+use crossterm::event::{KeyEvent, MouseEvent};
+use ratatui::{
+    prelude::{Backend, Rect},
+    Frame,
+};
 use tokio::sync::mpsc::UnboundedSender;
 
 use crate::state_store::{action::Action, State};
@@ -15,6 +18,23 @@ pub trait Component {
     fn name(&self) -> &str;
 
     fn handle_key_event(&mut self, key: KeyEvent);
+
+    /// Handle a mouse event, components which do not react to the mouse can ignore it
+    fn handle_mouse_event(&mut self, _mouse: MouseEvent) {}
+
+    /// Whether the last render has gone out of date without a state change, e.g. a clock has moved on,
+    /// in which case it is redrawn on the next tick. Components which only show the state can ignore it
+    fn is_stale(&self) -> bool {
+        false
+    }
+}
+
+/// Whether the mouse is over the given area of the terminal
+pub fn is_mouse_over(area: Rect, mouse: &MouseEvent) -> bool {
+    mouse.column >= area.x
+        && mouse.column < area.x.saturating_add(area.width)
+        && mouse.row >= area.y
+        && mouse.row < area.y.saturating_add(area.height)
 }
 
 pub trait ComponentRender<Props> {
@@ -1,4 +1,4 @@
-use crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
 use ratatui::{
     prelude::{Backend, Rect},
     style::{Color, Style, Stylize},
@@ -6,6 +6,7 @@ use ratatui::{
     Frame,
 };
 use tokio::sync::mpsc::UnboundedSender;
+use unicode_width::UnicodeWidthStr;
 
 use crate::state_store::{action::Action, State};
 
@@ -14,8 +15,10 @@ use super::{Component, ComponentRender};
 pub struct InputBox {
     /// Current value of the input box
     text: String,
-    /// Position of cursor in the editor area.
+    /// Position of cursor in the editor area, as a character index into the text
     cursor_position: usize,
+    /// Hides the text behind asterisks, e.g. for secrets
+    is_masked: bool,
 }
 
 impl InputBox {
@@ -25,7 +28,11 @@ impl InputBox {
 
     pub fn set_text(&mut self, new_text: &str) {
         self.text = String::from(new_text);
-        self.cursor_position = self.text.len();
+        self.cursor_position = self.text.chars().count();
+    }
+
+    pub fn set_masked(&mut self, is_masked: bool) {
+        self.is_masked = is_masked;
     }
 
     pub fn reset(&mut self) {
@@ -37,6 +44,40 @@ impl InputBox {
         self.text.is_empty()
     }
 
+    pub fn is_cursor_at_start(&self) -> bool {
+        self.cursor_position == 0
+    }
+
+    pub fn is_cursor_at_end(&self) -> bool {
+        self.cursor_position == self.text.chars().count()
+    }
+
+    /// Inserts a line break at the cursor, the text is rendered over multiple rows then
+    pub fn insert_line_break(&mut self) {
+        self.enter_char('\n');
+    }
+
+    /// How many rows the text takes, one per line
+    pub fn line_count(&self) -> usize {
+        self.text.split('\n').count()
+    }
+
+    /// The row and the column of the cursor, in the lines of the text
+    fn cursor_row_and_column(&self) -> (usize, usize) {
+        let before_cursor = &self.text[..self.byte_index()];
+        let row = before_cursor.matches('\n').count();
+        let line = before_cursor.rsplit('\n').next().unwrap_or(before_cursor);
+
+        // every masked character is shown as a single asterisk
+        let column = if self.is_masked {
+            line.chars().count()
+        } else {
+            line.width()
+        };
+
+        (row, column)
+    }
+
     fn move_cursor_left(&mut self) {
         let cursor_moved_left = self.cursor_position.saturating_sub(1);
         self.cursor_position = self.clamp_cursor(cursor_moved_left);
@@ -47,8 +88,18 @@ impl InputBox {
         self.cursor_position = self.clamp_cursor(cursor_moved_right);
     }
 
+    /// The byte offset of the cursor in the text, since [String] is indexed by bytes rather than chars
+    fn byte_index(&self) -> usize {
+        self.text
+            .char_indices()
+            .map(|(idx, _)| idx)
+            .nth(self.cursor_position)
+            .unwrap_or(self.text.len())
+    }
+
     fn enter_char(&mut self, new_char: char) {
-        self.text.insert(self.cursor_position, new_char);
+        let idx = self.byte_index();
+        self.text.insert(idx, new_char);
 
         self.move_cursor_right();
     }
@@ -75,8 +126,21 @@ impl InputBox {
         }
     }
 
+    /// Delete the text before the cursor, like Ctrl+U in readline
+    fn delete_to_start(&mut self) {
+        let idx = self.byte_index();
+        self.text.replace_range(..idx, "");
+        self.cursor_position = 0;
+    }
+
+    /// Delete the text after the cursor, like Ctrl+K in readline
+    fn delete_to_end(&mut self) {
+        let idx = self.byte_index();
+        self.text.truncate(idx);
+    }
+
     fn clamp_cursor(&self, new_cursor_pos: usize) -> usize {
-        new_cursor_pos.clamp(0, self.text.len())
+        new_cursor_pos.clamp(0, self.text.chars().count())
     }
 }
 
@@ -86,6 +150,7 @@ impl Component for InputBox {
             //
             text: String::new(),
             cursor_position: 0,
+            is_masked: false,
         }
     }
 
@@ -106,6 +171,12 @@ impl Component for InputBox {
         }
 
         match key.code {
+            KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.delete_to_start();
+            }
+            KeyCode::Char('k') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.delete_to_end();
+            }
             KeyCode::Char(to_insert) => {
                 self.enter_char(to_insert);
             }
@@ -132,8 +203,19 @@ pub struct RenderProps {
 
 impl ComponentRender<RenderProps> for InputBox {
     fn render<B: Backend>(&self, frame: &mut Frame<B>, props: RenderProps) {
-        let input = Paragraph::new(self.text.as_str())
+        let text = if self.is_masked {
+            "*".repeat(self.text.chars().count())
+        } else {
+            self.text.clone()
+        };
+        // keeps the row of the cursor in view, when there are more lines than the area can show
+        let (cursor_row, cursor_column) = self.cursor_row_and_column();
+        let visible_rows = props.area.height.saturating_sub(2).max(1) as usize;
+        let scroll = cursor_row.saturating_sub(visible_rows - 1);
+
+        let input = Paragraph::new(text)
             .style(Style::default().fg(Color::Yellow))
+            .scroll((scroll as u16, 0))
             .block(
                 Block::default()
                     .borders(Borders::ALL)
@@ -149,10 +231,108 @@ impl ComponentRender<RenderProps> for InputBox {
             frame.set_cursor(
                 // Draw the cursor at the current position in the input field.
                 // This position is can be controlled via the left and right arrow key
-                props.area.x + self.cursor_position as u16 + 1,
-                // Move one line down, from the border to the input line
-                props.area.y + 1,
+                props.area.x + cursor_column as u16 + 1,
+                // Move one line down, from the border to the line of the cursor
+                props.area.y + (cursor_row - scroll) as u16 + 1,
             )
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_input_box(text: &str) -> InputBox {
+        let mut input_box = InputBox {
+            text: String::new(),
+            cursor_position: 0,
+            is_masked: false,
+        };
+        input_box.set_text(text);
+
+        input_box
+    }
+
+    #[test]
+    fn test_editing_multi_byte_text() {
+        let mut input_box = create_input_box("héllo");
+
+        // move right after the é, which is two bytes long
+        for _ in 0..3 {
+            input_box.move_cursor_left();
+        }
+        input_box.enter_char('x');
+        assert_eq!(input_box.text(), "héxllo");
+
+        input_box.delete_char();
+        input_box.delete_char();
+        assert_eq!(input_box.text(), "hllo");
+
+        input_box.enter_char('🦀');
+        input_box.enter_char('é');
+        assert_eq!(input_box.text(), "h🦀éllo");
+    }
+
+    #[test]
+    fn test_deleting_to_start_and_end() {
+        let mut input_box = create_input_box("héllo 🦀 wörld");
+        for _ in 0..4 {
+            input_box.move_cursor_left();
+        }
+
+        input_box.handle_key_event(KeyEvent::new(KeyCode::Char('k'), KeyModifiers::CONTROL));
+        assert_eq!(input_box.text(), "héllo 🦀 w");
+        assert!(input_box.is_cursor_at_end());
+
+        input_box.move_cursor_left();
+        input_box.move_cursor_left();
+        input_box.handle_key_event(KeyEvent::new(KeyCode::Char('u'), KeyModifiers::CONTROL));
+        assert_eq!(input_box.text(), " w");
+        assert!(input_box.is_cursor_at_start());
+
+        input_box.enter_char('é');
+        assert_eq!(input_box.text(), "é w");
+    }
+
+    #[test]
+    fn test_cursor_position_over_multiple_lines() {
+        let mut input_box = create_input_box("héllo");
+        assert_eq!(input_box.line_count(), 1);
+        assert_eq!(input_box.cursor_row_and_column(), (0, 5));
+
+        input_box.insert_line_break();
+        assert_eq!(input_box.cursor_row_and_column(), (1, 0));
+
+        input_box.enter_char('日');
+        input_box.enter_char('本');
+        assert_eq!(input_box.text(), "héllo\n日本");
+        assert_eq!(input_box.line_count(), 2);
+        // the wide characters take two columns each
+        assert_eq!(input_box.cursor_row_and_column(), (1, 4));
+
+        for _ in 0..3 {
+            input_box.move_cursor_left();
+        }
+        assert_eq!(input_box.cursor_row_and_column(), (0, 5));
+    }
+
+    #[test]
+    fn test_editing_around_emoji() {
+        let mut input_box = create_input_box("a🦀b");
+
+        input_box.move_cursor_left();
+        input_box.delete_char();
+        assert_eq!(input_box.text(), "ab");
+
+        input_box.set_text("🦀🦀");
+        input_box.move_cursor_right();
+        input_box.enter_char('!');
+        assert_eq!(input_box.text(), "🦀🦀!");
+
+        input_box.move_cursor_left();
+        input_box.move_cursor_left();
+        input_box.enter_char('?');
+        assert_eq!(input_box.text(), "🦀?🦀!");
+    }
+}
@@ -1,4 +1,5 @@
 mod component;
 
 pub mod input_box;
-pub use component::{Component, ComponentRender};
+pub mod user_color;
+pub use component::{is_mouse_over, Component, ComponentRender};
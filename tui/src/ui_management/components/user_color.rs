@@ -0,0 +1,43 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use ratatui::style::Color;
+
+/// Colors the users are picked from, red and yellow are left out since they are used for errors and highlights
+const PALETTE: [Color; 8] = [
+    Color::Cyan,
+    Color::Green,
+    Color::Magenta,
+    Color::Blue,
+    Color::LightCyan,
+    Color::LightGreen,
+    Color::LightMagenta,
+    Color::LightBlue,
+];
+
+/// The color of the given user, the same user always gets the same color
+pub fn user_color(user_id: &str) -> Color {
+    let mut hasher = DefaultHasher::new();
+    user_id.hash(&mut hasher);
+
+    PALETTE[(hasher.finish() % PALETTE.len() as u64) as usize]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_color_is_stable() {
+        assert_eq!(user_color("abc12"), user_color("abc12"));
+
+        // a handful of users should not all end up with the same color
+        let colors = ["abc12", "def34", "ghi56", "jkl78", "mno90"]
+            .iter()
+            .map(|user_id| user_color(user_id))
+            .collect::<Vec<_>>();
+        assert!(colors.iter().any(|color| *color != colors[0]));
+    }
+}
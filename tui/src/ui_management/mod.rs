@@ -1,4 +1,4 @@
-pub use ui_manager::UiManager;
+pub use ui_manager::{UiManager, DEFAULT_TICK_RATE};
 
 mod components;
 mod pages;
@@ -1,14 +1,23 @@
-use std::collections::HashMap;
+use std::{
+    cell::Cell,
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Instant,
+};
 
-use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseEvent};
 use ratatui::{prelude::*, widgets::*, Frame};
 use tokio::sync::mpsc::UnboundedSender;
+use unicode_width::UnicodeWidthStr;
 
-use crate::state_store::{action::Action, MessageBoxItem, RoomData, State};
+use crate::state_store::{
+    action::Action, unix_now, RoomData, RoomUsersPreview, ServerConnectionStatus, State,
+};
 
 use super::{
     components::{
         message_input_box::{self, MessageInputBox},
+        message_list::{self, MessageList},
         room_list::{self, RoomList},
     },
     section::{
@@ -16,21 +25,23 @@ use super::{
         SectionActivation,
     },
 };
-use crate::ui_management::components::{Component, ComponentRender};
+use crate::ui_management::components::{user_color::user_color, Component, ComponentRender};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Section {
     MessageInput,
     RoomList,
+    Messages,
 }
 
 impl Section {
-    pub const COUNT: usize = 2;
+    pub const COUNT: usize = 3;
 
     fn to_usize(&self) -> usize {
         match self {
             Section::MessageInput => 0,
             Section::RoomList => 1,
+            Section::Messages => 2,
         }
     }
 }
@@ -42,34 +53,62 @@ impl TryFrom<usize> for Section {
         match value {
             0 => Ok(Section::MessageInput),
             1 => Ok(Section::RoomList),
+            2 => Ok(Section::Messages),
             _ => Err(()),
         }
     }
 }
 
 struct Props {
+    /// The connection to the server
+    server_connection_status: ServerConnectionStatus,
+    /// How many rooms the user has joined
+    joined_rooms: usize,
+    /// Whether the terminal bell rings on the mentions
+    is_bell_enabled: bool,
     /// The logged in user
     user_id: String,
+    /// Display names of the known users, keyed by user id
+    display_names: HashMap<String, String>,
+    /// Ids of the users who are currently away
+    away_users: HashSet<String>,
     /// The currently active room
     active_room: Option<String>,
-    /// The timer for the chat page
-    timer: usize,
-    /// The room data map
-    room_data_map: HashMap<String, RoomData>,
+    /// When the app first connected to a server
+    connected_at: Option<Instant>,
+    /// The room data map, sharing the rooms with the state
+    room_data_map: HashMap<String, Arc<RoomData>>,
+    /// The users of the room the user is peeking into
+    room_users_preview: Option<RoomUsersPreview>,
+    /// The toasts to show, oldest first
+    toasts: Vec<String>,
 }
 
 impl From<&State> for Props {
     fn from(state: &State) -> Self {
         Props {
+            server_connection_status: state.server_connection_status.clone(),
+            joined_rooms: state.joined_rooms().len(),
+            is_bell_enabled: state.is_bell_enabled,
             user_id: state.user_id.clone(),
+            display_names: state.display_names.clone(),
+            away_users: state.away_users.clone(),
             active_room: state.active_room.clone(),
-            timer: state.timer,
+            connected_at: state.connected_at,
             room_data_map: state.room_data_map.clone(),
+            room_users_preview: state.room_users_preview.clone(),
+            toasts: state
+                .toasts
+                .iter()
+                .map(|(content, _)| content.clone())
+                .collect(),
         }
     }
 }
 
 const DEFAULT_HOVERED_SECTION: Section = Section::MessageInput;
+/// The pages narrower than this show the messages alone, with the side panels behind toggles
+const COMPACT_LAYOUT_MAX_WIDTH: u16 = 80;
 
 /// ChatPage handles the UI and the state of the chat page
 pub struct ChatPage {
@@ -82,22 +121,55 @@ pub struct ChatPage {
     pub active_section: Option<Section>,
     /// Section that is currently hovered
     pub last_hovered_section: Section,
+    /// Whether the key bindings cheatsheet is shown over the page
+    pub show_help: bool,
+    /// The clocks shown on the last render, to know when they have moved on
+    rendered_clock: Cell<Clock>,
+    /// Whether the page was narrow enough for the compact layout on the last render
+    is_compact: Cell<bool>,
+    /// Whether the room list is shown next to the messages in the compact layout
+    pub show_rooms_panel: bool,
+    /// Whether the room users are shown next to the messages in the compact layout
+    pub show_users_panel: bool,
     // Child Components
     /// The room list widget that handles the listing of the rooms
     pub room_list: RoomList,
     /// The input box widget that handles the message input
     pub message_input_box: MessageInputBox,
+    /// The message list widget that handles the scrolling of the messages
+    pub message_list: MessageList,
 }
 
+/// The seconds since the app has connected, and the unix time the join times of the users are counted to
+type Clock = (u64, u64);
+
 impl ChatPage {
+    fn clock(&self) -> Clock {
+        (
+            self.props
+                .connected_at
+                .map_or(0, |connected_at| connected_at.elapsed().as_secs()),
+            unix_now(),
+        )
+    }
+
     fn get_room_data(&self, name: &str) -> Option<&RoomData> {
-        self.props.room_data_map.get(name)
+        self.props.room_data_map.get(name).map(Arc::as_ref)
+    }
+
+    fn get_display_name<'a>(&'a self, user_id: &'a str) -> &'a str {
+        self.props
+            .display_names
+            .get(user_id)
+            .map(String::as_str)
+            .unwrap_or(user_id)
     }
 
     fn get_component_for_section<'a>(&'a self, section: &Section) -> &'a dyn Component {
         match section {
             Section::MessageInput => &self.message_input_box,
             Section::RoomList => &self.room_list,
+            Section::Messages => &self.message_list,
         }
     }
 
@@ -105,6 +177,7 @@ impl ChatPage {
         match section {
             Section::MessageInput => &mut self.message_input_box,
             Section::RoomList => &mut self.room_list,
+            Section::Messages => &mut self.message_list,
         }
     }
 
@@ -115,6 +188,7 @@ impl ChatPage {
         match section {
             Section::MessageInput => &mut self.message_input_box,
             Section::RoomList => &mut self.room_list,
+            Section::Messages => &mut self.message_list,
         }
     }
 
@@ -148,6 +222,304 @@ impl ChatPage {
 
         self.active_section = None;
     }
+
+    /// Key bindings of the page when no section is active
+    fn global_usage_info(&self) -> UsageInfo {
+        UsageInfo {
+            description: Some("Select a widget".into()),
+            lines: vec![
+                UsageInfoLine {
+                    keys: vec!["e".into()],
+                    description: format!(
+                        "to activate {}",
+                        self.get_component_for_section(&self.last_hovered_section)
+                            .name()
+                    ),
+                },
+                UsageInfoLine {
+                    keys: vec!["?".into()],
+                    description: "to show all the key bindings".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["q".into()],
+                    description: "to exit".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["Tab".into(), "Shift+Tab".into(), "←".into(), "→".into()],
+                    description: "to hover widgets".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["PgUp".into(), "PgDn".into()],
+                    description: "to scroll the messages".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["d".into()],
+                    description: "to delete your last message".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["l".into()],
+                    description: "to leave the active room".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["m".into()],
+                    description: "to turn the mention bell on or off".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["Shift+Y".into()],
+                    description: "to copy the conversation".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["r".into(), "u".into()],
+                    description: "to show or hide the rooms and the users on narrow terminals"
+                        .into(),
+                },
+            ],
+        }
+    }
+
+    /// The status bar at the bottom of the page, with the connection and the user
+    fn status_line(&self) -> Line<'_> {
+        let connection = match &self.props.server_connection_status {
+            ServerConnectionStatus::Connected { addr } => {
+                Span::from(format!("● connected to {}", addr)).green()
+            }
+            ServerConnectionStatus::Reconnecting { addr, attempt, .. } => Span::from(format!(
+                "● reconnecting to {} (attempt {})",
+                addr,
+                attempt + 1
+            ))
+            .yellow(),
+            ServerConnectionStatus::Connecting => Span::from("● connecting").yellow(),
+            ServerConnectionStatus::Uninitalized | ServerConnectionStatus::Errored { .. } => {
+                Span::from("● disconnected").red()
+            }
+        };
+        let joined_rooms = match self.props.joined_rooms {
+            1 => String::from("1 room joined"),
+            count => format!("{} rooms joined", count),
+        };
+
+        let mut line = Line::from(vec![
+            connection,
+            " | ".into(),
+            Span::from(format!(
+                "@{} ({})",
+                self.get_display_name(&self.props.user_id),
+                self.props.user_id
+            )),
+            " | ".into(),
+            Span::from(joined_rooms),
+        ]);
+        if !self.props.is_bell_enabled {
+            line.spans.push(" | mention bell off".into());
+        }
+
+        line
+    }
+
+    /// Render the key bindings of the page and all of its sections as a popup
+    fn render_help<B: Backend>(&self, frame: &mut Frame<B>) {
+        let sections: [(&str, UsageInfo); 4] = [
+            (self.name(), self.global_usage_info()),
+            (self.room_list.name(), self.room_list.usage_info()),
+            (
+                self.message_input_box.name(),
+                self.message_input_box.usage_info(),
+            ),
+            (self.message_list.name(), self.message_list.usage_info()),
+        ];
+
+        let mut lines: Vec<Line> = vec![];
+        for (name, usage) in sections {
+            if !lines.is_empty() {
+                lines.push(Line::default());
+            }
+            lines.push(Line::from(Span::from(name).bold().underlined()));
+            lines.extend(
+                widget_usage_to_text(UsageInfo {
+                    description: None,
+                    ..usage
+                })
+                .lines,
+            );
+        }
+
+        let area = centered_rect(60, 80, frame.size());
+        let help = Paragraph::new(Text::from(lines))
+            .wrap(Wrap { trim: true })
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Key Bindings (press ? or Esc to close)"),
+            );
+
+        frame.render_widget(Clear, area);
+        frame.render_widget(help, area);
+    }
+
+    /// Render the users of the room the user is peeking into as a popup
+    fn render_room_users<B: Backend>(&self, frame: &mut Frame<B>, preview: &RoomUsersPreview) {
+        let users: Vec<ListItem> = if preview.users.is_empty() {
+            vec![ListItem::new(Line::from(
+                Span::from("Nobody is here yet").italic(),
+            ))]
+        } else {
+            preview
+                .users
+                .iter()
+                .map(|user_id| {
+                    ListItem::new(Line::from(
+                        Span::from(format!("@{}", self.get_display_name(user_id)))
+                            .fg(user_color(user_id)),
+                    ))
+                })
+                .collect()
+        };
+
+        let area = centered_rect(40, 60, frame.size());
+        let room_users =
+            List::new(users).block(Block::default().borders(Borders::ALL).title(format!(
+                "#{} ({}) (press Esc to close)",
+                preview.room,
+                preview.users.len()
+            )));
+
+        frame.render_widget(Clear, area);
+        frame.render_widget(room_users, area);
+    }
+
+    /// Render the room list and the information of the user
+    fn render_rooms_panel<B: Backend>(&self, frame: &mut Frame<B>, area: Rect, chatting_for: u64) {
+        let [container_room_list, container_user_info] = *Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(1), Constraint::Length(4)].as_ref())
+            .split(area)
+        else {
+            panic!("The left layout should have 2 chunks")
+        };
+
+        self.room_list.render(
+            frame,
+            room_list::RenderProps {
+                border_color: self.calculate_border_color(Section::RoomList),
+                area: container_room_list,
+            },
+        );
+
+        let user_info = Paragraph::new(Text::from(vec![
+            Line::from(format!(
+                "User: @{} ({})",
+                self.get_display_name(&self.props.user_id),
+                self.props.user_id
+            )),
+            Line::from(format!("Chatting for: {} secs", chatting_for)),
+        ]))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("User Information"),
+        );
+        frame.render_widget(user_info, container_user_info);
+    }
+
+    /// Render the users of the active room and the key bindings of the active section
+    fn render_users_panel<B: Backend>(&self, frame: &mut Frame<B>, area: Rect, now: u64) {
+        let [container_room_users, container_usage] = *Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(1), Constraint::Length(10)].as_ref())
+            .split(area)
+        else {
+            panic!("The right layout should have 2 chunks")
+        };
+
+        let (room_users_list_items, room_users_len) = self
+            .props
+            .active_room
+            .as_ref()
+            .and_then(|active_room| {
+                self.get_room_data(active_room).map(|room_data| {
+                    let room_users_len = room_data.users.len();
+                    let users_offset =
+                        calculate_list_offset(container_room_users.height, room_users_len);
+
+                    (
+                        room_data
+                            .users
+                            .iter()
+                            .skip(users_offset)
+                            .map(|user_id| {
+                                let display_name = self.get_display_name(user_id);
+                                let member_since = room_data
+                                    .joined_at
+                                    .get(user_id)
+                                    .map(|joined_at| {
+                                        format!(
+                                            " {}",
+                                            format_member_since(now.saturating_sub(*joined_at))
+                                        )
+                                    })
+                                    .unwrap_or_default();
+
+                                if self.props.away_users.contains(user_id) {
+                                    ListItem::new(Line::from(Span::raw(format!(
+                                        "@{} (away){}",
+                                        display_name, member_since
+                                    ))))
+                                    .dim()
+                                } else {
+                                    ListItem::new(Line::from(vec![
+                                        Span::from(format!("@{}", display_name))
+                                            .fg(user_color(user_id)),
+                                        Span::raw(member_since).dim(),
+                                    ]))
+                                }
+                            })
+                            .collect::<Vec<ListItem<'_>>>(),
+                        room_users_len,
+                    )
+                })
+            })
+            .unwrap_or_else(|| (vec![], 0));
+
+        let room_users_list = List::new(room_users_list_items).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!("Room Users ({})", room_users_len)),
+        );
+
+        frame.render_widget(room_users_list, container_room_users);
+
+        let mut usage_text: Text = widget_usage_to_text(self.usage_info());
+        usage_text.patch_style(Style::default());
+        let usage = Paragraph::new(usage_text)
+            .wrap(Wrap { trim: true })
+            .block(Block::default().borders(Borders::ALL).title("Usage"));
+        frame.render_widget(usage, container_usage);
+    }
+
+    /// Render the toasts stacked in the bottom right corner of the given area, the newest at the bottom
+    fn render_toasts<B: Backend>(&self, frame: &mut Frame<B>, area: Rect) {
+        if self.props.toasts.is_empty() {
+            return;
+        }
+
+        let area = toast_area(area, &self.props.toasts);
+        let toasts = Paragraph::new(
+            self.props
+                .toasts
+                .iter()
+                .map(|toast| Line::from(toast.as_str()))
+                .collect::<Vec<_>>(),
+        )
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().yellow()),
+        );
+
+        frame.render_widget(Clear, area);
+        frame.render_widget(toasts, area);
+    }
 }
 
 impl Component for ChatPage {
@@ -162,9 +534,15 @@ impl Component for ChatPage {
             // internal component state
             active_section: Option::None,
             last_hovered_section: DEFAULT_HOVERED_SECTION,
+            show_help: false,
+            rendered_clock: Cell::new((0, 0)),
+            is_compact: Cell::new(false),
+            show_rooms_panel: false,
+            show_users_panel: false,
             // child components
             room_list: RoomList::new(state, action_tx.clone()),
-            message_input_box: MessageInputBox::new(state, action_tx),
+            message_input_box: MessageInputBox::new(state, action_tx.clone()),
+            message_list: MessageList::new(state, action_tx),
         }
         .move_with_state(state)
     }
@@ -178,6 +556,7 @@ impl Component for ChatPage {
             // propogate the update to the child components
             room_list: self.room_list.move_with_state(state),
             message_input_box: self.message_input_box.move_with_state(state),
+            message_list: self.message_list.move_with_state(state),
             ..self
         }
     }
@@ -191,19 +570,66 @@ impl Component for ChatPage {
             return;
         }
 
+        // the cheatsheet covers the page, so it takes all the keys until it is closed
+        if self.show_help {
+            if matches!(key.code, KeyCode::Char('?') | KeyCode::Esc) {
+                self.show_help = false;
+            }
+            return;
+        }
+
+        // the room users popup covers the page as well, until it is dismissed
+        if self.props.room_users_preview.is_some() {
+            if matches!(key.code, KeyCode::Char('u') | KeyCode::Esc) {
+                let _ = self.action_tx.send(Action::CloseRoomUsers);
+            }
+            return;
+        }
+
+        // the messages can be scrolled by pages no matter which section is active
+        if matches!(key.code, KeyCode::PageUp | KeyCode::PageDown) {
+            self.message_list.handle_key_event(key);
+            return;
+        }
+
         let active_section = self.active_section.clone();
 
         match active_section {
             None => match key.code {
                 KeyCode::Char('e') => {
                     let last_hovered_section = self.last_hovered_section.clone();
+                    // the room list may be hidden in the compact layout, it is brought up to be used
+                    if last_hovered_section == Section::RoomList {
+                        self.show_rooms_panel = true;
+                    }
 
                     self.active_section = Some(last_hovered_section.clone());
                     self.get_section_activation_for_section(&last_hovered_section)
                         .activate();
                 }
-                KeyCode::Left => self.hover_previous(),
-                KeyCode::Right => self.hover_next(),
+                KeyCode::Char('d') => {
+                    let _ = self.action_tx.send(Action::DeleteLastMessage);
+                }
+                KeyCode::Char('l') => {
+                    let _ = self.action_tx.send(Action::LeaveRoom);
+                }
+                KeyCode::Char('?') => {
+                    self.show_help = true;
+                }
+                KeyCode::Char('Y') => {
+                    let _ = self.action_tx.send(Action::CopyConversation);
+                }
+                KeyCode::Char('m') => {
+                    let _ = self.action_tx.send(Action::ToggleMentionBell);
+                }
+                KeyCode::Char('r') if self.is_compact.get() => {
+                    self.show_rooms_panel = !self.show_rooms_panel;
+                }
+                KeyCode::Char('u') if self.is_compact.get() => {
+                    self.show_users_panel = !self.show_users_panel;
+                }
+                KeyCode::Left | KeyCode::BackTab => self.hover_previous(),
+                KeyCode::Right | KeyCode::Tab => self.hover_next(),
                 KeyCode::Char('q') => {
                     let _ = self.action_tx.send(Action::Exit);
                 }
@@ -213,6 +639,13 @@ impl Component for ChatPage {
                 _ => {}
             },
             Some(section) => {
+                // escape clears the room filter or the message search first, rather than leaving the section
+                let takes_escape = match section {
+                    Section::RoomList => self.room_list.is_filtering(),
+                    Section::Messages => self.message_list.has_search(),
+                    Section::MessageInput => false,
+                };
+
                 self.get_component_for_section_mut(&section)
                     .handle_key_event(key);
 
@@ -223,64 +656,204 @@ impl Component for ChatPage {
                     Section::RoomList if key.code == KeyCode::Enter => {
                         self.disable_section(&section)
                     }
-                    _ if key.code == KeyCode::Esc => self.disable_section(&section),
+                    _ if key.code == KeyCode::Esc && !takes_escape => {
+                        self.disable_section(&section)
+                    }
                     _ => (),
                 }
             }
         }
     }
+
+    fn handle_mouse_event(&mut self, mouse: MouseEvent) {
+        // the components know where they were last rendered, so they can tell whether the mouse is over them
+        // a hidden room list still remembers where it was, so it is not asked
+        if !self.is_compact.get() || self.show_rooms_panel {
+            self.room_list.handle_mouse_event(mouse);
+        }
+        self.message_list.handle_mouse_event(mouse);
+    }
+
+    fn is_stale(&self) -> bool {
+        self.clock() != self.rendered_clock.get()
+    }
+}
+
+pub(super) const NO_ROOM_SELECTED_MESSAGE: &str = "Join at least one room to start chatting!";
+
+/// A rect of the given percentage of the area, centered in it
+fn centered_rect(percent_x: u16, percent_y: u16, area: Rect) -> Rect {
+    let [_, middle, _] = *Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Percentage((100 - percent_y) / 2),
+                Constraint::Percentage(percent_y),
+                Constraint::Percentage((100 - percent_y) / 2),
+            ]
+            .as_ref(),
+        )
+        .split(area)
+    else {
+        panic!("The vertical layout should have 3 chunks")
+    };
+
+    let [_, center, _] = *Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(
+            [
+                Constraint::Percentage((100 - percent_x) / 2),
+                Constraint::Percentage(percent_x),
+                Constraint::Percentage((100 - percent_x) / 2),
+            ]
+            .as_ref(),
+        )
+        .split(middle)
+    else {
+        panic!("The horizontal layout should have 3 chunks")
+    };
+
+    center
+}
+
+/// The room list, the messages and the room users side by side
+fn wide_layout(area: Rect) -> (Option<Rect>, Rect, Option<Rect>) {
+    let [left, middle, right] = *Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(
+            [
+                Constraint::Percentage(20),
+                Constraint::Percentage(60),
+                Constraint::Percentage(20),
+            ]
+            .as_ref(),
+        )
+        .split(area)
+    else {
+        panic!("The main layout should have 3 chunks")
+    };
+
+    (Some(left), middle, Some(right))
+}
+
+/// The messages taking the whole width, sharing it with the side panels which are toggled on
+fn compact_layout(
+    area: Rect,
+    show_rooms_panel: bool,
+    show_users_panel: bool,
+) -> (Option<Rect>, Rect, Option<Rect>) {
+    let side_panels = [show_rooms_panel, show_users_panel]
+        .into_iter()
+        .filter(|is_shown| *is_shown)
+        .count() as u16;
+    // the messages keep at least the half of the width
+    let side_panel_percentage = 50 / side_panels.max(1);
+
+    let mut constraints = vec![];
+    if show_rooms_panel {
+        constraints.push(Constraint::Percentage(side_panel_percentage));
+    }
+    constraints.push(Constraint::Min(1));
+    if show_users_panel {
+        constraints.push(Constraint::Percentage(side_panel_percentage));
+    }
+
+    let mut chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(constraints)
+        .split(area)
+        .to_vec()
+        .into_iter();
+    let left = show_rooms_panel.then(|| chunks.next()).flatten();
+    let middle = chunks.next().unwrap_or(area);
+    let right = show_users_panel.then(|| chunks.next()).flatten();
+
+    (left, middle, right)
+}
+
+/// The area of the toasts in the bottom right corner of the given area, as wide as the longest toast
+/// up to half of the area, and one line per toast
+fn toast_area(area: Rect, toasts: &[String]) -> Rect {
+    let longest = toasts.iter().map(|toast| toast.width()).max().unwrap_or(0);
+    // the borders take a line or a column on each side
+    let width = (longest as u16).saturating_add(2).min(area.width / 2);
+    let height = (toasts.len() as u16).saturating_add(2).min(area.height);
+
+    Rect {
+        x: area.x + area.width - width,
+        y: area.y + area.height - height,
+        width,
+        height,
+    }
+}
+
+pub(super) fn calculate_list_offset(height: u16, items_len: usize) -> usize {
+    // go back by (container height - 2 for borders) to get the offset
+    // a container too short to fit its borders has no room for items, so all of them are skipped
+    items_len.saturating_sub(height.saturating_sub(2) as usize)
 }
 
-const NO_ROOM_SELECTED_MESSAGE: &str = "Join at least one room to start chatting!";
+/// Like [calculate_list_offset], for the items which take up the given number of lines
+/// The newest item is always shown, even if it does not fit the lines by itself
+pub(super) fn calculate_wrapped_list_offset(lines: usize, item_heights: &[usize]) -> usize {
+    let mut available = lines;
+    let mut offset = item_heights.len();
+
+    for height in item_heights.iter().rev() {
+        if *height > available {
+            break;
+        }
 
-fn calculate_list_offset(height: u16, items_len: usize) -> usize {
-    // go back by (container height + 2 for borders) to get the offset
-    items_len.saturating_sub(height as usize - 2)
+        available -= height;
+        offset -= 1;
+    }
+
+    if lines > 0 {
+        offset.min(item_heights.len().saturating_sub(1))
+    } else {
+        offset
+    }
+}
+
+/// Formats how long a user has been in a room in its largest unit, e.g. `5m` or `2d`
+pub(super) fn format_member_since(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
 }
 
 impl ComponentRender<()> for ChatPage {
     fn render<B: Backend>(&self, frame: &mut Frame<B>, _props: ()) {
-        let [left, middle, right] = *Layout::default()
-            .direction(Direction::Horizontal)
-            .constraints(
-                [
-                    Constraint::Percentage(20),
-                    Constraint::Percentage(60),
-                    Constraint::Percentage(20),
-                ]
-                .as_ref(),
-            )
-            .split(frame.size())
-        else {
-            panic!("The main layout should have 3 chunks")
-        };
+        let (chatting_for, now) = self.clock();
+        self.rendered_clock.set((chatting_for, now));
 
-        let [container_room_list, container_user_info] = *Layout::default()
+        let [container_page, container_status] = *Layout::default()
             .direction(Direction::Vertical)
-            .constraints([Constraint::Min(1), Constraint::Length(4)].as_ref())
-            .split(left)
+            .constraints([Constraint::Min(1), Constraint::Length(1)].as_ref())
+            .split(frame.size())
         else {
-            panic!("The left layout should have 2 chunks")
+            panic!("The page layout should have 2 chunks")
         };
 
-        self.room_list.render(
-            frame,
-            room_list::RenderProps {
-                border_color: self.calculate_border_color(Section::RoomList),
-                area: container_room_list,
-            },
+        frame.render_widget(
+            Paragraph::new(self.status_line()).style(Style::default().reversed()),
+            container_status,
         );
 
-        let user_info = Paragraph::new(Text::from(vec![
-            Line::from(format!("User: @{}", self.props.user_id)),
-            Line::from(format!("Chatting for: {} secs", self.props.timer)),
-        ]))
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title("User Information"),
-        );
-        frame.render_widget(user_info, container_user_info);
+        let is_compact = container_page.width < COMPACT_LAYOUT_MAX_WIDTH;
+        self.is_compact.set(is_compact);
+        let (left, middle, right) = if is_compact {
+            compact_layout(container_page, self.show_rooms_panel, self.show_users_panel)
+        } else {
+            wide_layout(container_page)
+        };
+
+        if let Some(left) = left {
+            self.render_rooms_panel(frame, left, chatting_for);
+        }
 
         let [container_highlight, container_messages, container_input] = *Layout::default()
             .direction(Direction::Vertical)
@@ -288,7 +861,7 @@ impl ComponentRender<()> for ChatPage {
                 [
                     Constraint::Length(3),
                     Constraint::Min(1),
-                    Constraint::Length(3),
+                    Constraint::Length(self.message_input_box.height()),
                 ]
                 .as_ref(),
             )
@@ -321,38 +894,13 @@ impl ComponentRender<()> for ChatPage {
         );
         frame.render_widget(help_message, container_highlight);
 
-        let messages = if let Some(active_room) = self.props.active_room.as_ref() {
-            self.get_room_data(active_room)
-                .map(|room_data| {
-                    let message_offset =
-                        calculate_list_offset(container_messages.height, room_data.messages.len());
-
-                    room_data
-                        .messages
-                        .asc_iter()
-                        .skip(message_offset)
-                        .map(|mbi| {
-                            let line = match mbi {
-                                MessageBoxItem::Message { user_id, content } => {
-                                    Line::from(Span::raw(format!("@{}: {}", user_id, content)))
-                                }
-                                MessageBoxItem::Notification(content) => {
-                                    Line::from(Span::raw(content.clone()).italic())
-                                }
-                            };
-
-                            ListItem::new(line)
-                        })
-                        .collect::<Vec<ListItem>>()
-                })
-                .unwrap_or_default()
-        } else {
-            vec![ListItem::new(Line::from(NO_ROOM_SELECTED_MESSAGE))]
-        };
-
-        let messages =
-            List::new(messages).block(Block::default().borders(Borders::ALL).title("Messages"));
-        frame.render_widget(messages, container_messages);
+        self.message_list.render(
+            frame,
+            message_list::RenderProps {
+                border_color: self.calculate_border_color(Section::Messages),
+                area: container_messages,
+            },
+        );
 
         self.message_input_box.render(
             frame,
@@ -367,53 +915,18 @@ impl ComponentRender<()> for ChatPage {
             },
         );
 
-        let [container_room_users, container_usage] = *Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([Constraint::Min(1), Constraint::Length(10)].as_ref())
-            .split(right)
-        else {
-            panic!("The left layout should have 2 chunks")
-        };
-
-        let (room_users_list_items, room_users_len) = self
-            .props
-            .active_room
-            .as_ref()
-            .and_then(|active_room| {
-                self.get_room_data(active_room).map(|room_data| {
-                    let room_users_len = room_data.users.len();
-                    let users_offset =
-                        calculate_list_offset(container_room_users.height, room_users_len);
-
-                    (
-                        room_data
-                            .users
-                            .iter()
-                            .skip(users_offset)
-                            .map(|user_id| {
-                                ListItem::new(Line::from(Span::raw(format!("@{user_id}"))))
-                            })
-                            .collect::<Vec<ListItem<'_>>>(),
-                        room_users_len,
-                    )
-                })
-            })
-            .unwrap_or_else(|| (vec![], 0));
-
-        let room_users_list = List::new(room_users_list_items).block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title(format!("Room Users ({})", room_users_len)),
-        );
-
-        frame.render_widget(room_users_list, container_room_users);
+        if let Some(right) = right {
+            self.render_users_panel(frame, right, now);
+        }
 
-        let mut usage_text: Text = widget_usage_to_text(self.usage_info());
-        usage_text.patch_style(Style::default());
-        let usage = Paragraph::new(usage_text)
-            .wrap(Wrap { trim: true })
-            .block(Block::default().borders(Borders::ALL).title("Usage"));
-        frame.render_widget(usage, container_usage);
+        // rendered last, so they overlay everything else
+        self.render_toasts(frame, container_page);
+        if let Some(preview) = self.props.room_users_preview.as_ref() {
+            self.render_room_users(frame, preview);
+        }
+        if self.show_help {
+            self.render_help(frame);
+        }
     }
 }
 
@@ -423,31 +936,243 @@ impl HasUsageInfo for ChatPage {
             let handler: &dyn HasUsageInfo = match section {
                 Section::RoomList => &self.room_list,
                 Section::MessageInput => &self.message_input_box,
+                Section::Messages => &self.message_list,
             };
 
             handler.usage_info()
         } else {
-            UsageInfo {
-                description: Some("Select a widget".into()),
-                lines: vec![
-                    UsageInfoLine {
-                        keys: vec!["q".into()],
-                        description: "to exit".into(),
-                    },
-                    UsageInfoLine {
-                        keys: vec!["←".into(), "→".into()],
-                        description: "to hover widgets".into(),
-                    },
-                    UsageInfoLine {
-                        keys: vec!["e".into()],
-                        description: format!(
-                            "to activate {}",
-                            self.get_component_for_section(&self.last_hovered_section)
-                                .name()
-                        ),
-                    },
-                ],
-            }
+            self.global_usage_info()
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::state_store::DEFAULT_MESSAGE_HISTORY_SIZE;
+
+    use super::*;
+
+    #[test]
+    fn test_calculate_list_offset() {
+        assert_eq!(calculate_list_offset(12, 30), 20);
+        assert_eq!(calculate_list_offset(12, 5), 0);
+
+        assert_eq!(calculate_list_offset(0, 30), 30);
+        assert_eq!(calculate_list_offset(1, 30), 30);
+        assert_eq!(calculate_list_offset(1, 0), 0);
+    }
+
+    #[test]
+    fn test_toast_area_sticks_to_the_bottom_right_corner() {
+        let area = Rect::new(0, 0, 80, 24);
+
+        assert_eq!(
+            toast_area(area, &["hello".into(), "hi".into()]),
+            Rect::new(73, 20, 7, 4)
+        );
+        // the long toasts are cut at the half of the area
+        assert_eq!(
+            toast_area(area, &["a".repeat(100)]),
+            Rect::new(40, 21, 40, 3)
+        );
+        assert_eq!(
+            toast_area(Rect::new(0, 0, 10, 2), &["hello".into()]),
+            Rect::new(5, 0, 5, 2)
+        );
+    }
+
+    #[test]
+    fn test_compact_layout_gives_the_rest_to_the_messages() {
+        let area = Rect::new(0, 0, 60, 20);
+
+        assert_eq!(compact_layout(area, false, false), (None, area, None));
+        assert_eq!(
+            compact_layout(area, true, false),
+            (
+                Some(Rect::new(0, 0, 30, 20)),
+                Rect::new(30, 0, 30, 20),
+                None
+            )
+        );
+        assert_eq!(
+            compact_layout(area, false, true),
+            (
+                None,
+                Rect::new(0, 0, 30, 20),
+                Some(Rect::new(30, 0, 30, 20))
+            )
+        );
+        assert_eq!(
+            compact_layout(area, true, true),
+            (
+                Some(Rect::new(0, 0, 15, 20)),
+                Rect::new(15, 0, 30, 20),
+                Some(Rect::new(45, 0, 15, 20))
+            )
+        );
+    }
+
+    #[test]
+    fn test_narrow_terminals_toggle_the_side_panels() {
+        let (action_tx, _action_rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut chat_page = ChatPage::new(&State::default(), action_tx);
+        let press = |chat_page: &mut ChatPage, code| {
+            chat_page.handle_key_event(KeyEvent::new(code, KeyModifiers::NONE))
+        };
+        let render = |chat_page: &ChatPage, width| {
+            Terminal::new(backend::TestBackend::new(width, 24))
+                .unwrap()
+                .draw(|frame| chat_page.render(frame, ()))
+                .unwrap();
+        };
+
+        // the wide layout always shows the side panels
+        render(&chat_page, 120);
+        press(&mut chat_page, KeyCode::Char('r'));
+        assert!(!chat_page.show_rooms_panel);
+
+        render(&chat_page, 60);
+        press(&mut chat_page, KeyCode::Char('r'));
+        press(&mut chat_page, KeyCode::Char('u'));
+        assert!(chat_page.show_rooms_panel);
+        assert!(chat_page.show_users_panel);
+        render(&chat_page, 60);
+
+        press(&mut chat_page, KeyCode::Char('r'));
+        assert!(!chat_page.show_rooms_panel);
+        // activating the hidden room list brings it up
+        chat_page.last_hovered_section = Section::RoomList;
+        press(&mut chat_page, KeyCode::Char('e'));
+        assert!(chat_page.show_rooms_panel);
+        assert_eq!(chat_page.active_section, Some(Section::RoomList));
+        render(&chat_page, 30);
+    }
+
+    #[test]
+    fn test_format_member_since() {
+        assert_eq!(format_member_since(0), "0s");
+        assert_eq!(format_member_since(59), "59s");
+        assert_eq!(format_member_since(60), "1m");
+        assert_eq!(format_member_since(3599), "59m");
+        assert_eq!(format_member_since(3600), "1h");
+        assert_eq!(format_member_since(86399), "23h");
+        assert_eq!(format_member_since(2 * 86400), "2d");
+    }
+
+    #[test]
+    fn test_calculate_wrapped_list_offset() {
+        assert_eq!(calculate_wrapped_list_offset(10, &[1; 30]), 20);
+        assert_eq!(calculate_wrapped_list_offset(10, &[1, 3, 3, 3]), 0);
+        assert_eq!(calculate_wrapped_list_offset(10, &[1, 3, 3, 4]), 1);
+        assert_eq!(calculate_wrapped_list_offset(10, &[1, 3, 3, 5]), 2);
+
+        // the newest item is shown even if it is taller than the pane
+        assert_eq!(calculate_wrapped_list_offset(10, &[1, 12]), 1);
+        assert_eq!(calculate_wrapped_list_offset(0, &[1, 1]), 2);
+        assert_eq!(calculate_wrapped_list_offset(10, &[]), 0);
+    }
+
+    #[test]
+    fn test_status_line() {
+        let (action_tx, _action_rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut state = State {
+            server_connection_status: ServerConnectionStatus::Connected {
+                addr: "localhost:8080".into(),
+            },
+            user_id: "abc12".into(),
+            ..Default::default()
+        };
+        state.display_names.insert("abc12".into(), "alice".into());
+        state.room_data_map.insert(
+            "rust".into(),
+            Arc::new(RoomData {
+                has_joined: true,
+                ..RoomData::new("rust".into(), "Rust".into(), DEFAULT_MESSAGE_HISTORY_SIZE)
+            }),
+        );
+        let chat_page = ChatPage::new(&state, action_tx);
+
+        let text = chat_page
+            .status_line()
+            .spans
+            .iter()
+            .map(|span| span.content.as_ref())
+            .collect::<String>();
+        assert_eq!(
+            text,
+            "● connected to localhost:8080 | @alice (abc12) | 1 room joined"
+        );
+    }
+
+    #[test]
+    fn test_the_page_is_stale_once_the_clocks_move_on() {
+        let (action_tx, _action_rx) = tokio::sync::mpsc::unbounded_channel();
+        let state = State {
+            connected_at: Instant::now().checked_sub(std::time::Duration::from_secs(5)),
+            ..Default::default()
+        };
+        let chat_page = ChatPage::new(&state, action_tx);
+        assert!(chat_page.is_stale());
+
+        let mut terminal = Terminal::new(backend::TestBackend::new(80, 24)).unwrap();
+        let before = chat_page.clock();
+        terminal.draw(|frame| chat_page.render(frame, ())).unwrap();
+        let rendered = chat_page.rendered_clock.get();
+        assert!(before <= rendered && rendered <= chat_page.clock());
+        assert!(rendered.0 >= 5);
+    }
+
+    #[test]
+    fn test_help_popup_takes_the_keys_until_closed() {
+        let (action_tx, mut action_rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut chat_page = ChatPage::new(&State::default(), action_tx);
+        let press = |chat_page: &mut ChatPage, code| {
+            chat_page.handle_key_event(KeyEvent::new(code, KeyModifiers::NONE))
+        };
+
+        press(&mut chat_page, KeyCode::Char('?'));
+        assert!(chat_page.show_help);
+
+        press(&mut chat_page, KeyCode::Char('q'));
+        assert!(action_rx.try_recv().is_err());
+
+        press(&mut chat_page, KeyCode::Esc);
+        assert!(!chat_page.show_help);
+    }
+
+    #[test]
+    fn test_room_users_popup_takes_the_keys_until_closed() {
+        let (action_tx, mut action_rx) = tokio::sync::mpsc::unbounded_channel();
+        let state = State {
+            room_users_preview: Some(RoomUsersPreview {
+                room: "rust".into(),
+                users: vec!["alice".into()],
+            }),
+            ..Default::default()
+        };
+        let mut chat_page = ChatPage::new(&state, action_tx);
+
+        chat_page.handle_key_event(KeyEvent::new(KeyCode::Char('q'), KeyModifiers::NONE));
+        assert!(action_rx.try_recv().is_err());
+
+        chat_page.handle_key_event(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+        assert!(matches!(action_rx.try_recv(), Ok(Action::CloseRoomUsers)));
+    }
+
+    #[test]
+    fn test_tab_cycles_the_hovered_section() {
+        let (action_tx, _action_rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut chat_page = ChatPage::new(&State::default(), action_tx);
+
+        for _ in 0..Section::COUNT {
+            chat_page.handle_key_event(KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE));
+        }
+        assert_eq!(chat_page.last_hovered_section, DEFAULT_HOVERED_SECTION);
+
+        chat_page.handle_key_event(KeyEvent::new(KeyCode::BackTab, KeyModifiers::SHIFT));
+        assert_eq!(
+            chat_page.last_hovered_section,
+            Section::try_from(Section::COUNT - 1).unwrap()
+        );
+    }
+}
@@ -1,4 +1,5 @@
-use crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
+use anyhow::anyhow;
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
 use ratatui::{
     prelude::{Backend, Rect},
     style::Color,
@@ -16,6 +17,48 @@ use crate::{
     ui_management::pages::chat_page::section::SectionActivation,
 };
 
+/// How many of the recently sent messages are kept to be recalled
+const MAX_HISTORY_LEN: usize = 50;
+/// How many lines of the message the input box grows to show, the rest is scrolled
+const MAX_VISIBLE_LINES: usize = 5;
+
+/// A command typed into the message input, starting with a `/`
+#[derive(Debug, PartialEq, Eq)]
+enum SlashCommand {
+    Join(String),
+    Leave,
+    /// Ask the server for a new user id
+    Reidentify,
+    Quit,
+}
+
+/// Parses the text as a slash command, None if it is a regular message.
+/// A message starting with `//` is a regular message with its first `/` escaped.
+fn parse_slash_command(text: &str) -> Option<anyhow::Result<SlashCommand>> {
+    let command = text.strip_prefix('/')?;
+    if command.starts_with('/') {
+        return None;
+    }
+
+    let mut parts = command.split_whitespace();
+    let name = parts.next().unwrap_or_default();
+    let args: Vec<&str> = parts.collect();
+
+    let result = match (name, args.as_slice()) {
+        ("join", [room]) => Ok(SlashCommand::Join(String::from(*room))),
+        ("join", _) => Err(anyhow!("usage: /join <room>")),
+        ("leave", []) => Ok(SlashCommand::Leave),
+        ("leave", _) => Err(anyhow!("usage: /leave")),
+        ("reidentify", []) => Ok(SlashCommand::Reidentify),
+        ("reidentify", _) => Err(anyhow!("usage: /reidentify")),
+        ("quit", []) => Ok(SlashCommand::Quit),
+        ("quit", _) => Err(anyhow!("usage: /quit")),
+        _ => Err(anyhow!("unknown command: /{}", name)),
+    };
+
+    Some(result)
+}
+
 struct Props {
     /// Active room that the user is chatting in
     active_room: Option<String>,
@@ -35,21 +78,88 @@ pub struct MessageInputBox {
     props: Props,
     // Internal State for the Component
     pub input_box: InputBox,
+    /// Recently sent messages, oldest first
+    history: Vec<String>,
+    /// Index of the recalled message in the history, None when the user is typing a new message
+    history_idx: Option<usize>,
+    /// The message the user was typing before recalling the history, restored when they navigate back
+    draft: String,
 }
 
 impl MessageInputBox {
+    /// The height the input box needs for the lines of the message, including the borders
+    pub fn height(&self) -> u16 {
+        self.input_box.line_count().min(MAX_VISIBLE_LINES) as u16 + 2
+    }
+
     fn submit_message(&mut self) {
         if self.input_box.is_empty() {
             return;
         }
 
+        let content = String::from(self.input_box.text());
+
+        let action = match parse_slash_command(&content) {
+            Some(Ok(SlashCommand::Join(room))) => Action::SelectRoom { room },
+            Some(Ok(SlashCommand::Leave)) => Action::LeaveRoom,
+            Some(Ok(SlashCommand::Reidentify)) => Action::Reidentify,
+            Some(Ok(SlashCommand::Quit)) => Action::Exit,
+            Some(Err(err)) => Action::ShowError {
+                message: format!("Error: {}", err),
+            },
+            None => Action::SendMessage {
+                content: content
+                    .strip_prefix('/')
+                    .filter(|escaped| escaped.starts_with('/'))
+                    .unwrap_or(&content)
+                    .into(),
+            },
+        };
+
         // TODO: handle the error scenario
-        let _ = self.action_tx.send(Action::SendMessage {
-            content: String::from(self.input_box.text()),
-        });
+        let _ = self.action_tx.send(action);
 
+        if self.history.last() != Some(&content) {
+            if self.history.len() == MAX_HISTORY_LEN {
+                self.history.remove(0);
+            }
+            self.history.push(content);
+        }
+        self.history_idx = None;
         self.input_box.reset();
     }
+
+    /// Replace the input with an older message from the history
+    fn recall_previous(&mut self) {
+        let idx = match self.history_idx {
+            Some(0) => return,
+            Some(idx) => idx - 1,
+            None if self.history.is_empty() => return,
+            None => {
+                self.draft = String::from(self.input_box.text());
+                self.history.len() - 1
+            }
+        };
+
+        self.history_idx = Some(idx);
+        self.input_box.set_text(&self.history[idx]);
+    }
+
+    /// Replace the input with a newer message from the history, or the draft once past the newest
+    fn recall_next(&mut self) {
+        match self.history_idx {
+            Some(idx) if idx + 1 < self.history.len() => {
+                self.history_idx = Some(idx + 1);
+                self.input_box.set_text(&self.history[idx + 1]);
+            }
+            Some(_) => {
+                self.history_idx = None;
+                let draft = std::mem::take(&mut self.draft);
+                self.input_box.set_text(&draft);
+            }
+            None => (),
+        }
+    }
 }
 
 impl Component for MessageInputBox {
@@ -59,6 +169,9 @@ impl Component for MessageInputBox {
             props: Props::from(state),
             //
             input_box: InputBox::new(state, action_tx),
+            history: Vec::new(),
+            history_idx: None,
+            draft: String::new(),
         }
     }
 
@@ -82,10 +195,23 @@ impl Component for MessageInputBox {
         }
 
         if self.props.active_room.is_some() {
-            self.input_box.handle_key_event(key);
+            // the history is only navigated when the user is not in the middle of editing the text
+            let is_cursor_at_edge =
+                self.input_box.is_cursor_at_start() || self.input_box.is_cursor_at_end();
 
-            if key.code == KeyCode::Enter {
-                self.submit_message();
+            match key.code {
+                // not every terminal tells Shift+Enter apart from Enter, Alt+Enter is the fallback
+                KeyCode::Enter
+                    if key
+                        .modifiers
+                        .intersects(KeyModifiers::SHIFT | KeyModifiers::ALT) =>
+                {
+                    self.input_box.insert_line_break()
+                }
+                KeyCode::Enter => self.submit_message(),
+                KeyCode::Up if is_cursor_at_edge => self.recall_previous(),
+                KeyCode::Down if is_cursor_at_edge => self.recall_next(),
+                _ => self.input_box.handle_key_event(key),
             }
         }
     }
@@ -96,6 +222,8 @@ impl SectionActivation for MessageInputBox {
 
     fn deactivate(&mut self) {
         self.input_box.reset();
+        self.history_idx = None;
+        self.draft.clear();
     }
 }
 
@@ -131,7 +259,10 @@ impl HasUsageInfo for MessageInputBox {
             }
         } else {
             UsageInfo {
-                description: Some("Type your message to send a message to the active room".into()),
+                description: Some(
+                    "Type your message to send a message to the active room, or /join <room>, /leave, /reidentify and /quit"
+                        .into(),
+                ),
                 lines: vec![
                     UsageInfoLine {
                         keys: vec!["Esc".into()],
@@ -141,8 +272,159 @@ impl HasUsageInfo for MessageInputBox {
                         keys: vec!["Enter".into()],
                         description: "to send your message".into(),
                     },
+                    UsageInfoLine {
+                        keys: vec!["Shift+Enter".into(), "Alt+Enter".into()],
+                        description: "to start a new line".into(),
+                    },
+                    UsageInfoLine {
+                        keys: vec!["↑".into(), "↓".into()],
+                        description: "to recall your sent messages".into(),
+                    },
                 ],
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::KeyModifiers;
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    fn press(message_input_box: &mut MessageInputBox, code: KeyCode) {
+        message_input_box.handle_key_event(KeyEvent::new(code, KeyModifiers::NONE));
+    }
+
+    fn type_text(message_input_box: &mut MessageInputBox, text: &str) {
+        for c in text.chars() {
+            press(message_input_box, KeyCode::Char(c));
+        }
+    }
+
+    #[test]
+    fn test_parse_slash_command() {
+        assert_eq!(
+            parse_slash_command("/join rust").unwrap().unwrap(),
+            SlashCommand::Join("rust".into())
+        );
+        assert_eq!(
+            parse_slash_command("/leave").unwrap().unwrap(),
+            SlashCommand::Leave
+        );
+        assert_eq!(
+            parse_slash_command("/quit ").unwrap().unwrap(),
+            SlashCommand::Quit
+        );
+        assert_eq!(
+            parse_slash_command("/reidentify").unwrap().unwrap(),
+            SlashCommand::Reidentify
+        );
+
+        assert!(parse_slash_command("/join").unwrap().is_err());
+        assert!(parse_slash_command("/join rust go").unwrap().is_err());
+        assert!(parse_slash_command("/leave now").unwrap().is_err());
+        assert!(parse_slash_command("/reidentify me").unwrap().is_err());
+        assert!(parse_slash_command("/dance").unwrap().is_err());
+        assert!(parse_slash_command("/").unwrap().is_err());
+
+        assert!(parse_slash_command("hello").is_none());
+        assert!(parse_slash_command("//join rust").is_none());
+    }
+
+    #[test]
+    fn test_submitting_slash_commands() {
+        let state = State {
+            active_room: Some("rust".into()),
+            ..Default::default()
+        };
+        let (action_tx, mut action_rx) = mpsc::unbounded_channel();
+        let mut message_input_box = MessageInputBox::new(&state, action_tx);
+
+        type_text(&mut message_input_box, "/join go");
+        press(&mut message_input_box, KeyCode::Enter);
+        assert!(matches!(
+            action_rx.try_recv().unwrap(),
+            Action::SelectRoom { room } if room == "go"
+        ));
+
+        type_text(&mut message_input_box, "/dance");
+        press(&mut message_input_box, KeyCode::Enter);
+        assert!(matches!(
+            action_rx.try_recv().unwrap(),
+            Action::ShowError { .. }
+        ));
+
+        type_text(&mut message_input_box, "//shrug");
+        press(&mut message_input_box, KeyCode::Enter);
+        assert!(matches!(
+            action_rx.try_recv().unwrap(),
+            Action::SendMessage { content } if content == "/shrug"
+        ));
+    }
+
+    #[test]
+    fn test_shift_and_alt_enter_insert_line_breaks() {
+        let state = State {
+            active_room: Some("rust".into()),
+            ..Default::default()
+        };
+        let (action_tx, mut action_rx) = mpsc::unbounded_channel();
+        let mut message_input_box = MessageInputBox::new(&state, action_tx);
+        assert_eq!(message_input_box.height(), 3);
+
+        type_text(&mut message_input_box, "first");
+        message_input_box.handle_key_event(KeyEvent::new(KeyCode::Enter, KeyModifiers::SHIFT));
+        type_text(&mut message_input_box, "second");
+        message_input_box.handle_key_event(KeyEvent::new(KeyCode::Enter, KeyModifiers::ALT));
+        type_text(&mut message_input_box, "third");
+        assert!(action_rx.try_recv().is_err());
+        assert_eq!(message_input_box.height(), 5);
+
+        press(&mut message_input_box, KeyCode::Enter);
+        assert!(matches!(
+            action_rx.try_recv().unwrap(),
+            Action::SendMessage { content } if content == "first\nsecond\nthird"
+        ));
+        assert_eq!(message_input_box.height(), 3);
+
+        // the input box stops growing, and scrolls the lines instead
+        for _ in 0..10 {
+            message_input_box.handle_key_event(KeyEvent::new(KeyCode::Enter, KeyModifiers::SHIFT));
+        }
+        assert_eq!(message_input_box.height(), MAX_VISIBLE_LINES as u16 + 2);
+    }
+
+    #[test]
+    fn test_recalling_sent_messages() {
+        let state = State {
+            active_room: Some("rust".into()),
+            ..Default::default()
+        };
+        let (action_tx, _action_rx) = mpsc::unbounded_channel();
+        let mut message_input_box = MessageInputBox::new(&state, action_tx);
+
+        for message in ["first", "second"] {
+            type_text(&mut message_input_box, message);
+            press(&mut message_input_box, KeyCode::Enter);
+        }
+        type_text(&mut message_input_box, "draft");
+
+        press(&mut message_input_box, KeyCode::Up);
+        assert_eq!(message_input_box.input_box.text(), "second");
+        press(&mut message_input_box, KeyCode::Up);
+        press(&mut message_input_box, KeyCode::Up);
+        assert_eq!(message_input_box.input_box.text(), "first");
+
+        press(&mut message_input_box, KeyCode::Down);
+        assert_eq!(message_input_box.input_box.text(), "second");
+        press(&mut message_input_box, KeyCode::Down);
+        assert_eq!(message_input_box.input_box.text(), "draft");
+
+        // the history is left alone while editing in the middle of the text
+        press(&mut message_input_box, KeyCode::Left);
+        press(&mut message_input_box, KeyCode::Up);
+        assert_eq!(message_input_box.input_box.text(), "draft");
+    }
+}
@@ -0,0 +1,994 @@
+use std::cell::Cell;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, MouseEvent, MouseEventKind};
+use ratatui::{
+    prelude::{Backend, Rect},
+    style::{Color, Modifier, Style, Stylize},
+    text::{Line, Span, Text},
+    widgets::{Block, Borders, List, ListItem, ListState},
+    Frame,
+};
+use tokio::sync::mpsc::UnboundedSender;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use super::super::{
+    chat_page::{calculate_wrapped_list_offset, NO_ROOM_SELECTED_MESSAGE},
+    section::usage::{HasUsageInfo, UsageInfo, UsageInfoLine},
+};
+use crate::{
+    state_store::{action::Action, MessageBoxItem, State},
+    ui_management::pages::chat_page::section::SectionActivation,
+};
+
+use crate::ui_management::components::{
+    is_mouse_over, user_color::user_color, Component, ComponentRender,
+};
+
+/// How many messages a single step of the mouse wheel scrolls
+const MOUSE_SCROLL_STEP: usize = 3;
+
+/// Splits the text into spans, highlighting the parts which match the query, ignoring the ASCII case
+fn highlight_matches(text: &str, query: &str) -> Vec<Span<'static>> {
+    if query.is_empty() {
+        return vec![Span::raw(text.to_string())];
+    }
+
+    // ASCII lowercasing keeps the byte offsets the same, so they can be used on the original text
+    let haystack = text.to_ascii_lowercase();
+    let needle = query.to_ascii_lowercase();
+
+    let mut spans = vec![];
+    let mut last = 0;
+    for (start, part) in haystack.match_indices(&needle) {
+        if start > last {
+            spans.push(Span::raw(text[last..start].to_string()));
+        }
+        spans.push(
+            Span::raw(text[start..start + part.len()].to_string())
+                .black()
+                .on_yellow(),
+        );
+        last = start + part.len();
+    }
+    if last < text.len() {
+        spans.push(Span::raw(text[last..].to_string()));
+    }
+
+    spans
+}
+
+/// Splits the text into the parts which are links and the parts which are not, in order
+/// A link starts with `http://` or `https://` and runs until the whitespace, without the trailing punctuation
+fn split_links(text: &str) -> Vec<(&str, bool)> {
+    let mut parts = vec![];
+    let mut last = 0;
+    let mut search_from = 0;
+
+    while let Some(found) = ["http://", "https://"]
+        .iter()
+        .filter_map(|scheme| text[search_from..].find(scheme))
+        .min()
+    {
+        let start = search_from + found;
+        let end = text[start..]
+            .find(char::is_whitespace)
+            .map(|len| start + len)
+            .unwrap_or(text.len());
+        let end = start
+            + text[start..end]
+                .trim_end_matches(|c: char| {
+                    matches!(c, '.' | ',' | ';' | ':' | '!' | '?' | ')' | '\'' | '"')
+                })
+                .len();
+
+        // a bare scheme is not a link
+        let is_link = !text[start..end].ends_with("//");
+        // and neither is a scheme in the middle of a word, e.g. `xhttp://`
+        let is_word_start = text[..start]
+            .chars()
+            .next_back()
+            .is_none_or(|c| c.is_whitespace() || matches!(c, '(' | '<' | '"' | '\''));
+
+        if is_link && is_word_start {
+            if start > last {
+                parts.push((&text[last..start], false));
+            }
+            parts.push((&text[start..end], true));
+            last = end;
+        }
+        search_from = end.max(start + 1);
+    }
+
+    if last < text.len() {
+        parts.push((&text[last..], false));
+    }
+
+    parts
+}
+
+/// The spans of a message content, with the links styled and the matches of the query highlighted
+fn content_spans(content: &str, query: &str) -> Vec<Span<'static>> {
+    split_links(content)
+        .into_iter()
+        .flat_map(|(part, is_link)| {
+            let spans = highlight_matches(part, query);
+
+            if is_link {
+                with_base_style(spans, Style::new().blue().underlined())
+            } else {
+                spans
+            }
+        })
+        .collect()
+}
+
+/// Applies the style under the own styles of the spans, so the highlights stay visible
+fn with_base_style(spans: Vec<Span<'static>>, base: Style) -> Vec<Span<'static>> {
+    spans
+        .into_iter()
+        .map(|span| Span::styled(span.content, base.patch(span.style)))
+        .collect()
+}
+
+/// Splits the text into runs of whitespace and runs of everything else
+fn split_words(text: &str) -> Vec<&str> {
+    let mut words = vec![];
+    let mut start = 0;
+
+    for (idx, c) in text.char_indices().skip(1) {
+        let previous = text[..idx].chars().next_back().unwrap();
+        if c.is_whitespace() != previous.is_whitespace() {
+            words.push(&text[start..idx]);
+            start = idx;
+        }
+    }
+    if start < text.len() {
+        words.push(&text[start..]);
+    }
+
+    words
+}
+
+/// Builds the lines of a wrapped text, indenting the continuation lines
+struct LineWrapper {
+    lines: Vec<Line<'static>>,
+    current: Vec<Span<'static>>,
+    current_width: usize,
+    /// Whether anything other than the indentation is on the current line
+    has_content: bool,
+    width: usize,
+    indent: usize,
+}
+
+impl LineWrapper {
+    fn push(&mut self, text: &str, style: Style) {
+        if !text.is_empty() {
+            self.current.push(Span::styled(text.to_string(), style));
+            self.current_width += text.width();
+            self.has_content = true;
+        }
+    }
+
+    fn break_line(&mut self) {
+        // the whitespace between the words is not needed at the end of a line
+        if self.has_content
+            && self
+                .current
+                .last()
+                .is_some_and(|span| span.content.trim().is_empty())
+        {
+            self.current.pop();
+        }
+
+        self.lines
+            .push(Line::from(std::mem::take(&mut self.current)));
+        if self.indent > 0 {
+            self.current.push(Span::raw(" ".repeat(self.indent)));
+        }
+        self.current_width = self.indent;
+        self.has_content = false;
+    }
+
+    /// Places a word which does not fit any line, breaking it wherever the line is full
+    fn push_broken(&mut self, word: &str, style: Style) {
+        let mut piece = String::new();
+        let mut piece_width = 0;
+
+        for c in word.chars() {
+            let char_width = c.width().unwrap_or(0);
+            if self.current_width + piece_width + char_width > self.width
+                && (self.has_content || piece_width > 0)
+            {
+                self.push(&piece, style);
+                self.break_line();
+                piece.clear();
+                piece_width = 0;
+            }
+
+            piece.push(c);
+            piece_width += char_width;
+        }
+
+        self.push(&piece, style);
+    }
+
+    fn finish(mut self) -> Vec<Line<'static>> {
+        if self.has_content || self.lines.is_empty() {
+            self.lines.push(Line::from(self.current));
+        }
+
+        self.lines
+    }
+}
+
+/// Wraps the spans into lines which fit the width, breaking at the whitespace where possible,
+/// and indents the continuation lines by `indent` columns. A width of 0 does not wrap at all.
+/// The line breaks in the text always start a new line.
+fn wrap_spans(spans: Vec<Span<'static>>, width: usize, indent: usize) -> Vec<Line<'static>> {
+    // without a width, the lines are only broken at the line breaks
+    let width = if width == 0 { usize::MAX } else { width };
+
+    let mut wrapper = LineWrapper {
+        lines: vec![],
+        current: vec![],
+        current_width: 0,
+        has_content: false,
+        width,
+        // a narrow pane is better used for the content than the indentation
+        indent: if indent * 2 > width { 0 } else { indent },
+    };
+
+    for span in spans {
+        for (idx, segment) in span.content.split('\n').enumerate() {
+            if idx > 0 {
+                wrapper.break_line();
+            }
+
+            wrap_segment(&mut wrapper, segment, span.style);
+        }
+    }
+
+    wrapper.finish()
+}
+
+/// Places the words of a text without line breaks, starting a new line whenever the current one is full
+fn wrap_segment(wrapper: &mut LineWrapper, segment: &str, style: Style) {
+    let width = wrapper.width;
+
+    for word in split_words(segment) {
+        let word_width = word.width();
+
+        if wrapper.current_width + word_width <= width {
+            wrapper.push(word, style);
+        } else if word.trim().is_empty() {
+            // the whitespace at the end of a line is dropped in favor of the line break
+            if wrapper.has_content {
+                wrapper.break_line();
+            }
+        } else if wrapper.has_content && wrapper.indent + word_width <= width {
+            wrapper.break_line();
+            wrapper.push(word, style);
+        } else {
+            wrapper.push_broken(word, style);
+        }
+    }
+}
+
+/// The text of a message which can be searched for
+fn searchable_text(item: &MessageBoxItem) -> &str {
+    match item {
+        MessageBoxItem::Message { content, .. }
+        | MessageBoxItem::PendingMessage { content, .. } => content,
+        MessageBoxItem::Notification(content) | MessageBoxItem::System(content) => content,
+    }
+}
+
+/// The author of a message, colored by the user and emphasized if it is the logged in user
+fn author_spans(user_id: &str, display_name: &str, own_user_id: &str) -> Vec<Span<'static>> {
+    let name = Span::from(format!("@{}", display_name)).fg(user_color(user_id));
+
+    if user_id == own_user_id {
+        vec![name.bold(), Span::from(" (you)").dim()]
+    } else {
+        vec![name]
+    }
+}
+
+struct Props {
+    /// The logged in user
+    user_id: String,
+    /// Active room that the user is chatting in
+    active_room: Option<String>,
+    /// Messages of the active room, oldest first
+    messages: Vec<MessageBoxItem>,
+    /// How many messages the active room has received so far
+    received_messages: usize,
+}
+
+impl From<&State> for Props {
+    fn from(state: &State) -> Self {
+        let room_data = state
+            .active_room
+            .as_ref()
+            .and_then(|active_room| state.room_data_map.get(active_room));
+
+        Self {
+            user_id: state.user_id.clone(),
+            active_room: state.active_room.clone(),
+            messages: room_data
+                .map(|room_data| room_data.messages.asc_iter().cloned().collect())
+                .unwrap_or_default(),
+            received_messages: room_data
+                .map(|room_data| room_data.received_messages)
+                .unwrap_or(0),
+        }
+    }
+}
+
+pub struct MessageList {
+    /// Action Sender
+    action_tx: UnboundedSender<Action>,
+    /// State Mapped MessageList Props
+    props: Props,
+    // Internal Component State
+    /// How many messages the view is scrolled up from the newest message, 0 follows the new messages
+    scroll_from_bottom: usize,
+    /// Whether new messages have arrived while the view was scrolled up
+    has_new_messages: bool,
+    /// How many messages fit the pane, recorded on each render to know the size of a page
+    page_size: Cell<usize>,
+    /// Where the pane was last rendered, to know whether the mouse is over it
+    area: Cell<Rect>,
+    /// The messages containing the query are highlighted
+    search: String,
+    /// Whether the keys are typed into the search query
+    is_searching: bool,
+    /// Index of the message the view has jumped to with `n` or `N`
+    current_match: Option<usize>,
+}
+
+impl MessageList {
+    /// The lines of a message, wrapped to the given width
+    fn item_lines(&self, item: &MessageBoxItem, width: usize) -> Vec<Line<'static>> {
+        match item {
+            MessageBoxItem::Message {
+                user_id,
+                display_name,
+                content,
+                ..
+            } => {
+                let mut spans = author_spans(user_id, display_name, &self.props.user_id);
+                spans.push(Span::raw(": "));
+                // the continuation lines start under the content, after the `@user: ` prefix
+                let indent = spans.iter().map(|span| span.content.width()).sum();
+                spans.extend(content_spans(content, &self.search));
+
+                wrap_spans(spans, width, indent)
+            }
+            MessageBoxItem::PendingMessage {
+                user_id,
+                display_name,
+                content,
+                ..
+            } => {
+                let mut spans = author_spans(user_id, display_name, &self.props.user_id);
+                spans.push(Span::raw(": "));
+                let indent = spans.iter().map(|span| span.content.width()).sum();
+                spans.extend(content_spans(content, &self.search));
+                spans.push(Span::from(" (sending…)").dim());
+
+                wrap_spans(spans, width, indent)
+            }
+            MessageBoxItem::Notification(content) => wrap_spans(
+                with_base_style(
+                    highlight_matches(content, &self.search),
+                    Style::new().italic(),
+                ),
+                width,
+                0,
+            ),
+            MessageBoxItem::System(content) => wrap_spans(
+                with_base_style(
+                    highlight_matches(content, &self.search),
+                    Style::new().bold().yellow(),
+                ),
+                width,
+                0,
+            ),
+        }
+    }
+
+    /// The width the messages are wrapped to, 0 until the pane is rendered
+    fn content_width(&self) -> usize {
+        self.area.get().width.saturating_sub(2) as usize
+    }
+
+    /// The index of the first message shown when the view follows the newest messages,
+    /// which is also how far the view can be scrolled up
+    fn max_scroll(&self) -> usize {
+        let width = self.content_width();
+        let item_heights = self
+            .props
+            .messages
+            .iter()
+            .map(|item| self.item_lines(item, width).len())
+            .collect::<Vec<_>>();
+
+        calculate_wrapped_list_offset(self.page_size.get(), &item_heights)
+    }
+
+    fn scroll_up(&mut self, by: usize) {
+        self.scroll_from_bottom = self
+            .scroll_from_bottom
+            .saturating_add(by)
+            .min(self.max_scroll());
+
+        // the oldest loaded message is in view, so the older ones are fetched from the server
+        if self.scroll_from_bottom == self.max_scroll() {
+            if let Some(room) = self.props.active_room.clone() {
+                let _ = self.action_tx.send(Action::FetchHistory { room });
+            }
+        }
+    }
+
+    /// Whether there is a search going on, which takes escape to be cleared
+    pub fn has_search(&self) -> bool {
+        self.is_searching || !self.search.is_empty()
+    }
+
+    fn clear_search(&mut self) {
+        self.search.clear();
+        self.is_searching = false;
+        self.current_match = None;
+    }
+
+    /// Indices of the messages matching the search query, oldest first
+    fn matches(&self) -> Vec<usize> {
+        if self.search.is_empty() {
+            return vec![];
+        }
+
+        let needle = self.search.to_ascii_lowercase();
+        self.props
+            .messages
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| searchable_text(item).to_ascii_lowercase().contains(&needle))
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    /// Jumps to the older match if `older` is set, otherwise to the newer one, wrapping around
+    fn jump_to_match(&mut self, older: bool) {
+        let matches = self.matches();
+        if matches.is_empty() {
+            self.current_match = None;
+            return;
+        }
+
+        let next = match self.current_match {
+            Some(current) if older => matches
+                .iter()
+                .rev()
+                .find(|idx| **idx < current)
+                .or(matches.last()),
+            Some(current) => matches
+                .iter()
+                .find(|idx| **idx > current)
+                .or(matches.first()),
+            // the search starts from the newest messages
+            None => matches.last(),
+        }
+        .copied();
+
+        if let Some(idx) = next {
+            self.current_match = Some(idx);
+            // bring the match to the top of the pane, or as close as the newest messages allow
+            self.scroll_from_bottom = self.max_scroll().saturating_sub(idx);
+            self.has_new_messages = self.has_new_messages && self.scroll_from_bottom > 0;
+        }
+    }
+
+    fn scroll_down(&mut self, by: usize) {
+        self.scroll_from_bottom = self.scroll_from_bottom.saturating_sub(by);
+
+        // back at the bottom, so the new messages are visible again
+        if self.scroll_from_bottom == 0 {
+            self.has_new_messages = false;
+        }
+    }
+}
+
+impl Component for MessageList {
+    fn new(state: &State, action_tx: UnboundedSender<Action>) -> Self {
+        Self {
+            action_tx,
+            props: Props::from(state),
+            //
+            scroll_from_bottom: 0,
+            has_new_messages: false,
+            page_size: Cell::new(0),
+            area: Cell::new(Rect::default()),
+            search: String::new(),
+            is_searching: false,
+            current_match: None,
+        }
+    }
+
+    fn move_with_state(self, state: &State) -> Self
+    where
+        Self: Sized,
+    {
+        let props = Props::from(state);
+        let new_messages = props
+            .received_messages
+            .saturating_sub(self.props.received_messages);
+
+        // the oldest messages are dropped once the room is full, which shifts the indices of the rest
+        let dropped_messages = new_messages.saturating_sub(
+            props
+                .messages
+                .len()
+                .saturating_sub(self.props.messages.len()),
+        );
+
+        let mut message_list = if props.active_room != self.props.active_room {
+            Self {
+                scroll_from_bottom: 0,
+                has_new_messages: false,
+                search: String::new(),
+                is_searching: false,
+                current_match: None,
+                ..self
+            }
+        } else if self.scroll_from_bottom > 0 && new_messages > 0 {
+            // keep looking at the same messages rather than snapping to the bottom
+            Self {
+                scroll_from_bottom: self.scroll_from_bottom + new_messages,
+                has_new_messages: true,
+                ..self
+            }
+        } else {
+            self
+        };
+
+        message_list.current_match = message_list
+            .current_match
+            .and_then(|idx| idx.checked_sub(dropped_messages));
+        message_list.props = props;
+        message_list.scroll_from_bottom = message_list
+            .scroll_from_bottom
+            .min(message_list.max_scroll());
+
+        message_list
+    }
+
+    fn name(&self) -> &str {
+        "Messages"
+    }
+
+    fn handle_key_event(&mut self, key: KeyEvent) {
+        if key.kind != KeyEventKind::Press {
+            return;
+        }
+
+        if self.is_searching {
+            match key.code {
+                KeyCode::Char(c) => {
+                    self.search.push(c);
+                    return;
+                }
+                KeyCode::Backspace => {
+                    self.search.pop();
+                    return;
+                }
+                KeyCode::Enter => {
+                    self.is_searching = false;
+                    self.current_match = None;
+                    self.jump_to_match(true);
+                    return;
+                }
+                _ => {}
+            }
+        }
+
+        match key.code {
+            KeyCode::Char('/') => {
+                self.clear_search();
+                self.is_searching = true;
+            }
+            KeyCode::Char('n') if !self.search.is_empty() => self.jump_to_match(true),
+            KeyCode::Char('N') if !self.search.is_empty() => self.jump_to_match(false),
+            KeyCode::Esc => self.clear_search(),
+            KeyCode::Up => self.scroll_up(1),
+            KeyCode::Down => self.scroll_down(1),
+            KeyCode::PageUp => self.scroll_up(self.page_size.get().max(1)),
+            KeyCode::PageDown => self.scroll_down(self.page_size.get().max(1)),
+            KeyCode::End => self.scroll_down(self.scroll_from_bottom),
+            _ => (),
+        }
+    }
+
+    fn handle_mouse_event(&mut self, mouse: MouseEvent) {
+        if !is_mouse_over(self.area.get(), &mouse) {
+            return;
+        }
+
+        match mouse.kind {
+            MouseEventKind::ScrollUp => self.scroll_up(MOUSE_SCROLL_STEP),
+            MouseEventKind::ScrollDown => self.scroll_down(MOUSE_SCROLL_STEP),
+            _ => (),
+        }
+    }
+}
+
+impl SectionActivation for MessageList {
+    fn activate(&mut self) {}
+
+    fn deactivate(&mut self) {
+        self.clear_search();
+    }
+}
+
+pub struct RenderProps {
+    pub border_color: Color,
+    pub area: Rect,
+}
+
+impl ComponentRender<RenderProps> for MessageList {
+    fn render<B: Backend>(&self, frame: &mut Frame<B>, props: RenderProps) {
+        self.area.set(props.area);
+        self.page_size
+            .set(props.area.height.saturating_sub(2) as usize);
+
+        let messages = if self.props.active_room.is_some() {
+            let width = self.content_width();
+
+            self.props
+                .messages
+                .iter()
+                .map(|mbi| ListItem::new(Text::from(self.item_lines(mbi, width))))
+                .collect::<Vec<ListItem>>()
+        } else {
+            vec![ListItem::new(Line::from(NO_ROOM_SELECTED_MESSAGE))]
+        };
+
+        let mut title = Line::from("Messages");
+        if self.has_search() {
+            let matches = self.matches();
+            let position = self
+                .current_match
+                .and_then(|current| matches.iter().position(|idx| *idx == current))
+                .map(|position| format!("{}/", position + 1))
+                .unwrap_or_default();
+
+            title.spans.push(Span::from(format!(
+                " /{} ({}{} matches)",
+                self.search,
+                position,
+                matches.len()
+            )));
+        }
+        if self.has_new_messages {
+            title.spans.push(" ".into());
+            title
+                .spans
+                .push(Span::from("(new messages below)").bold().yellow());
+        }
+
+        let mut list_state = ListState::default();
+        list_state.select(self.current_match);
+        *list_state.offset_mut() = self.max_scroll().saturating_sub(self.scroll_from_bottom);
+
+        let messages = List::new(messages)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(Style::new().fg(props.border_color))
+                    .title(title),
+            )
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(messages, props.area, &mut list_state);
+    }
+}
+
+impl HasUsageInfo for MessageList {
+    fn usage_info(&self) -> UsageInfo {
+        UsageInfo {
+            description: Some("Scroll through the messages of the room".into()),
+            lines: vec![
+                UsageInfoLine {
+                    keys: vec!["Esc".into()],
+                    description: "to cancel".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["↑".into(), "↓".into()],
+                    description: "to scroll".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["PgUp".into(), "PgDn".into()],
+                    description: "to scroll by a page".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["/".into()],
+                    description: "to search the messages, Esc clears the search".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["n".into(), "N".into()],
+                    description: "to jump to the older or newer match".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["End".into()],
+                    description: "to jump to the newest message".into(),
+                },
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::KeyModifiers;
+    use tokio::sync::mpsc;
+
+    use std::sync::Arc;
+
+    use crate::state_store::{RoomData, DEFAULT_MESSAGE_HISTORY_SIZE};
+
+    use super::*;
+
+    fn push_messages(state: &mut State, count: usize) {
+        let room_data = Arc::make_mut(state.room_data_map.get_mut("rust").unwrap());
+
+        for _ in 0..count {
+            room_data.push_message(MessageBoxItem::Notification("hello".into()));
+        }
+    }
+
+    #[test]
+    fn test_own_messages_are_tagged() {
+        let own = author_spans("abc12", "alice", "abc12");
+        assert_eq!(own[0].content, "@alice");
+        assert!(own[0].style.add_modifier.contains(Modifier::BOLD));
+        assert_eq!(own[1].content, " (you)");
+
+        let other = author_spans("def34", "bob", "abc12");
+        assert_eq!(other.len(), 1);
+        assert!(!other[0].style.add_modifier.contains(Modifier::BOLD));
+    }
+
+    #[test]
+    fn test_scrolled_up_view_does_not_follow_new_messages() {
+        let mut state = State {
+            active_room: Some("rust".into()),
+            ..Default::default()
+        };
+        state.room_data_map.insert(
+            "rust".into(),
+            Arc::new(RoomData::new(
+                "rust".into(),
+                "Rust".into(),
+                DEFAULT_MESSAGE_HISTORY_SIZE,
+            )),
+        );
+        push_messages(&mut state, 30);
+
+        let (action_tx, _action_rx) = mpsc::unbounded_channel();
+        let mut message_list = MessageList::new(&state, action_tx);
+        message_list.page_size.set(10);
+
+        message_list.scroll_up(5);
+        push_messages(&mut state, 3);
+        let mut message_list = message_list.move_with_state(&state);
+        assert_eq!(message_list.scroll_from_bottom, 8);
+        assert!(message_list.has_new_messages);
+
+        // scrolling back to the bottom follows the new messages again
+        message_list.scroll_down(8);
+        push_messages(&mut state, 3);
+        let message_list = message_list.move_with_state(&state);
+        assert_eq!(message_list.scroll_from_bottom, 0);
+        assert!(!message_list.has_new_messages);
+    }
+
+    #[test]
+    fn test_scrolling_to_the_top_fetches_the_older_messages() {
+        let mut state = State {
+            active_room: Some("rust".into()),
+            ..Default::default()
+        };
+        state.room_data_map.insert(
+            "rust".into(),
+            Arc::new(RoomData::new(
+                "rust".into(),
+                "Rust".into(),
+                DEFAULT_MESSAGE_HISTORY_SIZE,
+            )),
+        );
+        push_messages(&mut state, 30);
+
+        let (action_tx, mut action_rx) = mpsc::unbounded_channel();
+        let mut message_list = MessageList::new(&state, action_tx);
+        message_list.page_size.set(10);
+
+        message_list.scroll_up(5);
+        assert!(action_rx.try_recv().is_err());
+
+        message_list.scroll_up(100);
+        assert!(matches!(
+            action_rx.try_recv(),
+            Ok(Action::FetchHistory { room }) if room == "rust"
+        ));
+    }
+
+    fn line_texts(lines: &[Line]) -> Vec<String> {
+        lines
+            .iter()
+            .map(|line| {
+                line.spans
+                    .iter()
+                    .map(|span| span.content.as_ref())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_wrap_spans_indents_the_continuation_lines() {
+        let spans = vec![Span::raw("@bob: "), Span::raw("the quick brown fox jumps")];
+
+        assert_eq!(
+            line_texts(&wrap_spans(spans.clone(), 16, 6)),
+            vec!["@bob: the quick", "      brown fox", "      jumps"]
+        );
+        // without a width, the text is not wrapped
+        assert_eq!(wrap_spans(spans, 0, 6).len(), 1);
+    }
+
+    #[test]
+    fn test_wrap_spans_starts_a_new_line_at_each_line_break() {
+        let spans = vec![
+            Span::raw("@bob: "),
+            Span::raw("first line\n\nthe quick brown fox"),
+        ];
+
+        assert_eq!(
+            line_texts(&wrap_spans(spans.clone(), 16, 6)),
+            vec![
+                "@bob: first line",
+                "      ",
+                "      the quick",
+                "      brown fox"
+            ]
+        );
+        assert_eq!(
+            line_texts(&wrap_spans(spans, 0, 6)),
+            vec!["@bob: first line", "      ", "      the quick brown fox"]
+        );
+    }
+
+    #[test]
+    fn test_wrap_spans_breaks_long_and_wide_words() {
+        assert_eq!(
+            line_texts(&wrap_spans(vec![Span::raw("abcdefghij")], 4, 0)),
+            vec!["abcd", "efgh", "ij"]
+        );
+        // wide characters take two columns each
+        assert_eq!(
+            line_texts(&wrap_spans(vec![Span::raw("日本語です")], 5, 0)),
+            vec!["日本", "語で", "す"]
+        );
+    }
+
+    #[test]
+    fn test_wrap_spans_keeps_the_styles() {
+        let lines = wrap_spans(vec![Span::raw("plain "), Span::raw("red").red()], 6, 0);
+
+        assert_eq!(line_texts(&lines), vec!["plain", "red"]);
+        assert_eq!(lines[1].spans[0].style.fg, Some(Color::Red));
+    }
+
+    #[test]
+    fn test_split_links() {
+        assert_eq!(
+            split_links("see https://example.com/a?b=c, or (http://x.y)."),
+            vec![
+                ("see ", false),
+                ("https://example.com/a?b=c", true),
+                (", or (", false),
+                ("http://x.y", true),
+                (").", false),
+            ]
+        );
+        assert_eq!(split_links("no links"), vec![("no links", false)]);
+        assert_eq!(
+            split_links("just https:// and xhttp://a.b"),
+            vec![("just https:// and xhttp://a.b", false)]
+        );
+    }
+
+    #[test]
+    fn test_links_are_styled_and_highlighted() {
+        let spans = content_spans("go to https://rust-lang.org now", "rust");
+        let contents = spans
+            .iter()
+            .map(|span| span.content.as_ref())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            contents,
+            vec!["go to ", "https://", "rust", "-lang.org", " now"]
+        );
+        assert!(spans[1].style.add_modifier.contains(Modifier::UNDERLINED));
+        assert_eq!(spans[1].style.fg, Some(Color::Blue));
+        // the highlight wins over the link color
+        assert_eq!(spans[2].style.bg, Some(Color::Yellow));
+        assert_eq!(spans[4].style, Style::default());
+    }
+
+    #[test]
+    fn test_highlight_matches_ignores_the_case() {
+        let spans = highlight_matches("Rust is rusty", "rust");
+        let contents = spans
+            .iter()
+            .map(|span| span.content.as_ref())
+            .collect::<Vec<_>>();
+
+        assert_eq!(contents, vec!["Rust", " is ", "rust", "y"]);
+        assert_eq!(spans[0].style.bg, Some(Color::Yellow));
+        assert_eq!(spans[1].style.bg, None);
+        assert_eq!(highlight_matches("hello", "").len(), 1);
+    }
+
+    #[test]
+    fn test_search_jumps_between_the_matches() {
+        let mut state = State {
+            active_room: Some("rust".into()),
+            ..Default::default()
+        };
+        state.room_data_map.insert(
+            "rust".into(),
+            Arc::new(RoomData::new(
+                "rust".into(),
+                "Rust".into(),
+                DEFAULT_MESSAGE_HISTORY_SIZE,
+            )),
+        );
+        let room_data = Arc::make_mut(state.room_data_map.get_mut("rust").unwrap());
+        for i in 0..30 {
+            let content = if i % 10 == 0 { "needle" } else { "hay" };
+            room_data.push_message(MessageBoxItem::Notification(content.into()));
+        }
+
+        let (action_tx, _action_rx) = mpsc::unbounded_channel();
+        let mut message_list = MessageList::new(&state, action_tx);
+        message_list.page_size.set(10);
+        let press = |message_list: &mut MessageList, code| {
+            message_list.handle_key_event(KeyEvent::new(code, KeyModifiers::NONE))
+        };
+
+        press(&mut message_list, KeyCode::Char('/'));
+        for c in "NEEDLE".chars() {
+            press(&mut message_list, KeyCode::Char(c));
+        }
+        assert_eq!(message_list.matches(), vec![0, 10, 20]);
+
+        // the search starts from the newest match, n goes back in time
+        press(&mut message_list, KeyCode::Enter);
+        assert_eq!(message_list.current_match, Some(20));
+        assert_eq!(message_list.scroll_from_bottom, 0);
+        press(&mut message_list, KeyCode::Char('n'));
+        assert_eq!(message_list.current_match, Some(10));
+        assert_eq!(message_list.scroll_from_bottom, 10);
+        press(&mut message_list, KeyCode::Char('n'));
+        press(&mut message_list, KeyCode::Char('n'));
+        assert_eq!(message_list.current_match, Some(20));
+        press(&mut message_list, KeyCode::Char('N'));
+        assert_eq!(message_list.current_match, Some(0));
+
+        assert!(message_list.has_search());
+        press(&mut message_list, KeyCode::Esc);
+        assert!(!message_list.has_search());
+        assert_eq!(message_list.current_match, None);
+    }
+}
@@ -1,2 +1,3 @@
 pub mod message_input_box;
+pub mod message_list;
 pub mod room_list;
@@ -1,4 +1,6 @@
-use crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
+use std::cell::Cell;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, MouseButton, MouseEvent, MouseEventKind};
 use ratatui::{
     prelude::{Backend, Rect},
     style::{Color, Modifier, Style},
@@ -14,13 +16,71 @@ use crate::{
     ui_management::pages::chat_page::section::SectionActivation,
 };
 
-use crate::ui_management::components::{Component, ComponentRender};
+use crate::ui_management::components::{is_mouse_over, Component, ComponentRender};
 
 pub struct RoomState {
     pub name: String,
+    #[allow(dead_code)]
     pub description: String,
     pub has_joined: bool,
-    pub has_unread: bool,
+    pub unread_count: usize,
+    pub has_mention: bool,
+    pub is_muted: bool,
+    pub participant_count: usize,
+}
+
+/// The label of a room in the list, e.g. `#rust! (5 online) (3)` for a room
+/// with 5 users online and 3 unread messages, one of which mentions the user.
+/// The muted rooms are marked with `🔇`, they do not have unread messages or mentions
+fn room_tag(room_state: &RoomState) -> String {
+    let mut tag = format!("#{}", room_state.name);
+
+    if room_state.is_muted {
+        tag.push_str(" 🔇");
+    }
+
+    if room_state.has_mention {
+        tag.push('!');
+    }
+    if room_state.participant_count > 0 {
+        tag.push_str(&format!(" ({} online)", room_state.participant_count));
+    }
+    if room_state.unread_count > 0 {
+        tag.push_str(&format!(" ({})", room_state.unread_count));
+    }
+
+    tag
+}
+
+/// Marks the joined rooms, the rest are padded so the names line up
+fn room_prefix(room_state: &RoomState) -> &'static str {
+    if room_state.has_joined {
+        "✓ "
+    } else {
+        "  "
+    }
+}
+
+/// The active room is bold, the rooms which are not joined are dimmed,
+/// and the rooms with unread messages blink, in red if the user is mentioned
+fn room_style(room_state: &RoomState, is_active: bool) -> Style {
+    let style = if is_active {
+        Style::default().add_modifier(Modifier::BOLD)
+    } else if !room_state.has_joined {
+        Style::default().add_modifier(Modifier::DIM)
+    } else {
+        Style::default()
+    };
+
+    if room_state.has_mention {
+        style
+            .fg(Color::Red)
+            .add_modifier(Modifier::SLOW_BLINK | Modifier::BOLD)
+    } else if room_state.unread_count > 0 {
+        style.add_modifier(Modifier::SLOW_BLINK | Modifier::ITALIC)
+    } else {
+        style
+    }
 }
 
 struct Props {
@@ -39,7 +99,10 @@ impl From<&State> for Props {
                 name: name.clone(),
                 description: room_data.description.clone(),
                 has_joined: room_data.has_joined,
-                has_unread: room_data.has_unread,
+                unread_count: room_data.unread_count,
+                has_mention: room_data.has_mention,
+                is_muted: room_data.muted,
+                participant_count: room_data.participant_count,
             })
             .collect::<Vec<RoomState>>();
 
@@ -60,13 +123,32 @@ pub struct RoomList {
     // Internal Component State
     /// List with optional selection and current offset
     pub list_state: ListState,
+    /// Where the list was last rendered, and the offset it was rendered with, to map the mouse to the rooms
+    rendered_area: Cell<(Rect, usize)>,
+    /// Only the rooms with a name or description containing the filter are listed
+    filter: String,
+    /// Whether the keys are typed into the filter
+    is_filtering: bool,
+}
+
+/// Whether the name or the description of the room contains the filter, ignoring the case
+fn matches_filter(room_state: &RoomState, filter: &str) -> bool {
+    let filter = filter.to_lowercase();
+
+    room_state.name.to_lowercase().contains(&filter)
+        || room_state.description.to_lowercase().contains(&filter)
 }
 
 impl RoomList {
     fn next(&mut self) {
+        let len = self.rooms().len();
+        if len == 0 {
+            return;
+        }
+
         let i = match self.list_state.selected() {
             Some(i) => {
-                if i >= self.props.rooms.len() - 1 {
+                if i >= len - 1 {
                     0
                 } else {
                     i + 1
@@ -78,10 +160,15 @@ impl RoomList {
     }
 
     fn previous(&mut self) {
+        let len = self.rooms().len();
+        if len == 0 {
+            return;
+        }
+
         let i = match self.list_state.selected() {
             Some(i) => {
                 if i == 0 {
-                    self.props.rooms.len() - 1
+                    len - 1
                 } else {
                     i - 1
                 }
@@ -92,14 +179,56 @@ impl RoomList {
         self.list_state.select(Some(i));
     }
 
-    pub(super) fn rooms(&self) -> &Vec<RoomState> {
-        &self.props.rooms
-    }
-
-    fn get_room_idx(&self, name: &str) -> Option<usize> {
+    /// The rooms which match the filter, the selection is an index into these
+    pub(super) fn rooms(&self) -> Vec<&RoomState> {
         self.props
             .rooms
             .iter()
+            .filter(|room_state| matches_filter(room_state, &self.filter))
+            .collect()
+    }
+
+    /// The room under the selection, if any
+    fn selected_room(&self) -> Option<&RoomState> {
+        self.list_state
+            .selected()
+            .and_then(|idx| self.rooms().get(idx).copied())
+    }
+
+    /// Changes the filter, keeping the same room selected if it is still listed
+    fn set_filter(&mut self, filter: String) {
+        let selected = self
+            .selected_room()
+            .map(|room_state| room_state.name.clone());
+        self.filter = filter;
+
+        let rooms_len = self.rooms().len();
+        let idx = selected
+            .and_then(|name| self.get_room_idx(&name))
+            .or(if rooms_len > 0 { Some(0) } else { None });
+
+        *self.list_state.offset_mut() = 0;
+        self.list_state.select(idx);
+    }
+
+    /// Whether the keys are typed into the filter, rather than navigating the rooms
+    pub fn is_filtering(&self) -> bool {
+        self.is_filtering
+    }
+
+    fn select_room(&mut self, idx: usize) {
+        // the selection may be stale if the rooms have changed in the meantime
+        if let Some(room_state) = self.rooms().get(idx) {
+            // TODO: handle the error scenario somehow
+            let _ = self.action_tx.send(Action::SelectRoom {
+                room: room_state.name.clone(),
+            });
+        }
+    }
+
+    fn get_room_idx(&self, name: &str) -> Option<usize> {
+        self.rooms()
+            .into_iter()
             .enumerate()
             .find_map(|(idx, room_state)| {
                 if room_state.name == name {
@@ -118,6 +247,9 @@ impl Component for RoomList {
             props: Props::from(state),
             //
             list_state: ListState::default(),
+            rendered_area: Cell::new((Rect::default(), 0)),
+            filter: String::new(),
+            is_filtering: false,
         }
     }
 
@@ -140,23 +272,92 @@ impl Component for RoomList {
             return;
         }
 
+        if self.is_filtering {
+            match key.code {
+                KeyCode::Char(c) => {
+                    let mut filter = self.filter.clone();
+                    filter.push(c);
+                    self.set_filter(filter);
+                    return;
+                }
+                KeyCode::Backspace => {
+                    let mut filter = self.filter.clone();
+                    filter.pop();
+                    self.set_filter(filter);
+                    return;
+                }
+                KeyCode::Esc => {
+                    self.is_filtering = false;
+                    self.set_filter(String::new());
+                    return;
+                }
+                // the rooms can still be navigated and joined while filtering
+                _ => {}
+            }
+        }
+
         match key.code {
+            // there is nothing to navigate when no rooms are listed
+            KeyCode::Up | KeyCode::Down if self.rooms().is_empty() => {}
             KeyCode::Up => {
                 self.previous();
             }
             KeyCode::Down => {
                 self.next();
             }
+            KeyCode::Char('/') => {
+                self.is_filtering = true;
+            }
+            KeyCode::Char('r') => {
+                let _ = self.action_tx.send(Action::RefreshRooms);
+            }
+            KeyCode::Char('b') => {
+                if let Some(room_state) = self.selected_room() {
+                    let _ = self.action_tx.send(Action::JoinRoomInBackground {
+                        room: room_state.name.clone(),
+                    });
+                }
+            }
+            KeyCode::Char('u') => {
+                if let Some(room_state) = self.selected_room() {
+                    let _ = self.action_tx.send(Action::ListRoomUsers {
+                        room: room_state.name.clone(),
+                    });
+                }
+            }
+            KeyCode::Char('m') => {
+                if let Some(room_state) = self.selected_room() {
+                    let _ = self.action_tx.send(Action::ToggleMuteRoom {
+                        room: room_state.name.clone(),
+                    });
+                }
+            }
             KeyCode::Enter if self.list_state.selected().is_some() => {
                 let selected_idx = self.list_state.selected().unwrap();
 
-                let rooms = self.rooms();
-                let room_state = rooms.get(selected_idx).unwrap();
+                self.select_room(selected_idx);
+            }
+            _ => (),
+        }
+    }
+
+    fn handle_mouse_event(&mut self, mouse: MouseEvent) {
+        let (area, offset) = self.rendered_area.get();
+        if !is_mouse_over(area, &mouse) || self.rooms().is_empty() {
+            return;
+        }
+
+        match mouse.kind {
+            MouseEventKind::ScrollUp => self.previous(),
+            MouseEventKind::ScrollDown => self.next(),
+            // the first row is the border of the list, the rooms start right after it
+            MouseEventKind::Down(MouseButton::Left) if mouse.row > area.y => {
+                let idx = offset + (mouse.row - area.y - 1) as usize;
 
-                // TODO: handle the error scenario somehow
-                let _ = self.action_tx.send(Action::SelectRoom {
-                    room: room_state.name.clone(),
-                });
+                if idx < self.rooms().len() {
+                    self.list_state.select(Some(idx));
+                    self.select_room(idx);
+                }
             }
             _ => (),
         }
@@ -177,6 +378,8 @@ impl SectionActivation for RoomList {
     }
 
     fn deactivate(&mut self) {
+        self.is_filtering = false;
+        self.filter.clear();
         *self.list_state.offset_mut() = 0;
         self.list_state.select(None);
     }
@@ -194,34 +397,28 @@ impl ComponentRender<RenderProps> for RoomList {
             .rooms()
             .iter()
             .map(|room_state| {
-                let room_tag = format!(
-                    "#{}{}",
-                    room_state.name,
-                    if room_state.has_unread { "*" } else { "" }
-                );
-                let content = Line::from(Span::raw(room_tag));
-
-                let style = if self.list_state.selected().is_none()
-                    && active_room.is_some()
-                    && active_room.as_ref().unwrap().eq(&room_state.name)
-                {
-                    Style::default().add_modifier(Modifier::BOLD)
-                } else if room_state.has_unread {
-                    Style::default().add_modifier(Modifier::SLOW_BLINK | Modifier::ITALIC)
-                } else {
-                    Style::default()
-                };
+                let content = Line::from(vec![
+                    Span::raw(room_prefix(room_state)),
+                    Span::raw(room_tag(room_state)),
+                ]);
+                let is_active = active_room.as_ref() == Some(&room_state.name);
 
-                ListItem::new(content).style(style.bg(Color::Reset))
+                ListItem::new(content).style(room_style(room_state, is_active).bg(Color::Reset))
             })
             .collect();
 
+        let title = if self.is_filtering {
+            format!("Rooms /{}", self.filter)
+        } else {
+            String::from("Rooms")
+        };
+
         let room_list = List::new(room_list)
             .block(
                 Block::default()
                     .borders(Borders::ALL)
                     .border_style(Style::new().fg(props.border_color))
-                    .title("Rooms"),
+                    .title(title),
             )
             .highlight_style(
                 Style::default()
@@ -233,6 +430,8 @@ impl ComponentRender<RenderProps> for RoomList {
 
         let mut app_room_list_state = self.list_state.clone();
         frame.render_stateful_widget(room_list, props.area, &mut app_room_list_state);
+        self.rendered_area
+            .set((props.area, app_room_list_state.offset()));
     }
 }
 
@@ -253,7 +452,235 @@ impl HasUsageInfo for RoomList {
                     keys: vec!["Enter".into()],
                     description: "to join room".into(),
                 },
+                UsageInfoLine {
+                    keys: vec!["b".into()],
+                    description: "to join room in the background".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["u".into()],
+                    description: "to see who is in the room".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["m".into()],
+                    description: "to mute or unmute the room".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["/".into()],
+                    description: "to filter rooms, Esc clears the filter".into(),
+                },
+                UsageInfoLine {
+                    keys: vec!["r".into()],
+                    description: "to refresh rooms".into(),
+                },
             ],
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::KeyModifiers;
+    use tokio::sync::mpsc;
+    use tokio::sync::mpsc::error::TryRecvError;
+
+    use std::sync::Arc;
+
+    use crate::state_store::{RoomData, DEFAULT_MESSAGE_HISTORY_SIZE};
+
+    use super::*;
+
+    #[test]
+    fn test_room_tag() {
+        let mut room_state = RoomState {
+            name: "rust".into(),
+            description: "Rust".into(),
+            has_joined: false,
+            unread_count: 0,
+            has_mention: false,
+            is_muted: false,
+            participant_count: 0,
+        };
+        assert_eq!(room_tag(&room_state), "#rust");
+
+        room_state.participant_count = 5;
+        assert_eq!(room_tag(&room_state), "#rust (5 online)");
+
+        room_state.unread_count = 3;
+        room_state.has_mention = true;
+        assert_eq!(room_tag(&room_state), "#rust! (5 online) (3)");
+
+        room_state.unread_count = 0;
+        room_state.has_mention = false;
+        room_state.is_muted = true;
+        assert_eq!(room_tag(&room_state), "#rust 🔇 (5 online)");
+    }
+
+    #[test]
+    fn test_joined_and_active_rooms_are_distinguished() {
+        let mut room_state = RoomState {
+            name: "rust".into(),
+            description: "Rust".into(),
+            has_joined: false,
+            unread_count: 0,
+            has_mention: false,
+            is_muted: false,
+            participant_count: 0,
+        };
+        assert_eq!(room_prefix(&room_state), "  ");
+        assert!(room_style(&room_state, false)
+            .add_modifier
+            .contains(Modifier::DIM));
+
+        room_state.has_joined = true;
+        assert_eq!(room_prefix(&room_state), "✓ ");
+        assert_eq!(room_style(&room_state, false), Style::default());
+        assert!(room_style(&room_state, true)
+            .add_modifier
+            .contains(Modifier::BOLD));
+
+        // the active room stays bold while it has unread messages
+        room_state.unread_count = 3;
+        let style = room_style(&room_state, true);
+        assert!(style
+            .add_modifier
+            .contains(Modifier::BOLD | Modifier::ITALIC));
+    }
+
+    #[test]
+    fn test_navigating_without_rooms_does_not_panic() {
+        let (action_tx, _action_rx) = mpsc::unbounded_channel();
+        let mut room_list = RoomList::new(&State::default(), action_tx);
+
+        room_list.handle_key_event(KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        room_list.handle_key_event(KeyEvent::new(KeyCode::Up, KeyModifiers::NONE));
+        room_list.next();
+        room_list.previous();
+        assert_eq!(room_list.list_state.selected(), None);
+
+        room_list.activate();
+        room_list.handle_key_event(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+    }
+
+    #[test]
+    fn test_clicking_a_room_selects_it() {
+        let mut state = State::default();
+        for name in ["go", "rust"] {
+            state.room_data_map.insert(
+                name.into(),
+                Arc::new(RoomData::new(
+                    name.into(),
+                    name.into(),
+                    DEFAULT_MESSAGE_HISTORY_SIZE,
+                )),
+            );
+        }
+        let (action_tx, mut action_rx) = mpsc::unbounded_channel();
+        let mut room_list = RoomList::new(&state, action_tx);
+        room_list.rendered_area.set((Rect::new(0, 5, 20, 10), 0));
+
+        let click = |row| MouseEvent {
+            kind: MouseEventKind::Down(MouseButton::Left),
+            column: 3,
+            row,
+            modifiers: KeyModifiers::NONE,
+        };
+
+        // clicking the border or below the rooms does nothing
+        room_list.handle_mouse_event(click(5));
+        room_list.handle_mouse_event(click(8));
+        assert!(matches!(action_rx.try_recv(), Err(TryRecvError::Empty)));
+
+        room_list.handle_mouse_event(click(7));
+        assert!(matches!(
+            action_rx.try_recv(),
+            Ok(Action::SelectRoom { room }) if room == "rust"
+        ));
+        assert_eq!(room_list.list_state.selected(), Some(1));
+    }
+
+    #[test]
+    fn test_b_joins_and_m_mutes_the_selected_room() {
+        let mut state = State::default();
+        for name in ["go", "rust"] {
+            state.room_data_map.insert(
+                name.into(),
+                Arc::new(RoomData::new(
+                    name.into(),
+                    name.into(),
+                    DEFAULT_MESSAGE_HISTORY_SIZE,
+                )),
+            );
+        }
+        let (action_tx, mut action_rx) = mpsc::unbounded_channel();
+        let mut room_list = RoomList::new(&state, action_tx);
+
+        room_list.activate();
+        room_list.handle_key_event(KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        room_list.handle_key_event(KeyEvent::new(KeyCode::Char('b'), KeyModifiers::NONE));
+        assert!(matches!(
+            action_rx.try_recv(),
+            Ok(Action::JoinRoomInBackground { room }) if room == "rust"
+        ));
+
+        room_list.handle_key_event(KeyEvent::new(KeyCode::Char('m'), KeyModifiers::NONE));
+        assert!(matches!(
+            action_rx.try_recv(),
+            Ok(Action::ToggleMuteRoom { room }) if room == "rust"
+        ));
+    }
+
+    #[test]
+    fn test_filter_narrows_the_rooms_and_keeps_the_selection() {
+        let mut state = State::default();
+        for (name, description) in [("go", "Gophers"), ("rust", "Crabs"), ("ruby", "Gems")] {
+            state.room_data_map.insert(
+                name.into(),
+                Arc::new(RoomData::new(
+                    name.into(),
+                    description.into(),
+                    DEFAULT_MESSAGE_HISTORY_SIZE,
+                )),
+            );
+        }
+        let (action_tx, _action_rx) = mpsc::unbounded_channel();
+        let mut room_list = RoomList::new(&state, action_tx);
+        let press = |room_list: &mut RoomList, code| {
+            room_list.handle_key_event(KeyEvent::new(code, KeyModifiers::NONE))
+        };
+
+        room_list.activate();
+        // select rust, the last room
+        press(&mut room_list, KeyCode::Up);
+        assert_eq!(room_list.selected_room().unwrap().name, "rust");
+
+        press(&mut room_list, KeyCode::Char('/'));
+        press(&mut room_list, KeyCode::Char('R'));
+        press(&mut room_list, KeyCode::Char('u'));
+        let names = |room_list: &RoomList| {
+            room_list
+                .rooms()
+                .iter()
+                .map(|room_state| room_state.name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&room_list), vec!["ruby", "rust"]);
+        assert_eq!(room_list.selected_room().unwrap().name, "rust");
+
+        // descriptions match too, and the selection falls back to the first room
+        press(&mut room_list, KeyCode::Backspace);
+        press(&mut room_list, KeyCode::Backspace);
+        press(&mut room_list, KeyCode::Char('g'));
+        press(&mut room_list, KeyCode::Char('o'));
+        press(&mut room_list, KeyCode::Char('p'));
+        assert_eq!(names(&room_list), vec!["go"]);
+        assert_eq!(room_list.list_state.selected(), Some(0));
+
+        press(&mut room_list, KeyCode::Char('x'));
+        assert!(room_list.rooms().is_empty());
+        assert_eq!(room_list.list_state.selected(), None);
+
+        press(&mut room_list, KeyCode::Esc);
+        assert!(!room_list.is_filtering());
+        assert_eq!(room_list.rooms().len(), 3);
+    }
+}
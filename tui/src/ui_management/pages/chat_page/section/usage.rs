@@ -46,7 +46,7 @@ pub fn widget_usage_to_text<'a>(usage: UsageInfo) -> Text<'a> {
                     bindings.push(", ".into());
                 }
 
-                bindings.push("or".into());
+                bindings.push("or ".into());
                 bindings.push(key_to_span(wuk.keys.last().unwrap()));
 
                 bindings
@@ -10,6 +10,8 @@ use crate::ui_management::components::{input_box::InputBox, Component, Component
 
 struct Props {
     error_message: Option<String>,
+    /// Status of the automatic reconnection, if the connection was lost
+    reconnecting_message: Option<String>,
 }
 
 impl From<&State> for Props {
@@ -22,18 +24,46 @@ impl From<&State> for Props {
             } else {
                 None
             },
+            reconnecting_message: if let ServerConnectionStatus::Reconnecting {
+                addr,
+                attempt,
+                err,
+            } = &state.server_connection_status
+            {
+                Some(format!(
+                    "Reconnecting to {}… (attempt {}, {})",
+                    addr,
+                    attempt + 1,
+                    err
+                ))
+            } else {
+                None
+            },
         }
     }
 }
 
+/// The input boxes on the connect page which can be focused
+#[derive(Debug, Clone, PartialEq)]
+enum ConnectInput {
+    Address,
+    DisplayName,
+    Token,
+}
+
 /// ConnectPage handles the connection to the server
 pub struct ConnectPage {
     /// Action sender
     pub action_tx: UnboundedSender<Action>,
     // Mapped Props from State
     props: Props,
+    // Internal State
+    /// The input box that is receiving the key events
+    focused_input: ConnectInput,
     // Internal Components
     input_box: InputBox,
+    display_name_input_box: InputBox,
+    token_input_box: InputBox,
 }
 
 impl ConnectPage {
@@ -42,10 +72,55 @@ impl ConnectPage {
             return;
         }
 
+        let display_name = self.display_name_input_box.text().trim();
+        let token = self.token_input_box.text().trim();
+
         let _ = self.action_tx.send(Action::ConnectToServerRequest {
             addr: self.input_box.text().to_string(),
+            display_name: if display_name.is_empty() {
+                None
+            } else {
+                Some(display_name.to_string())
+            },
+            token: if token.is_empty() {
+                None
+            } else {
+                Some(token.to_string())
+            },
         });
     }
+
+    fn focus_next_input(&mut self) {
+        self.focused_input = match self.focused_input {
+            ConnectInput::Address => ConnectInput::DisplayName,
+            ConnectInput::DisplayName => ConnectInput::Token,
+            ConnectInput::Token => ConnectInput::Address,
+        };
+    }
+
+    fn focus_previous_input(&mut self) {
+        self.focused_input = match self.focused_input {
+            ConnectInput::Address => ConnectInput::Token,
+            ConnectInput::DisplayName => ConnectInput::Address,
+            ConnectInput::Token => ConnectInput::DisplayName,
+        };
+    }
+
+    fn get_focused_input_box_mut(&mut self) -> &mut InputBox {
+        match self.focused_input {
+            ConnectInput::Address => &mut self.input_box,
+            ConnectInput::DisplayName => &mut self.display_name_input_box,
+            ConnectInput::Token => &mut self.token_input_box,
+        }
+    }
+
+    fn calculate_border_color(&self, input: ConnectInput) -> Color {
+        if self.focused_input == input {
+            Color::Yellow
+        } else {
+            Color::Reset
+        }
+    }
 }
 
 const DEFAULT_SERVER_ADDR: &str = "localhost:8080";
@@ -57,13 +132,19 @@ impl Component for ConnectPage {
     {
         let mut input_box = InputBox::new(state, action_tx.clone());
         input_box.set_text(DEFAULT_SERVER_ADDR);
+        let mut token_input_box = InputBox::new(state, action_tx.clone());
+        token_input_box.set_masked(true);
 
         ConnectPage {
             action_tx: action_tx.clone(),
             //
             props: Props::from(state),
             //
+            focused_input: ConnectInput::Address,
+            //
             input_box,
+            display_name_input_box: InputBox::new(state, action_tx.clone()),
+            token_input_box,
         }
         .move_with_state(state)
     }
@@ -83,8 +164,6 @@ impl Component for ConnectPage {
     }
 
     fn handle_key_event(&mut self, key: KeyEvent) {
-        self.input_box.handle_key_event(key);
-
         if key.kind != KeyEventKind::Press {
             return;
         }
@@ -93,13 +172,19 @@ impl Component for ConnectPage {
             KeyCode::Enter => {
                 self.connect_to_server();
             }
-            KeyCode::Char('q') => {
-                let _ = self.action_tx.send(Action::Exit);
+            KeyCode::Tab => {
+                self.focus_next_input();
+            }
+            KeyCode::BackTab => {
+                self.focus_previous_input();
             }
             KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
                 let _ = self.action_tx.send(Action::Exit);
             }
-            _ => {}
+            _ => {
+                // every other key is typed into the focused input box
+                self.get_focused_input_box_mut().handle_key_event(key);
+            }
         }
     }
 }
@@ -136,11 +221,13 @@ impl ComponentRender<()> for ConnectPage {
             panic!("The horizontal layout should have 3 chunks")
         };
 
-        let [container_addr_input, container_help_text, container_error_message] =
+        let [container_addr_input, container_display_name_input, container_token_input, container_help_text, container_error_message] =
             *Layout::default()
                 .direction(Direction::Vertical)
                 .constraints(
                     [
+                        Constraint::Length(3),
+                        Constraint::Length(3),
                         Constraint::Length(3),
                         Constraint::Length(3),
                         Constraint::Min(1),
@@ -149,37 +236,64 @@ impl ComponentRender<()> for ConnectPage {
                 )
                 .split(both_centered)
         else {
-            panic!("The left layout should have 3 chunks")
+            panic!("The left layout should have 5 chunks")
         };
 
         self.input_box.render(
             frame,
             input_box::RenderProps {
-                title: "Server Host and Port".into(),
+                title: "Server Address or Socket".into(),
                 area: container_addr_input,
-                border_color: Color::Yellow,
-                show_cursor: true,
+                border_color: self.calculate_border_color(ConnectInput::Address),
+                show_cursor: self.focused_input == ConnectInput::Address,
+            },
+        );
+
+        self.display_name_input_box.render(
+            frame,
+            input_box::RenderProps {
+                title: "Display Name (optional)".into(),
+                area: container_display_name_input,
+                border_color: self.calculate_border_color(ConnectInput::DisplayName),
+                show_cursor: self.focused_input == ConnectInput::DisplayName,
+            },
+        );
+
+        self.token_input_box.render(
+            frame,
+            input_box::RenderProps {
+                title: "Token (optional)".into(),
+                area: container_token_input,
+                border_color: self.calculate_border_color(ConnectInput::Token),
+                show_cursor: self.focused_input == ConnectInput::Token,
             },
         );
 
         let help_text = Paragraph::new(Text::from(Line::from(vec![
             "Press ".into(),
             "<Enter>".bold(),
-            " to connect".into(),
+            " to connect, ".into(),
+            "<Tab>".bold(),
+            " to switch fields".into(),
         ])));
         frame.render_widget(help_text, container_help_text);
 
-        let error_message = Paragraph::new(if let Some(err) = self.props.error_message.as_ref() {
-            Text::from(format!("Error: {}", err.as_str()))
+        let error_message = if let Some(reconnecting) = self.props.reconnecting_message.as_ref() {
+            Paragraph::new(Text::from(reconnecting.as_str()))
+                .style(Style::default().fg(Color::Yellow).italic())
         } else {
-            Text::from("")
-        })
-        .wrap(Wrap { trim: true })
-        .style(
-            Style::default()
-                .fg(Color::Red)
-                .add_modifier(Modifier::SLOW_BLINK | Modifier::ITALIC),
-        );
+            Paragraph::new(if let Some(err) = self.props.error_message.as_ref() {
+                Text::from(format!("Error: {}", err.as_str()))
+            } else {
+                Text::from("")
+            })
+            .style(
+                Style::default()
+                    .fg(Color::Red)
+                    .add_modifier(Modifier::SLOW_BLINK | Modifier::ITALIC),
+            )
+        }
+        .wrap(Wrap { trim: true });
 
         frame.render_widget(error_message, container_error_message);
     }
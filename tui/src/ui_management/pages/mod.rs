@@ -1,5 +1,13 @@
-use crossterm::event::KeyEvent;
-use ratatui::{prelude::Backend, Frame};
+use std::cell::Cell;
+
+use crossterm::event::{KeyEvent, MouseEvent};
+use ratatui::{
+    prelude::{Alignment, Backend, Rect},
+    style::Stylize,
+    text::{Line, Text},
+    widgets::{Paragraph, Wrap},
+    Frame,
+};
 use tokio::sync::mpsc::UnboundedSender;
 
 use crate::state_store::{action::Action, ServerConnectionStatus, State};
@@ -11,6 +19,10 @@ use super::components::{Component, ComponentRender};
 mod chat_page;
 mod connect_page;
 
+/// The smallest terminal the pages are laid out in, a placeholder is shown in the smaller ones
+const MIN_WIDTH: u16 = 30;
+const MIN_HEIGHT: u16 = 12;
+
 enum ActivePage {
     ChatPage,
     ConnectPage,
@@ -33,6 +45,8 @@ impl From<&State> for Props {
 
 pub struct AppRouter {
     props: Props,
+    /// Whether the terminal was too small for the pages on the last render
+    is_too_small: Cell<bool>,
     //
     chat_page: ChatPage,
     connect_page: ConnectPage,
@@ -61,6 +75,7 @@ impl Component for AppRouter {
     {
         AppRouter {
             props: Props::from(state),
+            is_too_small: Cell::new(false),
             //
             chat_page: ChatPage::new(state, action_tx.clone()),
             connect_page: ConnectPage::new(state, action_tx.clone()),
@@ -77,6 +92,7 @@ impl Component for AppRouter {
             //
             chat_page: self.chat_page.move_with_state(state),
             connect_page: self.connect_page.move_with_state(state),
+            ..self
         }
     }
 
@@ -88,13 +104,99 @@ impl Component for AppRouter {
     fn handle_key_event(&mut self, key: KeyEvent) {
         self.get_active_page_component_mut().handle_key_event(key)
     }
+
+    fn handle_mouse_event(&mut self, mouse: MouseEvent) {
+        // the pages are not on the screen, only the areas they were last rendered to are remembered
+        if self.is_too_small.get() {
+            return;
+        }
+
+        self.get_active_page_component_mut()
+            .handle_mouse_event(mouse)
+    }
+
+    fn is_stale(&self) -> bool {
+        self.get_active_page_component().is_stale()
+    }
 }
 
 impl ComponentRender<()> for AppRouter {
     fn render<B: Backend>(&self, frame: &mut Frame<B>, props: ()) {
+        let size = frame.size();
+        self.is_too_small
+            .set(size.width < MIN_WIDTH || size.height < MIN_HEIGHT);
+        if self.is_too_small.get() {
+            render_too_small(frame, size);
+            return;
+        }
+
         match self.props.active_page {
             ActivePage::ChatPage => self.chat_page.render(frame, props),
             ActivePage::ConnectPage => self.connect_page.render(frame, props),
         }
     }
 }
+
+/// Asks the user to resize the terminal, in place of the pages which do not fit it
+fn render_too_small<B: Backend>(frame: &mut Frame<B>, size: Rect) {
+    let text = Text::from(vec![
+        Line::from("Terminal too small".bold()),
+        Line::from(format!(
+            "{}x{}, needs {}x{}",
+            size.width, size.height, MIN_WIDTH, MIN_HEIGHT
+        )),
+    ]);
+
+    frame.render_widget(
+        Paragraph::new(text)
+            .alignment(Alignment::Center)
+            .wrap(Wrap { trim: true }),
+        size,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::{backend::TestBackend, Terminal};
+
+    use super::*;
+
+    fn render(app_router: &AppRouter, width: u16, height: u16) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        terminal.draw(|frame| app_router.render(frame, ())).unwrap();
+
+        terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_pages_render_on_any_terminal_size() {
+        let (action_tx, _action_rx) = tokio::sync::mpsc::unbounded_channel();
+        let connected = State {
+            server_connection_status: ServerConnectionStatus::Connected {
+                addr: "localhost:8080".into(),
+            },
+            ..Default::default()
+        };
+
+        for state in [State::default(), connected] {
+            let app_router = AppRouter::new(&state, action_tx.clone());
+
+            // around the minimum size and the compact layout of the chat page
+            for width in [0, 1, 2, 10, 29, 30, 31, 50, 79, 80, 81, 200] {
+                for height in [0, 1, 2, 5, 11, 12, 13, 20, 60] {
+                    render(&app_router, width, height);
+                }
+            }
+
+            assert!(render(&app_router, 29, 40).contains("Terminal too small"));
+            assert!(render(&app_router, 100, 11).contains("Terminal too small"));
+            assert!(!render(&app_router, 30, 12).contains("Terminal too small"));
+        }
+    }
+}
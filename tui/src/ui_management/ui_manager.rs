@@ -5,14 +5,25 @@ use std::{
 
 use anyhow::Context;
 use crossterm::{
-    event::{DisableMouseCapture, EnableMouseCapture, Event, EventStream},
+    event::{
+        DisableMouseCapture, EnableMouseCapture, Event, EventStream, KeyboardEnhancementFlags,
+        MouseEventKind, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
     execute,
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    style::Print,
+    terminal::{
+        disable_raw_mode, enable_raw_mode, supports_keyboard_enhancement, EnterAlternateScreen,
+        LeaveAlternateScreen,
+    },
 };
 use ratatui::prelude::*;
-use tokio::sync::{
-    broadcast,
-    mpsc::{self, UnboundedReceiver},
+use tokio::{
+    sync::{
+        broadcast,
+        mpsc::{self, UnboundedReceiver},
+        watch,
+    },
+    time::MissedTickBehavior,
 };
 use tokio_stream::StreamExt;
 
@@ -24,50 +35,105 @@ use crate::{
 
 use super::pages::AppRouter;
 
-const RENDERING_TICK_RATE: Duration = Duration::from_millis(250);
+/// How often the ui checks whether what it shows has gone out of date, e.g. a clock, unless configured otherwise
+pub const DEFAULT_TICK_RATE: Duration = Duration::from_millis(250);
+/// The terminal bell, rung when the user is mentioned outside the active room
+const BELL: &str = "\x07";
 
+/// [UiManager] draws the pages and routes the terminal events to them.
+///
+/// The terminal is only redrawn when something has changed, i.e. the state, the user input or the size of the terminal.
+/// The ticks merely redraw the pages which show something changing with time, so an idle client stays idle.
 pub struct UiManager {
     action_tx: mpsc::UnboundedSender<Action>,
+    tick_rate: Duration,
 }
 
 impl UiManager {
     pub fn new() -> (Self, UnboundedReceiver<Action>) {
         let (action_tx, action_rx) = mpsc::unbounded_channel();
 
-        (Self { action_tx }, action_rx)
+        (
+            Self {
+                action_tx,
+                tick_rate: DEFAULT_TICK_RATE,
+            },
+            action_rx,
+        )
+    }
+
+    pub fn with_tick_rate(self, tick_rate: Duration) -> Self {
+        UiManager { tick_rate, ..self }
+    }
+
+    /// Dispatch an action on behalf of the user, e.g. to connect to a server given from the command line
+    pub fn dispatch(&self, action: Action) -> anyhow::Result<()> {
+        self.action_tx
+            .send(action)
+            .context("could not dispatch the action")
     }
 
     pub async fn main_loop(
         self,
-        mut state_rx: UnboundedReceiver<State>,
+        mut state_rx: watch::Receiver<State>,
         mut interrupt_rx: broadcast::Receiver<Interrupted>,
     ) -> anyhow::Result<Interrupted> {
         // consume the first state to initialize the ui app
         let mut app_router = {
-            let state = state_rx.recv().await.unwrap();
+            state_rx
+                .changed()
+                .await
+                .context("the state store has stopped before sending the initial state")?;
+            let state = state_rx.borrow_and_update().clone();
 
             AppRouter::new(&state, self.action_tx.clone())
         };
 
         let mut terminal = setup_terminal()?;
-        let mut ticker = tokio::time::interval(RENDERING_TICK_RATE);
+        let mut ticker = tokio::time::interval(self.tick_rate);
+        // the ticks missed while the machine was asleep are not worth catching up on
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
         let mut crossterm_events = EventStream::new();
+        // the bell is only rung for the mentions which have arrived since the last state
+        let mut seen_mentions = 0;
+        let mut needs_redraw = true;
 
         let result: anyhow::Result<Interrupted> = loop {
             tokio::select! {
-                // Tick to terminate the select every N milliseconds
-                _ = ticker.tick() => (),
+                // Tick to redraw the pages which show something changing with time
+                _ = ticker.tick() => needs_redraw = app_router.is_stale(),
                 // Catch and handle crossterm events
                maybe_event = crossterm_events.next() => match maybe_event {
                     Some(Ok(Event::Key(key)))  => {
+                        let _ = self.action_tx.send(Action::UserActivity);
                         app_router.handle_key_event(key);
+                        needs_redraw = true;
+                    },
+                    Some(Ok(Event::Mouse(mouse))) => {
+                        // merely moving the mouse around does not count as activity
+                        if mouse.kind != MouseEventKind::Moved {
+                            let _ = self.action_tx.send(Action::UserActivity);
+                        }
+                        app_router.handle_mouse_event(mouse);
+                        needs_redraw = true;
                     },
+                    // the terminal is resized on the next draw
+                    Some(Ok(Event::Resize(_, _))) => needs_redraw = true,
                     None => break Ok(Interrupted::UserInt),
                     _ => (),
                 },
-                // Handle state updates
-                Some(state) = state_rx.recv() => {
+                // Handle state updates, skipping the states replaced before they could be rendered
+                Ok(_) = state_rx.changed() => {
+                    // the state is cheap to clone, which releases the channel for the state store right away
+                    let state = state_rx.borrow_and_update().clone();
+
+                    if state.mention_count > seen_mentions && state.is_bell_enabled {
+                        let _ = execute!(terminal.backend_mut(), Print(BELL));
+                    }
+                    seen_mentions = state.mention_count;
+
                     app_router = app_router.move_with_state(&state);
+                    needs_redraw = true;
                 },
                 // Catch and handle interrupt signal to gracefully shutdown
                 Ok(interrupted) = interrupt_rx.recv() => {
@@ -75,6 +141,10 @@ impl UiManager {
                 }
             }
 
+            if !std::mem::take(&mut needs_redraw) {
+                continue;
+            }
+
             if let Err(err) = terminal
                 .draw(|frame| app_router.render(frame, ()))
                 .context("could not render to the terminal")
@@ -96,10 +166,22 @@ fn setup_terminal() -> anyhow::Result<Terminal<CrosstermBackend<Stdout>>> {
 
     execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
 
+    // lets the terminals which support it tell Shift+Enter apart from Enter
+    if supports_keyboard_enhancement().unwrap_or(false) {
+        execute!(
+            stdout,
+            PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES)
+        )?;
+    }
+
     Ok(Terminal::new(CrosstermBackend::new(stdout))?)
 }
 
 fn restore_terminal(terminal: &mut Terminal<CrosstermBackend<Stdout>>) -> anyhow::Result<()> {
+    if supports_keyboard_enhancement().unwrap_or(false) {
+        execute!(terminal.backend_mut(), PopKeyboardEnhancementFlags)?;
+    }
+
     disable_raw_mode()?;
 
     execute!(